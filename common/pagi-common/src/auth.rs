@@ -0,0 +1,255 @@
+//! Bearer token claims and the client side of service authentication.
+//!
+//! pagi-identity-service issues short-lived EdDSA JWTs scoped to a role (and, for twin
+//! tokens, a single twin); services verify them with `pagi_http::auth`. Services and plugins
+//! calling each other obtain a service token through [`ServiceToken`].
+
+use std::{
+    sync::OnceLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Scopes checked by the services. A token carries them space-separated in `scope`.
+pub mod scopes {
+    pub const TWINS_READ: &str = "twins:read";
+    pub const TWINS_WRITE: &str = "twins:write";
+    pub const TOKENS_ISSUE: &str = "tokens:issue";
+    pub const TOOLS_READ: &str = "tools:read";
+    pub const TOOLS_REGISTER: &str = "tools:register";
+    pub const TOOLS_EXECUTE: &str = "tools:execute";
+    pub const EVENTS_PUBLISH: &str = "events:publish";
+    pub const EVENTS_READ: &str = "events:read";
+    pub const MEMORY_READ: &str = "memory:read";
+    pub const MEMORY_WRITE: &str = "memory:write";
+    /// Planning, interaction, inference, context building and acting for a twin.
+    pub const AGENT_RUN: &str = "agent:run";
+
+    pub const ALL: &[&str] = &[
+        TWINS_READ,
+        TWINS_WRITE,
+        TOKENS_ISSUE,
+        TOOLS_READ,
+        TOOLS_REGISTER,
+        TOOLS_EXECUTE,
+        EVENTS_PUBLISH,
+        EVENTS_READ,
+        MEMORY_READ,
+        MEMORY_WRITE,
+        AGENT_RUN,
+    ];
+}
+
+/// Who a token was issued to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Acts for a single twin (`twin_id` is required).
+    Twin,
+    /// A PAGI service or plugin calling another one.
+    Service,
+    /// Operators; the only role that may issue tokens or change twins.
+    Admin,
+}
+
+impl Role {
+    /// Scopes granted when the token request does not narrow them.
+    pub fn default_scopes(self) -> &'static [&'static str] {
+        use scopes::*;
+        match self {
+            Role::Twin => &[TWINS_READ, TOOLS_READ, TOOLS_EXECUTE, MEMORY_READ, MEMORY_WRITE, AGENT_RUN],
+            Role::Service => &[
+                TWINS_READ,
+                TOOLS_READ,
+                TOOLS_REGISTER,
+                TOOLS_EXECUTE,
+                EVENTS_PUBLISH,
+                EVENTS_READ,
+                MEMORY_READ,
+                MEMORY_WRITE,
+                AGENT_RUN,
+            ],
+            Role::Admin => ALL,
+        }
+    }
+}
+
+/// JWT claims of a PAGI bearer token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub iss: String,
+    pub sub: String,
+    pub iat: u64,
+    pub exp: u64,
+    pub jti: Uuid,
+    pub role: Role,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub twin_id: Option<Uuid>,
+    /// Space-separated scopes.
+    #[serde(default)]
+    pub scope: String,
+}
+
+impl Claims {
+    pub fn scopes(&self) -> impl Iterator<Item = &str> {
+        self.scope.split_whitespace()
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes().any(|s| s == scope)
+    }
+
+    /// Twin tokens only act for their own twin; service and admin tokens act for any twin.
+    pub fn allows_twin(&self, twin_id: Uuid) -> bool {
+        match self.role {
+            Role::Twin => self.twin_id == Some(twin_id),
+            Role::Service | Role::Admin => true,
+        }
+    }
+}
+
+/// `POST /tokens` request body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenRequest {
+    pub role: Role,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub twin_id: Option<Uuid>,
+    /// Token subject; defaults to the twin id for twin tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// Narrow the role's default scopes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+}
+
+/// `POST /tokens` response body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: u64,
+    pub scope: String,
+}
+
+/// Refresh a cached token this long before it expires.
+const REFRESH_MARGIN: Duration = Duration::from_secs(30);
+
+struct Cached {
+    token: String,
+    refresh_at: SystemTime,
+}
+
+/// Service token for outgoing calls to other PAGI services.
+///
+/// Configuration:
+/// - `AUTH_TOKEN`: a pre-issued token, used as is
+/// - `AUTH_CLIENT_SECRET`: client secret presented to `AUTH_TOKEN_URL`
+/// - `AUTH_TOKEN_URL`: the identity service `/tokens` endpoint (default: `http://127.0.0.1:8002/tokens`)
+/// - `AUTH_CLIENT_ID`: subject of the requested tokens (default: the binary name)
+///
+/// With neither `AUTH_TOKEN` nor `AUTH_CLIENT_SECRET` set, calls go out without a token.
+pub struct ServiceToken {
+    http: reqwest::Client,
+    fixed: Option<String>,
+    token_url: Option<String>,
+    client_id: String,
+    client_secret: String,
+    cached: Mutex<Option<Cached>>,
+}
+
+static GLOBAL: OnceLock<ServiceToken> = OnceLock::new();
+
+impl ServiceToken {
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let client_secret = var("AUTH_CLIENT_SECRET");
+        let client_id = var("AUTH_CLIENT_ID").unwrap_or_else(|| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| "pagi-service".to_string())
+        });
+        Self {
+            http: crate::tls::client_builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default(),
+            fixed: var("AUTH_TOKEN"),
+            token_url: client_secret
+                .is_some()
+                .then(|| var("AUTH_TOKEN_URL").unwrap_or_else(|| "http://127.0.0.1:8002/tokens".to_string())),
+            client_id,
+            client_secret: client_secret.unwrap_or_default(),
+            cached: Mutex::new(None),
+        }
+    }
+
+    /// Process-wide service token configured from env.
+    pub fn global() -> &'static ServiceToken {
+        GLOBAL.get_or_init(Self::from_env)
+    }
+
+    /// The bearer token to send, or `None` when no token is configured.
+    pub async fn bearer(&self) -> Result<Option<String>, String> {
+        if let Some(token) = &self.fixed {
+            return Ok(Some(token.clone()));
+        }
+        let Some(url) = &self.token_url else {
+            return Ok(None);
+        };
+
+        let mut cached = self.cached.lock().await;
+        if let Some(c) = cached.as_ref().filter(|c| SystemTime::now() < c.refresh_at) {
+            return Ok(Some(c.token.clone()));
+        }
+
+        let request = TokenRequest {
+            role: Role::Service,
+            twin_id: None,
+            subject: Some(self.client_id.clone()),
+            scopes: None,
+            ttl_secs: None,
+        };
+        let resp = self
+            .http
+            .post(url)
+            .bearer_auth(&self.client_secret)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| format!("requesting service token: {e}"))?;
+        if !resp.status().is_success() {
+            return Err(format!("token endpoint returned {}", resp.status()));
+        }
+        let issued: TokenResponse = resp.json().await.map_err(|e| format!("invalid token response: {e}"))?;
+        let lifetime = Duration::from_secs(issued.expires_in);
+        *cached = Some(Cached {
+            token: issued.access_token.clone(),
+            refresh_at: SystemTime::now() + lifetime.saturating_sub(REFRESH_MARGIN),
+        });
+        Ok(Some(issued.access_token))
+    }
+
+    /// Drop the cached token, e.g. after a `401`, so the next call requests a new one.
+    pub async fn invalidate(&self) {
+        *self.cached.lock().await = None;
+    }
+}
+
+/// Attach the process-wide service token (if any) to an outgoing request.
+pub async fn with_service_token(req: reqwest::RequestBuilder) -> Result<reqwest::RequestBuilder, String> {
+    Ok(match ServiceToken::global().bearer().await? {
+        Some(token) => req.bearer_auth(token),
+        None => req,
+    })
+}
+
+/// Seconds since the Unix epoch.
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
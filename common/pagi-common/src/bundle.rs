@@ -0,0 +1,118 @@
+//! Twin export bundles: a signed, versioned archive of everything the stack holds for a twin,
+//! for moving it between nodes.
+//!
+//! The identity service assembles a bundle (`GET /twins/:id/export`) from its registry
+//! record, the twin's signing key sealed under an export passphrase, and sections fetched
+//! from the services holding twin data. The contents travel base64url-encoded exactly as
+//! signed, so verification does not depend on how JSON is re-serialized. The signature is
+//! made with the twin's own key, which the importing node recovers from the sealed key.
+
+use std::collections::BTreeMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use ed25519_dalek::{Signature, Signer as _, SigningKey, Verifier as _, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+pub const BUNDLE_FORMAT: &str = "pagi-twin-bundle";
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// Names of the data sections besides the registry record.
+pub mod sections {
+    /// `pagi-working-memory` items.
+    pub const WORKING_MEMORY: &str = "working_memory";
+    /// `pagi-emotion-state-manager` state.
+    pub const EMOTION: &str = "emotion";
+    /// Twin-scoped tools from the external gateway (global tools stay with the node).
+    pub const TOOLS: &str = "tools";
+    /// Pending DIDComm messages, by recipient DID.
+    pub const MAILBOX: &str = "mailbox";
+}
+
+/// The archive as stored and transferred.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwinBundle {
+    pub format: String,
+    pub version: u32,
+    /// base64url (no padding) JSON of [`BundleContents`], as signed.
+    pub contents: String,
+    pub signature: BundleSignature,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleSignature {
+    /// Always `EdDSA`.
+    pub alg: String,
+    /// The twin's verification method the bundle was signed with.
+    pub verification_method: String,
+    /// base64url Ed25519 signature over the `contents` string.
+    pub value: String,
+}
+
+/// What a bundle carries.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleContents {
+    pub twin_id: Uuid,
+    #[serde(with = "time::serde::rfc3339")]
+    pub exported_at: OffsetDateTime,
+    /// The node that exported the twin.
+    pub exported_by: String,
+    /// The identity service's registry record (state, DID, key history, services).
+    pub identity: Value,
+    /// The twin's signing key, sealed under the export passphrase (key file v1 format).
+    pub sealed_key: Value,
+    /// Data from other services by section name (see [`sections`]). A missing section was
+    /// not available on the exporting node.
+    #[serde(default)]
+    pub sections: BTreeMap<String, Value>,
+}
+
+impl TwinBundle {
+    /// Encode and sign `contents` with the twin's current key.
+    pub fn seal(contents: &BundleContents, signing_key: &SigningKey, verification_method: &str) -> Result<Self, String> {
+        let json = serde_json::to_vec(contents).map_err(|e| e.to_string())?;
+        let encoded = URL_SAFE_NO_PAD.encode(json);
+        let signature = signing_key.sign(encoded.as_bytes());
+        Ok(Self {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            contents: encoded,
+            signature: BundleSignature {
+                alg: "EdDSA".to_string(),
+                verification_method: verification_method.to_string(),
+                value: URL_SAFE_NO_PAD.encode(signature.to_bytes()),
+            },
+        })
+    }
+
+    /// Decode the contents after checking the format and version. The signature is not
+    /// checked yet: the key it verifies with is inside the contents (see [`TwinBundle::verify`]).
+    pub fn unverified_contents(&self) -> Result<BundleContents, String> {
+        if self.format != BUNDLE_FORMAT {
+            return Err(format!("not a twin bundle (format {:?})", self.format));
+        }
+        if self.version != BUNDLE_VERSION {
+            return Err(format!("unsupported bundle version {}", self.version));
+        }
+        let json = URL_SAFE_NO_PAD
+            .decode(&self.contents)
+            .map_err(|e| format!("bundle contents: {e}"))?;
+        serde_json::from_slice(&json).map_err(|e| format!("bundle contents: {e}"))
+    }
+
+    /// Check the signature against the twin's key.
+    pub fn verify(&self, key: &VerifyingKey) -> Result<(), String> {
+        if self.signature.alg != "EdDSA" {
+            return Err(format!("unsupported signature algorithm {}", self.signature.alg));
+        }
+        let bytes: [u8; 64] = URL_SAFE_NO_PAD
+            .decode(&self.signature.value)
+            .ok()
+            .and_then(|b| b.try_into().ok())
+            .ok_or_else(|| "malformed bundle signature".to_string())?;
+        key.verify(self.contents.as_bytes(), &Signature::from_bytes(&bytes))
+            .map_err(|_| "bundle signature does not verify".to_string())
+    }
+}
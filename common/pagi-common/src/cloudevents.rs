@@ -0,0 +1,361 @@
+//! CloudEvents 1.0 binding for [`EventEnvelope`].
+//!
+//! | Envelope          | CloudEvent                                   |
+//! |-------------------|----------------------------------------------|
+//! | `id`              | `id`                                         |
+//! | `event_type`      | `type`                                       |
+//! | `ts`              | `time` (RFC 3339)                            |
+//! | `source`          | `source` ([`UNKNOWN_SOURCE`] when unset)     |
+//! | `subject`         | `subject`                                    |
+//! | `payload`         | `data` (`datacontenttype: application/json`) |
+//! | `twin_id`, ...    | extension attributes (see [`ext`])           |
+//! | `extensions`      | any other extension attribute, as-is         |
+//!
+//! Conversion is lossless in both directions. CloudEvent ids that are not UUIDs are mapped
+//! to a deterministic UUIDv5 and the original is kept in the `ceid` extension.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{EventEnvelope, PagiError};
+
+pub const SPEC_VERSION: &str = "1.0";
+/// Structured mode content type (HTTP and Kafka).
+pub const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json";
+/// Batched structured mode content type (HTTP).
+pub const BATCH_CONTENT_TYPE: &str = "application/cloudevents-batch+json";
+/// `source` is required by CloudEvents; envelopes without one use this and map back to `None`.
+pub const UNKNOWN_SOURCE: &str = "urn:pagi:unknown-source";
+
+/// Extension attribute names for envelope fields that have no core CloudEvents attribute.
+pub mod ext {
+    pub const TWIN_ID: &str = "twinid";
+    pub const SCHEMA_VERSION: &str = "schemaversion";
+    pub const CORRELATION_ID: &str = "correlationid";
+    pub const CAUSATION_ID: &str = "causationid";
+    /// Distributed Tracing extension.
+    pub const TRACEPARENT: &str = "traceparent";
+    /// Original `id` of a CloudEvent whose id is not a UUID.
+    pub const ORIGINAL_ID: &str = "ceid";
+}
+
+/// A CloudEvent in its JSON (structured mode) representation.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CloudEvent {
+    pub specversion: String,
+    pub id: String,
+    pub source: String,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub datacontenttype: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_base64: Option<String>,
+    /// Extension attributes (and `dataschema`), kept as-is.
+    #[serde(flatten)]
+    pub extensions: BTreeMap<String, Value>,
+}
+
+/// Transport a CloudEvent travels over in binary mode, which decides how attributes
+/// become headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    /// `ce-<attr>` headers, percent-encoded values.
+    Http,
+    /// `ce_<attr>` headers, raw UTF-8 values.
+    Kafka,
+}
+
+impl Binding {
+    fn prefix(self) -> &'static str {
+        match self {
+            Binding::Http => "ce-",
+            Binding::Kafka => "ce_",
+        }
+    }
+}
+
+/// CloudEvents attribute naming: 1-20 lowercase ASCII letters or digits. Anything else may not
+/// survive a binding (e.g. as a header name).
+pub fn is_valid_attribute_name(name: &str) -> bool {
+    (1..=20).contains(&name.len()) && name.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+}
+
+/// Both bindings carry `datacontenttype` in a plain `content-type` header.
+const CONTENT_TYPE_HEADER: &str = "content-type";
+
+impl From<&EventEnvelope> for CloudEvent {
+    fn from(ev: &EventEnvelope) -> Self {
+        let mut extensions = ev.extensions.clone();
+        let mut put = |name: &str, value: Option<Value>| {
+            if let Some(value) = value {
+                extensions.insert(name.to_string(), value);
+            }
+        };
+        put(ext::TWIN_ID, ev.twin_id.map(|id| Value::String(id.to_string())));
+        put(ext::SCHEMA_VERSION, (ev.schema_version != 0).then(|| ev.schema_version.into()));
+        put(ext::CORRELATION_ID, ev.correlation_id.map(|id| Value::String(id.to_string())));
+        put(ext::CAUSATION_ID, ev.causation_id.map(|id| Value::String(id.to_string())));
+        put(ext::TRACEPARENT, ev.traceparent.clone().map(Value::String));
+
+        // A non-UUID CloudEvent id that came in through `TryFrom` goes back out unchanged.
+        let id = match extensions.remove(ext::ORIGINAL_ID) {
+            Some(Value::String(original)) => original,
+            _ => ev.id.to_string(),
+        };
+
+        Self {
+            specversion: SPEC_VERSION.to_string(),
+            id,
+            source: ev.source.clone().unwrap_or_else(|| UNKNOWN_SOURCE.to_string()),
+            event_type: ev.event_type.clone(),
+            datacontenttype: Some("application/json".to_string()),
+            subject: ev.subject.clone(),
+            time: ev.ts.format(&Rfc3339).ok(),
+            data: (!ev.payload.is_null()).then(|| ev.payload.clone()),
+            data_base64: None,
+            extensions,
+        }
+    }
+}
+
+impl From<EventEnvelope> for CloudEvent {
+    fn from(ev: EventEnvelope) -> Self {
+        Self::from(&ev)
+    }
+}
+
+impl TryFrom<CloudEvent> for EventEnvelope {
+    type Error = PagiError;
+
+    fn try_from(ce: CloudEvent) -> Result<Self, Self::Error> {
+        if ce.specversion != SPEC_VERSION {
+            return Err(PagiError::config(format!(
+                "unsupported CloudEvents specversion '{}' (expected {SPEC_VERSION})",
+                ce.specversion
+            )));
+        }
+        if ce.data_base64.is_some() {
+            return Err(PagiError::config("data_base64 is not supported; event data must be JSON"));
+        }
+        if let Some(ct) = &ce.datacontenttype {
+            if !is_json(ct) {
+                return Err(PagiError::config(format!("datacontenttype '{ct}' is not JSON")));
+            }
+        }
+        if ce.id.is_empty() || ce.source.is_empty() || ce.event_type.is_empty() {
+            return Err(PagiError::config("CloudEvent id, source and type are required"));
+        }
+
+        if let Some(name) = ce.extensions.keys().find(|name| !is_valid_attribute_name(name)) {
+            return Err(PagiError::config(format!(
+                "invalid CloudEvents attribute name '{name}' (expected 1-20 of a-z, 0-9)"
+            )));
+        }
+
+        let mut extensions = ce.extensions;
+        let id = match Uuid::parse_str(&ce.id) {
+            Ok(id) => id,
+            Err(_) => {
+                // (source, id) is unique per the spec, so it makes a stable UUID.
+                let id = Uuid::new_v5(&Uuid::NAMESPACE_URL, format!("{}#{}", ce.source, ce.id).as_bytes());
+                extensions.insert(ext::ORIGINAL_ID.to_string(), Value::String(ce.id));
+                id
+            }
+        };
+        let ts = match &ce.time {
+            Some(raw) => OffsetDateTime::parse(raw, &Rfc3339)
+                .map_err(|e| PagiError::config(format!("CloudEvent time '{raw}': {e}")))?,
+            None => OffsetDateTime::now_utc(),
+        };
+        let schema_version = match extensions.remove(ext::SCHEMA_VERSION) {
+            None => 0,
+            Some(v) => value_to_string(&v)
+                .parse()
+                .map_err(|_| PagiError::config(format!("extension {}: expected an integer", ext::SCHEMA_VERSION)))?,
+        };
+
+        Ok(EventEnvelope {
+            id,
+            event_type: ce.event_type,
+            ts,
+            schema_version,
+            twin_id: take_uuid(&mut extensions, ext::TWIN_ID)?,
+            subject: ce.subject,
+            source: (ce.source != UNKNOWN_SOURCE).then_some(ce.source),
+            correlation_id: take_uuid(&mut extensions, ext::CORRELATION_ID)?,
+            causation_id: take_uuid(&mut extensions, ext::CAUSATION_ID)?,
+            traceparent: extensions.remove(ext::TRACEPARENT).map(|v| value_to_string(&v)),
+            extensions,
+            payload: ce.data.unwrap_or(Value::Null),
+        })
+    }
+}
+
+impl CloudEvent {
+    /// Binary mode: every attribute except `data` as `(header, value)` pairs, including the
+    /// content type. The message body is [`CloudEvent::binary_body`].
+    pub fn binary_headers(&self, binding: Binding) -> Vec<(String, String)> {
+        let prefix = binding.prefix();
+        let mut attrs: Vec<(&str, String)> = vec![
+            ("specversion", self.specversion.clone()),
+            ("id", self.id.clone()),
+            ("source", self.source.clone()),
+            ("type", self.event_type.clone()),
+        ];
+        if let Some(subject) = &self.subject {
+            attrs.push(("subject", subject.clone()));
+        }
+        if let Some(time) = &self.time {
+            attrs.push(("time", time.clone()));
+        }
+        for (name, value) in &self.extensions {
+            attrs.push((name, value_to_string(value)));
+        }
+
+        let mut headers: Vec<(String, String)> = attrs
+            .into_iter()
+            .map(|(name, value)| {
+                let value = match binding {
+                    Binding::Http => percent_encode(&value),
+                    Binding::Kafka => value,
+                };
+                (format!("{prefix}{name}"), value)
+            })
+            .collect();
+        if let Some(ct) = &self.datacontenttype {
+            headers.push((CONTENT_TYPE_HEADER.to_string(), ct.clone()));
+        }
+        headers
+    }
+
+    /// Binary mode body: the JSON `data`, empty when there is none.
+    pub fn binary_body(&self) -> Vec<u8> {
+        self.data
+            .as_ref()
+            .and_then(|data| serde_json::to_vec(data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Parse a binary mode message. Header names are matched case-insensitively; headers
+    /// without the binding's prefix (other than the content type) are ignored.
+    pub fn from_binary<'a>(
+        binding: Binding,
+        headers: impl IntoIterator<Item = (&'a str, &'a [u8])>,
+        body: &[u8],
+    ) -> Result<Self, PagiError> {
+        let prefix = binding.prefix();
+        let mut attrs: BTreeMap<String, String> = BTreeMap::new();
+        let mut datacontenttype = None;
+        for (name, raw) in headers {
+            let name = name.to_ascii_lowercase();
+            let value = match binding {
+                Binding::Http => percent_decode(raw),
+                Binding::Kafka => String::from_utf8(raw.to_vec()).ok(),
+            }
+            .ok_or_else(|| PagiError::config(format!("header {name}: invalid encoding")))?;
+            if name == CONTENT_TYPE_HEADER {
+                datacontenttype = Some(value);
+            } else if let Some(attr) = name.strip_prefix(prefix) {
+                attrs.insert(attr.to_string(), value);
+            }
+        }
+
+        let mut required = |name: &str| {
+            attrs
+                .remove(name)
+                .ok_or_else(|| PagiError::config(format!("missing {prefix}{name} header")))
+        };
+        let specversion = required("specversion")?;
+        let id = required("id")?;
+        let source = required("source")?;
+        let event_type = required("type")?;
+
+        let data = if body.is_empty() {
+            None
+        } else if datacontenttype.as_deref().is_none_or(is_json) {
+            Some(serde_json::from_slice(body).map_err(|e| PagiError::config(format!("event data: {e}")))?)
+        } else {
+            return Err(PagiError::config(format!(
+                "content type '{}' is not JSON",
+                datacontenttype.unwrap_or_default()
+            )));
+        };
+
+        Ok(Self {
+            specversion,
+            id,
+            source,
+            event_type,
+            datacontenttype,
+            subject: attrs.remove("subject"),
+            time: attrs.remove("time"),
+            data,
+            data_base64: None,
+            extensions: attrs.into_iter().map(|(k, v)| (k, Value::String(v))).collect(),
+        })
+    }
+}
+
+/// `application/json`, `text/json` or any `+json` media type, ignoring parameters.
+pub fn is_json(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+    essence == "application/json" || essence == "text/json" || essence.ends_with("+json")
+}
+
+fn take_uuid(extensions: &mut BTreeMap<String, Value>, name: &str) -> Result<Option<Uuid>, PagiError> {
+    extensions
+        .remove(name)
+        .map(|v| {
+            Uuid::parse_str(&value_to_string(&v))
+                .map_err(|e| PagiError::config(format!("extension {name}: {e}")))
+        })
+        .transpose()
+}
+
+/// Binary mode carries every attribute as a string.
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// HTTP binding: space, `"`, `%` and anything outside printable ASCII is percent-encoded.
+fn percent_encode(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if (0x21..=0x7e).contains(&b) && b != b'"' && b != b'%' {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+fn percent_decode(raw: &[u8]) -> Option<String> {
+    let mut out = Vec::with_capacity(raw.len());
+    let mut i = 0;
+    while i < raw.len() {
+        if raw[i] == b'%' {
+            let hex = std::str::from_utf8(raw.get(i + 1..i + 3)?).ok()?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(raw[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
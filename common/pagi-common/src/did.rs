@@ -0,0 +1,267 @@
+//! Ed25519 public key resolution for `did:key` and `did:web` DIDs.
+//!
+//! `did:key` is decoded locally. `did:web` documents are fetched over HTTPS
+//! (`did:web:example.com:twins:abc` → `https://example.com/twins/abc/did.json`, a bare domain →
+//! `/.well-known/did.json`); set `DID_WEB_ALLOW_HTTP=true` to resolve over plain HTTP in local
+//! development, and `DID_WEB_TIMEOUT_SECS` to bound each fetch (default: 10).
+//!
+//! Only keys listed under a document's `assertionMethod` sign anything new. Keys retired by a
+//! rotation stay in `verificationMethod` with a `revoked` time, and verify signatures made
+//! before it (see [`DidResolver::ed25519_keys_at`]).
+//!
+//! Signers find the DID to sign as with [`DidResolver::signing_did`]: a twin's `did:web` as
+//! registered with the identity service at `IDENTITY_SERVICE_URL`, or its key's `did:key`.
+
+use std::time::Duration;
+
+use serde_json::Value;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use uuid::Uuid;
+
+/// Multicodec prefix for an Ed25519 public key.
+const ED25519_PUB: [u8; 2] = [0xed, 0x01];
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub struct DidResolver {
+    http: reqwest::Client,
+    allow_http: bool,
+    timeout: Duration,
+    identity_url: Option<String>,
+}
+
+/// The DID a twin signs as, and the verification method naming its current key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SigningDid {
+    pub did: String,
+    pub key_id: String,
+}
+
+impl DidResolver {
+    pub fn new(http: reqwest::Client, allow_http: bool) -> Self {
+        Self {
+            http,
+            allow_http,
+            timeout: DEFAULT_TIMEOUT,
+            identity_url: None,
+        }
+    }
+
+    pub fn from_env(http: reqwest::Client) -> Self {
+        let allow_http = std::env::var("DID_WEB_ALLOW_HTTP")
+            .map(|v| v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+        let timeout = std::env::var("DID_WEB_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok())
+            .filter(|secs| *secs > 0)
+            .map_or(DEFAULT_TIMEOUT, Duration::from_secs);
+        let identity_url = std::env::var("IDENTITY_SERVICE_URL").ok().filter(|u| !u.trim().is_empty());
+        Self {
+            timeout,
+            ..Self::new(http, allow_http).with_identity_url(identity_url)
+        }
+    }
+
+    /// Where [`signing_did`](Self::signing_did) looks up twins' DIDs; `None` signs as `did:key`.
+    pub fn with_identity_url(mut self, identity_url: Option<String>) -> Self {
+        self.identity_url = identity_url.map(|u| u.trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Public keys a DID (or DID URL) can verify new signatures with.
+    ///
+    /// For `did:web`, the document's `assertionMethod` keys; a `#fragment` narrows it to that
+    /// method, which must be one of them.
+    pub async fn ed25519_keys(&self, did_url: &str) -> Result<Vec<[u8; 32]>, String> {
+        self.keys(did_url, None).await
+    }
+
+    /// Like [`ed25519_keys`](Self::ed25519_keys), plus keys retired after `signed_at`, for
+    /// signatures that carry their signing time (e.g. a credential proof's `created`).
+    pub async fn ed25519_keys_at(&self, did_url: &str, signed_at: OffsetDateTime) -> Result<Vec<[u8; 32]>, String> {
+        self.keys(did_url, Some(signed_at)).await
+    }
+
+    async fn keys(&self, did_url: &str, signed_at: Option<OffsetDateTime>) -> Result<Vec<[u8; 32]>, String> {
+        let (did, fragment) = match did_url.split_once('#') {
+            Some((did, fragment)) => (did, Some(fragment)),
+            None => (did_url, None),
+        };
+
+        if let Some(method_id) = did.strip_prefix("did:key:") {
+            return Ok(vec![ed25519_from_multibase(method_id)?]);
+        }
+        if !did.starts_with("did:web:") {
+            return Err(format!("unsupported DID method (expected did:key or did:web): {did}"));
+        }
+
+        let doc = self.document(did).await?;
+        let mut keys = Vec::new();
+        for method in verification_methods(did, &doc)? {
+            if fragment.is_some_and(|f| method.id != format!("{did}#{f}")) {
+                continue;
+            }
+            let usable = match method.revoked {
+                None => method.asserts,
+                Some(revoked) => signed_at.is_some_and(|at| at < revoked),
+            };
+            if usable {
+                keys.push(ed25519_from_multibase(&method.public_key_multibase)?);
+            }
+        }
+        if keys.is_empty() {
+            return Err(format!("{did_url}: no usable Ed25519 assertion method in DID document"));
+        }
+        Ok(keys)
+    }
+
+    /// The DID `twin_id` signs as with `public_key`: its DID as registered with the identity
+    /// service, or the key's `did:key` when the twin has one or no identity service is set.
+    ///
+    /// Fails when the key is not the twin's current assertion key (e.g. mid-rotation) or the
+    /// twin is deleted, rather than signing under a DID that would not verify.
+    pub async fn signing_did(&self, twin_id: Uuid, public_key: &[u8; 32]) -> Result<SigningDid, String> {
+        let method_id = ed25519_multibase(public_key);
+        let did_key = || SigningDid {
+            did: format!("did:key:{method_id}"),
+            key_id: format!("did:key:{method_id}#{method_id}"),
+        };
+        let Some(base) = &self.identity_url else {
+            return Ok(did_key());
+        };
+        let url = format!("{base}/twins/{twin_id}/did");
+        let resp = self
+            .http
+            .get(&url)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(|e| format!("twin {twin_id}: fetching {url}: {e}"))?;
+        if !resp.status().is_success() {
+            return Err(format!("twin {twin_id}: {url} returned {}", resp.status()));
+        }
+        let doc: Value = resp.json().await.map_err(|e| format!("twin {twin_id}: invalid DID document: {e}"))?;
+        let did = doc
+            .get("id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("twin {twin_id}: DID document has no id"))?;
+        if did.starts_with("did:key:") {
+            return Ok(did_key());
+        }
+        verification_methods(did, &doc)?
+            .into_iter()
+            .find(|m| m.asserts && m.revoked.is_none() && m.public_key_multibase == method_id)
+            .map(|m| SigningDid { did: did.to_string(), key_id: m.id })
+            .ok_or_else(|| format!("twin {twin_id}: key file is not a current key of {did}"))
+    }
+
+    /// Fetch a `did:web` DID document; its `id` must be the DID itself.
+    pub async fn document(&self, did: &str) -> Result<Value, String> {
+        let url = did_web_url(did, self.allow_http)?;
+        let resp = self
+            .http
+            .get(&url)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(|e| format!("{did}: fetching {url}: {e}"))?;
+        if !resp.status().is_success() {
+            return Err(format!("{did}: {url} returned {}", resp.status()));
+        }
+        let doc: Value = resp.json().await.map_err(|e| format!("{did}: invalid DID document: {e}"))?;
+        if doc.get("id").and_then(Value::as_str) != Some(did) {
+            return Err(format!("{did}: DID document id does not match"));
+        }
+        Ok(doc)
+    }
+}
+
+/// A verification method with a multibase key, with its id made absolute.
+struct Method {
+    id: String,
+    public_key_multibase: String,
+    /// Listed under `assertionMethod`.
+    asserts: bool,
+    revoked: Option<OffsetDateTime>,
+}
+
+/// The document's multibase verification methods, including those embedded in
+/// `assertionMethod`.
+fn verification_methods(did: &str, doc: &Value) -> Result<Vec<Method>, String> {
+    let absolute = |id: &str| match id.strip_prefix('#') {
+        Some(relative) => format!("{did}#{relative}"),
+        None => id.to_string(),
+    };
+    let assertion = doc.get("assertionMethod").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+    let referenced: Vec<String> = assertion.iter().filter_map(Value::as_str).map(absolute).collect();
+    let embedded = assertion.iter().filter(|m| m.is_object());
+    let listed = doc.get("verificationMethod").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+
+    let mut methods = Vec::new();
+    for (method, embedded) in listed.iter().map(|m| (m, false)).chain(embedded.map(|m| (m, true))) {
+        let Some(encoded) = method.get("publicKeyMultibase").and_then(Value::as_str) else {
+            continue;
+        };
+        let id = absolute(method.get("id").and_then(Value::as_str).unwrap_or_default());
+        let revoked = match method.get("revoked").and_then(Value::as_str) {
+            Some(raw) => Some(OffsetDateTime::parse(raw, &Rfc3339).map_err(|e| format!("{id}: revoked: {e}"))?),
+            None => None,
+        };
+        methods.push(Method {
+            asserts: embedded || referenced.contains(&id),
+            id,
+            public_key_multibase: encoded.to_string(),
+            revoked,
+        });
+    }
+    if methods.is_empty() {
+        return Err(format!("{did}: DID document has no verificationMethod"));
+    }
+    Ok(methods)
+}
+
+/// Where a `did:web` DID's document lives.
+pub fn did_web_url(did: &str, allow_http: bool) -> Result<String, String> {
+    let method_id = did
+        .strip_prefix("did:web:")
+        .ok_or_else(|| format!("not a did:web DID: {did}"))?;
+    let mut parts = method_id.split(':');
+    let host = parts.next().unwrap_or_default().replace("%3A", ":").replace("%3a", ":");
+    if host.is_empty() || host.contains('/') {
+        return Err(format!("invalid did:web host in {did}"));
+    }
+    let path: Vec<&str> = parts.collect();
+    if path.iter().any(|p| p.is_empty() || p.contains('/')) {
+        return Err(format!("invalid did:web path in {did}"));
+    }
+
+    let scheme = if allow_http { "http" } else { "https" };
+    if path.is_empty() {
+        Ok(format!("{scheme}://{host}/.well-known/did.json"))
+    } else {
+        Ok(format!("{scheme}://{host}/{}/did.json", path.join("/")))
+    }
+}
+
+/// Encode an Ed25519 public key as `publicKeyMultibase` / a `did:key` method id.
+pub fn ed25519_multibase(public_key: &[u8; 32]) -> String {
+    let mut bytes = ED25519_PUB.to_vec();
+    bytes.extend_from_slice(public_key);
+    multibase::encode(multibase::Base::Base58Btc, bytes)
+}
+
+/// Decode `publicKeyMultibase` / a `did:key` method id holding an Ed25519 public key.
+pub fn ed25519_from_multibase(encoded: &str) -> Result<[u8; 32], String> {
+    let (_base, bytes) = multibase::decode(encoded).map_err(|e| format!("multibase decode failed: {e}"))?;
+    if bytes.len() != 2 + 32 {
+        return Err(format!("unexpected multikey decoded length: {}", bytes.len()));
+    }
+    if bytes[..2] != ED25519_PUB {
+        return Err("unsupported key type (expected ed25519-pub multicodec 0xed01)".to_string());
+    }
+    let mut pk = [0u8; 32];
+    pk.copy_from_slice(&bytes[2..]);
+    Ok(pk)
+}
@@ -0,0 +1,243 @@
+//! Twin erasure and data retention.
+//!
+//! `DELETE /twins/:id` on the identity service moves the twin to `deleted`, destroys its key
+//! file and delivers a [`CoreEvent::TwinDeleted`] envelope to `POST /events` on every service
+//! holding twin data. Each service confirms the deletion with the identity service
+//! ([`DeletionConfirmer`]), purges what it holds for the twin (and its DIDs), answers with a
+//! [`PurgeReport`] and publishes it as [`CoreEvent::TwinDataPurged`]; the identity service
+//! keeps the reports with the deleted twin as proof of erasure.
+//!
+//! Independently of deletion, services drop data older than their [`Retention`] on a
+//! background sweep, reporting each twin they purged the same way.
+
+use std::{collections::BTreeMap, fmt, future::Future, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{CoreEvent, EventEnvelope, TwinStatus};
+
+/// Why a service purged twin data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PurgeTrigger {
+    TwinDeleted,
+    Retention,
+}
+
+/// A twin to erase, from a [`CoreEvent::TwinDeleted`] envelope.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwinDeletion {
+    pub twin_id: Uuid,
+    /// The twin's DID followed by its previous DIDs.
+    pub dids: Vec<String>,
+}
+
+impl TwinDeletion {
+    /// `None` for any other event, and for deletions without a twin id.
+    pub fn from_event(ev: &EventEnvelope) -> Option<Self> {
+        let twin_id = ev.twin_id?;
+        match ev.core_event().ok()? {
+            CoreEvent::TwinDeleted { did, previous_dids, .. } => Some(Self {
+                twin_id,
+                dids: std::iter::once(did).chain(previous_dids).collect(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Why a deletion was not confirmed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Unconfirmed {
+    /// The identity service does not report the twin as deleted (`None`: it does not know it).
+    NotDeleted(Option<TwinStatus>),
+    /// The identity service could not be asked, or is not configured.
+    Unavailable(String),
+}
+
+impl fmt::Display for Unconfirmed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Unconfirmed::NotDeleted(Some(status)) => write!(f, "twin is {status}, not deleted"),
+            Unconfirmed::NotDeleted(None) => write!(f, "twin is not registered"),
+            Unconfirmed::Unavailable(err) => write!(f, "twin deletion could not be confirmed: {err}"),
+        }
+    }
+}
+
+/// Confirms [`TwinDeletion`]s with the identity service before any data is purged.
+///
+/// Anyone able to post to a service's `/events` could otherwise erase a live twin, so the
+/// twin must be `deleted` in the registry, and the DIDs to purge come from the registry rather
+/// than the event. Without `IDENTITY_SERVICE_URL` nothing is confirmed and nothing purged.
+pub struct DeletionConfirmer {
+    http: reqwest::Client,
+    identity_url: Option<String>,
+}
+
+/// The part of `GET /twins/:id` a confirmation needs.
+#[derive(Deserialize)]
+struct RegisteredTwin {
+    status: TwinStatus,
+    #[serde(default)]
+    did: Option<String>,
+    #[serde(default)]
+    previous_dids: Vec<String>,
+}
+
+impl DeletionConfirmer {
+    pub fn new(identity_url: Option<String>) -> Self {
+        Self {
+            http: crate::tls::client_builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default(),
+            identity_url: identity_url.map(|u| u.trim_end_matches('/').to_string()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(std::env::var("IDENTITY_SERVICE_URL").ok().filter(|u| !u.trim().is_empty()))
+    }
+
+    /// The deletion as the registry records it, once the identity service reports the twin
+    /// `deleted`.
+    pub async fn confirm(&self, deletion: &TwinDeletion) -> Result<TwinDeletion, Unconfirmed> {
+        let Some(base) = &self.identity_url else {
+            return Err(Unconfirmed::Unavailable("IDENTITY_SERVICE_URL is not set".to_string()));
+        };
+        let twin_id = deletion.twin_id;
+        let twin = self.fetch(base, twin_id).await.map_err(Unconfirmed::Unavailable)?;
+        let twin = match twin {
+            Some(twin) if twin.status == TwinStatus::Deleted => twin,
+            other => return Err(Unconfirmed::NotDeleted(other.map(|t| t.status))),
+        };
+        Ok(TwinDeletion {
+            twin_id,
+            dids: twin.did.into_iter().chain(twin.previous_dids).collect(),
+        })
+    }
+
+    async fn fetch(&self, base: &str, twin_id: Uuid) -> Result<Option<RegisteredTwin>, String> {
+        let req = crate::auth::with_service_token(self.http.get(format!("{base}/twins/{twin_id}"))).await?;
+        let resp = req.send().await.map_err(|e| e.to_string())?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            return Err(format!("identity service returned {}", resp.status()));
+        }
+        resp.json().await.map(Some).map_err(|e| e.to_string())
+    }
+}
+
+/// What one service removed for a twin.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PurgeReport {
+    pub service: String,
+    pub twin_id: Uuid,
+    pub trigger: PurgeTrigger,
+    /// Records removed, by kind. Zero counts are kept: "nothing was held" is part of the proof.
+    pub purged: BTreeMap<String, u64>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub completed_at: OffsetDateTime,
+}
+
+impl PurgeReport {
+    pub fn new(service: &str, twin_id: Uuid, trigger: PurgeTrigger) -> Self {
+        Self {
+            service: service.to_string(),
+            twin_id,
+            trigger,
+            purged: BTreeMap::new(),
+            completed_at: OffsetDateTime::now_utc(),
+        }
+    }
+
+    /// Add `count` removed records of `kind`.
+    pub fn with(mut self, kind: &str, count: u64) -> Self {
+        *self.purged.entry(kind.to_string()).or_default() += count;
+        self
+    }
+
+    pub fn total(&self) -> u64 {
+        self.purged.values().sum()
+    }
+
+    /// The report as a [`CoreEvent::TwinDataPurged`] envelope from `service`.
+    pub fn event(&self) -> EventEnvelope {
+        let mut ev = EventEnvelope::new_core(
+            self.twin_id,
+            CoreEvent::TwinDataPurged {
+                service: self.service.clone(),
+                trigger: self.trigger,
+                purged: self.purged.clone(),
+            },
+        );
+        ev.source = Some(self.service.clone());
+        ev
+    }
+}
+
+/// How long a service keeps twin data.
+///
+/// Configuration:
+/// - `max_age_var` (e.g. `WORKING_MEMORY_MAX_AGE_SECS`): maximum age in seconds; unset or `0`
+///   keeps data until the twin is deleted
+/// - `RETENTION_SWEEP_SECS`: how often expired data is swept (default: 300)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    pub max_age: Option<Duration>,
+    pub sweep_every: Duration,
+}
+
+impl Retention {
+    /// A malformed value is an error rather than "keep forever", since retention is a
+    /// compliance setting.
+    pub fn from_env(max_age_var: &str) -> Result<Self, String> {
+        let secs = |name: &str| -> Result<Option<u64>, String> {
+            match std::env::var(name) {
+                Ok(raw) if !raw.trim().is_empty() => raw
+                    .trim()
+                    .parse()
+                    .map(Some)
+                    .map_err(|_| format!("{name} must be a number of seconds, got {raw:?}")),
+                _ => Ok(None),
+            }
+        };
+        let max_age = secs(max_age_var)?.filter(|s| *s > 0).map(Duration::from_secs);
+        let sweep_every = Duration::from_secs(secs("RETENTION_SWEEP_SECS")?.unwrap_or(300).max(1));
+        Ok(Self { max_age, sweep_every })
+    }
+
+    /// Data stamped before this has expired; `None` when nothing expires.
+    pub fn cutoff(&self, now: OffsetDateTime) -> Option<OffsetDateTime> {
+        self.max_age.map(|age| now - age)
+    }
+
+    /// Run `sweep` with the current cutoff every `sweep_every`. Does nothing when no
+    /// maximum age is configured.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn<F, Fut>(self, sweep: F)
+    where
+        F: Fn(OffsetDateTime) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        if self.max_age.is_none() {
+            return;
+        }
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(self.sweep_every);
+            tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tick.tick().await;
+                if let Some(cutoff) = self.cutoff(OffsetDateTime::now_utc()) {
+                    sweep(cutoff).await;
+                }
+            }
+        });
+    }
+}
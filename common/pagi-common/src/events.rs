@@ -1,15 +1,33 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use time::OffsetDateTime;
 use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::{erasure::PurgeTrigger, TwinState, TwinStatus};
+
+/// Payload schema version stamped on envelopes built from a typed [`CoreEvent`].
+///
+/// Version history:
+/// - `0`: legacy/untyped payloads (flat `json!` objects, no `type`/`data` tagging).
+/// - `1`: payload is a tagged [`CoreEvent`] (`{"type": ..., "data": {...}}`).
+pub const CURRENT_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum EventType {
     GoalReceived,
     TwinRegistered,
     TwinStateUpdated,
+    TwinKeyRotated,
+    TwinDeleted,
+    TwinDataPurged,
     WorkingMemoryAppended,
+    WorkingMemoryUpdated,
+    WorkingMemoryDeleted,
+    WorkingMemoryConsolidated,
+    WorkingMemoryRejected,
     ContextBuilt,
     InferenceRequested,
     InferenceCompleted,
@@ -20,12 +38,40 @@ pub enum EventType {
 }
 
 impl EventType {
+    pub const ALL: &'static [EventType] = &[
+        EventType::GoalReceived,
+        EventType::TwinRegistered,
+        EventType::TwinStateUpdated,
+        EventType::TwinKeyRotated,
+        EventType::TwinDeleted,
+        EventType::TwinDataPurged,
+        EventType::WorkingMemoryAppended,
+        EventType::WorkingMemoryUpdated,
+        EventType::WorkingMemoryDeleted,
+        EventType::WorkingMemoryConsolidated,
+        EventType::WorkingMemoryRejected,
+        EventType::ContextBuilt,
+        EventType::InferenceRequested,
+        EventType::InferenceCompleted,
+        EventType::PlanCreated,
+        EventType::PlanGenerated,
+        EventType::EmotionStateUpdated,
+        EventType::ActionRequested,
+    ];
+
     pub fn as_str(&self) -> &'static str {
         match self {
             EventType::GoalReceived => "goal_received",
             EventType::TwinRegistered => "twin_registered",
             EventType::TwinStateUpdated => "twin_state_updated",
+            EventType::TwinKeyRotated => "twin_key_rotated",
+            EventType::TwinDeleted => "twin_deleted",
+            EventType::TwinDataPurged => "twin_data_purged",
             EventType::WorkingMemoryAppended => "working_memory_appended",
+            EventType::WorkingMemoryUpdated => "working_memory_updated",
+            EventType::WorkingMemoryDeleted => "working_memory_deleted",
+            EventType::WorkingMemoryConsolidated => "working_memory_consolidated",
+            EventType::WorkingMemoryRejected => "working_memory_rejected",
             EventType::ContextBuilt => "context_built",
             EventType::InferenceRequested => "inference_requested",
             EventType::InferenceCompleted => "inference_completed",
@@ -35,26 +81,190 @@ impl EventType {
             EventType::ActionRequested => "action_requested",
         }
     }
+
+    /// Parse a wire name (e.g. `"twin_registered"`) back into a known event type.
+    pub fn parse(raw: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|t| t.as_str() == raw)
+    }
 }
 
 /// Typed, core events for the AGI orchestration loop.
 ///
 /// These are serialized into [`EventEnvelope::payload`](common/pagi-common/src/events.rs:73)
 /// so services can share a common contract even when communicating via Kafka.
+///
+/// The twin is carried on the envelope (`twin_id`), not repeated in `data`.
+/// Fields added after a variant first shipped must be `#[serde(default)]` so older
+/// payloads keep parsing; anything more invasive belongs in [`upcast_payload`].
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data", rename_all = "snake_case")]
 pub enum CoreEvent {
-    GoalReceived { goal: String },
-    PlanGenerated { plan: String },
+    GoalReceived {
+        goal: String,
+    },
+    TwinRegistered {
+        state: TwinState,
+    },
+    TwinStateUpdated {
+        state: TwinState,
+        /// Set when the update was a lifecycle transition.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        previous_status: Option<TwinStatus>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+        /// Who made the change.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    TwinKeyRotated {
+        did: String,
+        /// Verification method id of the new signing key.
+        verification_method: String,
+        /// The key it replaced; kept in the DID document so older signatures still verify.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        previous_verification_method: Option<String>,
+        /// Set when the rotation also changed the DID (migrating a `did:key` twin).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        previous_did: Option<String>,
+    },
+    /// The twin was deleted; services holding its data purge it (see [`crate::erasure`]).
+    TwinDeleted {
+        did: String,
+        /// DIDs the twin was known by before, whose data goes too.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        previous_dids: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        actor: Option<String>,
+    },
+    /// A service removed twin data, on deletion or by its retention policy.
+    TwinDataPurged {
+        service: String,
+        trigger: PurgeTrigger,
+        /// Records removed, by kind (e.g. `items`, `messages`).
+        purged: BTreeMap<String, u64>,
+    },
+    WorkingMemoryAppended {
+        /// The appended item, as serialized by pagi-working-memory.
+        item: Value,
+    },
+    WorkingMemoryUpdated {
+        /// The item after the update.
+        item: Value,
+    },
+    WorkingMemoryDeleted {
+        item_id: Uuid,
+    },
+    /// Items that left working memory were moved to the long-term tier.
+    WorkingMemoryConsolidated {
+        /// `episodic` or `semantic`.
+        tier: String,
+        /// Working memory items consolidated.
+        items: u64,
+        /// Long-term records created.
+        records: u64,
+    },
+    /// An item did not match the twin's memory schema.
+    WorkingMemoryRejected {
+        item: serde_json::Value,
+        violations: Vec<String>,
+        /// `rejected` or `quarantined`.
+        action: String,
+    },
+    ContextBuilt {
+        #[serde(default)]
+        sources: Vec<String>,
+    },
+    InferenceRequested {
+        #[serde(default)]
+        has_context: bool,
+    },
+    InferenceCompleted {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        model: Option<String>,
+        output_len: usize,
+    },
+    PlanCreated {
+        step_count: usize,
+    },
+    PlanGenerated {
+        plan: String,
+    },
+    EmotionStateUpdated {
+        mood: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        stress: Option<f32>,
+    },
+    ActionRequested {
+        tool: String,
+        #[serde(default)]
+        args: Value,
+    },
 }
 
 impl CoreEvent {
-    pub fn event_type(&self) -> &'static str {
+    pub fn kind(&self) -> EventType {
         match self {
-            CoreEvent::GoalReceived { .. } => EventType::GoalReceived.as_str(),
-            CoreEvent::PlanGenerated { .. } => EventType::PlanGenerated.as_str(),
+            CoreEvent::GoalReceived { .. } => EventType::GoalReceived,
+            CoreEvent::TwinRegistered { .. } => EventType::TwinRegistered,
+            CoreEvent::TwinStateUpdated { .. } => EventType::TwinStateUpdated,
+            CoreEvent::TwinKeyRotated { .. } => EventType::TwinKeyRotated,
+            CoreEvent::TwinDeleted { .. } => EventType::TwinDeleted,
+            CoreEvent::TwinDataPurged { .. } => EventType::TwinDataPurged,
+            CoreEvent::WorkingMemoryAppended { .. } => EventType::WorkingMemoryAppended,
+            CoreEvent::WorkingMemoryUpdated { .. } => EventType::WorkingMemoryUpdated,
+            CoreEvent::WorkingMemoryDeleted { .. } => EventType::WorkingMemoryDeleted,
+            CoreEvent::WorkingMemoryConsolidated { .. } => EventType::WorkingMemoryConsolidated,
+            CoreEvent::WorkingMemoryRejected { .. } => EventType::WorkingMemoryRejected,
+            CoreEvent::ContextBuilt { .. } => EventType::ContextBuilt,
+            CoreEvent::InferenceRequested { .. } => EventType::InferenceRequested,
+            CoreEvent::InferenceCompleted { .. } => EventType::InferenceCompleted,
+            CoreEvent::PlanCreated { .. } => EventType::PlanCreated,
+            CoreEvent::PlanGenerated { .. } => EventType::PlanGenerated,
+            CoreEvent::EmotionStateUpdated { .. } => EventType::EmotionStateUpdated,
+            CoreEvent::ActionRequested { .. } => EventType::ActionRequested,
         }
     }
+
+    pub fn event_type(&self) -> &'static str {
+        self.kind().as_str()
+    }
+}
+
+/// Bring a payload written at `schema_version` up to [`CURRENT_SCHEMA_VERSION`].
+///
+/// Each step only knows how to go from `N` to `N + 1`; steps are applied in order so
+/// producers that lag several versions behind still parse into the current [`CoreEvent`].
+pub fn upcast_payload(event_type: &str, schema_version: u32, payload: Value) -> Value {
+    let mut payload = payload;
+    if schema_version < 1 {
+        payload = upcast_v0_to_v1(event_type, payload);
+    }
+    payload
+}
+
+/// v0 → v1: wrap flat `json!` payloads into the tagged `{"type", "data"}` shape.
+///
+/// `goal_received` / `plan_generated` were already emitted tagged before versioning
+/// existed, so anything that already carries a matching `type` tag is left untouched.
+fn upcast_v0_to_v1(event_type: &str, payload: Value) -> Value {
+    let already_tagged = payload.get("type").and_then(|v| v.as_str()) == Some(event_type)
+        && payload.get("data").is_some();
+    if already_tagged {
+        return payload;
+    }
+
+    let data = match payload {
+        Value::Object(mut map) => {
+            // v0 producers duplicated the twin into the payload; it now lives on the envelope.
+            map.remove("twin_id");
+            Value::Object(map)
+        }
+        Value::Null => Value::Object(Default::default()),
+        other => other,
+    };
+    serde_json::json!({ "type": event_type, "data": data })
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -63,6 +273,12 @@ pub struct EventEnvelope {
     pub event_type: String,
     pub ts: OffsetDateTime,
 
+    /// Version of the `payload` shape (see [`CURRENT_SCHEMA_VERSION`]).
+    ///
+    /// Envelopes produced before versioning existed deserialize as `0`.
+    #[serde(default)]
+    pub schema_version: u32,
+
     /// Optional correlation key.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub twin_id: Option<Uuid>,
@@ -73,32 +289,76 @@ pub struct EventEnvelope {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub source: Option<String>,
 
+    /// Shared by every event produced on behalf of one request (see [`crate::TraceContext`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<Uuid>,
+    /// `id` of the event that caused this one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub causation_id: Option<Uuid>,
+    /// W3C `traceparent` of the span that produced the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub traceparent: Option<String>,
+
+    /// CloudEvents extension attributes with no envelope field, carried through unchanged
+    /// (see [`crate::cloudevents`]).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extensions: BTreeMap<String, Value>,
+
     pub payload: Value,
 }
 
 impl EventEnvelope {
+    /// Build an envelope around an untyped payload.
+    ///
+    /// Prefer [`EventEnvelope::new_core`] for core event types; untyped envelopes are
+    /// stamped schema version `0` so consumers route them through [`upcast_payload`].
     pub fn new(event_type: EventType, payload: Value) -> Self {
         Self {
             id: Uuid::new_v4(),
             event_type: event_type.as_str().to_string(),
             ts: OffsetDateTime::now_utc(),
+            schema_version: 0,
             twin_id: None,
             subject: None,
             source: None,
+            correlation_id: None,
+            causation_id: None,
+            traceparent: None,
+            extensions: BTreeMap::new(),
             payload,
         }
     }
 
-    pub fn new_core(twin_id: Uuid, core: CoreEvent) -> Self {
+    /// Build an envelope from a typed [`CoreEvent`] that is not scoped to a twin.
+    pub fn from_core(core: CoreEvent) -> Self {
         let payload = serde_json::to_value(&core).unwrap_or(Value::Null);
         Self {
             id: Uuid::new_v4(),
             event_type: core.event_type().to_string(),
             ts: OffsetDateTime::now_utc(),
-            twin_id: Some(twin_id),
+            schema_version: CURRENT_SCHEMA_VERSION,
+            twin_id: None,
             subject: None,
             source: None,
+            correlation_id: None,
+            causation_id: None,
+            traceparent: None,
+            extensions: BTreeMap::new(),
             payload,
         }
     }
+
+    pub fn new_core(twin_id: Uuid, core: CoreEvent) -> Self {
+        let mut ev = Self::from_core(core);
+        ev.twin_id = Some(twin_id);
+        ev
+    }
+
+    /// Parse the payload into the current [`CoreEvent`], upcasting older shapes first.
+    ///
+    /// Fails for unknown event types and for payloads that do not match their schema.
+    pub fn core_event(&self) -> Result<CoreEvent, serde_json::Error> {
+        let payload = upcast_payload(&self.event_type, self.schema_version, self.payload.clone());
+        serde_json::from_value(payload)
+    }
 }
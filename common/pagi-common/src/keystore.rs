@@ -0,0 +1,330 @@
+//! Twin signing key files (`<keys_dir>/<twin_id>.ed25519`), sealed at rest.
+//!
+//! Written by the identity service and read by the plugins that sign on a twin's behalf
+//! (did, vc, didcomm). Two on-disk formats are understood:
+//!
+//! - **legacy**: the raw 32-byte Ed25519 secret; only written when no KEK is configured
+//! - **v1**: a JSON document holding the secret sealed with XChaCha20-Poly1305 under a
+//!   key-encryption key (KEK) derived from a passphrase or keyfile with Argon2id. The
+//!   Argon2 salt and cost parameters travel with the file; the twin id is bound in as
+//!   associated data so files cannot be swapped between twins.
+//!
+//! KEKs come from the environment: `IDENTITY_KEK` (passphrase) or `IDENTITY_KEK_FILE`
+//! (keyfile contents) for the current KEK, and `IDENTITY_KEK_PREVIOUS` /
+//! `IDENTITY_KEK_PREVIOUS_FILE` for the one being rotated away from. Files sealed under
+//! either can be opened, so readers keep working while keys are re-wrapped.
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD as B64, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use uuid::Uuid;
+
+pub const FORMAT_VERSION: u32 = 1;
+pub const KEY_EXTENSION: &str = "ed25519";
+const KDF: &str = "argon2id";
+const AEAD: &str = "xchacha20poly1305";
+/// Upper bounds on the Argon2 costs a key file may ask for, so a tampered file cannot make
+/// readers allocate gigabytes or spin for minutes: 256 MiB, 16 passes, 16 lanes.
+const MAX_M_COST: u32 = 256 * 1024;
+const MAX_T_COST: u32 = 16;
+const MAX_P_COST: u32 = 16;
+
+pub fn key_path(keys_dir: &Path, twin_id: Uuid) -> PathBuf {
+    keys_dir.join(format!("{twin_id}.{KEY_EXTENSION}"))
+}
+
+/// A v1 key file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SealedKey {
+    version: u32,
+    kdf: String,
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+    salt: String,
+    aead: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Which KEK a key file is sealed under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SealedWith {
+    Plaintext,
+    Current,
+    Previous,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct KdfParams {
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Cache key for a derived KEK: which secret, with which salt and cost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Derivation {
+    previous: bool,
+    salt: [u8; 16],
+    params: KdfParams,
+}
+
+/// The current and previous KEK secrets, with derived keys cached per salt.
+pub struct Keyring {
+    current: Option<Vec<u8>>,
+    previous: Option<Vec<u8>>,
+    /// Salt for files written by this process, so the KEK is derived once.
+    write_salt: [u8; 16],
+    derived: Mutex<HashMap<Derivation, [u8; 32]>>,
+}
+
+impl std::fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Keyring")
+            .field("current", &self.current.is_some())
+            .field("previous", &self.previous.is_some())
+            .finish()
+    }
+}
+
+fn secret_from_env(var: &str) -> Result<Option<Vec<u8>>, String> {
+    if let Some(passphrase) = std::env::var(var).ok().filter(|v| !v.is_empty()) {
+        return Ok(Some(passphrase.into_bytes()));
+    }
+    let file_var = format!("{var}_FILE");
+    match std::env::var(&file_var).ok().filter(|v| !v.is_empty()) {
+        Some(path) => {
+            let secret = std::fs::read(&path).map_err(|e| format!("{file_var} {path}: {e}"))?;
+            if secret.is_empty() {
+                return Err(format!("{file_var} {path} is empty"));
+            }
+            Ok(Some(secret))
+        }
+        None => Ok(None),
+    }
+}
+
+impl Keyring {
+    pub fn new(current: Option<Vec<u8>>, previous: Option<Vec<u8>>) -> Self {
+        let mut write_salt = [0u8; 16];
+        OsRng.fill_bytes(&mut write_salt);
+        Self {
+            current,
+            previous,
+            write_salt,
+            derived: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        Ok(Self::new(
+            secret_from_env("IDENTITY_KEK")?,
+            secret_from_env("IDENTITY_KEK_PREVIOUS")?,
+        ))
+    }
+
+    /// Whether new key files are sealed (a current KEK is configured).
+    pub fn is_sealing(&self) -> bool {
+        self.current.is_some()
+    }
+
+    fn derive(&self, previous: bool, salt: [u8; 16], params: KdfParams) -> Result<Option<[u8; 32]>, String> {
+        let secret = match if previous { &self.previous } else { &self.current } {
+            Some(secret) => secret,
+            None => return Ok(None),
+        };
+        let derivation = Derivation { previous, salt, params };
+        let mut cache = self.derived.lock().unwrap_or_else(|p| p.into_inner());
+        if let Some(key) = cache.get(&derivation) {
+            return Ok(Some(*key));
+        }
+        let argon = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(params.m_cost, params.t_cost, params.p_cost, Some(32)).map_err(|e| e.to_string())?,
+        );
+        let mut key = [0u8; 32];
+        argon
+            .hash_password_into(secret, &salt, &mut key)
+            .map_err(|e| format!("kek derivation: {e}"))?;
+        cache.insert(derivation, key);
+        Ok(Some(key))
+    }
+
+    /// Encode a secret for `twin_id`: sealed under the current KEK, or raw when none is set.
+    pub fn seal(&self, twin_id: Uuid, secret: &[u8; 32]) -> Result<Vec<u8>, String> {
+        let params = KdfParams::default();
+        let Some(kek) = self.derive(false, self.write_salt, params)? else {
+            return Ok(secret.to_vec());
+        };
+        let mut nonce = [0u8; 24];
+        OsRng.fill_bytes(&mut nonce);
+        let aad = associated_data(twin_id);
+        let ciphertext = XChaCha20Poly1305::new(&kek.into())
+            .encrypt(XNonce::from_slice(&nonce), Payload { msg: secret, aad: &aad })
+            .map_err(|_| "key encryption failed".to_string())?;
+        let sealed = SealedKey {
+            version: FORMAT_VERSION,
+            kdf: KDF.to_string(),
+            m_cost: params.m_cost,
+            t_cost: params.t_cost,
+            p_cost: params.p_cost,
+            salt: B64.encode(self.write_salt),
+            aead: AEAD.to_string(),
+            nonce: B64.encode(nonce),
+            ciphertext: B64.encode(ciphertext),
+        };
+        serde_json::to_vec_pretty(&sealed).map_err(|e| e.to_string())
+    }
+
+    /// Decode a key file's contents, reporting which KEK (if any) it was sealed under.
+    pub fn open(&self, twin_id: Uuid, raw: &[u8]) -> Result<([u8; 32], SealedWith), String> {
+        // A v1 document is always far longer than a bare secret.
+        if raw.len() == 32 {
+            let mut secret = [0u8; 32];
+            secret.copy_from_slice(raw);
+            return Ok((secret, SealedWith::Plaintext));
+        }
+        let sealed: SealedKey =
+            serde_json::from_slice(raw).map_err(|e| format!("unrecognised key file format: {e}"))?;
+        if sealed.version != FORMAT_VERSION {
+            return Err(format!("unsupported key file version {}", sealed.version));
+        }
+        if sealed.kdf != KDF || sealed.aead != AEAD {
+            return Err(format!("unsupported key file algorithms {}/{}", sealed.kdf, sealed.aead));
+        }
+        let salt: [u8; 16] = decode_fixed(&sealed.salt, "salt")?;
+        let nonce: [u8; 24] = decode_fixed(&sealed.nonce, "nonce")?;
+        let ciphertext = B64.decode(&sealed.ciphertext).map_err(|e| format!("ciphertext: {e}"))?;
+        let params = KdfParams {
+            m_cost: sealed.m_cost,
+            t_cost: sealed.t_cost,
+            p_cost: sealed.p_cost,
+        };
+        if params.m_cost > MAX_M_COST || params.t_cost > MAX_T_COST || params.p_cost > MAX_P_COST {
+            return Err(format!(
+                "key file kdf cost m={} t={} p={} exceeds the limit m={MAX_M_COST} t={MAX_T_COST} p={MAX_P_COST}",
+                params.m_cost, params.t_cost, params.p_cost
+            ));
+        }
+        let aad = associated_data(twin_id);
+
+        let mut tried = false;
+        for (previous, with) in [(false, SealedWith::Current), (true, SealedWith::Previous)] {
+            let Some(kek) = self.derive(previous, salt, params)? else {
+                continue;
+            };
+            tried = true;
+            let opened = XChaCha20Poly1305::new(&kek.into())
+                .decrypt(XNonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &aad });
+            if let Ok(plain) = opened {
+                let secret: [u8; 32] = plain
+                    .as_slice()
+                    .try_into()
+                    .map_err(|_| format!("sealed key has {} bytes, expected 32", plain.len()))?;
+                return Ok((secret, with));
+            }
+        }
+        if tried {
+            Err("key file could not be decrypted with the configured KEKs".to_string())
+        } else {
+            Err("key file is encrypted but no KEK is configured (IDENTITY_KEK / IDENTITY_KEK_FILE)".to_string())
+        }
+    }
+
+    /// Read and decode `<keys_dir>/<twin_id>.ed25519`.
+    pub fn read_key(&self, keys_dir: &Path, twin_id: Uuid) -> Result<[u8; 32], String> {
+        let path = key_path(keys_dir, twin_id);
+        let raw = std::fs::read(&path).map_err(|e| format!("failed to read key {path:?}: {e}"))?;
+        self.open(twin_id, &raw)
+            .map(|(secret, _)| secret)
+            .map_err(|e| format!("key {path:?}: {e}"))
+    }
+
+    /// Seal and write a key file, readable by its owner only (`0600` on unix). The write is
+    /// atomic, so concurrent readers see either the old or the new file.
+    pub fn write_key(&self, keys_dir: &Path, twin_id: Uuid, secret: &[u8; 32]) -> Result<(), String> {
+        std::fs::create_dir_all(keys_dir).map_err(|e| e.to_string())?;
+        let path = key_path(keys_dir, twin_id);
+        let tmp = path.with_extension(format!("{KEY_EXTENSION}.tmp"));
+        let sealed = self.seal(twin_id, secret)?;
+        // A leftover temp file could carry looser permissions; the mode only applies on create.
+        match std::fs::remove_file(&tmp) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.to_string()),
+            _ => {}
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp).map_err(|e| format!("failed to create key {tmp:?}: {e}"))?;
+        std::io::Write::write_all(&mut file, &sealed)
+            .and_then(|_| file.sync_all())
+            .map_err(|e| format!("failed to write key {tmp:?}: {e}"))?;
+        std::fs::rename(&tmp, &path).map_err(|e| e.to_string())
+    }
+
+    /// Re-seal a key file under the current KEK unless it already is. Returns whether the
+    /// file was rewritten.
+    pub fn rewrap(&self, keys_dir: &Path, twin_id: Uuid) -> Result<bool, String> {
+        if !self.is_sealing() {
+            return Err("no current KEK configured".to_string());
+        }
+        let path = key_path(keys_dir, twin_id);
+        let raw = std::fs::read(&path).map_err(|e| format!("failed to read key {path:?}: {e}"))?;
+        let (secret, with) = self.open(twin_id, &raw)?;
+        if with == SealedWith::Current {
+            return Ok(false);
+        }
+        self.write_key(keys_dir, twin_id, &secret)?;
+        Ok(true)
+    }
+}
+
+fn associated_data(twin_id: Uuid) -> Vec<u8> {
+    format!("pagi-twin-key:v{FORMAT_VERSION}:{twin_id}").into_bytes()
+}
+
+fn decode_fixed<const N: usize>(raw: &str, what: &str) -> Result<[u8; N], String> {
+    let bytes = B64.decode(raw).map_err(|e| format!("{what}: {e}"))?;
+    bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| format!("{what}: expected {N} bytes, found {}", bytes.len()))
+}
+
+/// Twin ids with a key file in `keys_dir` (an absent directory has none).
+pub fn key_files(keys_dir: &Path) -> Result<Vec<Uuid>, String> {
+    let entries = match std::fs::read_dir(keys_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("keys dir {}: {e}", keys_dir.display())),
+    };
+    Ok(entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|e| e.to_str()) == Some(KEY_EXTENSION))
+        .filter_map(|path| path.file_stem().and_then(|s| s.to_str()).and_then(|s| Uuid::parse_str(s).ok()))
+        .collect())
+}
@@ -1,10 +1,23 @@
+pub mod auth;
+pub mod bundle;
+pub mod cloudevents;
+pub mod did;
+pub mod erasure;
 pub mod events;
+pub mod keystore;
+pub mod lifecycle;
+pub mod outbox;
 pub mod swarm;
+pub mod tls;
+pub mod trace_context;
 pub mod types;
 
-pub use events::{CoreEvent, EventEnvelope, EventType};
+pub use cloudevents::CloudEvent;
+pub use events::{CoreEvent, EventEnvelope, EventType, CURRENT_SCHEMA_VERSION};
+pub use outbox::{Outbox, OutboxConfig};
 pub use swarm::{InstructionsField, Playbook, PlaybookInstructions, RefinementArtifact, ToolSchema};
-pub use types::{TwinId, TwinState};
+pub use trace_context::TraceContext;
+pub use types::{TwinId, TwinState, TwinStatus};
 
 /// Common error type for cross-crate APIs.
 ///
@@ -137,17 +150,17 @@ impl From<toml::de::Error> for PagiError {
 
 /// Publish an event to the PAGI-EventRouter.
 ///
-/// `EVENT_ROUTER_URL` can be either:
-/// - a base URL (e.g. `http://localhost:8000`)
-/// - the full publish endpoint (e.g. `http://localhost:8000/publish`)
-pub async fn publish_event(envelope: EventEnvelope) -> Result<(), reqwest::Error> {
-    let client = reqwest::Client::new();
-
-    let mut url = std::env::var("EVENT_ROUTER_URL").unwrap_or_else(|_| "http://127.0.0.1:8000".to_string());
-    if !url.ends_with("/publish") {
-        url = format!("{}/publish", url.trim_end_matches('/'));
+/// Events go through the process-wide [`Outbox`]: they are spooled locally and
+/// delivered in order with retry, so this never fails and never blocks on the router.
+/// See [`OutboxConfig::from_env`] for `EVENT_ROUTER_URL` and spool settings.
+///
+/// Inside a request scope the envelope is stamped with the current [`TraceContext`]
+/// (correlation/causation ids and `traceparent`) unless the caller set them already.
+///
+/// Must be called from within a Tokio runtime.
+pub fn publish_event(mut envelope: EventEnvelope) {
+    if let Some(ctx) = TraceContext::current() {
+        ctx.stamp(&mut envelope);
     }
-
-    client.post(url).json(&envelope).send().await?.error_for_status()?;
-    Ok(())
+    Outbox::global().enqueue(envelope);
 }
@@ -0,0 +1,132 @@
+//! Lifecycle enforcement for services that act on behalf of twins.
+//!
+//! Twin status lives in pagi-identity-service; [`LifecycleGuard`] looks it up (with a short
+//! cache) so suspended, archived and deleted twins are refused before any work starts.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use uuid::Uuid;
+
+use crate::{TwinState, TwinStatus};
+
+/// Why a twin's request was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Refusal {
+    /// The twin's status does not accept work.
+    Inactive(TwinStatus),
+    /// The status could not be checked and the guard fails closed.
+    Unavailable(String),
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Refusal::Inactive(status) => write!(f, "twin is {status}"),
+            Refusal::Unavailable(err) => write!(f, "twin status unavailable: {err}"),
+        }
+    }
+}
+
+/// Checks twin status against the identity service.
+///
+/// Configuration:
+/// - `IDENTITY_SERVICE_URL`: identity service base URL; unset disables the check
+/// - `TWIN_STATUS_CACHE_SECS`: how long a looked-up status is trusted (default: 5)
+/// - `TWIN_LIFECYCLE_FAIL_CLOSED`: `false` lets twins whose status cannot be looked up
+///   through with a warning (default: `true`, refuse them)
+///
+/// Twins the identity service does not know, and the nil (global) twin, are let through.
+pub struct LifecycleGuard {
+    http: reqwest::Client,
+    identity_url: Option<String>,
+    ttl: Duration,
+    fail_closed: bool,
+    cache: Mutex<HashMap<Uuid, (Instant, Option<TwinStatus>)>>,
+}
+
+impl LifecycleGuard {
+    pub fn new(identity_url: Option<String>, ttl: Duration, fail_closed: bool) -> Self {
+        Self {
+            http: crate::tls::client_builder()
+                .timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default(),
+            identity_url: identity_url.map(|u| u.trim_end_matches('/').to_string()),
+            ttl,
+            fail_closed,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let identity_url = std::env::var("IDENTITY_SERVICE_URL").ok().filter(|u| !u.trim().is_empty());
+        let ttl = std::env::var("TWIN_STATUS_CACHE_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(5);
+        let fail_closed = std::env::var("TWIN_LIFECYCLE_FAIL_CLOSED")
+            .map(|v| !v.trim().eq_ignore_ascii_case("false"))
+            .unwrap_or(true);
+        Self::new(identity_url, Duration::from_secs(ttl), fail_closed)
+    }
+
+    /// `Ok` when work may proceed for `twin_id`.
+    pub async fn check(&self, twin_id: Uuid) -> Result<(), Refusal> {
+        let Some(base) = &self.identity_url else {
+            return Ok(());
+        };
+        if twin_id.is_nil() {
+            return Ok(());
+        }
+
+        let cached = {
+            let cache = self.cache.lock().unwrap_or_else(|p| p.into_inner());
+            cache
+                .get(&twin_id)
+                .filter(|(at, _)| at.elapsed() < self.ttl)
+                .map(|(_, status)| *status)
+        };
+        let status = match cached {
+            Some(status) => status,
+            None => match self.fetch(base, twin_id).await {
+                Ok(status) => {
+                    let mut cache = self.cache.lock().unwrap_or_else(|p| p.into_inner());
+                    cache.retain(|_, (at, _)| at.elapsed() < self.ttl);
+                    cache.insert(twin_id, (Instant::now(), status));
+                    status
+                }
+                Err(err) if self.fail_closed => return Err(Refusal::Unavailable(err)),
+                Err(err) => {
+                    tracing::warn!(%twin_id, error = %err, "twin status lookup failed; allowing");
+                    return Ok(());
+                }
+            },
+        };
+
+        match status {
+            Some(status) if !status.accepts_work() => Err(Refusal::Inactive(status)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn fetch(&self, base: &str, twin_id: Uuid) -> Result<Option<TwinStatus>, String> {
+        let req = crate::auth::with_service_token(self.http.get(format!("{base}/twins/{twin_id}"))).await?;
+        let resp = req
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            return Err(format!("identity service returned {}", resp.status()));
+        }
+        let state: TwinState = resp.json().await.map_err(|e| e.to_string())?;
+        Ok(Some(state.status))
+    }
+}
@@ -0,0 +1,640 @@
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc, Mutex, OnceLock,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use tokio::sync::{oneshot, Notify};
+use uuid::Uuid;
+
+use crate::{
+    auth::{with_service_token, ServiceToken},
+    EventEnvelope, EventType,
+};
+
+const DEFAULT_ROUTER_URL: &str = "http://127.0.0.1:8000";
+
+/// Compact the spool once it lists at least this many delivered or rejected envelopes, and at
+/// least as many as are still pending.
+const SPOOL_COMPACT_MIN: usize = 64;
+
+/// Most events the event router accepts per `/publish/batch` request unless its
+/// `PUBLISH_BATCH_MAX` says otherwise; `EVENT_OUTBOX_BATCH_SIZE` is clamped to it.
+pub const DEFAULT_PUBLISH_BATCH_MAX: usize = 500;
+
+/// Outbox tuning. Every field has an env override (see [`OutboxConfig::from_env`]).
+#[derive(Debug, Clone)]
+pub struct OutboxConfig {
+    /// Full `/publish` endpoint of the event router.
+    pub publish_url: String,
+    /// JSONL spool mirroring the pending queue. `None` keeps the queue in memory only, so
+    /// pending events are lost on restart.
+    pub spool_path: Option<PathBuf>,
+    /// Maximum number of pending envelopes; the oldest is dropped beyond this.
+    pub max_queue: usize,
+    /// Pending envelopes sent per `/publish/batch` request; `1` always uses `/publish`.
+    pub batch_size: usize,
+    /// How long an idle worker waits after the first new envelope, so a burst goes out as one
+    /// batch instead of one request per event.
+    pub linger: Duration,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub request_timeout: Duration,
+}
+
+impl Default for OutboxConfig {
+    fn default() -> Self {
+        Self {
+            publish_url: publish_url(DEFAULT_ROUTER_URL),
+            spool_path: None,
+            max_queue: 10_000,
+            batch_size: 100,
+            linger: Duration::from_millis(20),
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(30),
+            request_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl OutboxConfig {
+    /// Env:
+    /// - `EVENT_ROUTER_URL`: base URL or full `/publish` endpoint
+    /// - `EVENT_OUTBOX_DIR`: durable spool directory; the file is named after the binary. Unset, the
+    ///   queue is kept in memory only: a temp dir may not survive the restart the spool is meant for.
+    /// - `EVENT_OUTBOX_SPOOL`: `false` disables the spool even when a directory is set
+    /// - `EVENT_OUTBOX_MAX_QUEUE`, `EVENT_OUTBOX_BATCH_SIZE`, `EVENT_OUTBOX_LINGER_MS`, `EVENT_OUTBOX_MAX_BACKOFF_SECS`
+    pub fn from_env() -> Self {
+        let mut cfg = Self::default();
+
+        let router = std::env::var("EVENT_ROUTER_URL").unwrap_or_else(|_| DEFAULT_ROUTER_URL.to_string());
+        cfg.publish_url = publish_url(&router);
+
+        let spool_enabled = std::env::var("EVENT_OUTBOX_SPOOL")
+            .unwrap_or_else(|_| "true".to_string())
+            .to_lowercase()
+            != "false";
+        let dir = std::env::var("EVENT_OUTBOX_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty())
+            .map(PathBuf::from);
+        match dir {
+            Some(dir) if spool_enabled => {
+                let name = std::env::current_exe()
+                    .ok()
+                    .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+                    .unwrap_or_else(|| "pagi".to_string());
+                cfg.spool_path = Some(dir.join(format!("{name}.jsonl")));
+            }
+            None if spool_enabled => {
+                tracing::warn!("EVENT_OUTBOX_DIR is not set; pending events are kept in memory only and lost on restart");
+            }
+            _ => {}
+        }
+
+        if let Some(n) = std::env::var("EVENT_OUTBOX_MAX_QUEUE").ok().and_then(|s| s.parse().ok()) {
+            cfg.max_queue = n;
+        }
+        if let Some(n) = std::env::var("EVENT_OUTBOX_BATCH_SIZE").ok().and_then(|s| s.parse::<usize>().ok()) {
+            if n > DEFAULT_PUBLISH_BATCH_MAX {
+                tracing::warn!(
+                    requested = n,
+                    max = DEFAULT_PUBLISH_BATCH_MAX,
+                    "EVENT_OUTBOX_BATCH_SIZE exceeds what the event router accepts; clamped"
+                );
+            }
+            cfg.batch_size = n.clamp(1, DEFAULT_PUBLISH_BATCH_MAX);
+        }
+        if let Some(ms) = std::env::var("EVENT_OUTBOX_LINGER_MS").ok().and_then(|s| s.parse().ok()) {
+            cfg.linger = Duration::from_millis(ms);
+        }
+        if let Some(secs) = std::env::var("EVENT_OUTBOX_MAX_BACKOFF_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+        {
+            cfg.max_backoff = Duration::from_secs(secs);
+        }
+        cfg
+    }
+}
+
+fn publish_url(router: &str) -> String {
+    if router.ends_with("/publish") {
+        router.to_string()
+    } else {
+        format!("{}/publish", router.trim_end_matches('/'))
+    }
+}
+
+/// Durable, ordered outbox in front of the event router.
+///
+/// Envelopes are queued in memory and mirrored to an append-only JSONL spool so a
+/// restart of either the producer or the router does not lose them. The spool is written by
+/// its own thread, in order, so neither the queue lock nor the runtime waits on the disk; a
+/// crash can lose the last envelopes not yet written. A single worker
+/// delivers the queue head-first, backing off exponentially while the router is down.
+/// Envelopes go out through `/publish/batch`, up to `batch_size` per request (a burst is
+/// gathered for `linger` first), falling back to `/publish` against routers that do not have it.
+/// A batch the router finds too large (`413`) halves the batch size for later requests.
+///
+/// Metrics (no-ops unless the process installs a `metrics` recorder):
+/// - `pagi_outbox_depth` (gauge)
+/// - `pagi_outbox_delivered_total` (counter)
+/// - `pagi_outbox_dropped_total{reason}` (counter; `overflow` or `rejected`)
+#[derive(Clone)]
+pub struct Outbox {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    cfg: OutboxConfig,
+    queue: Mutex<VecDeque<EventEnvelope>>,
+    notify: Notify,
+    http: reqwest::Client,
+    /// Cleared the first time the router answers `/publish/batch` with 404/405.
+    batching: AtomicBool,
+    /// Envelopes per batch request: `batch_size`, halved whenever the router answers `413`.
+    batch_size: AtomicUsize,
+    /// Lines in the spool file, counting writes still queued for it. The queue is always its
+    /// tail: delivered and dropped envelopes leave the front of both, and the file only catches
+    /// up when it is compacted.
+    spooled: AtomicUsize,
+    spool: Option<Spool>,
+}
+
+static GLOBAL: OnceLock<Outbox> = OnceLock::new();
+
+impl Outbox {
+    /// Create an outbox and reload anything left in its spool. Does not start delivery.
+    pub fn new(cfg: OutboxConfig) -> Self {
+        let mut pending = cfg
+            .spool_path
+            .as_deref()
+            .map(load_spool)
+            .unwrap_or_default();
+        let spooled = pending.len();
+        // Overflow appends rather than rewriting, so the spool may hold more than fits.
+        while pending.len() > cfg.max_queue {
+            pending.pop_front();
+        }
+        if !pending.is_empty() {
+            tracing::info!(pending = pending.len(), "outbox restored spooled events");
+        }
+
+        let http = crate::tls::client_builder()
+            .timeout(cfg.request_timeout)
+            .build()
+            .unwrap_or_default();
+
+        let batch_size = cfg.batch_size.max(1);
+        let spool = cfg.spool_path.clone().map(Spool::start);
+        let outbox = Self {
+            inner: Arc::new(Inner {
+                cfg,
+                queue: Mutex::new(pending),
+                notify: Notify::new(),
+                http,
+                batching: AtomicBool::new(true),
+                batch_size: AtomicUsize::new(batch_size),
+                spooled: AtomicUsize::new(spooled),
+                spool,
+            }),
+        };
+        outbox.record_depth(outbox.depth());
+        outbox
+    }
+
+    /// Process-wide outbox configured from env; the delivery worker is spawned on first use.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn global() -> &'static Outbox {
+        GLOBAL.get_or_init(|| {
+            let outbox = Outbox::new(OutboxConfig::from_env());
+            outbox.start();
+            outbox
+        })
+    }
+
+    /// Spawn the delivery worker on the current Tokio runtime.
+    pub fn start(&self) {
+        let this = self.clone();
+        tokio::spawn(async move { this.run().await });
+    }
+
+    /// Queue an envelope for delivery. Never blocks on the network.
+    pub fn enqueue(&self, envelope: EventEnvelope) {
+        let depth = {
+            let mut queue = self.lock_queue();
+            queue.push_back(envelope);
+
+            let mut overflowed = false;
+            while queue.len() > self.inner.cfg.max_queue {
+                queue.pop_front();
+                overflowed = true;
+                metrics::counter!("pagi_outbox_dropped_total", "reason" => "overflow").increment(1);
+            }
+
+            if let Some(last) = queue.back() {
+                self.append_spool(last);
+            }
+            // Dropped envelopes stay in the spool until it is compacted; while the router is
+            // down that only happens here, once they make up half of the file.
+            if overflowed && self.inner.spooled.load(Ordering::Relaxed) >= 2 * self.inner.cfg.max_queue.max(1) {
+                self.rewrite_spool(&queue);
+            }
+            queue.len()
+        };
+        self.record_depth(depth);
+        self.inner.notify.notify_one();
+    }
+
+    /// Number of envelopes waiting for delivery.
+    pub fn depth(&self) -> usize {
+        self.lock_queue().len()
+    }
+
+    /// Snapshot of the pending envelopes, oldest first.
+    pub fn pending(&self) -> Vec<EventEnvelope> {
+        self.lock_queue().iter().cloned().collect()
+    }
+
+    /// Wait until the spool file holds everything queued for it so far. Blocks the calling
+    /// thread; meant for shutdown and tests.
+    pub fn flush_spool(&self) {
+        if let Some(spool) = &self.inner.spool {
+            let (done, wait) = mpsc::channel();
+            if spool.send(SpoolOp::Flush(done)) {
+                let _ = wait.recv();
+            }
+        }
+    }
+
+    /// Drop the envelopes queued for `twin_id` and compact the spool, so they are gone from
+    /// disk too. The erasure events reporting the twin's deletion stay queued. Returns how many
+    /// envelopes were dropped, once the spool has been rewritten.
+    pub async fn purge_twin(&self, twin_id: Uuid) -> std::io::Result<usize> {
+        let erasure = [EventType::TwinDeleted.as_str(), EventType::TwinDataPurged.as_str()];
+        let (dropped, depth, rewritten) = {
+            let mut queue = self.lock_queue();
+            let before = queue.len();
+            queue.retain(|ev| ev.twin_id != Some(twin_id) || erasure.contains(&ev.event_type.as_str()));
+            // Delivered envelopes linger in the spool until it is compacted, so always rewrite.
+            let rewritten = self.inner.spool.as_ref().map(|spool| {
+                let (done, rewritten) = oneshot::channel();
+                spool.send(SpoolOp::Rewrite(queue.iter().cloned().collect(), Some(done)));
+                self.inner.spooled.store(queue.len(), Ordering::Relaxed);
+                rewritten
+            });
+            (before - queue.len(), queue.len(), rewritten)
+        };
+        self.record_depth(depth);
+        if let Some(rewritten) = rewritten {
+            rewritten
+                .await
+                .unwrap_or_else(|_| Err(std::io::Error::other("outbox spool writer stopped")))?;
+        }
+        Ok(dropped)
+    }
+
+    async fn run(self) {
+        let mut backoff = self.inner.cfg.initial_backoff;
+        let mut lingered = false;
+        loop {
+            let batching = self.inner.batching.load(Ordering::Relaxed);
+            let batch_size = if batching { self.inner.batch_size.load(Ordering::Relaxed) } else { 1 };
+            let batch: Vec<EventEnvelope> = self.lock_queue().iter().take(batch_size).cloned().collect();
+            if batch.is_empty() {
+                self.inner.notify.notified().await;
+                continue;
+            }
+            // Give a burst that is still arriving the chance to fill the batch.
+            if batch.len() < batch_size && !lingered && !self.inner.cfg.linger.is_zero() {
+                lingered = true;
+                tokio::time::sleep(self.inner.cfg.linger).await;
+                continue;
+            }
+            lingered = false;
+
+            let outcomes = if batch.len() == 1 {
+                vec![self.send(&batch[0]).await]
+            } else {
+                match self.send_batch(&batch).await {
+                    Some(outcomes) => outcomes,
+                    None => continue,
+                }
+            };
+
+            // Settle head-first; stop at the first retryable event to keep ordering.
+            let mut retry = None;
+            for (envelope, outcome) in batch.iter().zip(outcomes) {
+                match outcome {
+                    Delivery::Delivered => {
+                        self.pop_head(envelope);
+                        metrics::counter!("pagi_outbox_delivered_total").increment(1);
+                    }
+                    Delivery::Rejected(reason) => {
+                        // Retrying a request the router refuses would block the queue forever.
+                        tracing::warn!(event_id = %envelope.id, event_type = %envelope.event_type, %reason, "outbox dropped rejected event");
+                        self.pop_head(envelope);
+                        metrics::counter!("pagi_outbox_dropped_total", "reason" => "rejected").increment(1);
+                    }
+                    Delivery::Retry(reason) => {
+                        retry = Some((envelope.id, reason));
+                        break;
+                    }
+                }
+            }
+
+            match retry {
+                None => backoff = self.inner.cfg.initial_backoff,
+                Some((event_id, reason)) => {
+                    tracing::debug!(%event_id, %reason, backoff_ms = backoff.as_millis() as u64, "outbox delivery failed; retrying");
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.inner.cfg.max_backoff);
+                }
+            }
+        }
+    }
+
+    async fn send(&self, envelope: &EventEnvelope) -> Delivery {
+        let req = match with_service_token(self.inner.http.post(&self.inner.cfg.publish_url).json(envelope)).await {
+            Ok(req) => req,
+            Err(err) => return Delivery::Retry(err),
+        };
+        let resp = match req.send().await {
+            Ok(resp) => resp,
+            Err(err) => return Delivery::Retry(err.to_string()),
+        };
+        if resp.status() == reqwest::StatusCode::UNAUTHORIZED {
+            ServiceToken::global().invalidate().await;
+        }
+        classify(resp.status())
+    }
+
+    /// One outcome per envelope, or `None` if the router has no batch endpoint or found the
+    /// batch too large (batching is then switched off or the batch size halved, and the caller
+    /// should just try again).
+    async fn send_batch(&self, batch: &[EventEnvelope]) -> Option<Vec<Delivery>> {
+        let url = format!("{}/batch", self.inner.cfg.publish_url);
+        let all = |delivery: fn(String) -> Delivery, reason: String| {
+            Some(batch.iter().map(|_| delivery(reason.clone())).collect())
+        };
+
+        let req = match with_service_token(self.inner.http.post(&url).json(batch)).await {
+            Ok(req) => req,
+            Err(err) => return all(Delivery::Retry, err),
+        };
+        let resp = match req.send().await {
+            Ok(resp) => resp,
+            Err(err) => return all(Delivery::Retry, err.to_string()),
+        };
+        let status = resp.status();
+        if status == reqwest::StatusCode::UNAUTHORIZED {
+            ServiceToken::global().invalidate().await;
+            return all(Delivery::Retry, status.to_string());
+        }
+        if status == reqwest::StatusCode::NOT_FOUND || status == reqwest::StatusCode::METHOD_NOT_ALLOWED {
+            tracing::info!(%url, "event router has no batch endpoint; publishing one event at a time");
+            self.inner.batching.store(false, Ordering::Relaxed);
+            return None;
+        }
+        if status == reqwest::StatusCode::PAYLOAD_TOO_LARGE {
+            let smaller = (batch.len() / 2).max(1);
+            tracing::info!(%url, batch_size = smaller, "event router refused the batch as too large; sending smaller batches");
+            self.inner.batch_size.fetch_min(smaller, Ordering::Relaxed);
+            return None;
+        }
+        if !status.is_success() {
+            // The request as a whole was refused; send these envelopes one at a time so each
+            // is settled on its own. Later batches still use the batch endpoint.
+            if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                let mut outcomes = Vec::with_capacity(batch.len());
+                for envelope in batch {
+                    let outcome = self.send(envelope).await;
+                    let retry = matches!(outcome, Delivery::Retry(_));
+                    outcomes.push(outcome);
+                    if retry {
+                        break;
+                    }
+                }
+                outcomes.resize_with(batch.len(), || Delivery::Retry(status.to_string()));
+                return Some(outcomes);
+            }
+            return all(Delivery::Retry, status.to_string());
+        }
+
+        let results = match resp.json::<BatchResults>().await {
+            Ok(body) if body.results.len() == batch.len() => body.results,
+            Ok(_) => return all(Delivery::Retry, "batch response length mismatch".to_string()),
+            Err(err) => return all(Delivery::Retry, err.to_string()),
+        };
+        Some(
+            results
+                .into_iter()
+                .map(|r| match reqwest::StatusCode::from_u16(r.status) {
+                    Ok(status) => classify(status),
+                    Err(_) => Delivery::Retry(format!("invalid status {}", r.status)),
+                })
+                .collect(),
+        )
+    }
+
+    /// Remove a settled head.
+    ///
+    /// Between compactions the spool still lists settled envelopes, so a crash can redeliver
+    /// them (at-least-once, deduped by `id`): at most [`SPOOL_COMPACT_MIN`] or as many as are
+    /// pending, whichever is more.
+    fn pop_head(&self, settled: &EventEnvelope) {
+        let depth = {
+            let mut queue = self.lock_queue();
+            // The head can only have moved if overflow evicted it meanwhile.
+            if queue.front().map(|e| e.id) == Some(settled.id) {
+                queue.pop_front();
+            }
+            let stale = self.inner.spooled.load(Ordering::Relaxed).saturating_sub(queue.len());
+            if stale >= SPOOL_COMPACT_MIN.max(queue.len()) {
+                self.rewrite_spool(&queue);
+            }
+            queue.len()
+        };
+        self.record_depth(depth);
+    }
+
+    fn lock_queue(&self) -> std::sync::MutexGuard<'_, VecDeque<EventEnvelope>> {
+        self.inner.queue.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn record_depth(&self, depth: usize) {
+        metrics::gauge!("pagi_outbox_depth").set(depth as f64);
+    }
+
+    /// Queue an append to the spool. Call with the queue locked, so spool writes follow the
+    /// queue's order.
+    fn append_spool(&self, envelope: &EventEnvelope) {
+        if let Some(spool) = &self.inner.spool {
+            if spool.send(SpoolOp::Append(envelope.clone())) {
+                self.inner.spooled.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Queue a rewrite of the spool to `queue`. Call with the queue locked.
+    fn rewrite_spool(&self, queue: &VecDeque<EventEnvelope>) {
+        if let Some(spool) = &self.inner.spool {
+            if spool.send(SpoolOp::Rewrite(queue.iter().cloned().collect(), None)) {
+                self.inner.spooled.store(queue.len(), Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// The thread writing an outbox's spool file, fed in queue order.
+struct Spool {
+    ops: Option<mpsc::Sender<SpoolOp>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+enum SpoolOp {
+    Append(EventEnvelope),
+    /// Replace the file's contents, reporting the outcome if asked to.
+    Rewrite(Vec<EventEnvelope>, Option<oneshot::Sender<std::io::Result<()>>>),
+    Flush(mpsc::Sender<()>),
+}
+
+impl Spool {
+    fn start(path: PathBuf) -> Self {
+        let (ops, rx) = mpsc::channel();
+        let writer = std::thread::Builder::new()
+            .name("outbox-spool".to_string())
+            .spawn(move || write_spool(&path, rx))
+            .map_err(|err| tracing::warn!(error = %err, "outbox spool writer not started; events kept in memory only"))
+            .ok();
+        Self {
+            ops: writer.is_some().then_some(ops),
+            writer,
+        }
+    }
+
+    /// `false` if there is no writer to take it.
+    fn send(&self, op: SpoolOp) -> bool {
+        self.ops.as_ref().is_some_and(|ops| ops.send(op).is_ok())
+    }
+}
+
+impl Drop for Spool {
+    /// Let the writer finish what is queued, so a dropped outbox leaves a complete spool.
+    fn drop(&mut self) {
+        self.ops.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn write_spool(path: &Path, ops: mpsc::Receiver<SpoolOp>) {
+    for op in ops {
+        match op {
+            SpoolOp::Append(envelope) => {
+                if let Err(err) = append_line(path, &envelope) {
+                    tracing::warn!(path = %path.display(), error = %err, "outbox spool append failed");
+                }
+            }
+            SpoolOp::Rewrite(queue, done) => {
+                let result = rewrite_file(path, &queue);
+                if let Err(err) = &result {
+                    tracing::warn!(path = %path.display(), error = %err, "outbox spool rewrite failed");
+                }
+                if let Some(done) = done {
+                    let _ = done.send(result);
+                }
+            }
+            SpoolOp::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+    }
+}
+
+enum Delivery {
+    Delivered,
+    Rejected(String),
+    Retry(String),
+}
+
+fn classify(status: reqwest::StatusCode) -> Delivery {
+    if status.is_success() {
+        Delivery::Delivered
+    } else if status.is_client_error()
+        // A rejected token is refreshed and retried rather than dropping the event.
+        && status != reqwest::StatusCode::UNAUTHORIZED
+        && status != reqwest::StatusCode::REQUEST_TIMEOUT
+        && status != reqwest::StatusCode::TOO_MANY_REQUESTS
+    {
+        Delivery::Rejected(status.to_string())
+    } else {
+        Delivery::Retry(status.to_string())
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct BatchResults {
+    results: Vec<BatchResult>,
+}
+
+#[derive(serde::Deserialize)]
+struct BatchResult {
+    status: u16,
+}
+
+fn load_spool(path: &Path) -> VecDeque<EventEnvelope> {
+    let Ok(file) = File::open(path) else {
+        return VecDeque::new();
+    };
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str::<EventEnvelope>(&line) {
+            Ok(ev) => Some(ev),
+            Err(err) => {
+                // A torn final line after a crash is expected; skip it rather than refuse to start.
+                tracing::warn!(path = %path.display(), error = %err, "skipping unreadable spool entry");
+                None
+            }
+        })
+        .collect()
+}
+
+fn append_line(path: &Path, envelope: &EventEnvelope) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut line = serde_json::to_vec(envelope)?;
+    line.push(b'\n');
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(&line)
+}
+
+fn rewrite_file(path: &Path, queue: &[EventEnvelope]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    {
+        let mut file = File::create(&tmp)?;
+        for envelope in queue {
+            let mut line = serde_json::to_vec(envelope)?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+        }
+        file.sync_all()?;
+    }
+    std::fs::rename(tmp, path)
+}
@@ -0,0 +1,116 @@
+//! Mutual TLS between PAGI services: configuration and the client side.
+//!
+//! Every service gets a certificate from the internal CA (`pagi-ca`) naming it twice in the
+//! subject alternative names: as a DNS name (its hostname) and as a `spiffe://pagi/<service>`
+//! URI that identifies it as a peer. `pagi_http::tls` serves with the same files and reads
+//! the caller's service name from its client certificate.
+//!
+//! Configuration (all three or none):
+//! - `TLS_CERT_FILE`: PEM certificate chain of this service
+//! - `TLS_KEY_FILE`: PEM private key of this service
+//! - `TLS_CA_FILE`: PEM certificate of the internal CA
+//!
+//! Without them services serve and call each other over plain HTTP.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// URI SAN prefix naming a PAGI service in its certificate.
+pub const SERVICE_URI_PREFIX: &str = "spiffe://pagi/";
+
+/// The URI SAN identifying `service`.
+pub fn service_uri(service: &str) -> String {
+    format!("{SERVICE_URI_PREFIX}{service}")
+}
+
+/// The service named by a URI SAN, if it is a PAGI service URI.
+pub fn service_from_uri(uri: &str) -> Option<&str> {
+    uri.strip_prefix(SERVICE_URI_PREFIX)
+        .filter(|name| !name.is_empty() && !name.contains('/'))
+}
+
+/// Paths of this service's certificate, key and the internal CA.
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub ca: PathBuf,
+}
+
+impl TlsFiles {
+    /// `Ok(None)` when TLS is not configured; an error when only some of the variables are set.
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        match (var("TLS_CERT_FILE"), var("TLS_KEY_FILE"), var("TLS_CA_FILE")) {
+            (None, None, None) => Ok(None),
+            (Some(cert), Some(key), Some(ca)) => Ok(Some(Self {
+                cert: cert.into(),
+                key: key.into(),
+                ca: ca.into(),
+            })),
+            _ => Err("TLS_CERT_FILE, TLS_KEY_FILE and TLS_CA_FILE must be set together".to_string()),
+        }
+    }
+
+    /// Process-wide configuration from env; a partial configuration is logged and ignored.
+    pub fn global() -> Option<&'static TlsFiles> {
+        static GLOBAL: OnceLock<Option<TlsFiles>> = OnceLock::new();
+        GLOBAL
+            .get_or_init(|| {
+                Self::from_env().unwrap_or_else(|e| {
+                    tracing::error!(error = %e, "TLS disabled");
+                    None
+                })
+            })
+            .as_ref()
+    }
+
+    fn read(path: &Path) -> Result<Vec<u8>, String> {
+        std::fs::read(path).map_err(|e| format!("reading {}: {e}", path.display()))
+    }
+
+    /// Certificate chain followed by the private key, as `reqwest::Identity::from_pem` expects.
+    pub fn identity_pem(&self) -> Result<Vec<u8>, String> {
+        let mut pem = Self::read(&self.cert)?;
+        pem.push(b'\n');
+        pem.extend(Self::read(&self.key)?);
+        Ok(pem)
+    }
+
+    pub fn ca_pem(&self) -> Result<Vec<u8>, String> {
+        Self::read(&self.ca)
+    }
+
+    /// Apply the client certificate and trust the internal CA (in addition to the public roots,
+    /// so the same client can still reach external services).
+    pub fn configure(&self, builder: reqwest::ClientBuilder) -> Result<reqwest::ClientBuilder, String> {
+        let identity = reqwest::Identity::from_pem(&self.identity_pem()?).map_err(|e| format!("client identity: {e}"))?;
+        let ca = reqwest::Certificate::from_pem(&self.ca_pem()?).map_err(|e| format!("CA certificate: {e}"))?;
+        Ok(builder.use_rustls_tls().identity(identity).add_root_certificate(ca))
+    }
+}
+
+/// `reqwest::ClientBuilder` presenting this service's certificate when TLS is configured.
+///
+/// Unreadable certificate files are logged; the builder then falls back to a client without
+/// a certificate, which mTLS peers will reject.
+pub fn client_builder() -> reqwest::ClientBuilder {
+    let builder = reqwest::Client::builder();
+    let Some(files) = TlsFiles::global() else {
+        return builder;
+    };
+    match files.configure(reqwest::Client::builder()) {
+        Ok(configured) => configured,
+        Err(e) => {
+            tracing::error!(error = %e, "client TLS configuration failed");
+            builder
+        }
+    }
+}
+
+/// A default client for calls to other PAGI services.
+pub fn client() -> reqwest::Client {
+    client_builder().build().unwrap_or_default()
+}
@@ -0,0 +1,143 @@
+use std::future::Future;
+
+use uuid::Uuid;
+
+use crate::EventEnvelope;
+
+/// W3C Trace Context header (`00-<trace-id>-<parent-id>-<flags>`).
+pub const TRACEPARENT_HEADER: &str = "traceparent";
+pub const CORRELATION_ID_HEADER: &str = "x-correlation-id";
+pub const CAUSATION_ID_HEADER: &str = "x-causation-id";
+
+tokio::task_local! {
+    static CURRENT: TraceContext;
+}
+
+/// Trace identity of the unit of work currently being handled.
+///
+/// Set per request by the pagi-http middleware and read back by outgoing HTTP calls and
+/// [`crate::publish_event`], so every hop and every event of one interaction share a trace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: [u8; 16],
+    /// Id of the current span; becomes the parent id of outgoing calls.
+    pub span_id: [u8; 8],
+    pub sampled: bool,
+    /// Groups everything done on behalf of one request. Defaults to the trace id.
+    pub correlation_id: Uuid,
+    /// The event that caused the current work, if any.
+    pub causation_id: Option<Uuid>,
+}
+
+impl TraceContext {
+    /// Start a new trace.
+    pub fn new_root() -> Self {
+        let trace_id = *Uuid::new_v4().as_bytes();
+        Self {
+            trace_id,
+            span_id: new_span_id(),
+            sampled: true,
+            correlation_id: Uuid::from_bytes(trace_id),
+            causation_id: None,
+        }
+    }
+
+    /// Parse a `traceparent` header. Only version `00` is understood; invalid or all-zero
+    /// ids are rejected as the spec requires.
+    pub fn from_traceparent(raw: &str) -> Option<Self> {
+        let mut parts = raw.trim().split('-');
+        let (version, trace, parent, flags) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        if version != "00" || parts.next().is_some() {
+            return None;
+        }
+        let trace_id: [u8; 16] = decode_hex(trace)?;
+        let span_id: [u8; 8] = decode_hex(parent)?;
+        let [flags]: [u8; 1] = decode_hex(flags)?;
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+        Some(Self {
+            trace_id,
+            span_id,
+            sampled: flags & 0x01 == 1,
+            correlation_id: Uuid::from_bytes(trace_id),
+            causation_id: None,
+        })
+    }
+
+    /// Same trace, new span (for the local handling of an incoming request or an outgoing call).
+    pub fn child(&self) -> Self {
+        Self {
+            span_id: new_span_id(),
+            ..*self
+        }
+    }
+
+    pub fn with_causation(self, causation_id: Uuid) -> Self {
+        Self {
+            causation_id: Some(causation_id),
+            ..self
+        }
+    }
+
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-{:02x}",
+            self.trace_id_hex(),
+            encode_hex(&self.span_id),
+            u8::from(self.sampled)
+        )
+    }
+
+    pub fn trace_id_hex(&self) -> String {
+        encode_hex(&self.trace_id)
+    }
+
+    pub fn span_id_hex(&self) -> String {
+        encode_hex(&self.span_id)
+    }
+
+    /// The context of the task currently running inside [`TraceContext::scope`], if any.
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(|ctx| *ctx).ok()
+    }
+
+    /// Run `f` with `self` as the current context.
+    pub async fn scope<F: Future>(self, f: F) -> F::Output {
+        CURRENT.scope(self, f).await
+    }
+
+    /// Fill in the envelope's trace fields that the producer left empty.
+    pub fn stamp(&self, ev: &mut EventEnvelope) {
+        ev.correlation_id.get_or_insert(self.correlation_id);
+        if ev.causation_id.is_none() {
+            ev.causation_id = self.causation_id;
+        }
+        if ev.traceparent.is_none() {
+            ev.traceparent = Some(self.traceparent());
+        }
+    }
+}
+
+fn new_span_id() -> [u8; 8] {
+    let bytes = Uuid::new_v4().into_bytes();
+    let mut span = [0u8; 8];
+    span.copy_from_slice(&bytes[..8]);
+    span
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode_hex<const N: usize>(raw: &str) -> Option<[u8; N]> {
+    // Uppercase hex is invalid in traceparent.
+    if raw.len() != N * 2 || !raw.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)) {
+        return None;
+    }
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&raw[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(out)
+}
@@ -1,22 +1,95 @@
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TwinId(pub Uuid);
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TwinState {
-    pub status: String,
+    pub status: TwinStatus,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub note: Option<String>,
 }
 
-impl Default for TwinState {
-    fn default() -> Self {
-        Self {
-            status: "registered".to_string(),
-            note: None,
+/// Where a twin is in its lifecycle:
+///
+/// ```text
+/// registered ─▶ active ◀─▶ suspended
+///     │            │           │
+///     └──────────▶ archived ◀──┘ ─▶ deleted
+/// ```
+///
+/// Registered twins can also be suspended or deleted outright, and archived twins can be
+/// reactivated. `deleted` is terminal. Only `registered` and `active` twins accept work.
+///
+/// Statuses written before the lifecycle existed were free-form; any value that is not a
+/// lifecycle status reads as `suspended`, so such twins do no work until an operator
+/// reactivates them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", from = "String")]
+pub enum TwinStatus {
+    #[default]
+    Registered,
+    Active,
+    Suspended,
+    Archived,
+    Deleted,
+}
+
+impl TwinStatus {
+    pub const ALL: &'static [TwinStatus] = &[
+        TwinStatus::Registered,
+        TwinStatus::Active,
+        TwinStatus::Suspended,
+        TwinStatus::Archived,
+        TwinStatus::Deleted,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TwinStatus::Registered => "registered",
+            TwinStatus::Active => "active",
+            TwinStatus::Suspended => "suspended",
+            TwinStatus::Archived => "archived",
+            TwinStatus::Deleted => "deleted",
+        }
+    }
+
+    /// Strict parse of a wire name; unlike deserialization, unknown values are rejected.
+    pub fn parse(raw: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|s| s.as_str() == raw)
+    }
+
+    /// Whether the lifecycle allows moving from `self` to `next`. Staying put is allowed
+    /// (except for `deleted`), so a state update can change only the note.
+    pub fn can_transition_to(self, next: TwinStatus) -> bool {
+        use TwinStatus::*;
+        match (self, next) {
+            (Deleted, _) => false,
+            (a, b) if a == b => true,
+            (Registered, Active | Suspended | Archived | Deleted) => true,
+            (Active, Suspended | Archived) => true,
+            (Suspended, Active | Archived) => true,
+            (Archived, Active | Deleted) => true,
+            _ => false,
         }
     }
+
+    /// Whether services should act on behalf of a twin in this status.
+    pub fn accepts_work(self) -> bool {
+        matches!(self, TwinStatus::Registered | TwinStatus::Active)
+    }
 }
 
+impl From<String> for TwinStatus {
+    fn from(raw: String) -> Self {
+        Self::parse(&raw).unwrap_or(TwinStatus::Suspended)
+    }
+}
+
+impl fmt::Display for TwinStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
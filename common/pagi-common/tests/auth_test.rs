@@ -0,0 +1,40 @@
+use pagi_common::auth::{scopes, Claims, Role};
+use serde_json::json;
+use uuid::Uuid;
+
+#[test]
+fn claims_scopes_and_twin_binding() {
+    let twin = Uuid::new_v4();
+    let claims: Claims = serde_json::from_value(json!({
+        "iss": "pagi-identity-service",
+        "sub": twin.to_string(),
+        "iat": 1_700_000_000u64,
+        "exp": 1_700_000_900u64,
+        "jti": Uuid::new_v4(),
+        "role": "twin",
+        "twin_id": twin,
+        "scope": "tools:execute  agent:run",
+    }))
+    .unwrap();
+    assert!(claims.has_scope(scopes::TOOLS_EXECUTE));
+    assert!(claims.has_scope(scopes::AGENT_RUN));
+    assert!(!claims.has_scope(scopes::TOOLS_REGISTER));
+    assert!(claims.allows_twin(twin));
+    assert!(!claims.allows_twin(Uuid::new_v4()));
+    assert!(!claims.allows_twin(Uuid::nil()), "twin tokens cannot act globally");
+
+    let service = Claims {
+        role: Role::Service,
+        twin_id: None,
+        ..claims
+    };
+    assert!(service.allows_twin(Uuid::new_v4()));
+
+    // Only admins issue tokens or change twins; twins never register tools.
+    for role in [Role::Twin, Role::Service] {
+        assert!(!role.default_scopes().contains(&scopes::TOKENS_ISSUE));
+        assert!(!role.default_scopes().contains(&scopes::TWINS_WRITE));
+    }
+    assert!(!Role::Twin.default_scopes().contains(&scopes::TOOLS_REGISTER));
+    assert_eq!(Role::Admin.default_scopes(), scopes::ALL);
+}
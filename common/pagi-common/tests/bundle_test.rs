@@ -0,0 +1,37 @@
+use ed25519_dalek::SigningKey;
+use pagi_common::bundle::{sections, BundleContents, TwinBundle, BUNDLE_VERSION};
+use serde_json::json;
+use uuid::Uuid;
+
+#[test]
+fn bundle_signature_covers_contents() {
+    let key = SigningKey::from_bytes(&[7u8; 32]);
+    let twin_id = Uuid::new_v4();
+    let contents = BundleContents {
+        twin_id,
+        exported_at: time::OffsetDateTime::now_utc(),
+        exported_by: "edge-1".to_string(),
+        identity: json!({"twin_id": twin_id}),
+        sealed_key: json!({"version": 1}),
+        sections: [(sections::EMOTION.to_string(), json!({"mood": "calm"}))].into(),
+    };
+    let bundle = TwinBundle::seal(&contents, &key, "did:web:example.com#key-1").unwrap();
+    assert_eq!(bundle.unverified_contents().unwrap().sections[sections::EMOTION]["mood"], "calm");
+    bundle.verify(&key.verifying_key()).unwrap();
+    assert!(bundle.verify(&SigningKey::from_bytes(&[8u8; 32]).verifying_key()).is_err());
+
+    // Any change to the signed contents breaks the signature.
+    let mut tampered = contents.clone();
+    tampered.sections.insert(sections::EMOTION.to_string(), json!({"mood": "angry"}));
+    let forged = TwinBundle {
+        contents: TwinBundle::seal(&tampered, &SigningKey::from_bytes(&[9u8; 32]), "x").unwrap().contents,
+        ..bundle.clone()
+    };
+    assert!(forged.verify(&key.verifying_key()).is_err());
+
+    let future = TwinBundle {
+        version: BUNDLE_VERSION + 1,
+        ..bundle
+    };
+    assert!(future.unverified_contents().is_err());
+}
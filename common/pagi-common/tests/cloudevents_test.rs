@@ -0,0 +1,72 @@
+use pagi_common::{
+    cloudevents::{Binding, CloudEvent},
+    CoreEvent, EventEnvelope, TraceContext,
+};
+use serde_json::json;
+
+fn sample_envelope() -> EventEnvelope {
+    let mut ev = EventEnvelope::new_core(uuid::Uuid::new_v4(), CoreEvent::GoalReceived { goal: "plan a trip".into() });
+    ev.source = Some("pagi-executive-engine".into());
+    ev.subject = Some("twin goals".into());
+    TraceContext::new_root().with_causation(uuid::Uuid::new_v4()).stamp(&mut ev);
+    ev
+}
+
+#[test]
+fn envelope_round_trips_through_structured_and_binary_modes() {
+    let ev = sample_envelope();
+    let original = serde_json::to_value(&ev).unwrap();
+
+    let ce = CloudEvent::from(&ev);
+    assert_eq!(ce.event_type, "goal_received");
+    assert_eq!(ce.extensions["twinid"], json!(ev.twin_id.unwrap().to_string()));
+
+    let structured: CloudEvent = serde_json::from_value(serde_json::to_value(&ce).unwrap()).unwrap();
+    let back = EventEnvelope::try_from(structured).unwrap();
+    assert_eq!(serde_json::to_value(&back).unwrap(), original);
+
+    for binding in [Binding::Http, Binding::Kafka] {
+        let headers = ce.binary_headers(binding);
+        let parsed = CloudEvent::from_binary(
+            binding,
+            headers.iter().map(|(k, v)| (k.as_str(), v.as_bytes())),
+            &ce.binary_body(),
+        )
+        .unwrap();
+        let back = EventEnvelope::try_from(parsed).unwrap();
+        assert_eq!(serde_json::to_value(&back).unwrap(), original, "{binding:?}");
+    }
+}
+
+#[test]
+fn foreign_cloudevent_round_trips_unchanged() {
+    let ce: CloudEvent = serde_json::from_value(json!({
+        "specversion": "1.0",
+        "id": "A234-1234-1234",
+        "source": "https://github.com/cloudevents/spec/pull",
+        "type": "com.github.pull_request.opened",
+        "datacontenttype": "application/json",
+        "time": "2018-04-05T17:31:00Z",
+        "comexampleextension1": "value",
+        "data": {"number": 123}
+    }))
+    .unwrap();
+
+    let ev = EventEnvelope::try_from(ce.clone()).unwrap();
+    assert_eq!(ev.id, EventEnvelope::try_from(ce.clone()).unwrap().id, "id mapping is deterministic");
+    assert_eq!(ev.source.as_deref(), Some("https://github.com/cloudevents/spec/pull"));
+
+    assert_eq!(CloudEvent::from(&ev), ce);
+}
+
+#[test]
+fn extension_names_must_be_lowercase_alphanumeric() {
+    for name in ["comExample", "com-example", "com_example", "", "abcdefghijklmnopqrstu"] {
+        let mut ce = CloudEvent::from(&sample_envelope());
+        ce.extensions.insert(name.to_string(), json!("value"));
+        assert!(EventEnvelope::try_from(ce).is_err(), "{name:?}");
+    }
+    let mut ce = CloudEvent::from(&sample_envelope());
+    ce.extensions.insert("abcdefghij0123456789".to_string(), json!("value"));
+    assert!(EventEnvelope::try_from(ce).is_ok());
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use pagi_common::did::{did_web_url, ed25519_from_multibase, ed25519_multibase, DidResolver, SigningDid};
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
+use uuid::Uuid;
+
+#[test]
+fn did_web_maps_to_document_urls() {
+    assert_eq!(
+        did_web_url("did:web:example.com", false).unwrap(),
+        "https://example.com/.well-known/did.json"
+    );
+    assert_eq!(
+        did_web_url("did:web:example.com%3A8443:twins:abc", false).unwrap(),
+        "https://example.com:8443/twins/abc/did.json"
+    );
+    assert_eq!(
+        did_web_url("did:web:localhost%3A8002:orgs:acme", true).unwrap(),
+        "http://localhost:8002/orgs/acme/did.json"
+    );
+    assert!(did_web_url("did:web:", false).is_err());
+    assert!(did_web_url("did:web:example.com::x", false).is_err());
+    assert!(did_web_url("did:key:z6Mk", false).is_err());
+}
+
+#[tokio::test]
+async fn did_key_resolves_locally() {
+    let method_id = "z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK";
+    let key = ed25519_from_multibase(method_id).unwrap();
+    let resolver = DidResolver::new(reqwest::Client::new(), false);
+
+    let did = format!("did:key:{method_id}");
+    assert_eq!(resolver.ed25519_keys(&did).await.unwrap(), vec![key]);
+    assert_eq!(resolver.ed25519_keys(&format!("{did}#{method_id}")).await.unwrap(), vec![key]);
+    assert!(resolver.ed25519_keys("did:example:123").await.is_err());
+}
+
+/// Serves the documents `documents(addr)` returns by request path, one request per
+/// connection, and returns the server's address.
+async fn document_server(documents: impl FnOnce(&str) -> HashMap<String, Value>) -> String {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let documents = documents(&addr);
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let documents = documents.clone();
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(socket);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).await.unwrap();
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).await.unwrap() == 0 || header.trim().is_empty() {
+                        break;
+                    }
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+                let (status, body) = match documents.get(path) {
+                    Some(doc) => ("200 OK", doc.to_string()),
+                    None => ("404 Not Found", String::new()),
+                };
+                let resp = format!(
+                    "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                reader.get_mut().write_all(resp.as_bytes()).await.unwrap();
+            });
+        }
+    });
+    addr
+}
+
+#[tokio::test]
+async fn retired_did_web_keys_only_verify_older_signatures() {
+    let (old_key, new_key) = ([1u8; 32], [2u8; 32]);
+    let retired_at = OffsetDateTime::parse("2026-01-01T00:00:00Z", &Rfc3339).unwrap();
+    let twin_id = Uuid::new_v4();
+    let doc = |did: &str| {
+        json!({
+            "id": did,
+            "verificationMethod": [
+                {"id": format!("{did}#key-1"), "publicKeyMultibase": ed25519_multibase(&old_key), "revoked": "2026-01-01T00:00:00Z"},
+                {"id": "#key-2", "publicKeyMultibase": ed25519_multibase(&new_key)},
+            ],
+            "assertionMethod": ["#key-2"],
+        })
+    };
+    let did_for = |addr: &str| format!("did:web:{}:twins:{twin_id}", addr.replace(':', "%3A"));
+    let addr = document_server(|addr| {
+        let did = did_for(addr);
+        HashMap::from([
+            (format!("/twins/{twin_id}/did.json"), doc(&did)),
+            (format!("/twins/{twin_id}/did"), doc(&did)),
+        ])
+    })
+    .await;
+    let did = did_for(&addr);
+    let resolver = DidResolver::new(reqwest::Client::new(), true).with_identity_url(Some(format!("http://{addr}")));
+
+    assert_eq!(resolver.ed25519_keys(&did).await.unwrap(), vec![new_key]);
+    assert!(resolver.ed25519_keys(&format!("{did}#key-1")).await.is_err());
+    let before = retired_at - Duration::hours(1);
+    assert_eq!(resolver.ed25519_keys_at(&did, before).await.unwrap(), vec![old_key, new_key]);
+    assert_eq!(resolver.ed25519_keys_at(&format!("{did}#key-1"), before).await.unwrap(), vec![old_key]);
+    assert_eq!(resolver.ed25519_keys_at(&did, retired_at + Duration::hours(1)).await.unwrap(), vec![new_key]);
+
+    // A did:web twin signs as its did:web, and only with its current key.
+    assert_eq!(
+        resolver.signing_did(twin_id, &new_key).await.unwrap(),
+        SigningDid { did: did.clone(), key_id: format!("{did}#key-2") }
+    );
+    assert!(resolver.signing_did(twin_id, &old_key).await.is_err());
+    // Without an identity service, twins sign as their key's did:key.
+    let method_id = ed25519_multibase(&new_key);
+    assert_eq!(
+        DidResolver::new(reqwest::Client::new(), true).signing_did(twin_id, &new_key).await.unwrap().did,
+        format!("did:key:{method_id}")
+    );
+}
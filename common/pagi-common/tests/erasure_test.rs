@@ -0,0 +1,146 @@
+use std::{collections::HashMap, time::Duration};
+
+use pagi_common::{
+    erasure::{DeletionConfirmer, PurgeReport, PurgeTrigger, Retention, TwinDeletion, Unconfirmed},
+    CoreEvent, EventEnvelope, TwinStatus,
+};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Answers `GET /twins/:id` from `twins`, one request per connection.
+async fn identity_server(twins: HashMap<Uuid, Value>) -> String {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let twins = twins.clone();
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(socket);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).await.unwrap();
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).await.unwrap() == 0 || header.trim().is_empty() {
+                        break;
+                    }
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+                let twin = path.strip_prefix("/twins/").and_then(|id| id.parse().ok()).and_then(|id| twins.get(&id));
+                let (status, body) = match twin {
+                    Some(twin) => ("200 OK", twin.to_string()),
+                    None => ("404 Not Found", String::new()),
+                };
+                let resp = format!(
+                    "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                reader.get_mut().write_all(resp.as_bytes()).await.unwrap();
+            });
+        }
+    });
+    format!("http://{addr}")
+}
+
+#[test]
+fn deletion_events_name_every_did_and_reports_round_trip() {
+    let twin_id = Uuid::new_v4();
+    let ev = EventEnvelope::new_core(
+        twin_id,
+        CoreEvent::TwinDeleted {
+            did: "did:web:relay.example:twins:a".to_string(),
+            previous_dids: vec!["did:key:z6Mkold".to_string()],
+            reason: Some("erasure request".to_string()),
+            actor: None,
+        },
+    );
+    assert_eq!(ev.event_type, "twin_deleted");
+    let wire: EventEnvelope = serde_json::from_str(&serde_json::to_string(&ev).unwrap()).unwrap();
+    assert_eq!(
+        TwinDeletion::from_event(&wire),
+        Some(TwinDeletion {
+            twin_id,
+            dids: vec!["did:web:relay.example:twins:a".to_string(), "did:key:z6Mkold".to_string()],
+        })
+    );
+
+    // Other events, and deletions without a twin, are not deletions.
+    let other = EventEnvelope::new_core(twin_id, CoreEvent::GoalReceived { goal: "x".to_string() });
+    assert_eq!(TwinDeletion::from_event(&other), None);
+    let mut anonymous = ev.clone();
+    anonymous.twin_id = None;
+    assert_eq!(TwinDeletion::from_event(&anonymous), None);
+
+    let report = PurgeReport::new("pagi-working-memory", twin_id, PurgeTrigger::TwinDeleted)
+        .with("items", 3)
+        .with("items", 2)
+        .with("indexes", 0);
+    assert_eq!(report.total(), 5);
+    assert_eq!(report.purged.get("indexes"), Some(&0), "empty kinds stay in the report");
+    let parsed: PurgeReport = serde_json::from_value(serde_json::to_value(&report).unwrap()).unwrap();
+    assert_eq!(parsed, report);
+
+    let purged = report.event();
+    assert_eq!(purged.source.as_deref(), Some("pagi-working-memory"));
+    match purged.core_event().unwrap() {
+        CoreEvent::TwinDataPurged { trigger, purged, .. } => {
+            assert_eq!(trigger, PurgeTrigger::TwinDeleted);
+            assert_eq!(purged.get("items"), Some(&5));
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+#[test]
+fn retention_cutoff() {
+    let now = OffsetDateTime::now_utc();
+    let keep_forever = Retention {
+        max_age: None,
+        sweep_every: Duration::from_secs(300),
+    };
+    assert_eq!(keep_forever.cutoff(now), None);
+
+    let day = Retention {
+        max_age: Some(Duration::from_secs(86_400)),
+        ..keep_forever
+    };
+    assert_eq!(day.cutoff(now), Some(now - time::Duration::days(1)));
+}
+
+#[tokio::test]
+async fn deletions_are_confirmed_with_the_registry() {
+    let (deleted, active, unknown) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+    let twins = HashMap::from([
+        (
+            deleted,
+            json!({"status": "deleted", "did": "did:web:node.example:twins:a", "previous_dids": ["did:key:z6Mkold"]}),
+        ),
+        (active, json!({"status": "active", "did": "did:key:z6Mkactive"})),
+    ]);
+    let confirmer = DeletionConfirmer::new(Some(identity_server(twins).await));
+    let claim = |twin_id| TwinDeletion {
+        twin_id,
+        dids: vec!["did:key:z6Mksomeone-else".to_string()],
+    };
+
+    // The DIDs to purge are the registry's, whatever the event claimed.
+    assert_eq!(
+        confirmer.confirm(&claim(deleted)).await,
+        Ok(TwinDeletion {
+            twin_id: deleted,
+            dids: vec!["did:web:node.example:twins:a".to_string(), "did:key:z6Mkold".to_string()],
+        })
+    );
+    assert_eq!(
+        confirmer.confirm(&claim(active)).await,
+        Err(Unconfirmed::NotDeleted(Some(TwinStatus::Active)))
+    );
+    assert_eq!(confirmer.confirm(&claim(unknown)).await, Err(Unconfirmed::NotDeleted(None)));
+
+    // Without an identity service nothing is purged.
+    let unconfigured = DeletionConfirmer::new(None).confirm(&claim(deleted)).await;
+    assert!(matches!(unconfigured, Err(Unconfirmed::Unavailable(_))), "{unconfigured:?}");
+}
@@ -1,4 +1,4 @@
-use pagi_common::{EventEnvelope, EventType};
+use pagi_common::{CoreEvent, EventEnvelope, EventType, CURRENT_SCHEMA_VERSION};
 use serde_json::json;
 
 #[test]
@@ -10,3 +10,42 @@ fn event_envelope_new_sets_required_fields() {
     assert!(ev.payload.get("twin_id").is_some());
 }
 
+
+#[test]
+fn new_core_round_trips_through_core_event() {
+    let twin = uuid::Uuid::new_v4();
+    let ev = EventEnvelope::new_core(twin, CoreEvent::PlanCreated { step_count: 4 });
+
+    assert_eq!(ev.schema_version, CURRENT_SCHEMA_VERSION);
+    assert_eq!(ev.event_type, "plan_created");
+    assert!(matches!(ev.core_event().unwrap(), CoreEvent::PlanCreated { step_count: 4 }));
+}
+
+#[test]
+fn legacy_flat_payload_is_upcast() {
+    // Shape emitted by pagi-emotion-state-manager before payloads were typed/versioned.
+    let legacy = EventEnvelope::new(
+        EventType::EmotionStateUpdated,
+        json!({"twin_id": uuid::Uuid::new_v4(), "mood": "calm", "stress": 0.25}),
+    );
+    let mut raw = serde_json::to_value(&legacy).unwrap();
+    raw.as_object_mut().unwrap().remove("schema_version");
+    let ev: EventEnvelope = serde_json::from_value(raw).unwrap();
+    assert_eq!(ev.schema_version, 0);
+
+    match ev.core_event().unwrap() {
+        CoreEvent::EmotionStateUpdated { mood, stress } => {
+            assert_eq!(mood, "calm");
+            assert_eq!(stress, Some(0.25));
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+}
+
+#[test]
+fn every_event_type_parses_from_its_wire_name() {
+    for t in EventType::ALL {
+        assert_eq!(EventType::parse(t.as_str()), Some(*t));
+    }
+    assert_eq!(EventType::parse("not_an_event"), None);
+}
@@ -0,0 +1,59 @@
+use pagi_common::keystore::{key_files, Keyring, SealedWith};
+use uuid::Uuid;
+
+#[test]
+fn sealed_keys_survive_kek_rotation() {
+    let dir = std::env::temp_dir().join(format!("pagi-keystore-{}", Uuid::new_v4()));
+    let twin = Uuid::new_v4();
+    let secret = [7u8; 32];
+
+    // Legacy plaintext file, as written before a KEK was configured.
+    Keyring::new(None, None).write_key(&dir, twin, &secret).unwrap();
+    let old = Keyring::new(Some(b"old passphrase".to_vec()), None);
+    assert_eq!(old.read_key(&dir, twin).unwrap(), secret);
+    assert!(old.rewrap(&dir, twin).unwrap());
+    assert!(!old.rewrap(&dir, twin).unwrap(), "already sealed under the current KEK");
+
+    let raw = std::fs::read(dir.join(format!("{twin}.ed25519"))).unwrap();
+    assert!(!raw.windows(secret.len()).any(|w| w == secret));
+    assert!(Keyring::new(None, None).read_key(&dir, twin).is_err());
+    assert!(Keyring::new(Some(b"wrong".to_vec()), None).read_key(&dir, twin).is_err());
+    // Bound to the twin id: another twin's name does not open it.
+    assert!(old.open(Uuid::new_v4(), &raw).is_err());
+
+    // During rotation both KEKs open the file; re-wrapping moves it to the new one.
+    let rotating = Keyring::new(Some(b"new passphrase".to_vec()), Some(b"old passphrase".to_vec()));
+    assert_eq!(rotating.open(twin, &raw).unwrap(), (secret, SealedWith::Previous));
+    assert!(rotating.rewrap(&dir, twin).unwrap());
+
+    let new = Keyring::new(Some(b"new passphrase".to_vec()), None);
+    assert_eq!(new.read_key(&dir, twin).unwrap(), secret);
+    assert!(old.read_key(&dir, twin).is_err());
+    assert_eq!(key_files(&dir).unwrap(), vec![twin]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn key_files_are_owner_only() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir().join(format!("pagi-keystore-{}", Uuid::new_v4()));
+    let twin = Uuid::new_v4();
+    Keyring::new(Some(b"passphrase".to_vec()), None).write_key(&dir, twin, &[1u8; 32]).unwrap();
+    let mode = std::fs::metadata(dir.join(format!("{twin}.ed25519"))).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn oversized_kdf_costs_are_refused() {
+    let twin = Uuid::new_v4();
+    let keyring = Keyring::new(Some(b"passphrase".to_vec()), None);
+    let sealed = keyring.seal(twin, &[1u8; 32]).unwrap();
+    let mut doc: serde_json::Value = serde_json::from_slice(&sealed).unwrap();
+    doc["m_cost"] = serde_json::json!(4 * 1024 * 1024);
+    let err = keyring.open(twin, &serde_json::to_vec(&doc).unwrap()).unwrap_err();
+    assert!(err.contains("exceeds the limit"), "{err}");
+}
@@ -0,0 +1,34 @@
+use pagi_common::{CoreEvent, EventEnvelope, EventType, TwinState, TwinStatus};
+use serde_json::json;
+
+#[test]
+fn lifecycle_transitions_and_legacy_statuses() {
+    use TwinStatus::*;
+    assert!(Registered.can_transition_to(Active));
+    assert!(Active.can_transition_to(Suspended));
+    assert!(Suspended.can_transition_to(Active));
+    assert!(Archived.can_transition_to(Deleted));
+    assert!(Active.can_transition_to(Active), "note-only updates keep the status");
+    assert!(!Active.can_transition_to(Registered));
+    assert!(!Active.can_transition_to(Deleted), "only archived twins are deleted");
+    assert!(!Deleted.can_transition_to(Deleted));
+    assert!(!Deleted.can_transition_to(Active));
+    assert!(!Suspended.accepts_work());
+
+    // Free-form statuses from before the lifecycle read as suspended; requests parse strictly.
+    let legacy: TwinState = serde_json::from_value(json!({"status": "busy"})).unwrap();
+    assert_eq!(legacy.status, Suspended);
+    assert_eq!(TwinStatus::parse("busy"), None);
+    assert_eq!(serde_json::to_value(Suspended).unwrap(), json!("suspended"));
+
+    // Events from before transitions carried a reason still parse.
+    let mut ev = EventEnvelope::new(EventType::TwinStateUpdated, json!({"state": {"status": "online"}}));
+    ev.twin_id = Some(uuid::Uuid::new_v4());
+    match ev.core_event().unwrap() {
+        CoreEvent::TwinStateUpdated { state, previous_status, .. } => {
+            assert_eq!(state.status, Suspended);
+            assert_eq!(previous_status, None);
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
@@ -0,0 +1,237 @@
+use pagi_common::{EventEnvelope, EventType, Outbox, OutboxConfig};
+use serde_json::json;
+
+fn spool_config(max_queue: usize) -> (OutboxConfig, std::path::PathBuf) {
+    let path = std::env::temp_dir()
+        .join("pagi-outbox-test")
+        .join(format!("{}.jsonl", uuid::Uuid::new_v4()));
+    let cfg = OutboxConfig {
+        spool_path: Some(path.clone()),
+        max_queue,
+        ..OutboxConfig::default()
+    };
+    (cfg, path)
+}
+
+#[test]
+fn spooled_events_survive_restart_in_order() {
+    let (cfg, path) = spool_config(100);
+
+    let outbox = Outbox::new(cfg.clone());
+    let ids: Vec<_> = (0..3)
+        .map(|i| {
+            let ev = EventEnvelope::new(EventType::ContextBuilt, json!({"n": i}));
+            let id = ev.id;
+            outbox.enqueue(ev);
+            id
+        })
+        .collect();
+    drop(outbox);
+
+    let restored = Outbox::new(cfg);
+    let restored_ids: Vec<_> = restored.pending().iter().map(|e| e.id).collect();
+    assert_eq!(restored_ids, ids);
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[test]
+fn overflow_drops_oldest_and_compacts_spool() {
+    let (cfg, path) = spool_config(2);
+
+    let outbox = Outbox::new(cfg.clone());
+    let evs: Vec<_> = (0..3)
+        .map(|i| EventEnvelope::new(EventType::ContextBuilt, json!({"n": i})))
+        .collect();
+    for ev in &evs {
+        outbox.enqueue(ev.clone());
+    }
+    assert_eq!(outbox.depth(), 2);
+    assert_eq!(outbox.pending()[0].id, evs[1].id);
+    outbox.flush_spool();
+
+    let restored = Outbox::new(cfg.clone());
+    assert_eq!(restored.depth(), 2);
+    assert_eq!(restored.pending()[0].id, evs[1].id);
+    drop(restored);
+
+    // Overflow appends; the spool is compacted once it holds twice the queue.
+    for _ in 0..2 {
+        outbox.enqueue(EventEnvelope::new(EventType::ContextBuilt, json!({})));
+    }
+    outbox.flush_spool();
+    let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+    assert_eq!(lines, 3);
+    let ids = |outbox: &Outbox| outbox.pending().iter().map(|e| e.id).collect::<Vec<_>>();
+    assert_eq!(ids(&Outbox::new(cfg)), ids(&outbox));
+
+    let _ = std::fs::remove_file(path);
+}
+
+#[tokio::test]
+async fn purging_a_twin_drops_its_queued_events_but_not_its_erasure_events() {
+    let (cfg, path) = spool_config(100);
+    let (twin, other) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+    let event = |twin_id, event_type| {
+        let mut ev = EventEnvelope::new(event_type, json!({}));
+        ev.twin_id = Some(twin_id);
+        ev
+    };
+    let kept = [event(other, EventType::ContextBuilt), event(twin, EventType::TwinDeleted)];
+
+    let outbox = Outbox::new(cfg.clone());
+    outbox.enqueue(event(twin, EventType::ContextBuilt));
+    outbox.enqueue(kept[0].clone());
+    outbox.enqueue(event(twin, EventType::GoalReceived));
+    outbox.enqueue(kept[1].clone());
+    assert_eq!(outbox.purge_twin(twin).await.unwrap(), 2);
+
+    let ids = |outbox: &Outbox| outbox.pending().iter().map(|e| e.id).collect::<Vec<_>>();
+    assert_eq!(ids(&outbox), kept.iter().map(|e| e.id).collect::<Vec<_>>());
+    // The spool no longer holds them either.
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+    assert_eq!(ids(&Outbox::new(cfg)), ids(&outbox));
+
+    let _ = std::fs::remove_file(path);
+}
+
+/// Accepts `/publish/batch` requests of up to `max` events, answering 202 for every event (and
+/// `413` to larger batches), and reports how many events each request carried.
+async fn batch_router(max: usize) -> (String, tokio::sync::mpsc::UnboundedReceiver<(String, usize)>) {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/publish", listener.local_addr().unwrap());
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let mut reader = BufReader::new(socket);
+                loop {
+                    let mut request_line = String::new();
+                    if reader.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let path = request_line.split_whitespace().nth(1).unwrap_or_default().to_string();
+                    let mut len = 0;
+                    loop {
+                        let mut header = String::new();
+                        reader.read_line(&mut header).await.unwrap();
+                        if header.trim().is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = header.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                len = value.trim().parse().unwrap();
+                            }
+                        }
+                    }
+                    let mut body = vec![0; len];
+                    reader.read_exact(&mut body).await.unwrap();
+                    let events = serde_json::from_slice::<Vec<serde_json::Value>>(&body).map_or(1, |b| b.len());
+                    let results: Vec<_> = (0..events).map(|_| json!({"status": 202})).collect();
+                    let (status, resp) = if events > max {
+                        ("413 Payload Too Large", String::new())
+                    } else {
+                        ("200 OK", json!({ "results": results }).to_string())
+                    };
+                    let head = format!(
+                        "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n",
+                        resp.len()
+                    );
+                    let socket = reader.get_mut();
+                    socket.write_all(head.as_bytes()).await.unwrap();
+                    socket.write_all(resp.as_bytes()).await.unwrap();
+                    let _ = tx.send((path, events));
+                }
+            });
+        }
+    });
+    (url, rx)
+}
+
+#[tokio::test]
+async fn bursts_are_published_as_one_batch() {
+    let (publish_url, mut requests) = batch_router(usize::MAX).await;
+    let outbox = Outbox::new(OutboxConfig {
+        publish_url,
+        linger: std::time::Duration::from_millis(100),
+        ..OutboxConfig::default()
+    });
+    outbox.start();
+
+    for i in 0..5 {
+        outbox.enqueue(EventEnvelope::new(EventType::ContextBuilt, json!({"n": i})));
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    }
+    let (path, events) = requests.recv().await.unwrap();
+    assert_eq!((path.as_str(), events), ("/publish/batch", 5));
+
+    for _ in 0..50 {
+        if outbox.depth() == 0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(outbox.depth(), 0);
+}
+
+#[tokio::test]
+async fn batches_the_router_finds_too_large_are_split() {
+    let (publish_url, mut requests) = batch_router(3).await;
+    let outbox = Outbox::new(OutboxConfig {
+        publish_url,
+        batch_size: 8,
+        ..OutboxConfig::default()
+    });
+    for i in 0..8 {
+        outbox.enqueue(EventEnvelope::new(EventType::ContextBuilt, json!({"n": i})));
+    }
+    outbox.start();
+
+    let mut sizes = Vec::new();
+    let mut delivered = 0;
+    while delivered < 8 {
+        let (path, events) = requests.recv().await.unwrap();
+        assert_eq!(path, "/publish/batch");
+        if events <= 3 {
+            delivered += events;
+        }
+        sizes.push(events);
+    }
+    // Halved on each 413, then kept: batching stays on.
+    assert_eq!(sizes, [8, 4, 2, 2, 2, 2]);
+    for _ in 0..50 {
+        if outbox.depth() == 0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(outbox.depth(), 0);
+}
+
+#[tokio::test]
+async fn the_spool_is_compacted_once_mostly_delivered() {
+    let (publish_url, _requests) = batch_router(usize::MAX).await;
+    let (cfg, path) = spool_config(1000);
+    let cfg = OutboxConfig { publish_url, ..cfg };
+    let outbox = Outbox::new(cfg.clone());
+    for i in 0..70 {
+        outbox.enqueue(EventEnvelope::new(EventType::ContextBuilt, json!({"n": i})));
+    }
+    outbox.start();
+    for _ in 0..100 {
+        if outbox.depth() == 0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(outbox.depth(), 0);
+
+    // Rewritten once 64 deliveries made up most of it, not after every one since.
+    outbox.flush_spool();
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 6);
+    let _ = std::fs::remove_file(path);
+}
@@ -0,0 +1,13 @@
+use pagi_common::tls::{service_from_uri, service_uri};
+
+#[test]
+fn service_uri_round_trip() {
+    let uri = service_uri("pagi-external-gateway");
+    assert_eq!(uri, "spiffe://pagi/pagi-external-gateway");
+    assert_eq!(service_from_uri(&uri), Some("pagi-external-gateway"));
+
+    // Other trust domains, nested paths and empty names do not identify a PAGI service.
+    assert_eq!(service_from_uri("spiffe://example.org/pagi-external-gateway"), None);
+    assert_eq!(service_from_uri("spiffe://pagi/ns/pagi-external-gateway"), None);
+    assert_eq!(service_from_uri("spiffe://pagi/"), None);
+}
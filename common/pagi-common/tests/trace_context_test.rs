@@ -0,0 +1,50 @@
+use pagi_common::{CoreEvent, EventEnvelope, TraceContext};
+
+#[test]
+fn traceparent_round_trips_and_rejects_invalid_headers() {
+    let raw = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+    let ctx = TraceContext::from_traceparent(raw).unwrap();
+
+    assert_eq!(ctx.traceparent(), raw);
+    assert_eq!(ctx.correlation_id.simple().to_string(), ctx.trace_id_hex());
+    assert!(ctx.sampled);
+
+    let child = ctx.child();
+    assert_eq!(child.trace_id, ctx.trace_id);
+    assert_ne!(child.span_id, ctx.span_id);
+
+    for bad in [
+        "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+    ] {
+        assert!(TraceContext::from_traceparent(bad).is_none(), "{bad}");
+    }
+}
+
+#[tokio::test]
+async fn scope_stamps_only_missing_trace_fields() {
+    let cause = uuid::Uuid::new_v4();
+    let ctx = TraceContext::new_root().with_causation(cause);
+
+    let (stamped, explicit) = ctx
+        .scope(async {
+            let current = TraceContext::current().unwrap();
+
+            let mut stamped = EventEnvelope::from_core(CoreEvent::PlanCreated { step_count: 1 });
+            current.stamp(&mut stamped);
+
+            let mut explicit = EventEnvelope::from_core(CoreEvent::PlanCreated { step_count: 2 });
+            explicit.causation_id = Some(stamped.id);
+            current.stamp(&mut explicit);
+            (stamped, explicit)
+        })
+        .await;
+
+    assert_eq!(stamped.correlation_id, Some(ctx.correlation_id));
+    assert_eq!(stamped.causation_id, Some(cause));
+    assert_eq!(stamped.traceparent, Some(ctx.traceparent()));
+    assert_eq!(explicit.causation_id, Some(stamped.id));
+    assert!(TraceContext::current().is_none());
+}
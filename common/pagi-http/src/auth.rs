@@ -0,0 +1,447 @@
+//! Bearer token verification for inbound requests and service tokens for outbound ones.
+//!
+//! Tokens are EdDSA JWTs issued by pagi-identity-service (see [`pagi_common::auth`]).
+//! Services build an [`Auth`] with [`Auth::from_env`] and guard routes with
+//! [`Auth::require`]:
+//!
+//! ```ignore
+//! .route("/execute/:tool", post(execute).route_layer(auth.require(scopes::TOOLS_EXECUTE)))
+//! ```
+//!
+//! Handlers that act for a twin additionally check [`Caller::check_twin`], since twin tokens
+//! are only good for their own twin.
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use ed25519_dalek::{Signature, Verifier as _, VerifyingKey};
+use pagi_common::{
+    auth::{unix_now, Claims, Role, ServiceToken},
+    PagiError,
+};
+use reqwest_middleware::{ClientBuilder, Middleware};
+use serde::Deserialize;
+use tokio::sync::{Mutex, RwLock};
+use tower::{Layer, Service};
+use uuid::Uuid;
+
+use crate::{
+    errors::PagiAxumError,
+    trace_context::{PropagateTrace, TracedClient},
+};
+
+/// Accepted clock skew when checking `exp`.
+const LEEWAY_SECS: u64 = 30;
+/// Minimum time between JWKS fetches triggered by an unknown `kid`.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(30);
+
+/// Why a request was not authorized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// No bearer token was sent.
+    Missing,
+    /// The token is malformed, expired, or its signature does not verify.
+    Invalid(String),
+    /// The token is valid but lacks a scope or is bound to another twin.
+    Forbidden(String),
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        let (status, challenge, message) = match self {
+            AuthError::Missing => (StatusCode::UNAUTHORIZED, "Bearer".to_string(), "missing bearer token".to_string()),
+            AuthError::Invalid(msg) => (
+                StatusCode::UNAUTHORIZED,
+                r#"Bearer error="invalid_token""#.to_string(),
+                format!("invalid token: {msg}"),
+            ),
+            AuthError::Forbidden(msg) => (
+                StatusCode::FORBIDDEN,
+                r#"Bearer error="insufficient_scope""#.to_string(),
+                msg,
+            ),
+        };
+        let mut resp = PagiAxumError::with_status(PagiError::config(message), status).into_response();
+        if let Ok(v) = HeaderValue::from_str(&challenge) {
+            resp.headers_mut().insert(header::WWW_AUTHENTICATE, v);
+        }
+        resp
+    }
+}
+
+/// Verifies bearer tokens. Cheap to clone; a disabled `Auth` lets every request through.
+#[derive(Clone, Default)]
+pub struct Auth {
+    verifier: Option<Arc<TokenVerifier>>,
+}
+
+struct TokenVerifier {
+    issuer: Option<String>,
+    jwks: Option<Jwks>,
+    keys: RwLock<HashMap<String, VerifyingKey>>,
+}
+
+struct Jwks {
+    http: reqwest::Client,
+    url: String,
+    last_fetch: Mutex<Option<Instant>>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+}
+
+impl Auth {
+    /// Lets every request through.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Configuration:
+    /// - `AUTH_REQUIRED`: `true` to require bearer tokens (default: `false`)
+    /// - `AUTH_JWKS_URL`: the issuer's JWKS (default: `http://127.0.0.1:8002/.well-known/jwks.json`)
+    /// - `AUTH_ISSUER`: required `iss` claim (default: any)
+    pub fn from_env() -> Self {
+        if !required_from_env() {
+            tracing::error!("AUTH_REQUIRED=false: requests are not authenticated");
+            return Self::disabled();
+        }
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        let url = var("AUTH_JWKS_URL").unwrap_or_else(|| "http://127.0.0.1:8002/.well-known/jwks.json".to_string());
+        Self::jwks(url, var("AUTH_ISSUER"))
+    }
+
+    /// Verify against keys fetched from a JWKS endpoint (refetched when a token names an unknown `kid`).
+    pub fn jwks(url: String, issuer: Option<String>) -> Self {
+        let http = pagi_common::tls::client_builder()
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap_or_default();
+        Self {
+            verifier: Some(Arc::new(TokenVerifier {
+                issuer,
+                jwks: Some(Jwks {
+                    http,
+                    url,
+                    last_fetch: Mutex::new(None),
+                }),
+                keys: RwLock::new(HashMap::new()),
+            })),
+        }
+    }
+
+    /// Verify against known keys, for the issuer itself.
+    pub fn with_keys(keys: HashMap<String, VerifyingKey>, issuer: Option<String>) -> Self {
+        Self {
+            verifier: Some(Arc::new(TokenVerifier {
+                issuer,
+                jwks: None,
+                keys: RwLock::new(keys),
+            })),
+        }
+    }
+
+    /// Route layer requiring a token with `scope`; the verified [`Claims`] are added to the
+    /// request extensions (see [`Caller`]).
+    pub fn require(&self, scope: &'static str) -> RequireScope {
+        RequireScope {
+            auth: self.clone(),
+            scope,
+        }
+    }
+
+    /// Check the request's bearer token for `scope`. `Ok(None)` when authentication is disabled.
+    pub async fn authorize(&self, headers: &HeaderMap, scope: &str) -> Result<Option<Claims>, AuthError> {
+        let Some(verifier) = &self.verifier else {
+            return Ok(None);
+        };
+        let token = bearer_token(headers).ok_or(AuthError::Missing)?;
+        let claims = verifier.verify(token).await?;
+        if !claims.has_scope(scope) {
+            return Err(AuthError::Forbidden(format!("token lacks scope {scope}")));
+        }
+        Ok(Some(claims))
+    }
+
+    /// Verify a token without checking scopes.
+    pub async fn verify(&self, token: &str) -> Result<Option<Claims>, AuthError> {
+        match &self.verifier {
+            Some(verifier) => verifier.verify(token).await.map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// `AUTH_REQUIRED=true`.
+pub fn required_from_env() -> bool {
+    std::env::var("AUTH_REQUIRED")
+        .unwrap_or_else(|_| "false".to_string())
+        .to_lowercase()
+        == "true"
+}
+
+/// The token from an `Authorization: Bearer` header.
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim()).filter(|t| !t.is_empty())
+}
+
+impl TokenVerifier {
+    async fn verify(&self, token: &str) -> Result<Claims, AuthError> {
+        let invalid = |msg: &str| AuthError::Invalid(msg.to_string());
+        let mut parts = token.split('.');
+        let (Some(header_b64), Some(payload_b64), Some(sig_b64), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid("not a JWT"));
+        };
+
+        let header: Header = decode_json(header_b64).ok_or_else(|| invalid("malformed header"))?;
+        if header.alg != "EdDSA" {
+            return Err(invalid("unsupported alg (expected EdDSA)"));
+        }
+        let kid = header.kid.ok_or_else(|| invalid("missing kid"))?;
+        let key = self.key(&kid).await.ok_or_else(|| invalid("unknown signing key"))?;
+
+        let sig = URL_SAFE_NO_PAD
+            .decode(sig_b64)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or_else(|| invalid("malformed signature"))?;
+        let signing_input = &token[..header_b64.len() + 1 + payload_b64.len()];
+        key.verify(signing_input.as_bytes(), &sig)
+            .map_err(|_| invalid("signature does not verify"))?;
+
+        let claims: Claims = decode_json(payload_b64).ok_or_else(|| invalid("malformed claims"))?;
+        if claims.exp + LEEWAY_SECS < unix_now() {
+            return Err(invalid("token expired"));
+        }
+        if self.issuer.as_ref().is_some_and(|iss| *iss != claims.iss) {
+            return Err(invalid("unexpected issuer"));
+        }
+        Ok(claims)
+    }
+
+    async fn key(&self, kid: &str) -> Option<VerifyingKey> {
+        if let Some(key) = self.keys.read().await.get(kid) {
+            return Some(*key);
+        }
+        let jwks = self.jwks.as_ref()?;
+
+        let mut last_fetch = jwks.last_fetch.lock().await;
+        // Another request may have refreshed the keys while this one waited.
+        if let Some(key) = self.keys.read().await.get(kid) {
+            return Some(*key);
+        }
+        if last_fetch.is_some_and(|at| at.elapsed() < JWKS_MIN_REFRESH) {
+            return None;
+        }
+        *last_fetch = Some(Instant::now());
+        match jwks.fetch().await {
+            Ok(keys) => {
+                let key = keys.get(kid).copied();
+                *self.keys.write().await = keys;
+                key
+            }
+            Err(err) => {
+                tracing::warn!(url = %jwks.url, error = %err, "fetching JWKS failed");
+                None
+            }
+        }
+    }
+}
+
+impl Jwks {
+    async fn fetch(&self) -> Result<HashMap<String, VerifyingKey>, String> {
+        let resp = self.http.get(&self.url).send().await.map_err(|e| e.to_string())?;
+        if !resp.status().is_success() {
+            return Err(format!("JWKS endpoint returned {}", resp.status()));
+        }
+        let set: JwkSet = resp.json().await.map_err(|e| e.to_string())?;
+        Ok(set
+            .keys
+            .into_iter()
+            .filter(|k| k.kty == "OKP" && k.crv.as_deref() == Some("Ed25519"))
+            .filter_map(|k| {
+                let bytes: [u8; 32] = URL_SAFE_NO_PAD.decode(k.x?).ok()?.try_into().ok()?;
+                Some((k.kid?, VerifyingKey::from_bytes(&bytes).ok()?))
+            })
+            .collect())
+    }
+}
+
+fn decode_json<T: serde::de::DeserializeOwned>(part: &str) -> Option<T> {
+    serde_json::from_slice(&URL_SAFE_NO_PAD.decode(part).ok()?).ok()
+}
+
+/// Layer returned by [`Auth::require`].
+#[derive(Clone)]
+pub struct RequireScope {
+    auth: Auth,
+    scope: &'static str,
+}
+
+impl<S> Layer<S> for RequireScope {
+    type Service = RequireScopeService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequireScopeService {
+            inner,
+            auth: self.auth.clone(),
+            scope: self.scope,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequireScopeService<S> {
+    inner: S,
+    auth: Auth,
+    scope: &'static str,
+}
+
+impl<S> Service<Request> for RequireScopeService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request) -> Self::Future {
+        // Use the instance that was polled ready; leave a fresh clone behind.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let auth = self.auth.clone();
+        let scope = self.scope;
+        Box::pin(async move {
+            match auth.authorize(req.headers(), scope).await {
+                Ok(Some(claims)) => {
+                    req.extensions_mut().insert(claims);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    tracing::warn!(path = %req.uri().path(), error = ?err, "request not authorized");
+                    return Ok(err.into_response());
+                }
+            }
+            inner.call(req).await
+        })
+    }
+}
+
+/// The verified claims of the caller, or `None` when authentication is disabled.
+#[derive(Debug, Clone, Default)]
+pub struct Caller(pub Option<Claims>);
+
+impl Caller {
+    /// `403` unless the caller may act for `twin_id`.
+    pub fn check_twin(&self, twin_id: Uuid) -> Result<(), PagiAxumError> {
+        match &self.0 {
+            Some(claims) if !claims.allows_twin(twin_id) => Err(PagiAxumError::with_status(
+                PagiError::config(format!("token is not valid for twin {twin_id}")),
+                StatusCode::FORBIDDEN,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// `403` for twin tokens, on routes only services and operators may call.
+    pub fn check_not_twin(&self) -> Result<(), PagiAxumError> {
+        match &self.0 {
+            Some(claims) if claims.role == Role::Twin => Err(PagiAxumError::with_status(
+                PagiError::config("twin tokens may not call this route"),
+                StatusCode::FORBIDDEN,
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Caller {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Caller(parts.extensions.get::<Claims>().cloned()))
+    }
+}
+
+/// Outbound middleware: adds the process-wide service token ([`ServiceToken`]) unless the
+/// request already carries an `Authorization` header.
+pub struct AttachServiceToken;
+
+#[async_trait]
+impl Middleware for AttachServiceToken {
+    async fn handle(
+        &self,
+        mut req: reqwest::Request,
+        extensions: &mut axum::http::Extensions,
+        next: reqwest_middleware::Next<'_>,
+    ) -> reqwest_middleware::Result<reqwest::Response> {
+        if !req.headers().contains_key(header::AUTHORIZATION) {
+            match ServiceToken::global().bearer().await {
+                Ok(Some(token)) => {
+                    if let Ok(v) = HeaderValue::from_str(&format!("Bearer {token}")) {
+                        req.headers_mut().insert(header::AUTHORIZATION, v);
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => tracing::warn!(error = %err, "no service token; calling without one"),
+            }
+        }
+        let resp = next.run(req, extensions).await?;
+        if resp.status() == reqwest::StatusCode::UNAUTHORIZED {
+            ServiceToken::global().invalidate().await;
+        }
+        Ok(resp)
+    }
+}
+
+/// A [`TracedClient`] that also authenticates as this service. Only use it for calls to other
+/// PAGI services: the token would be handed to whatever host the client talks to.
+pub fn service_client() -> TracedClient {
+    ClientBuilder::new(pagi_common::tls::client())
+        .with(PropagateTrace)
+        .with(AttachServiceToken)
+        .build()
+}
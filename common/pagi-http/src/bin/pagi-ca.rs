@@ -0,0 +1,142 @@
+//! Internal CA for mutual TLS between PAGI services.
+//!
+//! ```text
+//! pagi-ca init  --dir certs
+//! pagi-ca issue --dir certs pagi-external-gateway --san localhost --san 127.0.0.1
+//! ```
+//!
+//! `issue` writes `{service}.pem` and `{service}.key`, valid for both server and client
+//! authentication, with the service name as DNS SAN (its hostname in docker-compose) and as
+//! the `spiffe://pagi/{service}` URI SAN that `pagi_http::tls` reads the peer from.
+
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use clap::{Parser, Subcommand};
+use pagi_common::tls::service_uri;
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose, SanType,
+};
+use time::{Duration, OffsetDateTime};
+
+#[derive(Parser)]
+#[command(name = "pagi-ca", about = "Issue certificates for mutual TLS between PAGI services")]
+struct Cli {
+    /// Directory holding ca.pem / ca.key and the issued certificates.
+    #[arg(long, env = "PAGI_CA_DIR", default_value = "certs")]
+    dir: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create the CA certificate and key.
+    Init {
+        #[arg(long, default_value_t = 3650)]
+        days: i64,
+        /// Replace an existing CA (invalidates every issued certificate).
+        #[arg(long)]
+        force: bool,
+    },
+    /// Issue a certificate for a service.
+    Issue {
+        /// Service name, e.g. `pagi-external-gateway`.
+        service: String,
+        /// Additional DNS names or IP addresses the service is reached at.
+        #[arg(long)]
+        san: Vec<String>,
+        #[arg(long, default_value_t = 365)]
+        days: i64,
+    },
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Init { days, force } => init(&cli.dir, days, force),
+        Command::Issue { service, san, days } => issue(&cli.dir, &service, &san, days),
+    }
+}
+
+fn init(dir: &Path, days: i64, force: bool) -> anyhow::Result<()> {
+    if dir.join("ca.key").exists() && !force {
+        bail!("{} already holds a CA; pass --force to replace it", dir.display());
+    }
+    fs::create_dir_all(dir)?;
+
+    let mut params = CertificateParams::default();
+    params.distinguished_name.push(DnType::CommonName, "PAGI internal CA");
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    set_validity(&mut params, days);
+
+    let key = KeyPair::generate()?;
+    let cert = params.self_signed(&key)?;
+    write(&dir.join("ca.pem"), &cert.pem(), false)?;
+    write(&dir.join("ca.key"), &key.serialize_pem(), true)?;
+    println!("wrote {}", dir.join("ca.pem").display());
+    Ok(())
+}
+
+fn issue(dir: &Path, service: &str, extra: &[String], days: i64) -> anyhow::Result<()> {
+    if service.is_empty() || !service.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.') {
+        bail!("invalid service name {service:?}");
+    }
+    let ca_pem = fs::read_to_string(dir.join("ca.pem")).context("reading ca.pem (run `pagi-ca init` first)")?;
+    let ca_key = KeyPair::from_pem(&fs::read_to_string(dir.join("ca.key")).context("reading ca.key")?)?;
+    // Re-signing the stored parameters with the same key reproduces the CA's subject and key
+    // identifier, which is all the issued certificate refers to.
+    let ca = CertificateParams::from_ca_cert_pem(&ca_pem)?.self_signed(&ca_key)?;
+
+    let names: Vec<String> = std::iter::once(service.to_string()).chain(extra.iter().cloned()).collect();
+    let mut params = CertificateParams::new(names)?;
+    params.subject_alt_names.push(SanType::URI(service_uri(service).try_into()?));
+    params.distinguished_name.push(DnType::CommonName, service);
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature, KeyUsagePurpose::KeyEncipherment];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth, ExtendedKeyUsagePurpose::ClientAuth];
+    params.use_authority_key_identifier_extension = true;
+    set_validity(&mut params, days);
+
+    let key = KeyPair::generate()?;
+    let cert = params.signed_by(&key, &ca, &ca_key)?;
+    let cert_path = dir.join(format!("{service}.pem"));
+    write(&cert_path, &cert.pem(), false)?;
+    write(&dir.join(format!("{service}.key")), &key.serialize_pem(), true)?;
+    println!("wrote {}", cert_path.display());
+    Ok(())
+}
+
+fn set_validity(params: &mut CertificateParams, days: i64) {
+    let now = OffsetDateTime::now_utc();
+    params.not_before = now - Duration::minutes(5);
+    params.not_after = now + Duration::days(days);
+}
+
+/// Private keys are created owner-only (`0600`), so they are never readable by others, even
+/// between writing and setting permissions.
+fn write(path: &Path, contents: &str, private: bool) -> anyhow::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+        // The mode only applies on create, so a key being replaced is removed first.
+        if let Err(e) = fs::remove_file(path) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e).with_context(|| format!("replacing {}", path.display()));
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .with_context(|| format!("writing {}", path.display()))
+}
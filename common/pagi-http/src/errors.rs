@@ -3,7 +3,7 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
-use pagi_common::{ErrorCode, PagiError};
+use pagi_common::{erasure::Unconfirmed, lifecycle::Refusal, ErrorCode, PagiError};
 use serde::Serialize;
 use time::OffsetDateTime;
 
@@ -13,6 +13,37 @@ impl From<reqwest::Error> for PagiAxumError {
     }
 }
 
+impl From<reqwest_middleware::Error> for PagiAxumError {
+    fn from(value: reqwest_middleware::Error) -> Self {
+        match value {
+            reqwest_middleware::Error::Reqwest(err) => err.into(),
+            reqwest_middleware::Error::Middleware(err) => PagiError::Unknown(err.to_string()).into(),
+        }
+    }
+}
+
+/// `403` for twins whose lifecycle status does not accept work, `503` when it could not be checked.
+impl From<Refusal> for PagiAxumError {
+    fn from(value: Refusal) -> Self {
+        let status = match value {
+            Refusal::Inactive(_) => StatusCode::FORBIDDEN,
+            Refusal::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        PagiAxumError::with_status(PagiError::config(value.to_string()), status)
+    }
+}
+
+/// `409` for deletions the identity service does not report, `503` when it could not be asked.
+impl From<Unconfirmed> for PagiAxumError {
+    fn from(value: Unconfirmed) -> Self {
+        let status = match value {
+            Unconfirmed::NotDeleted(_) => StatusCode::CONFLICT,
+            Unconfirmed::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        PagiAxumError::with_status(PagiError::config(value.to_string()), status)
+    }
+}
+
 impl From<std::io::Error> for PagiAxumError {
     fn from(value: std::io::Error) -> Self {
         PagiError::from(value).into()
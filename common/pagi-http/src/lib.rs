@@ -1,3 +1,6 @@
+pub mod auth;
 pub mod config;
 pub mod errors;
+pub mod tls;
+pub mod trace_context;
 pub mod tracing;
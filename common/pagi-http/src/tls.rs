@@ -0,0 +1,241 @@
+//! Serving over mutual TLS and identifying the calling service.
+//!
+//! Services start with [`serve`] instead of `axum::serve`. When [`TlsFiles`] are configured
+//! it terminates TLS with rustls, verifies client certificates against the internal CA and
+//! adds the caller's [`Peer`] (the service named in its certificate) to every request;
+//! otherwise it serves plain HTTP as before.
+//!
+//! Routes restricted to some services add a [`PeerAllowlist`]:
+//!
+//! ```ignore
+//! .route("/register_tool", post(register_tool).route_layer(
+//!     axum::middleware::from_fn_with_state(PeerAllowlist::from_env("REGISTER_TOOL_PEERS"), require_peer),
+//! ))
+//! ```
+
+use std::{collections::HashSet, io, sync::Arc, time::Duration};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{request::Parts, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Router,
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use pagi_common::{
+    tls::{service_from_uri, TlsFiles},
+    PagiError,
+};
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer},
+    server::WebPkiClientVerifier,
+    RootCertStore, ServerConfig,
+};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt as _;
+use x509_parser::prelude::{FromDer, GeneralName, X509Certificate};
+
+use crate::errors::PagiAxumError;
+
+/// The service that made the request, from the URI SAN of its client certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peer {
+    pub service: String,
+}
+
+/// Rejects with `401` when the request came without a client certificate (plain HTTP, or
+/// `TLS_CLIENT_AUTH=optional`). Use `Option<Peer>` to accept both.
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Peer {
+    type Rejection = PagiAxumError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts.extensions.get::<Peer>().cloned().ok_or_else(|| {
+            PagiAxumError::with_status(
+                PagiError::config("request carried no client certificate"),
+                StatusCode::UNAUTHORIZED,
+            )
+        })
+    }
+}
+
+/// Services allowed to call a route, from a comma-separated env var.
+///
+/// Unset or empty allows every caller. The list is only enforced when TLS is configured,
+/// since plain HTTP callers cannot be identified.
+#[derive(Debug, Clone, Default)]
+pub struct PeerAllowlist {
+    allowed: Option<Arc<HashSet<String>>>,
+}
+
+impl PeerAllowlist {
+    pub fn new<I: IntoIterator<Item = String>>(services: I) -> Self {
+        Self {
+            allowed: Some(Arc::new(services.into_iter().collect())),
+        }
+    }
+
+    pub fn from_env(var: &str) -> Self {
+        let services: Vec<String> = std::env::var(var)
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        if services.is_empty() {
+            return Self::default();
+        }
+        if TlsFiles::global().is_none() {
+            tracing::warn!(%var, "peer allowlist is not enforced without TLS_CERT_FILE/TLS_KEY_FILE/TLS_CA_FILE");
+            return Self::default();
+        }
+        Self::new(services)
+    }
+
+    pub fn allows(&self, peer: Option<&Peer>) -> bool {
+        match &self.allowed {
+            None => true,
+            Some(allowed) => peer.is_some_and(|p| allowed.contains(&p.service)),
+        }
+    }
+}
+
+/// Axum middleware: `403` unless the [`PeerAllowlist`] admits the calling service.
+pub async fn require_peer(State(allowlist): State<PeerAllowlist>, req: Request, next: Next) -> Response {
+    let peer = req.extensions().get::<Peer>();
+    if !allowlist.allows(peer) {
+        let caller = peer.map(|p| p.service.as_str()).unwrap_or("unidentified caller");
+        tracing::warn!(path = %req.uri().path(), %caller, "peer not allowed");
+        return PagiAxumError::with_status(
+            PagiError::config(format!("{caller} may not call {}", req.uri().path())),
+            StatusCode::FORBIDDEN,
+        )
+        .into_response();
+    }
+    next.run(req).await
+}
+
+/// rustls server configuration from [`TlsFiles`].
+///
+/// `TLS_CLIENT_AUTH`: `required` (default) rejects handshakes without a certificate signed by
+/// the internal CA; `optional` also accepts clients without one (e.g. operators with bearer
+/// tokens), which then have no [`Peer`].
+pub fn server_config(files: &TlsFiles) -> Result<ServerConfig, String> {
+    let certs = read_certs(&files.cert)?;
+    let key = read_key(&files.key)?;
+    let mut roots = RootCertStore::empty();
+    for ca in read_certs(&files.ca)? {
+        roots.add(ca).map_err(|e| format!("CA certificate: {e}"))?;
+    }
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone());
+    let verifier = match std::env::var("TLS_CLIENT_AUTH").as_deref() {
+        Ok("optional") => verifier.allow_unauthenticated(),
+        Ok("required") | Err(_) => verifier,
+        Ok(other) => return Err(format!("TLS_CLIENT_AUTH must be required or optional, got {other}")),
+    };
+    let verifier = verifier.build().map_err(|e| format!("client verifier: {e}"))?;
+
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("server certificate: {e}"))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+fn read_certs(path: &std::path::Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let pem = std::fs::read(path).map_err(|e| format!("reading {}: {e}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("parsing {}: {e}", path.display()))?;
+    if certs.is_empty() {
+        return Err(format!("no certificates in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+fn read_key(path: &std::path::Path) -> Result<PrivateKeyDer<'static>, String> {
+    let pem = std::fs::read(path).map_err(|e| format!("reading {}: {e}", path.display()))?;
+    rustls_pemfile::private_key(&mut pem.as_slice())
+        .map_err(|e| format!("parsing {}: {e}", path.display()))?
+        .ok_or_else(|| format!("no private key in {}", path.display()))
+}
+
+/// The PAGI service named in a certificate's URI SAN.
+pub fn peer_from_cert(cert: &[u8]) -> Option<Peer> {
+    let (_, cert) = X509Certificate::from_der(cert).ok()?;
+    let san = cert.subject_alternative_name().ok()??;
+    san.value.general_names.iter().find_map(|name| match name {
+        GeneralName::URI(uri) => service_from_uri(uri).map(|service| Peer {
+            service: service.to_string(),
+        }),
+        _ => None,
+    })
+}
+
+/// Serve `app` on `listener`: over mutual TLS when [`TlsFiles`] are configured, plain HTTP
+/// otherwise.
+pub async fn serve(listener: TcpListener, app: Router) -> io::Result<()> {
+    let files = TlsFiles::from_env().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let Some(files) = files else {
+        return axum::serve(listener, app).await;
+    };
+    let config = server_config(&files).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    tracing::info!(cert = %files.cert.display(), "serving over mutual TLS");
+    serve_tls(listener, app, TlsAcceptor::from(Arc::new(config))).await
+}
+
+async fn serve_tls(listener: TcpListener, app: Router, acceptor: TlsAcceptor) -> io::Result<()> {
+    loop {
+        let (tcp, remote) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                // Usually fd exhaustion; back off instead of spinning.
+                tracing::warn!(error = %e, "accept failed");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let tls = match acceptor.accept(tcp).await {
+                Ok(tls) => tls,
+                Err(e) => {
+                    tracing::debug!(%remote, error = %e, "TLS handshake failed");
+                    return;
+                }
+            };
+            let peer = tls
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(|certs| certs.first())
+                .and_then(|cert| peer_from_cert(cert));
+            let service = app.map_request(move |mut req: Request<hyper::body::Incoming>| {
+                if let Some(peer) = &peer {
+                    req.extensions_mut().insert(peer.clone());
+                }
+                req
+            });
+            let conn = auto::Builder::new(TokioExecutor::new());
+            if let Err(e) = conn
+                .serve_connection_with_upgrades(TokioIo::new(tls), TowerToHyperService::new(service))
+                .await
+            {
+                tracing::debug!(%remote, error = %e, "connection closed with error");
+            }
+        });
+    }
+}
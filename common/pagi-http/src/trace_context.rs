@@ -0,0 +1,128 @@
+//! W3C trace context propagation for inbound (axum) and outbound (reqwest) HTTP.
+//!
+//! Services add [`propagate`] as a router layer and make outgoing calls through [`client`];
+//! anything published with [`pagi_common::publish_event`] while handling the request is
+//! stamped with the same trace.
+
+use std::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request},
+    http::{request::Parts, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use pagi_common::trace_context::{TraceContext, CAUSATION_ID_HEADER, CORRELATION_ID_HEADER, TRACEPARENT_HEADER};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware};
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// reqwest client that forwards the current trace context on every request.
+pub type TracedClient = ClientWithMiddleware;
+
+/// Context for an incoming request: continues the caller's trace when it sent a valid
+/// `traceparent` (with a new local span), starts a new trace otherwise.
+pub fn from_headers(headers: &HeaderMap) -> TraceContext {
+    let mut ctx = headers
+        .get(TRACEPARENT_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(TraceContext::from_traceparent)
+        .map(|parent| parent.child())
+        .unwrap_or_else(TraceContext::new_root);
+    if let Some(id) = header_uuid(headers, CORRELATION_ID_HEADER) {
+        ctx.correlation_id = id;
+    }
+    ctx.causation_id = header_uuid(headers, CAUSATION_ID_HEADER);
+    ctx
+}
+
+fn header_uuid(headers: &HeaderMap, name: &str) -> Option<Uuid> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| Uuid::parse_str(v.trim()).ok())
+}
+
+/// Axum middleware: `.layer(axum::middleware::from_fn(pagi_http::trace_context::propagate))`.
+///
+/// Runs the handler inside a `request` span carrying the trace/correlation ids and makes
+/// the context available to [`TraceContext::current`] and the [`Trace`] extractor. The
+/// correlation id is echoed back in `X-Correlation-Id`.
+pub async fn propagate(mut req: Request, next: Next) -> Response {
+    let ctx = from_headers(req.headers());
+    let span = tracing::info_span!(
+        "request",
+        method = %req.method(),
+        path = %req.uri().path(),
+        trace_id = %ctx.trace_id_hex(),
+        span_id = %ctx.span_id_hex(),
+        correlation_id = %ctx.correlation_id,
+    );
+    req.extensions_mut().insert(ctx);
+
+    let mut resp = ctx.scope(next.run(req).instrument(span)).await;
+    if let Ok(v) = HeaderValue::from_str(&ctx.correlation_id.to_string()) {
+        resp.headers_mut().insert(CORRELATION_ID_HEADER, v);
+    }
+    resp
+}
+
+/// Extracts the request's [`TraceContext`] (from [`propagate`], or from the headers when the
+/// middleware is not installed).
+#[derive(Debug, Clone, Copy)]
+pub struct Trace(pub TraceContext);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Trace {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ctx = parts
+            .extensions
+            .get::<TraceContext>()
+            .copied()
+            .unwrap_or_else(|| from_headers(&parts.headers));
+        Ok(Trace(ctx))
+    }
+}
+
+/// Outbound half: sets `traceparent` (as a child span), `X-Correlation-Id` and
+/// `X-Causation-Id` from [`TraceContext::current`]. Headers set explicitly by the caller win.
+pub struct PropagateTrace;
+
+#[async_trait]
+impl Middleware for PropagateTrace {
+    async fn handle(
+        &self,
+        mut req: reqwest::Request,
+        extensions: &mut axum::http::Extensions,
+        next: reqwest_middleware::Next<'_>,
+    ) -> reqwest_middleware::Result<reqwest::Response> {
+        if let Some(ctx) = TraceContext::current() {
+            let headers = req.headers_mut();
+            let mut set = |name: &'static str, value: String| {
+                if let (false, Ok(v)) = (headers.contains_key(name), HeaderValue::from_str(&value)) {
+                    headers.insert(name, v);
+                }
+            };
+            set(TRACEPARENT_HEADER, ctx.child().traceparent());
+            set(CORRELATION_ID_HEADER, ctx.correlation_id.to_string());
+            if let Some(causation_id) = ctx.causation_id {
+                set(CAUSATION_ID_HEADER, causation_id.to_string());
+            }
+        }
+        next.run(req, extensions).await
+    }
+}
+
+/// A [`TracedClient`] around a default `reqwest::Client` (presenting this service's
+/// certificate when mTLS is configured, see [`pagi_common::tls`]).
+pub fn client() -> TracedClient {
+    wrap(pagi_common::tls::client())
+}
+
+/// Add trace propagation to a configured `reqwest::Client`.
+pub fn wrap(client: reqwest::Client) -> TracedClient {
+    ClientBuilder::new(client).with(PropagateTrace).build()
+}
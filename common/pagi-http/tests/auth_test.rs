@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use ed25519_dalek::{Signer, SigningKey};
+use pagi_common::auth::{unix_now, Claims, Role};
+use pagi_http::auth::{Auth, AuthError};
+use serde_json::json;
+use uuid::Uuid;
+
+const ISSUER: &str = "pagi-identity-service";
+
+fn claims(iss: &str, exp: u64) -> Claims {
+    Claims {
+        iss: iss.to_string(),
+        sub: "svc".to_string(),
+        iat: unix_now(),
+        exp,
+        jti: Uuid::new_v4(),
+        role: Role::Service,
+        twin_id: None,
+        scope: "events:read".to_string(),
+    }
+}
+
+fn token(key: &SigningKey, alg: &str, kid: &str, claims: &Claims) -> String {
+    let header = URL_SAFE_NO_PAD.encode(json!({"alg": alg, "typ": "JWT", "kid": kid}).to_string());
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).unwrap());
+    let signing_input = format!("{header}.{payload}");
+    let sig = URL_SAFE_NO_PAD.encode(key.sign(signing_input.as_bytes()).to_bytes());
+    format!("{signing_input}.{sig}")
+}
+
+fn invalid(result: Result<Option<Claims>, AuthError>) -> String {
+    match result {
+        Err(AuthError::Invalid(msg)) => msg,
+        other => panic!("expected an invalid token, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn verify_checks_signature_alg_expiry_kid_and_issuer() {
+    let key = SigningKey::from_bytes(&[3u8; 32]);
+    let auth = Auth::with_keys(HashMap::from([("k1".to_string(), key.verifying_key())]), Some(ISSUER.to_string()));
+    let fresh = claims(ISSUER, unix_now() + 300);
+
+    let verified = auth.verify(&token(&key, "EdDSA", "k1", &fresh)).await.unwrap().unwrap();
+    assert_eq!((verified.sub.as_str(), verified.jti), ("svc", fresh.jti));
+
+    let other = SigningKey::from_bytes(&[4u8; 32]);
+    assert!(invalid(auth.verify(&token(&other, "EdDSA", "k1", &fresh)).await).contains("signature"));
+
+    assert!(invalid(auth.verify(&token(&key, "HS256", "k1", &fresh)).await).contains("alg"));
+    assert!(invalid(auth.verify(&token(&key, "none", "k1", &fresh)).await).contains("alg"));
+
+    let expired = claims(ISSUER, unix_now() - 120);
+    assert!(invalid(auth.verify(&token(&key, "EdDSA", "k1", &expired)).await).contains("expired"));
+
+    assert!(invalid(auth.verify(&token(&key, "EdDSA", "k2", &fresh)).await).contains("unknown signing key"));
+
+    let foreign = claims("someone-else", unix_now() + 300);
+    assert!(invalid(auth.verify(&token(&key, "EdDSA", "k1", &foreign)).await).contains("issuer"));
+
+    assert!(invalid(auth.verify("not.a.jwt.at-all").await).contains("not a JWT"));
+}
@@ -0,0 +1,137 @@
+use std::{path::Path, sync::Arc};
+
+use pagi_common::tls::{service_uri, TlsFiles};
+use pagi_http::tls::{peer_from_cert, server_config, Peer, PeerAllowlist};
+use rcgen::{BasicConstraints, CertificateParams, CertifiedKey, IsCa, KeyPair, SanType};
+use rustls::{
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig, RootCertStore,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+use uuid::Uuid;
+
+struct Ca {
+    cert: rcgen::Certificate,
+    key: KeyPair,
+}
+
+impl Ca {
+    fn new() -> Self {
+        let mut params = CertificateParams::default();
+        params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+        let key = KeyPair::generate().unwrap();
+        Self { cert: params.self_signed(&key).unwrap(), key }
+    }
+
+    /// A certificate naming `service` as its DNS and `spiffe://pagi/...` URI SANs, as
+    /// `pagi-ca issue` writes them.
+    fn issue(&self, service: &str) -> CertifiedKey {
+        let mut params = CertificateParams::new(vec![service.to_string()]).unwrap();
+        params.subject_alt_names.push(SanType::URI(service_uri(service).try_into().unwrap()));
+        let key_pair = KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key_pair, &self.cert, &self.key).unwrap();
+        CertifiedKey { cert, key_pair }
+    }
+}
+
+fn write_files(dir: &Path, ca: &Ca, server: &CertifiedKey) -> TlsFiles {
+    std::fs::create_dir_all(dir).unwrap();
+    let files = TlsFiles {
+        cert: dir.join("server.pem"),
+        key: dir.join("server.key"),
+        ca: dir.join("ca.pem"),
+    };
+    std::fs::write(&files.cert, server.cert.pem()).unwrap();
+    std::fs::write(&files.key, server.key_pair.serialize_pem()).unwrap();
+    std::fs::write(&files.ca, ca.cert.pem()).unwrap();
+    files
+}
+
+fn client_config(ca: &Ca, client: Option<&CertifiedKey>) -> ClientConfig {
+    let mut roots = RootCertStore::empty();
+    roots.add(ca.cert.der().clone()).unwrap();
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+    match client {
+        Some(client) => builder
+            .with_client_auth_cert(
+                vec![client.cert.der().clone()],
+                PrivateKeyDer::Pkcs8(client.key_pair.serialize_der().into()),
+            )
+            .unwrap(),
+        None => builder.with_no_client_auth(),
+    }
+}
+
+/// Connect to a fresh server with `client`; the server's view of the peer, or the handshake
+/// error.
+async fn handshake(acceptor: TlsAcceptor, client: ClientConfig) -> Result<Option<Peer>, String> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        let mut tls = acceptor.accept(tcp).await.map_err(|e| e.to_string())?;
+        let peer = tls
+            .get_ref()
+            .1
+            .peer_certificates()
+            .and_then(|certs| certs.first())
+            .and_then(|cert: &CertificateDer| peer_from_cert(cert));
+        tls.write_all(b"ok").await.map_err(|e| e.to_string())?;
+        tls.shutdown().await.map_err(|e| e.to_string())?;
+        Ok::<_, String>(peer)
+    });
+
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let name = ServerName::try_from("pagi-test-server").unwrap();
+    // TLS 1.3 reports a rejected client certificate only when the client next reads.
+    let client_result = async {
+        let mut tls = TlsConnector::from(Arc::new(client)).connect(name, tcp).await?;
+        let mut reply = Vec::new();
+        tls.read_to_end(&mut reply).await?;
+        Ok::<_, std::io::Error>(reply)
+    }
+    .await;
+    let peer = server.await.unwrap()?;
+    assert_eq!(client_result.map_err(|e| e.to_string())?, b"ok");
+    Ok(peer)
+}
+
+#[tokio::test]
+async fn mutual_tls_identifies_the_calling_service() {
+    let dir = std::env::temp_dir().join(format!("pagi-tls-{}", Uuid::new_v4()));
+    let ca = Ca::new();
+    let files = write_files(&dir, &ca, &ca.issue("pagi-test-server"));
+    let acceptor = TlsAcceptor::from(Arc::new(server_config(&files).unwrap()));
+
+    let client = ca.issue("pagi-executive-engine");
+    let peer = handshake(acceptor.clone(), client_config(&ca, Some(&client))).await.unwrap();
+    assert_eq!(peer, Some(Peer { service: "pagi-executive-engine".to_string() }));
+
+    let allowlist = PeerAllowlist::new(["pagi-executive-engine".to_string()]);
+    assert!(allowlist.allows(peer.as_ref()));
+    assert!(!allowlist.allows(Some(&Peer { service: "pagi-sensor-actuator".to_string() })));
+    assert!(!allowlist.allows(None));
+    assert!(PeerAllowlist::default().allows(None));
+
+    // Client certificates are required, and must come from the internal CA.
+    assert!(handshake(acceptor.clone(), client_config(&ca, None)).await.is_err());
+    let stranger = Ca::new().issue("pagi-executive-engine");
+    assert!(handshake(acceptor, client_config(&ca, Some(&stranger))).await.is_err());
+
+    // A certificate without a PAGI URI SAN identifies no service.
+    let plain = CertificateParams::new(vec!["example.com".to_string()])
+        .unwrap()
+        .self_signed(&KeyPair::generate().unwrap())
+        .unwrap();
+    assert_eq!(peer_from_cert(plain.der()), None);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
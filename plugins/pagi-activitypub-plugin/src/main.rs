@@ -5,7 +5,7 @@ use axum::{
     routing::{get, post},
     Json, Router,
 };
-use pagi_common::TwinId;
+use pagi_common::{auth::with_service_token, TwinId};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::{
@@ -72,7 +72,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         == "true";
 
     let state = AppState {
-        http: reqwest::Client::new(),
+        http: pagi_common::tls::client(),
         external_gateway_url,
         plugin_url,
         actor_id,
@@ -107,7 +107,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 9070).into());
     info!(%addr, "pagi-activitypub-plugin listening");
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    pagi_http::tls::serve(listener, app).await?;
     Ok(())
 }
 
@@ -154,10 +154,8 @@ async fn register_tools_with_gateway(state: &AppState) -> Result<(), String> {
 
     for tool in tools {
         let payload = GatewayRegisterPayload { twin_id: None, tool };
-        state
-            .http
-            .post(&register_url)
-            .json(&payload)
+        with_service_token(state.http.post(&register_url).json(&payload))
+            .await?
             .send()
             .await
             .map_err(|e| e.to_string())?
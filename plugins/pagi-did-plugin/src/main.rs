@@ -7,13 +7,14 @@ use axum::{
 };
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use multibase::Base;
-use pagi_common::{PagiError, TwinId};
+use pagi_common::{auth::with_service_token, did::DidResolver, keystore::Keyring, PagiError, TwinId};
 use pagi_http::errors::PagiAxumError;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::{
     net::SocketAddr,
     path::{Path, PathBuf},
+    sync::Arc,
 };
 use tracing::{error, info};
 use uuid::Uuid;
@@ -24,6 +25,8 @@ struct AppState {
     external_gateway_url: String,
     plugin_url: String,
     identity_keys_dir: PathBuf,
+    keyring: Arc<Keyring>,
+    did_resolver: DidResolver,
 }
 
 #[tokio::main]
@@ -37,11 +40,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .map(PathBuf::from)
         .unwrap_or_else(|_| PathBuf::from("/data/identity/keys"));
 
+    let http = pagi_common::tls::client();
     let state = AppState {
-        http: reqwest::Client::new(),
+        http: http.clone(),
         external_gateway_url,
         plugin_url,
         identity_keys_dir,
+        keyring: Arc::new(Keyring::from_env()?),
+        did_resolver: DidResolver::from_env(http),
     };
 
     // Best-effort: register tools with ExternalGateway on startup.
@@ -62,7 +68,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 9020).into());
     info!(%addr, "pagi-did-plugin listening");
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    pagi_http::tls::serve(listener, app).await?;
     Ok(())
 }
 
@@ -119,10 +125,8 @@ async fn register_tools_with_gateway(state: &AppState) -> Result<(), String> {
 
     for tool in tools {
         let payload = GatewayRegisterPayload { twin_id: None, tool };
-        state
-            .http
-            .post(&register_url)
-            .json(&payload)
+        with_service_token(state.http.post(&register_url).json(&payload))
+            .await?
             .send()
             .await
             .map_err(|e| e.to_string())?
@@ -148,7 +152,7 @@ struct SignResponse {
 }
 
 async fn sign_artifact(State(state): State<AppState>, Json(req): Json<SignRequest>) -> impl IntoResponse {
-    match sign_with_twin_key(&state.identity_keys_dir, req.twin_id, &req.artifact) {
+    match sign_with_twin_key(&state.did_resolver, &state.keyring, &state.identity_keys_dir, req.twin_id, &req.artifact).await {
         Ok((did, signature)) => (StatusCode::OK, Json(SignResponse { did, signature, artifact: req.artifact })).into_response(),
         Err(err) => PagiAxumError::with_status(PagiError::config(err), StatusCode::BAD_REQUEST).into_response(),
     }
@@ -166,55 +170,41 @@ struct VerifyResponse {
     pub valid: bool,
 }
 
-async fn verify_artifact(Json(req): Json<VerifyRequest>) -> impl IntoResponse {
-    match verify_with_did_key(&req.did, &req.signature, &req.artifact) {
+async fn verify_artifact(State(state): State<AppState>, Json(req): Json<VerifyRequest>) -> impl IntoResponse {
+    match verify_with_did(&state.did_resolver, &req.did, &req.signature, &req.artifact).await {
         Ok(valid) => (StatusCode::OK, Json(VerifyResponse { valid })).into_response(),
         Err(err) => PagiAxumError::with_status(PagiError::config(err), StatusCode::BAD_REQUEST).into_response(),
     }
 }
 
-fn read_signing_key(identity_keys_dir: &Path, twin_id: Uuid) -> Result<SigningKey, String> {
-    let key_path = identity_keys_dir.join(format!("{twin_id}.ed25519"));
-    let raw = std::fs::read(&key_path).map_err(|e| format!("failed to read key {key_path:?}: {e}"))?;
-    if raw.len() != 32 {
-        return Err(format!("invalid key length: expected 32 bytes, got {}", raw.len()));
-    }
-    let mut sk = [0u8; 32];
-    sk.copy_from_slice(&raw);
+/// Key files are written by the identity service, sealed under `IDENTITY_KEK` when set.
+fn read_signing_key(keyring: &Keyring, identity_keys_dir: &Path, twin_id: Uuid) -> Result<SigningKey, String> {
+    let sk = keyring.read_key(identity_keys_dir, twin_id)?;
     Ok(SigningKey::from_bytes(&sk))
 }
 
-fn did_from_public_key(public_key: &VerifyingKey) -> String {
-    let pub_bytes = public_key.to_bytes();
-    let mut codec_and_key = Vec::with_capacity(2 + pub_bytes.len());
-    codec_and_key.push(0xed);
-    codec_and_key.push(0x01);
-    codec_and_key.extend_from_slice(&pub_bytes);
-    let method_id = multibase::encode(Base::Base58Btc, codec_and_key);
-    format!("did:key:{method_id}")
-}
-
-fn verifying_key_from_did(did: &str) -> Result<VerifyingKey, String> {
-    let method_id = did
-        .strip_prefix("did:key:")
-        .ok_or_else(|| "did must start with did:key:".to_string())?;
-
-    let (_base, bytes) = multibase::decode(method_id).map_err(|e| format!("multibase decode failed: {e}"))?;
-    if bytes.len() != 2 + 32 {
-        return Err(format!("unexpected did:key decoded length: {}", bytes.len()));
-    }
-    if bytes[0] != 0xed || bytes[1] != 0x01 {
-        return Err("unsupported key type (expected ed25519-pub multicodec 0xed01)".to_string());
-    }
-    let mut pk = [0u8; 32];
-    pk.copy_from_slice(&bytes[2..]);
-    VerifyingKey::from_bytes(&pk).map_err(|e| e.to_string())
-}
-
-fn sign_with_twin_key(identity_keys_dir: &Path, twin_id: Uuid, artifact: &serde_json::Value) -> Result<(String, String), String> {
-    let signing_key = read_signing_key(identity_keys_dir, twin_id)?;
-    let verifying_key = signing_key.verifying_key();
-    let did = did_from_public_key(&verifying_key);
+/// Keys `did` can verify with: decoded from a `did:key`, or the current assertion keys (or
+/// the `#fragment` one) in a `did:web` document.
+async fn verifying_keys_from_did(resolver: &DidResolver, did: &str) -> Result<Vec<VerifyingKey>, String> {
+    resolver
+        .ed25519_keys(did)
+        .await?
+        .iter()
+        .map(|pk| VerifyingKey::from_bytes(pk).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Sign as the twin's registered DID (its `did:web` once it has one), so the signature
+/// verifies against the DID the twin is known by.
+async fn sign_with_twin_key(
+    resolver: &DidResolver,
+    keyring: &Keyring,
+    identity_keys_dir: &Path,
+    twin_id: Uuid,
+    artifact: &serde_json::Value,
+) -> Result<(String, String), String> {
+    let signing_key = read_signing_key(keyring, identity_keys_dir, twin_id)?;
+    let did = resolver.signing_did(twin_id, signing_key.verifying_key().as_bytes()).await?.did;
 
     let msg = serde_json::to_vec(artifact).map_err(|e| e.to_string())?;
     let sig: Signature = signing_key.sign(&msg);
@@ -222,10 +212,10 @@ fn sign_with_twin_key(identity_keys_dir: &Path, twin_id: Uuid, artifact: &serde_
     Ok((did, signature))
 }
 
-fn verify_with_did_key(did: &str, signature: &str, artifact: &serde_json::Value) -> Result<bool, String> {
-    let verifying_key = verifying_key_from_did(did)?;
+async fn verify_with_did(resolver: &DidResolver, did: &str, signature: &str, artifact: &serde_json::Value) -> Result<bool, String> {
+    let verifying_keys = verifying_keys_from_did(resolver, did).await?;
     let (_base, sig_bytes) = multibase::decode(signature).map_err(|e| format!("signature multibase decode failed: {e}"))?;
     let sig = Signature::from_slice(&sig_bytes).map_err(|e| e.to_string())?;
     let msg = serde_json::to_vec(artifact).map_err(|e| e.to_string())?;
-    Ok(verifying_key.verify(&msg, &sig).is_ok())
+    Ok(verifying_keys.iter().any(|k| k.verify(&msg, &sig).is_ok()))
 }
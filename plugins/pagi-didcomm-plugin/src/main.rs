@@ -8,12 +8,18 @@ use axum::{
 use base64::Engine;
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use multibase::Base;
-use pagi_common::{PagiError, TwinId};
+use pagi_common::{
+    auth::with_service_token,
+    did::DidResolver,
+    erasure::{DeletionConfirmer, PurgeReport, PurgeTrigger, Retention, TwinDeletion},
+    keystore::Keyring,
+    publish_event, EventEnvelope, Outbox, PagiError, TwinId,
+};
 use pagi_http::errors::PagiAxumError;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     net::SocketAddr,
     path::{Path, PathBuf},
     sync::Arc,
@@ -29,7 +35,10 @@ struct AppState {
     external_gateway_url: String,
     plugin_url: String,
     identity_keys_dir: PathBuf,
+    keyring: Arc<Keyring>,
+    did_resolver: DidResolver,
     mailbox: Mailbox,
+    deletions: Arc<DeletionConfirmer>,
 }
 
 #[derive(Clone)]
@@ -60,6 +69,13 @@ impl Mailbox {
 
     async fn put(&self, did: &str, mut msg: SignedMessage) {
         msg.relay_received_at = Some(time::OffsetDateTime::now_utc().unix_timestamp());
+        self.append(did, msg).await;
+    }
+
+    /// Store a message as is (keeping its `relay_received_at`, or stamping it now when it has
+    /// none, so retention applies to it).
+    async fn append(&self, did: &str, mut msg: SignedMessage) {
+        msg.relay_received_at.get_or_insert_with(|| time::OffsetDateTime::now_utc().unix_timestamp());
         {
             let mut mem = self.mem.write().await;
             let q = mem.entry(did.to_string()).or_default();
@@ -106,6 +122,107 @@ impl Mailbox {
         }
     }
 
+    /// Pending messages for `did`, left in place (for twin export).
+    async fn peek(&self, did: &str) -> Vec<SignedMessage> {
+        let mut out = self.mem.read().await.get(did).cloned().unwrap_or_default();
+        if let Some(dir) = self.dir.as_ref() {
+            let _guard = self.file_lock.lock().await;
+            let path = dir.join(format!("{}.jsonl", Self::did_to_filename(did)));
+            if let Ok(text) = tokio::fs::read_to_string(&path).await {
+                out.extend(text.lines().filter_map(|line| serde_json::from_str::<SignedMessage>(line).ok()));
+            }
+        }
+        // Persisted messages are also held in memory until the next restart.
+        let mut seen = HashSet::new();
+        out.retain(|m| seen.insert(m.id.clone()));
+        out
+    }
+
+    /// Remove every pending message for `did`, in memory and on disk; returns how many there
+    /// were. Unlike [`Mailbox::take_all`], failing to delete the file is an error.
+    async fn purge(&self, did: &str) -> Result<u64, String> {
+        let mut ids: HashSet<String> = {
+            let mut mem = self.mem.write().await;
+            mem.remove(did).unwrap_or_default().into_iter().map(|m| m.id).collect()
+        };
+        if let Some(dir) = self.dir.as_ref() {
+            let _guard = self.file_lock.lock().await;
+            let path = dir.join(format!("{}.jsonl", Self::did_to_filename(did)));
+            match tokio::fs::read_to_string(&path).await {
+                Ok(text) => {
+                    ids.extend(text.lines().filter_map(|line| serde_json::from_str::<SignedMessage>(line).ok()).map(|m| m.id));
+                    tokio::fs::remove_file(&path)
+                        .await
+                        .map_err(|e| format!("removing {}: {e}", path.display()))?;
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(format!("reading {}: {err}", path.display())),
+            }
+        }
+        Ok(ids.len() as u64)
+    }
+
+    /// Drop messages received before `cutoff` (Unix seconds); returns how many were dropped.
+    async fn expire(&self, cutoff: i64) -> Result<u64, String> {
+        let expired = |m: &SignedMessage| m.relay_received_at.is_some_and(|at| at < cutoff);
+        let mut ids = HashSet::new();
+        {
+            let mut mem = self.mem.write().await;
+            for queue in mem.values_mut() {
+                ids.extend(queue.iter().filter(|m| expired(m)).map(|m| m.id.clone()));
+                queue.retain(|m| !expired(m));
+            }
+            mem.retain(|_, queue| !queue.is_empty());
+        }
+
+        let Some(dir) = self.dir.as_ref() else {
+            return Ok(ids.len() as u64);
+        };
+        let _guard = self.file_lock.lock().await;
+        let mut entries = match tokio::fs::read_dir(dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(ids.len() as u64),
+            Err(err) => return Err(format!("reading {}: {err}", dir.display())),
+        };
+        while let Some(entry) = entries.next_entry().await.map_err(|e| e.to_string())? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("jsonl") {
+                continue;
+            }
+            let text = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|e| format!("reading {}: {e}", path.display()))?;
+            let mut kept = String::new();
+            let mut dropped = false;
+            for line in text.lines().filter(|l| !l.trim().is_empty()) {
+                match serde_json::from_str::<SignedMessage>(line) {
+                    Ok(m) if expired(&m) => {
+                        ids.insert(m.id);
+                        dropped = true;
+                    }
+                    _ => {
+                        kept.push_str(line);
+                        kept.push('\n');
+                    }
+                }
+            }
+            if !dropped {
+                continue;
+            }
+            let result = if kept.is_empty() {
+                tokio::fs::remove_file(&path).await
+            } else {
+                let tmp = path.with_extension("jsonl.tmp");
+                match tokio::fs::write(&tmp, kept).await {
+                    Ok(()) => tokio::fs::rename(&tmp, &path).await,
+                    Err(err) => Err(err),
+                }
+            };
+            result.map_err(|e| format!("rewriting {}: {e}", path.display()))?;
+        }
+        Ok(ids.len() as u64)
+    }
+
     async fn take_all(&self, did: &str) -> Vec<SignedMessage> {
         let mut out = {
             let mut mem = self.mem.write().await;
@@ -158,14 +275,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .and_then(|v| v.parse::<usize>().ok())
         .unwrap_or(10_000);
 
+    // Pending messages older than this are dropped, also from relays that were never polled.
+    let retention = Retention::from_env("DIDCOMM_MAILBOX_MAX_AGE_SECS")?;
+
+    let http = pagi_common::tls::client();
     let state = AppState {
-        http: reqwest::Client::new(),
+        http: http.clone(),
         external_gateway_url,
         plugin_url,
         identity_keys_dir,
+        keyring: Arc::new(Keyring::from_env()?),
+        did_resolver: DidResolver::from_env(http),
         mailbox: Mailbox::new(mailbox_dir, max_per_did),
+        deletions: Arc::new(DeletionConfirmer::from_env()),
     };
 
+    let mailbox = state.mailbox.clone();
+    retention.spawn(move |cutoff| {
+        let mailbox = mailbox.clone();
+        async move {
+            match mailbox.expire(cutoff.unix_timestamp()).await {
+                Ok(0) => {}
+                Ok(expired) => info!(expired, "expired mailbox messages removed"),
+                Err(err) => error!(error = %err, "mailbox retention sweep failed"),
+            }
+        }
+    });
+
     // Best-effort: register tools with ExternalGateway on startup.
     let st = state.clone();
     tokio::spawn(async move {
@@ -181,6 +317,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .route("/send_with_relay", post(send_message_with_relay))
         .route("/poll_relay", post(poll_relay))
         .route("/inbox", post(get_inbox))
+        // Twin export/import (called by the identity service)
+        .route("/mailbox/export", post(export_mailbox))
+        .route("/mailbox/import", post(import_mailbox))
+        // Twin deletion (delivered by the identity service)
+        .route("/events", post(handle_event))
         // Public receive endpoint for peers
         .route("/receive", post(receive_message))
         .with_state(state)
@@ -189,7 +330,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 9030).into());
     info!(%addr, "pagi-didcomm-plugin listening");
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    pagi_http::tls::serve(listener, app).await?;
     Ok(())
 }
 
@@ -277,10 +418,8 @@ async fn register_tools_with_gateway(state: &AppState) -> Result<(), String> {
 
     for tool in tools {
         let payload = GatewayRegisterPayload { twin_id: None, tool };
-        state
-            .http
-            .post(&register_url)
-            .json(&payload)
+        with_service_token(state.http.post(&register_url).json(&payload))
+            .await?
             .send()
             .await
             .map_err(|e| e.to_string())?
@@ -317,7 +456,16 @@ struct SendRequest {
 }
 
 async fn send_message(State(state): State<AppState>, Json(req): Json<SendRequest>) -> impl IntoResponse {
-    let (from_did, signature) = match sign_payload(&state.identity_keys_dir, req.from_twin_id, &req.to_did, &req.msg_type, &req.body)
+    let (from_did, signature) = match sign_payload(
+        &state.did_resolver,
+        &state.keyring,
+        &state.identity_keys_dir,
+        req.from_twin_id,
+        &req.to_did,
+        &req.msg_type,
+        &req.body,
+    )
+    .await
     {
         Ok(v) => v,
         Err(e) => {
@@ -365,12 +513,16 @@ async fn send_message_with_relay(
     Json(req): Json<SendWithRelayRequest>,
 ) -> impl IntoResponse {
     let (from_did, signature) = match sign_payload(
+        &state.did_resolver,
+        &state.keyring,
         &state.identity_keys_dir,
         req.from_twin_id,
         &req.to_did,
         &req.msg_type,
         &req.body,
-    ) {
+    )
+    .await
+    {
         Ok(v) => v,
         Err(e) => {
             return PagiAxumError::with_status(PagiError::config(e), StatusCode::BAD_REQUEST).into_response();
@@ -418,7 +570,7 @@ async fn receive_message(State(state): State<AppState>, Json(msg): Json<SignedMe
         return PagiAxumError::with_status(PagiError::config("to_did required"), StatusCode::BAD_REQUEST).into_response();
     }
 
-    match verify_payload(&msg) {
+    match verify_payload(&state.did_resolver, &msg).await {
         Ok(true) => {
             let did = msg.to_did.clone();
             state.mailbox.put(&did, msg).await;
@@ -439,6 +591,73 @@ async fn get_inbox(State(state): State<AppState>, Json(req): Json<InboxRequest>)
     Json(json!({"did": req.did, "messages": msgs})).into_response()
 }
 
+#[derive(Debug, Deserialize)]
+struct MailboxExportRequest {
+    pub dids: Vec<String>,
+}
+
+/// Mailbox contents by recipient DID, as carried in a twin bundle.
+#[derive(Debug, Serialize, Deserialize)]
+struct MailboxSection {
+    pub messages: HashMap<String, Vec<SignedMessage>>,
+}
+
+/// `POST /mailbox/export`: pending messages for a twin's DIDs, without taking them.
+async fn export_mailbox(State(state): State<AppState>, Json(req): Json<MailboxExportRequest>) -> impl IntoResponse {
+    let mut messages = HashMap::new();
+    for did in req.dids {
+        let pending = state.mailbox.peek(&did).await;
+        if !pending.is_empty() {
+            messages.insert(did, pending);
+        }
+    }
+    Json(MailboxSection { messages })
+}
+
+/// `POST /mailbox/import`: queue messages exported from another node.
+async fn import_mailbox(State(state): State<AppState>, Json(section): Json<MailboxSection>) -> impl IntoResponse {
+    let mut imported = 0usize;
+    for (did, msgs) in section.messages {
+        for msg in msgs {
+            state.mailbox.append(&did, msg).await;
+            imported += 1;
+        }
+    }
+    info!(imported, "mailbox messages imported");
+    Json(json!({ "imported": imported }))
+}
+
+/// `POST /events`: drop pending messages for every DID of a twin on `twin_deleted`,
+/// answering with the purge report. Other events are ignored (`204`). The twin's DIDs are the
+/// ones the identity service lists, not the event's.
+async fn handle_event(State(state): State<AppState>, Json(ev): Json<EventEnvelope>) -> impl IntoResponse {
+    let Some(deletion) = TwinDeletion::from_event(&ev) else {
+        return StatusCode::NO_CONTENT.into_response();
+    };
+    let deletion = match state.deletions.confirm(&deletion).await {
+        Ok(deletion) => deletion,
+        Err(err) => return PagiAxumError::from(err).into_response(),
+    };
+    let mut report = PurgeReport::new("pagi-didcomm-plugin", deletion.twin_id, PurgeTrigger::TwinDeleted);
+    for did in &deletion.dids {
+        match state.mailbox.purge(did).await {
+            Ok(count) => report = report.with("messages", count),
+            Err(err) => {
+                error!(twin_id = %deletion.twin_id, %did, error = %err, "mailbox purge failed");
+                return PagiAxumError::with_status(PagiError::plugin_exec(err), StatusCode::INTERNAL_SERVER_ERROR)
+                    .into_response();
+            }
+        }
+    }
+    match Outbox::global().purge_twin(deletion.twin_id).await {
+        Ok(count) => report = report.with("outbox_events", count as u64),
+        Err(err) => return PagiAxumError::from(err).into_response(),
+    }
+    info!(twin_id = %deletion.twin_id, messages = report.total(), "mailbox purged for deleted twin");
+    publish_event(report.event());
+    Json(report).into_response()
+}
+
 #[derive(Debug, Deserialize)]
 struct PollRelayRequest {
     pub did: String,
@@ -463,41 +682,21 @@ async fn poll_relay(State(state): State<AppState>, Json(req): Json<PollRelayRequ
     }
 }
 
-fn read_signing_key(identity_keys_dir: &Path, twin_id: Uuid) -> Result<SigningKey, String> {
-    let key_path = identity_keys_dir.join(format!("{twin_id}.ed25519"));
-    let raw = std::fs::read(&key_path).map_err(|e| format!("failed to read key {key_path:?}: {e}"))?;
-    if raw.len() != 32 {
-        return Err(format!("invalid key length: expected 32 bytes, got {}", raw.len()));
-    }
-    let mut sk = [0u8; 32];
-    sk.copy_from_slice(&raw);
+/// Key files are written by the identity service, sealed under `IDENTITY_KEK` when set.
+fn read_signing_key(keyring: &Keyring, identity_keys_dir: &Path, twin_id: Uuid) -> Result<SigningKey, String> {
+    let sk = keyring.read_key(identity_keys_dir, twin_id)?;
     Ok(SigningKey::from_bytes(&sk))
 }
 
-fn did_from_public_key(public_key: &VerifyingKey) -> String {
-    let pub_bytes = public_key.to_bytes();
-    let mut codec_and_key = Vec::with_capacity(2 + pub_bytes.len());
-    codec_and_key.push(0xed);
-    codec_and_key.push(0x01);
-    codec_and_key.extend_from_slice(&pub_bytes);
-    let method_id = multibase::encode(Base::Base58Btc, codec_and_key);
-    format!("did:key:{method_id}")
-}
-
-fn verifying_key_from_did(did: &str) -> Result<VerifyingKey, String> {
-    let method_id = did
-        .strip_prefix("did:key:")
-        .ok_or_else(|| "did must start with did:key:".to_string())?;
-    let (_base, bytes) = multibase::decode(method_id).map_err(|e| format!("multibase decode failed: {e}"))?;
-    if bytes.len() != 2 + 32 {
-        return Err(format!("unexpected did:key decoded length: {}", bytes.len()));
-    }
-    if bytes[0] != 0xed || bytes[1] != 0x01 {
-        return Err("unsupported key type (expected ed25519-pub multicodec 0xed01)".to_string());
-    }
-    let mut pk = [0u8; 32];
-    pk.copy_from_slice(&bytes[2..]);
-    VerifyingKey::from_bytes(&pk).map_err(|e| e.to_string())
+/// Keys `did` can verify with: decoded from a `did:key`, or the current assertion keys (or
+/// the `#fragment` one) in a `did:web` document.
+async fn verifying_keys_from_did(resolver: &DidResolver, did: &str) -> Result<Vec<VerifyingKey>, String> {
+    resolver
+        .ed25519_keys(did)
+        .await?
+        .iter()
+        .map(|pk| VerifyingKey::from_bytes(pk).map_err(|e| e.to_string()))
+        .collect()
 }
 
 fn unsigned_payload(from_did: &str, to_did: &str, msg_type: &str, body: &Value) -> Result<Vec<u8>, String> {
@@ -510,26 +709,29 @@ fn unsigned_payload(from_did: &str, to_did: &str, msg_type: &str, body: &Value)
     .map_err(|e| e.to_string())
 }
 
-fn sign_payload(
+/// Sign as the sending twin's registered DID (its `did:web` once it has one), so receivers
+/// resolve the key the twin is known by.
+async fn sign_payload(
+    resolver: &DidResolver,
+    keyring: &Keyring,
     identity_keys_dir: &Path,
     from_twin_id: Uuid,
     to_did: &str,
     msg_type: &str,
     body: &Value,
 ) -> Result<(String, String), String> {
-    let signing_key = read_signing_key(identity_keys_dir, from_twin_id)?;
-    let verifying_key = signing_key.verifying_key();
-    let from_did = did_from_public_key(&verifying_key);
+    let signing_key = read_signing_key(keyring, identity_keys_dir, from_twin_id)?;
+    let from_did = resolver.signing_did(from_twin_id, signing_key.verifying_key().as_bytes()).await?.did;
     let bytes = unsigned_payload(&from_did, to_did, msg_type, body)?;
     let sig: Signature = signing_key.sign(&bytes);
     let signature = multibase::encode(Base::Base64Url, sig.to_bytes());
     Ok((from_did, signature))
 }
 
-fn verify_payload(msg: &SignedMessage) -> Result<bool, String> {
-    let verifying_key = verifying_key_from_did(&msg.from_did)?;
+async fn verify_payload(resolver: &DidResolver, msg: &SignedMessage) -> Result<bool, String> {
+    let verifying_keys = verifying_keys_from_did(resolver, &msg.from_did).await?;
     let bytes = unsigned_payload(&msg.from_did, &msg.to_did, &msg.msg_type, &msg.body)?;
     let (_base, sig_bytes) = multibase::decode(&msg.signature).map_err(|e| format!("signature multibase decode failed: {e}"))?;
     let sig = Signature::from_slice(&sig_bytes).map_err(|e| e.to_string())?;
-    Ok(verifying_key.verify(&bytes, &sig).is_ok())
+    Ok(verifying_keys.iter().any(|k| k.verify(&bytes, &sig).is_ok()))
 }
@@ -4,7 +4,7 @@ use axum::{
     routing::{get, post},
     Router,
 };
-use pagi_common::{PagiError, TwinId};
+use pagi_common::{auth::with_service_token, PagiError, TwinId};
 use pagi_http::errors::PagiAxumError;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -59,7 +59,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let lotus_token = std::env::var("LOTUS_TOKEN").ok();
 
     let state = AppState {
-        http: reqwest::Client::new(),
+        http: pagi_common::tls::client(),
         external_gateway_url,
         plugin_url,
         lotus_rpc_url,
@@ -87,7 +87,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 8097).into());
     info!(%addr, "pagi-filecoin-plugin listening");
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    pagi_http::tls::serve(listener, app).await?;
     Ok(())
 }
 
@@ -127,10 +127,8 @@ async fn register_tools_with_gateway(state: &AppState) -> Result<(), String> {
 
     for tool in tools {
         let payload = GatewayRegisterPayload { twin_id: None, tool };
-        state
-            .http
-            .post(&register_url)
-            .json(&payload)
+        with_service_token(state.http.post(&register_url).json(&payload))
+            .await?
             .send()
             .await
             .map_err(|e| e.to_string())?
@@ -6,7 +6,7 @@ use axum::{
     Json, Router,
 };
 use git2::{build::CheckoutBuilder, Cred, FetchOptions, PushOptions, RemoteCallbacks, Repository, Signature};
-use pagi_common::{PagiError, Playbook, RefinementArtifact, TwinId};
+use pagi_common::{auth::with_service_token, PagiError, Playbook, RefinementArtifact, TwinId};
 use pagi_http::errors::PagiAxumError;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -56,7 +56,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     let state = AppState {
-        http: reqwest::Client::new(),
+        http: pagi_common::tls::client(),
         external_gateway_url,
         plugin_url,
         git,
@@ -80,7 +80,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 9050).into());
     info!(%addr, "pagi-hive-sync-plugin listening");
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    pagi_http::tls::serve(listener, app).await?;
     Ok(())
 }
 
@@ -130,10 +130,8 @@ async fn register_tools_with_gateway(state: &AppState) -> Result<(), String> {
 
     for tool in tools {
         let payload = GatewayRegisterPayload { twin_id: None, tool };
-        state
-            .http
-            .post(&register_url)
-            .json(&payload)
+        with_service_token(state.http.post(&register_url).json(&payload))
+            .await?
             .send()
             .await
             .map_err(|e| e.to_string())?
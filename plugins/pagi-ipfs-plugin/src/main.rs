@@ -4,7 +4,7 @@ use axum::{
     Router,
 };
 use base64::Engine;
-use pagi_common::{PagiError, TwinId};
+use pagi_common::{auth::with_service_token, PagiError, TwinId};
 use pagi_http::errors::PagiAxumError;
 use reqwest::multipart;
 use serde::{Deserialize, Serialize};
@@ -67,7 +67,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let ipfs_api_url = std::env::var("IPFS_API_URL").unwrap_or_else(|_| "http://127.0.0.1:5001".to_string());
 
     let state = AppState {
-        http: reqwest::Client::new(),
+        http: pagi_common::tls::client(),
         external_gateway_url,
         plugin_url,
         ipfs_api_url,
@@ -109,7 +109,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 8096).into());
     info!(%addr, "pagi-ipfs-plugin listening");
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    pagi_http::tls::serve(listener, app).await?;
     Ok(())
 }
 
@@ -206,10 +206,8 @@ async fn register_tools_with_gateway(state: &AppState) -> Result<(), String> {
 
     for tool in tools {
         let payload = GatewayRegisterPayload { twin_id: None, tool };
-        state
-            .http
-            .post(&register_url)
-            .json(&payload)
+        with_service_token(state.http.post(&register_url).json(&payload))
+            .await?
             .send()
             .await
             .map_err(|e| e.to_string())?
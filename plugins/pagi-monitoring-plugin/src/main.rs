@@ -5,6 +5,7 @@ use axum::{
     Json, Router,
 };
 use clap::Parser;
+use pagi_common::auth::with_service_token;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
@@ -63,7 +64,7 @@ async fn main() -> anyhow::Result<()> {
     let plugin_id = format!("pagi-monitoring-plugin-{}", Uuid::new_v4());
 
     let state = PluginState {
-        client: Client::new(),
+        client: pagi_common::tls::client(),
         plugin_id: plugin_id.clone(),
         external_gateway_url: args.external_gateway_url.clone(),
         plugin_public_url: args.plugin_public_url.clone(),
@@ -82,7 +83,7 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!(%addr, plugin_id = %plugin_id, "PAGI Monitoring Plugin listening");
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    pagi_http::tls::serve(listener, app).await?;
     Ok(())
 }
 
@@ -171,7 +172,11 @@ async fn register_tools(state: &PluginState) -> anyhow::Result<()> {
             tool: tool.clone(),
         };
 
-        let resp = state.client.post(&url).json(&payload).send().await?;
+        let resp = with_service_token(state.client.post(&url).json(&payload))
+            .await
+            .map_err(anyhow::Error::msg)?
+            .send()
+            .await?;
         if !resp.status().is_success() {
             let body = resp.text().await.unwrap_or_default();
             anyhow::bail!("tool registration failed for '{}': {}", tool.name, body);
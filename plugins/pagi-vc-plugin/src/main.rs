@@ -7,14 +7,16 @@ use axum::{
 };
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use multibase::Base;
-use pagi_common::PagiError;
+use pagi_common::{auth::with_service_token, did::DidResolver, keystore::Keyring, PagiError};
 use pagi_http::errors::PagiAxumError;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::{
     net::SocketAddr,
     path::{Path, PathBuf},
+    sync::Arc,
 };
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 use tracing::{error, info};
 use uuid::Uuid;
 
@@ -24,6 +26,8 @@ struct AppState {
     external_gateway_url: String,
     plugin_url: String,
     identity_keys_dir: PathBuf,
+    keyring: Arc<Keyring>,
+    did_resolver: DidResolver,
 }
 
 #[tokio::main]
@@ -37,11 +41,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .map(PathBuf::from)
         .unwrap_or_else(|_| PathBuf::from("/data/identity/keys"));
 
+    let http = pagi_common::tls::client();
     let state = AppState {
-        http: reqwest::Client::new(),
+        http: http.clone(),
         external_gateway_url,
         plugin_url,
         identity_keys_dir,
+        keyring: Arc::new(Keyring::from_env()?),
+        did_resolver: DidResolver::from_env(http),
     };
 
     // Best-effort: register tools with ExternalGateway on startup.
@@ -62,7 +69,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 9040).into());
     info!(%addr, "pagi-vc-plugin listening");
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    pagi_http::tls::serve(listener, app).await?;
     Ok(())
 }
 
@@ -117,10 +124,8 @@ async fn register_tools_with_gateway(state: &AppState) -> Result<(), String> {
 
     for tool in tools {
         let payload = GatewayRegisterPayload { twin_id: None, tool };
-        state
-            .http
-            .post(&register_url)
-            .json(&payload)
+        with_service_token(state.http.post(&register_url).json(&payload))
+            .await?
             .send()
             .await
             .map_err(|e| e.to_string())?
@@ -141,7 +146,7 @@ struct IssueReputationRequest {
 }
 
 async fn issue_reputation_vc(State(state): State<AppState>, Json(req): Json<IssueReputationRequest>) -> impl IntoResponse {
-    match issue_reputation_credential(&state.identity_keys_dir, &req) {
+    match issue_reputation_credential(&state.did_resolver, &state.keyring, &state.identity_keys_dir, &req).await {
         Ok(vc) => (StatusCode::OK, Json(vc)).into_response(),
         Err(err) => PagiAxumError::with_status(PagiError::config(err), StatusCode::BAD_REQUEST).into_response(),
     }
@@ -152,58 +157,49 @@ struct VerifyVcRequest {
     pub credential: Value,
 }
 
-async fn verify_vc(Json(req): Json<VerifyVcRequest>) -> impl IntoResponse {
-    match verify_credential(&req.credential) {
+async fn verify_vc(State(state): State<AppState>, Json(req): Json<VerifyVcRequest>) -> impl IntoResponse {
+    match verify_credential(&state.did_resolver, &req.credential).await {
         Ok(valid) => (StatusCode::OK, Json(json!({"valid": valid}))).into_response(),
         Err(err) => PagiAxumError::with_status(PagiError::config(err), StatusCode::BAD_REQUEST).into_response(),
     }
 }
 
-fn read_signing_key(identity_keys_dir: &Path, twin_id: Uuid) -> Result<SigningKey, String> {
-    let key_path = identity_keys_dir.join(format!("{twin_id}.ed25519"));
-    let raw = std::fs::read(&key_path).map_err(|e| format!("failed to read key {key_path:?}: {e}"))?;
-    if raw.len() != 32 {
-        return Err(format!("invalid key length: expected 32 bytes, got {}", raw.len()));
-    }
-    let mut sk = [0u8; 32];
-    sk.copy_from_slice(&raw);
+/// Key files are written by the identity service, sealed under `IDENTITY_KEK` when set.
+fn read_signing_key(keyring: &Keyring, identity_keys_dir: &Path, twin_id: Uuid) -> Result<SigningKey, String> {
+    let sk = keyring.read_key(identity_keys_dir, twin_id)?;
     Ok(SigningKey::from_bytes(&sk))
 }
 
-fn did_from_public_key(public_key: &VerifyingKey) -> String {
-    let pub_bytes = public_key.to_bytes();
-    let mut codec_and_key = Vec::with_capacity(2 + pub_bytes.len());
-    codec_and_key.push(0xed);
-    codec_and_key.push(0x01);
-    codec_and_key.extend_from_slice(&pub_bytes);
-    let method_id = multibase::encode(Base::Base58Btc, codec_and_key);
-    format!("did:key:{method_id}")
-}
-
-fn verifying_key_from_did(did: &str) -> Result<VerifyingKey, String> {
-    let method_id = did
-        .strip_prefix("did:key:")
-        .ok_or_else(|| "did must start with did:key:".to_string())?;
-    let (_base, bytes) = multibase::decode(method_id).map_err(|e| format!("multibase decode failed: {e}"))?;
-    if bytes.len() != 2 + 32 {
-        return Err(format!("unexpected did:key decoded length: {}", bytes.len()));
-    }
-    if bytes[0] != 0xed || bytes[1] != 0x01 {
-        return Err("unsupported key type (expected ed25519-pub multicodec 0xed01)".to_string());
-    }
-    let mut pk = [0u8; 32];
-    pk.copy_from_slice(&bytes[2..]);
-    VerifyingKey::from_bytes(&pk).map_err(|e| e.to_string())
+/// Keys `did` can verify a proof made at `created` with: decoded from a `did:key`, or the
+/// `did:web` document's assertion keys (or the `#fragment` one), including keys retired
+/// after `created`.
+async fn verifying_keys_from_did(
+    resolver: &DidResolver,
+    did: &str,
+    created: Option<OffsetDateTime>,
+) -> Result<Vec<VerifyingKey>, String> {
+    let keys = match created {
+        Some(created) => resolver.ed25519_keys_at(did, created).await?,
+        None => resolver.ed25519_keys(did).await?,
+    };
+    keys.iter()
+        .map(|pk| VerifyingKey::from_bytes(pk).map_err(|e| e.to_string()))
+        .collect()
 }
 
-fn issue_reputation_credential(identity_keys_dir: &Path, req: &IssueReputationRequest) -> Result<Value, String> {
-    let signing_key = read_signing_key(identity_keys_dir, req.issuer_twin_id)?;
-    let issuer_vk = signing_key.verifying_key();
-    let issuer_did = did_from_public_key(&issuer_vk);
-    let method_id = issuer_did
-        .strip_prefix("did:key:")
-        .ok_or_else(|| "issuer did is not did:key".to_string())?;
-    let verification_method = format!("{}#{}", issuer_did, method_id);
+/// Issued under the twin's registered DID (its `did:web` once it has one), with the current
+/// key as the proof's verification method.
+async fn issue_reputation_credential(
+    resolver: &DidResolver,
+    keyring: &Keyring,
+    identity_keys_dir: &Path,
+    req: &IssueReputationRequest,
+) -> Result<Value, String> {
+    let signing_key = read_signing_key(keyring, identity_keys_dir, req.issuer_twin_id)?;
+    let signer = resolver
+        .signing_did(req.issuer_twin_id, signing_key.verifying_key().as_bytes())
+        .await?;
+    let (issuer_did, verification_method) = (signer.did, signer.key_id);
 
     let now = time::OffsetDateTime::now_utc().format(&time::format_description::well_known::Rfc3339).unwrap_or_else(|_| "".to_string());
 
@@ -237,7 +233,7 @@ fn issue_reputation_credential(identity_keys_dir: &Path, req: &IssueReputationRe
     Ok(credential)
 }
 
-fn verify_credential(credential: &Value) -> Result<bool, String> {
+async fn verify_credential(resolver: &DidResolver, credential: &Value) -> Result<bool, String> {
     let issuer = credential
         .get("issuer")
         .and_then(|v| v.as_str())
@@ -251,7 +247,18 @@ fn verify_credential(credential: &Value) -> Result<bool, String> {
         .and_then(|v| v.as_str())
         .ok_or_else(|| "proof.proofValue missing".to_string())?;
 
-    let verifying_key = verifying_key_from_did(issuer)?;
+    // Prefer the proof's verification method (it pins one key of a did:web issuer), as
+    // long as it belongs to the issuer.
+    let verification_method = proof
+        .get("verificationMethod")
+        .and_then(|v| v.as_str())
+        .filter(|vm| vm.split('#').next() == Some(issuer))
+        .unwrap_or(issuer);
+    let created = proof
+        .get("created")
+        .and_then(Value::as_str)
+        .and_then(|raw| OffsetDateTime::parse(raw, &Rfc3339).ok());
+    let verifying_keys = verifying_keys_from_did(resolver, verification_method, created).await?;
 
     // Recreate the signed payload: credential without proof.
     let mut unsigned = credential.clone();
@@ -262,5 +269,5 @@ fn verify_credential(credential: &Value) -> Result<bool, String> {
 
     let (_base, sig_bytes) = multibase::decode(proof_value).map_err(|e| format!("proofValue decode failed: {e}"))?;
     let sig = Signature::from_slice(&sig_bytes).map_err(|e| e.to_string())?;
-    Ok(verifying_key.verify(&msg, &sig).is_ok())
+    Ok(verifying_keys.iter().any(|k| k.verify(&msg, &sig).is_ok()))
 }
@@ -4,18 +4,30 @@ use axum::{
     routing::{get, post},
     Json, Router,
 };
-use pagi_common::{publish_event, EventEnvelope, EventType, Playbook};
-use pagi_http::errors::PagiAxumError;
+use pagi_common::{auth::scopes, publish_event, swarm::PlaybookMemory, CoreEvent, EventEnvelope, Playbook};
+use pagi_http::{
+    auth::{Auth, Caller},
+    errors::PagiAxumError,
+};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use std::net::SocketAddr;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use uuid::Uuid;
 
 #[derive(Clone)]
 struct AppState {
     working_memory_url: String,
-    http: reqwest::Client,
+    /// Newest working memory items put into the context (`CONTEXT_MEMORY_ITEMS`).
+    memory_items: usize,
+    /// Records from each long-term store put into the context (`CONTEXT_LONG_TERM_ITEMS`).
+    long_term_items: usize,
+    /// Playbook `memory` sections last registered with working memory, per twin.
+    synced_playbooks: Arc<Mutex<HashMap<Uuid, String>>>,
+    http: pagi_http::trace_context::TracedClient,
     ethics: EthicsLayer,
     principles: PrinciplesLayer,
 }
@@ -68,7 +80,7 @@ impl EthicsLayer {
 }
 
 fn split_list(raw: &str) -> Vec<String> {
-    raw.split(|c| c == ',' || c == '\n' || c == ';')
+    raw.split([',', '\n', ';'])
         .map(|s| s.trim())
         .filter(|s| !s.is_empty())
         .map(|s| s.to_string())
@@ -131,6 +143,98 @@ struct BuildRequest {
 
     #[serde(default)]
     pub playbook: Option<Playbook>,
+
+    /// Narrows the working memory slice put into the context.
+    #[serde(default)]
+    pub memory: MemorySlice,
+}
+
+/// Working memory query filters (see `GET /memory/:twin_id`).
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct MemorySlice {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub since: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Defaults to `CONTEXT_MEMORY_ITEMS`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// `recent` or `importance`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_importance: Option<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MemoryPage {
+    pub items: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LongTermPage {
+    pub records: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchRequest<'a> {
+    pub query: &'a str,
+    pub top_k: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchResults {
+    pub results: Vec<SearchHit>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchHit {
+    pub item: serde_json::Value,
+}
+
+/// Search instead of the most recent items when the playbook sets `memory.retrieval_strategy`
+/// (other than `recent` or `importance`) or `context_engineering.retrieval_top_k`.
+fn retrieval(playbook: Option<&Playbook>) -> Option<(Option<&str>, Option<usize>)> {
+    let playbook = playbook?;
+    let strategy = playbook
+        .memory
+        .as_ref()
+        .map(|m| m.retrieval_strategy.trim())
+        .filter(|s| !s.is_empty());
+    let top_k = playbook
+        .context_engineering
+        .as_ref()
+        .and_then(|ce| ce.retrieval_top_k)
+        .map(|k| k as usize);
+    match strategy {
+        Some(s) if s.eq_ignore_ascii_case("recent") || s.eq_ignore_ascii_case("importance") => None,
+        Some(_) => Some((strategy, top_k)),
+        None => top_k.map(|k| (None, Some(k))),
+    }
+}
+
+/// The most important items rather than the most recent, when the playbook sets
+/// `memory.retrieval_strategy = "importance"`; `retrieval_top_k` then caps their number.
+fn importance_ranking(playbook: Option<&Playbook>) -> Option<Option<usize>> {
+    let playbook = playbook?;
+    let strategy = playbook.memory.as_ref()?.retrieval_strategy.trim();
+    strategy.eq_ignore_ascii_case("importance").then(|| {
+        playbook
+            .context_engineering
+            .as_ref()
+            .and_then(|ce| ce.retrieval_top_k)
+            .map(|k| k as usize)
+    })
 }
 
 #[derive(Debug, Serialize)]
@@ -147,22 +251,33 @@ async fn main() {
     let state = AppState {
         working_memory_url: std::env::var("WORKING_MEMORY_URL")
             .unwrap_or_else(|_| "http://127.0.0.1:8003".to_string()),
-        http: reqwest::Client::new(),
+        memory_items: std::env::var("CONTEXT_MEMORY_ITEMS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(50),
+        long_term_items: std::env::var("CONTEXT_LONG_TERM_ITEMS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(5),
+        synced_playbooks: Arc::new(Mutex::new(HashMap::new())),
+        http: pagi_http::auth::service_client(),
         ethics: EthicsLayer::from_env(),
         principles: PrinciplesLayer::from_env(),
     };
 
+    let auth = Auth::from_env();
     let app = Router::new()
         .route("/healthz", get(healthz))
-        .route("/build", post(build_context))
+        .route("/build", post(build_context).route_layer(auth.require(scopes::AGENT_RUN)))
         .with_state(state)
+        .layer(axum::middleware::from_fn(pagi_http::trace_context::propagate))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive());
 
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 8004).into());
     tracing::info!(%addr, "listening");
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    pagi_http::tls::serve(listener, app).await.unwrap();
 }
 
 async fn healthz() -> (StatusCode, &'static str) {
@@ -171,20 +286,59 @@ async fn healthz() -> (StatusCode, &'static str) {
 
 async fn build_context(
     State(state): State<AppState>,
+    caller: Caller,
     Json(req): Json<BuildRequest>,
 ) -> Result<Json<BuildResponse>, PagiAxumError> {
+    caller.check_twin(req.twin_id)?;
     let mem_endpoint = format!(
         "{}/memory/{}",
         state.working_memory_url.trim_end_matches('/'),
         req.twin_id
     );
-    let mem: Vec<serde_json::Value> = state
-        .http
-        .get(mem_endpoint)
-        .send()
-        .await?
-        .json()
-        .await?;
+    if let Some(memory) = req.playbook.as_ref().and_then(|p| p.memory.as_ref()) {
+        sync_playbook(&state, req.twin_id, &mem_endpoint, memory).await;
+    }
+    let mut slice = req.memory;
+    let (mem, memory_source) = match retrieval(req.playbook.as_ref()) {
+        Some((strategy, top_k)) => {
+            let search = SearchRequest {
+                query: &req.query,
+                top_k: top_k.or(slice.limit).unwrap_or(state.memory_items),
+                strategy,
+                role: slice.role,
+                tag: slice.tag,
+            };
+            let found = state
+                .http
+                .post(format!("{mem_endpoint}/search"))
+                .json(&search)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<SearchResults>()
+                .await?;
+            let items = found.results.into_iter().map(|hit| hit.item).collect();
+            (items, "working_memory_search")
+        }
+        None => {
+            let mut limit = slice.limit;
+            if let Some(top_k) = importance_ranking(req.playbook.as_ref()) {
+                slice.sort.get_or_insert_with(|| "importance".to_string());
+                limit = limit.or(top_k);
+            }
+            slice.limit = Some(limit.unwrap_or(state.memory_items));
+            let page = state
+                .http
+                .get(&mem_endpoint)
+                .query(&slice)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<MemoryPage>()
+                .await?;
+            (page.items, "working_memory")
+        }
+    };
 
     // Base memory layer.
     let mut memory_layer = String::new();
@@ -197,6 +351,21 @@ async fn build_context(
             .unwrap_or_default();
         memory_layer.push_str(&format!("- {}: {}\n", role, content));
     }
+    // Items in the context are reinforced against forgetting, unless the playbook replaces the
+    // memory layer.
+    let memory_replaced = req
+        .playbook
+        .as_ref()
+        .and_then(|p| p.context_engineering.as_ref())
+        .is_some_and(|ce| !ce.layers.memory.trim().is_empty());
+    if !memory_replaced {
+        record_retrievals(&state, req.twin_id, &mem_endpoint, &mem).await;
+    }
+    let mut sources = vec![memory_source.to_string()];
+    if let Some(long_term) = long_term_memory(&state, &mem_endpoint, req.playbook.as_ref()).await {
+        memory_layer.push_str(&format!("\n{long_term}"));
+        sources.push("long_term_memory".to_string());
+    }
 
     // If a playbook with ACE config is provided, assemble context using layers + priority.
     let context = if let Some(playbook) = &req.playbook {
@@ -257,13 +426,105 @@ async fn build_context(
     let resp = BuildResponse {
         twin_id: req.twin_id,
         context,
-        sources: vec!["working_memory".to_string()],
+        sources,
     };
 
-    let mut ev = EventEnvelope::new(EventType::ContextBuilt, json!({"twin_id": req.twin_id}));
-    ev.twin_id = Some(req.twin_id);
+    let mut ev = EventEnvelope::new_core(
+        req.twin_id,
+        CoreEvent::ContextBuilt {
+            sources: resp.sources.clone(),
+        },
+    );
     ev.source = Some("pagi-context-builder".to_string());
-    let _ = publish_event(ev).await;
+    publish_event(ev);
 
     Ok(Json(resp))
 }
+
+/// Register the playbook's `memory` section with working memory, which consolidates departing
+/// items as it says. Sent again only when it changes; failures are retried on the next build.
+async fn sync_playbook(state: &AppState, twin_id: Uuid, mem_endpoint: &str, memory: &PlaybookMemory) {
+    let Ok(serialized) = serde_json::to_string(memory) else {
+        return;
+    };
+    let synced = |playbooks: &HashMap<Uuid, String>| playbooks.get(&twin_id) == Some(&serialized);
+    if synced(&state.synced_playbooks.lock().unwrap_or_else(|p| p.into_inner())) {
+        return;
+    }
+    let sent = state
+        .http
+        .put(format!("{mem_endpoint}/playbook"))
+        .json(memory)
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status().map_err(Into::into));
+    match sent {
+        Ok(_) => {
+            state
+                .synced_playbooks
+                .lock()
+                .unwrap_or_else(|p| p.into_inner())
+                .insert(twin_id, serialized);
+        }
+        Err(err) => tracing::warn!(%twin_id, error = %err, "playbook memory settings not synced to working memory"),
+    }
+}
+
+/// The newest semantic and episodic long-term records, when the playbook keeps long-term memory.
+async fn long_term_memory(state: &AppState, mem_endpoint: &str, playbook: Option<&Playbook>) -> Option<String> {
+    let storage = playbook?.memory.as_ref()?.long_term_storage.as_deref()?.trim();
+    if storage.is_empty() || storage.eq_ignore_ascii_case("none") || state.long_term_items == 0 {
+        return None;
+    }
+    let mut section = String::new();
+    for kind in ["semantic", "episodic"] {
+        let page = state
+            .http
+            .get(format!("{mem_endpoint}/long_term/{kind}"))
+            .query(&[("limit", state.long_term_items)])
+            .send()
+            .await
+            .and_then(|resp| resp.error_for_status().map_err(Into::into));
+        let page = match page {
+            Ok(resp) => resp.json::<LongTermPage>().await.map_err(Into::into),
+            Err(err) => Err(err),
+        };
+        let records = match page {
+            Ok(page) => page.records,
+            Err(err) => {
+                tracing::warn!(kind, error = %err, "long-term memory unavailable");
+                continue;
+            }
+        };
+        for record in &records {
+            let content = record.get("content").and_then(|v| v.as_str()).unwrap_or_default();
+            match record.get("role").and_then(|v| v.as_str()) {
+                Some(role) => section.push_str(&format!("- {role}: {content}\n")),
+                None => section.push_str(&format!("- {content}\n")),
+            }
+        }
+    }
+    if section.is_empty() {
+        return None;
+    }
+    Some(format!("# Long-term Memory\n{section}"))
+}
+
+/// Tell working memory which items went into a context. Best effort: failures only cost the
+/// items some importance.
+async fn record_retrievals(state: &AppState, twin_id: Uuid, mem_endpoint: &str, items: &[serde_json::Value]) {
+    let item_ids: Vec<&str> = items.iter().filter_map(|item| item.get("id")?.as_str()).collect();
+    if item_ids.is_empty() {
+        return;
+    }
+    let sent = state
+        .http
+        .post(format!("{mem_endpoint}/retrieved"))
+        .json(&serde_json::json!({ "item_ids": item_ids }))
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status().map_err(Into::into));
+    if let Err(err) = sent {
+        tracing::warn!(%twin_id, error = %err, "memory retrievals not recorded");
+    }
+}
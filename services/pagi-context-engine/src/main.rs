@@ -4,10 +4,12 @@ use axum::{
     http::StatusCode,
     Router,
 };
-use pagi_common::{publish_event, EventEnvelope, EventType};
-use pagi_http::errors::PagiAxumError;
+use pagi_common::{auth::scopes, publish_event, CoreEvent, EventEnvelope};
+use pagi_http::{
+    auth::{Auth, Caller},
+    errors::PagiAxumError,
+};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use std::net::SocketAddr;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use uuid::Uuid;
@@ -15,7 +17,9 @@ use uuid::Uuid;
 #[derive(Clone)]
 struct AppState {
     working_memory_url: String,
-    http: reqwest::Client,
+    /// Newest working memory items put into the context (`CONTEXT_MEMORY_ITEMS`).
+    memory_items: usize,
+    http: pagi_http::trace_context::TracedClient,
 }
 
 #[derive(Debug, Deserialize)]
@@ -25,6 +29,11 @@ struct BuildRequest {
     pub query: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct MemoryPage {
+    pub items: Vec<serde_json::Value>,
+}
+
 #[derive(Debug, Serialize)]
 struct BuildResponse {
     pub twin_id: Uuid,
@@ -39,20 +48,26 @@ async fn main() {
     let state = AppState {
         working_memory_url: std::env::var("WORKING_MEMORY_URL")
             .unwrap_or_else(|_| "http://127.0.0.1:8003".to_string()),
-        http: reqwest::Client::new(),
+        memory_items: std::env::var("CONTEXT_MEMORY_ITEMS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(50),
+        http: pagi_http::auth::service_client(),
     };
 
+    let auth = Auth::from_env();
     let app = Router::new()
         .route("/healthz", get(healthz))
-        .route("/build", post(build_context))
+        .route("/build", post(build_context).route_layer(auth.require(scopes::AGENT_RUN)))
         .with_state(state)
+        .layer(axum::middleware::from_fn(pagi_http::trace_context::propagate))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive());
 
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 8004).into());
     tracing::info!(%addr, "listening");
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    pagi_http::tls::serve(listener, app).await.unwrap();
 }
 
 async fn healthz() -> (StatusCode, &'static str) {
@@ -61,14 +76,25 @@ async fn healthz() -> (StatusCode, &'static str) {
 
 async fn build_context(
     State(state): State<AppState>,
+    caller: Caller,
     Json(req): Json<BuildRequest>,
 ) -> Result<(StatusCode, Json<BuildResponse>), PagiAxumError> {
+    caller.check_twin(req.twin_id)?;
     let mem_endpoint = format!(
-        "{}/memory/{}",
+        "{}/memory/{}?limit={}",
         state.working_memory_url.trim_end_matches('/'),
-        req.twin_id
+        req.twin_id,
+        state.memory_items
     );
-    let mem: Vec<serde_json::Value> = state.http.get(mem_endpoint).send().await?.json().await?;
+    let mem = state
+        .http
+        .get(mem_endpoint)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<MemoryPage>()
+        .await?
+        .items;
 
     let mut context = String::new();
     context.push_str("# Working Memory\n");
@@ -89,10 +115,14 @@ async fn build_context(
         sources: vec!["working_memory".to_string()],
     };
 
-    let mut ev = EventEnvelope::new(EventType::ContextBuilt, json!({"twin_id": req.twin_id}));
-    ev.twin_id = Some(req.twin_id);
+    let mut ev = EventEnvelope::new_core(
+        req.twin_id,
+        CoreEvent::ContextBuilt {
+            sources: resp.sources.clone(),
+        },
+    );
     ev.source = Some("pagi-context-engine".to_string());
-    let _ = publish_event(ev).await;
+    publish_event(ev);
 
     Ok((StatusCode::OK, Json(resp)))
 }
@@ -1,13 +1,22 @@
 use axum::{
     extract::{Path, State},
     http::StatusCode,
-    routing::get,
+    response::{IntoResponse, Response},
+    routing::{get, post},
     Json, Router,
 };
-use pagi_common::{publish_event, EventEnvelope, EventType};
+use pagi_common::{
+    auth::scopes,
+    erasure::{DeletionConfirmer, PurgeReport, PurgeTrigger, Retention, TwinDeletion},
+    publish_event, CoreEvent, EventEnvelope, Outbox,
+};
+use pagi_http::{
+    auth::{Auth, Caller},
+    errors::PagiAxumError,
+};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+use time::OffsetDateTime;
 use tokio::sync::RwLock;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use uuid::Uuid;
@@ -17,6 +26,9 @@ struct EmotionState {
     pub mood: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub stress: Option<f32>,
+    /// Set by the service on every update; retention is measured from here.
+    #[serde(default = "OffsetDateTime::now_utc", with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
 }
 
 impl Default for EmotionState {
@@ -24,6 +36,7 @@ impl Default for EmotionState {
         Self {
             mood: "neutral".to_string(),
             stress: None,
+            updated_at: OffsetDateTime::now_utc(),
         }
     }
 }
@@ -31,52 +44,118 @@ impl Default for EmotionState {
 #[derive(Clone)]
 struct AppState {
     store: Arc<RwLock<HashMap<Uuid, EmotionState>>>,
+    deletions: Arc<DeletionConfirmer>,
 }
 
+const SERVICE: &str = "pagi-emotion-state-manager";
+
 #[tokio::main]
-async fn main() {
-    pagi_http::tracing::init("pagi-emotion-state-manager");
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    pagi_http::tracing::init(SERVICE);
 
     let state = AppState {
         store: Arc::new(RwLock::new(HashMap::new())),
+        deletions: Arc::new(DeletionConfirmer::from_env()),
     };
 
+    // States not updated within the maximum age are dropped.
+    let retention = Retention::from_env("EMOTION_STATE_MAX_AGE_SECS")?;
+    let sweeping = state.clone();
+    retention.spawn(move |cutoff| {
+        let state = sweeping.clone();
+        async move { sweep_expired(&state, cutoff).await }
+    });
+
+    let auth = Auth::from_env();
     let app = Router::new()
         .route("/healthz", get(healthz))
-        .route("/emotion/:twin_id", get(get_state).put(set_state))
+        .route(
+            "/emotion/:twin_id",
+            get(get_state).put(set_state).route_layer(auth.require(scopes::AGENT_RUN)),
+        )
+        .route("/events", post(handle_event).route_layer(auth.require(scopes::AGENT_RUN)))
         .with_state(state)
+        .layer(axum::middleware::from_fn(pagi_http::trace_context::propagate))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive());
 
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 8007).into());
     tracing::info!(%addr, "listening");
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    pagi_http::tls::serve(listener, app).await?;
+    Ok(())
 }
 
 async fn healthz() -> (StatusCode, &'static str) {
     (StatusCode::OK, "ok")
 }
 
-async fn get_state(State(state): State<AppState>, Path(twin_id): Path<Uuid>) -> Json<EmotionState> {
+async fn get_state(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+) -> Result<Json<EmotionState>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
     let guard = state.store.read().await;
-    Json(guard.get(&twin_id).cloned().unwrap_or_default())
+    Ok(Json(guard.get(&twin_id).cloned().unwrap_or_default()))
 }
 
 async fn set_state(
     State(state): State<AppState>,
+    caller: Caller,
     Path(twin_id): Path<Uuid>,
-    Json(new_state): Json<EmotionState>,
-) -> Json<EmotionState> {
+    Json(mut new_state): Json<EmotionState>,
+) -> Result<Json<EmotionState>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    new_state.updated_at = OffsetDateTime::now_utc();
     state.store.write().await.insert(twin_id, new_state.clone());
 
-    let mut ev = EventEnvelope::new(
-        EventType::EmotionStateUpdated,
-        json!({"twin_id": twin_id, "mood": new_state.mood, "stress": new_state.stress}),
+    let mut ev = EventEnvelope::new_core(
+        twin_id,
+        CoreEvent::EmotionStateUpdated {
+            mood: new_state.mood.clone(),
+            stress: new_state.stress,
+        },
     );
-    ev.twin_id = Some(twin_id);
-    ev.source = Some("pagi-emotion-state-manager".to_string());
-    let _ = publish_event(ev).await;
+    ev.source = Some(SERVICE.to_string());
+    publish_event(ev);
+
+    Ok(Json(new_state))
+}
 
-    Json(new_state)
+/// `POST /events`: drop a twin's state on `twin_deleted`, answering with the
+/// [`PurgeReport`]. Other events are ignored (`204`).
+async fn handle_event(
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(ev): Json<EventEnvelope>,
+) -> Result<Response, PagiAxumError> {
+    caller.check_not_twin()?;
+    let Some(deletion) = TwinDeletion::from_event(&ev) else {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+    let deletion = state.deletions.confirm(&deletion).await?;
+    let removed = state.store.write().await.remove(&deletion.twin_id).is_some();
+    let report = PurgeReport::new(SERVICE, deletion.twin_id, PurgeTrigger::TwinDeleted)
+        .with("states", removed as u64)
+        .with("outbox_events", Outbox::global().purge_twin(deletion.twin_id).await? as u64);
+    tracing::info!(twin_id = %deletion.twin_id, removed, "emotion state purged for deleted twin");
+    publish_event(report.event());
+    Ok(Json(report).into_response())
+}
+
+/// Drop states last updated before `cutoff` (see [`Retention`]).
+async fn sweep_expired(state: &AppState, cutoff: OffsetDateTime) {
+    let mut expired = Vec::new();
+    state.store.write().await.retain(|twin_id, emotion| {
+        let keep = emotion.updated_at >= cutoff;
+        if !keep {
+            expired.push(*twin_id);
+        }
+        keep
+    });
+    for twin_id in expired {
+        tracing::info!(%twin_id, "expired emotion state removed");
+        publish_event(PurgeReport::new(SERVICE, twin_id, PurgeTrigger::Retention).with("states", 1).event());
+    }
 }
@@ -0,0 +1,375 @@
+use async_trait::async_trait;
+use pagi_common::{cloudevents::Binding, CloudEvent, EventEnvelope, EventType};
+use rdkafka::{
+    admin::{AdminClient, AdminOptions, NewTopic, TopicReplication},
+    message::{Header, OwnedHeaders},
+    producer::{FutureProducer, FutureRecord},
+    ClientConfig,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Write,
+    path::PathBuf,
+    sync::Mutex,
+    time::Duration,
+};
+use uuid::Uuid;
+
+/// A single record handed to the bus: the event encoded per `EVENT_BUS_FORMAT`
+/// (see [`EventFormat`](crate::format::EventFormat)), plus record headers for CloudEvents.
+#[derive(Debug, Clone, Default)]
+pub struct BusRecord {
+    pub key: String,
+    pub payload: String,
+    /// Kafka record headers; the file backend does not store them.
+    pub headers: Vec<(String, String)>,
+}
+
+/// Transport the router produces into.
+///
+/// Backends (`EVENT_BUS`):
+/// - `kafka` (default): rdkafka producer against `KAFKA_BROKERS`
+/// - `memory`: in-process, bounded per topic; for local development and CI
+/// - `file`: append-only JSONL log per topic under `EVENT_LOG_DIR`; for single-node/edge deployments
+#[async_trait]
+pub trait EventBus: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Create the topic if it does not exist yet (best-effort; failures are logged).
+    async fn ensure_topic(&self, topic: &str, partitions: i32);
+
+    async fn produce(&self, topic: &str, record: &BusRecord) -> Result<(), String>;
+
+    /// Drop the stored events of `twin_id`, dead letters included, returning how many were
+    /// dropped. The twin's erasure events themselves are kept.
+    async fn purge_twin(&self, twin_id: Uuid) -> Result<u64, String>;
+}
+
+/// The event a stored record holds: a binary-mode CloudEvent when its headers carry the
+/// attributes, else the envelope or structured CloudEvent in the payload, unwrapping dead letters.
+fn stored_event(payload: &str, headers: &[(String, String)]) -> Option<EventEnvelope> {
+    if headers.iter().any(|(name, _)| name == "ce_specversion") {
+        let headers = headers.iter().map(|(name, value)| (name.as_str(), value.as_bytes()));
+        let ce = CloudEvent::from_binary(Binding::Kafka, headers, payload.as_bytes()).ok()?;
+        return EventEnvelope::try_from(ce).ok();
+    }
+    let mut value: serde_json::Value = serde_json::from_str(payload).ok()?;
+    if value.get("stage").is_some() {
+        value = value.get_mut("body")?.take();
+    }
+    let cloudevent = value.get("specversion").is_some();
+    crate::format::decode_value(value, cloudevent).ok()
+}
+
+/// Whether a stored record is an event of `twin_id` other than its erasure events, which
+/// have to outlive the purge they announce.
+fn of_twin(payload: &str, headers: &[(String, String)], twin_id: Uuid) -> bool {
+    let erasure = [EventType::TwinDeleted.as_str(), EventType::TwinDataPurged.as_str()];
+    stored_event(payload, headers)
+        .is_some_and(|ev| ev.twin_id == Some(twin_id) && !erasure.contains(&ev.event_type.as_str()))
+}
+
+pub fn from_env() -> Result<Box<dyn EventBus>, String> {
+    let backend = std::env::var("EVENT_BUS").unwrap_or_else(|_| "kafka".to_string());
+    match backend.to_lowercase().as_str() {
+        "kafka" => {
+            let brokers = std::env::var("KAFKA_BROKERS").unwrap_or_else(|_| "localhost:9092".to_string());
+            Ok(Box::new(KafkaBus::new(brokers)?))
+        }
+        "memory" => {
+            let retain = std::env::var("EVENT_BUS_MEMORY_RETAIN")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(10_000);
+            Ok(Box::new(MemoryBus::new(retain)))
+        }
+        "file" => {
+            let dir = std::env::var("EVENT_LOG_DIR").unwrap_or_else(|_| "/data/events".to_string());
+            Ok(Box::new(FileLogBus::new(PathBuf::from(dir))?))
+        }
+        other => Err(format!("unknown EVENT_BUS backend '{other}' (expected kafka|memory|file)")),
+    }
+}
+
+pub struct KafkaBus {
+    brokers: String,
+    producer: FutureProducer,
+}
+
+impl KafkaBus {
+    pub fn new(brokers: String) -> Result<Self, String> {
+        let producer: FutureProducer = ClientConfig::new()
+            .set("bootstrap.servers", &brokers)
+            .set("message.timeout.ms", "5000")
+            .create()
+            .map_err(|e| format!("kafka producer: {e}"))?;
+        tracing::info!(%brokers, "event bus: kafka");
+        Ok(Self { brokers, producer })
+    }
+}
+
+#[async_trait]
+impl EventBus for KafkaBus {
+    fn name(&self) -> &'static str {
+        "kafka"
+    }
+
+    async fn ensure_topic(&self, topic: &str, partitions: i32) {
+        let admin: AdminClient<_> = match ClientConfig::new().set("bootstrap.servers", &self.brokers).create() {
+            Ok(a) => a,
+            Err(err) => {
+                tracing::warn!(error = %err, "failed to create kafka admin client");
+                return;
+            }
+        };
+
+        let new_topic = NewTopic::new(topic, partitions, TopicReplication::Fixed(1));
+        match admin.create_topics([&new_topic], &AdminOptions::new()).await {
+            Ok(results) => {
+                for res in results {
+                    match res {
+                        Ok(name) => tracing::info!(%name, "topic ready"),
+                        Err((name, err)) => tracing::info!(%name, error = %err, "topic create skipped/failed"),
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::warn!(error = %err, "create_topics failed");
+            }
+        }
+    }
+
+    async fn produce(&self, topic: &str, record: &BusRecord) -> Result<(), String> {
+        let mut fr = FutureRecord::to(topic).payload(&record.payload).key(&record.key);
+        if !record.headers.is_empty() {
+            let headers = record.headers.iter().fold(OwnedHeaders::new(), |h, (key, value)| {
+                h.insert(Header {
+                    key,
+                    value: Some(value.as_str()),
+                })
+            });
+            fr = fr.headers(headers);
+        }
+        self.producer
+            .send(fr, Duration::from_secs(5))
+            .await
+            .map(|_| ())
+            .map_err(|(e, _)| format!("kafka produce failed: {e}"))
+    }
+
+    /// Kafka records cannot be deleted one by one; they age out under the topics' retention.
+    async fn purge_twin(&self, twin_id: Uuid) -> Result<u64, String> {
+        tracing::info!(%twin_id, "kafka records of the twin are left to topic retention");
+        Ok(0)
+    }
+}
+
+/// In-process bus: keeps the most recent `retain` records per topic and nothing else.
+pub struct MemoryBus {
+    retain: usize,
+    topics: Mutex<HashMap<String, VecDeque<BusRecord>>>,
+}
+
+impl MemoryBus {
+    pub fn new(retain: usize) -> Self {
+        tracing::info!(retain, "event bus: in-process memory");
+        Self {
+            retain,
+            topics: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl EventBus for MemoryBus {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn ensure_topic(&self, topic: &str, _partitions: i32) {
+        let mut topics = self.topics.lock().unwrap_or_else(|p| p.into_inner());
+        topics.entry(topic.to_string()).or_default();
+    }
+
+    async fn produce(&self, topic: &str, record: &BusRecord) -> Result<(), String> {
+        let mut topics = self.topics.lock().unwrap_or_else(|p| p.into_inner());
+        let log = topics.entry(topic.to_string()).or_default();
+        if log.len() >= self.retain {
+            log.pop_front();
+        }
+        log.push_back(record.clone());
+        Ok(())
+    }
+
+    async fn purge_twin(&self, twin_id: Uuid) -> Result<u64, String> {
+        let mut topics = self.topics.lock().unwrap_or_else(|p| p.into_inner());
+        let mut purged = 0;
+        for log in topics.values_mut() {
+            let before = log.len();
+            log.retain(|record| !of_twin(&record.payload, &record.headers, twin_id));
+            purged += (before - log.len()) as u64;
+        }
+        Ok(purged)
+    }
+}
+
+/// Append-only JSONL log: one `{topic}.jsonl` file per topic, one envelope per line.
+///
+/// Keys are not stored; they are derived from the envelope (`twin_id`, else `id`) when read back.
+pub struct FileLogBus {
+    dir: PathBuf,
+    // Serializes appends so concurrent publishes never interleave partial lines.
+    write_lock: Mutex<()>,
+}
+
+impl FileLogBus {
+    pub fn new(dir: PathBuf) -> Result<Self, String> {
+        std::fs::create_dir_all(&dir).map_err(|e| format!("event log dir {}: {e}", dir.display()))?;
+        tracing::info!(dir = %dir.display(), "event bus: append-only file log");
+        Ok(Self {
+            dir,
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    fn topic_path(&self, topic: &str) -> PathBuf {
+        self.dir.join(format!("{topic}.jsonl"))
+    }
+}
+
+#[async_trait]
+impl EventBus for FileLogBus {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn ensure_topic(&self, topic: &str, _partitions: i32) {
+        let path = self.topic_path(topic);
+        if let Err(err) = std::fs::OpenOptions::new().create(true).append(true).open(&path) {
+            tracing::warn!(path = %path.display(), error = %err, "failed to create topic log");
+        }
+    }
+
+    async fn produce(&self, topic: &str, record: &BusRecord) -> Result<(), String> {
+        let path = self.topic_path(topic);
+        // Compact JSON never contains raw newlines, so one record is exactly one line.
+        let line = format!("{}\n", record.payload);
+
+        let _guard = self.write_lock.lock().unwrap_or_else(|p| p.into_inner());
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("open {}: {e}", path.display()))?;
+        file.write_all(line.as_bytes())
+            .map_err(|e| format!("append {}: {e}", path.display()))
+    }
+
+    /// Rewrites each topic log holding records of the twin.
+    async fn purge_twin(&self, twin_id: Uuid) -> Result<u64, String> {
+        let entries = std::fs::read_dir(&self.dir).map_err(|e| format!("read {}: {e}", self.dir.display()))?;
+        let _guard = self.write_lock.lock().unwrap_or_else(|p| p.into_inner());
+        let mut purged = 0;
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension().is_none_or(|ext| ext != "jsonl") {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path).map_err(|e| format!("read {}: {e}", path.display()))?;
+            let kept: Vec<&str> = text.lines().filter(|line| !of_twin(line, &[], twin_id)).collect();
+            let dropped = text.lines().count() - kept.len();
+            if dropped == 0 {
+                continue;
+            }
+            let tmp = path.with_extension("jsonl.tmp");
+            let mut body = kept.join("\n");
+            if !kept.is_empty() {
+                body.push('\n');
+            }
+            std::fs::write(&tmp, body)
+                .and_then(|()| std::fs::rename(&tmp, &path))
+                .map_err(|e| format!("rewrite {}: {e}", path.display()))?;
+            purged += dropped as u64;
+        }
+        Ok(purged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::EventFormat;
+
+    fn event(twin_id: Uuid, event_type: EventType, payload: serde_json::Value) -> EventEnvelope {
+        let mut ev = EventEnvelope::new(event_type, payload);
+        ev.twin_id = Some(twin_id);
+        ev
+    }
+
+    fn record(ev: &EventEnvelope, format: EventFormat) -> BusRecord {
+        let (payload, headers) = format.encode(ev, Binding::Kafka).unwrap();
+        BusRecord {
+            key: ev.twin_id.unwrap().to_string(),
+            payload,
+            headers,
+        }
+    }
+
+    /// Records of `twin` that go (one per format, plus a dead letter) and three that stay:
+    /// its erasure events and another twin's event that mentions it.
+    fn records(twin: Uuid) -> (Vec<BusRecord>, Vec<BusRecord>) {
+        let ev = event(twin, EventType::ContextBuilt, serde_json::json!({"n": 1}));
+        let letter = serde_json::json!({"stage": "produce", "reason": "timeout", "body": ev});
+        let purged = vec![
+            record(&ev, EventFormat::Envelope),
+            record(&ev, EventFormat::Cloudevents),
+            record(&ev, EventFormat::CloudeventsBinary),
+            BusRecord {
+                key: twin.to_string(),
+                payload: letter.to_string(),
+                headers: Vec::new(),
+            },
+        ];
+        let other = event(Uuid::new_v4(), EventType::ContextBuilt, serde_json::json!({"peer": twin}));
+        let kept = vec![
+            record(&event(twin, EventType::TwinDeleted, serde_json::json!({})), EventFormat::Envelope),
+            record(&event(twin, EventType::TwinDataPurged, serde_json::json!({})), EventFormat::Cloudevents),
+            record(&other, EventFormat::Envelope),
+        ];
+        (purged, kept)
+    }
+
+    #[tokio::test]
+    async fn memory_purge_drops_only_the_twins_own_events() {
+        let twin = Uuid::new_v4();
+        let (purged, kept) = records(twin);
+        let bus = MemoryBus::new(100);
+        for record in purged.iter().chain(&kept) {
+            bus.produce("events", record).await.unwrap();
+        }
+
+        assert_eq!(bus.purge_twin(twin).await.unwrap(), purged.len() as u64);
+        let topics = bus.topics.lock().unwrap();
+        let left: Vec<_> = topics["events"].iter().map(|r| r.payload.clone()).collect();
+        assert_eq!(left, kept.iter().map(|r| r.payload.clone()).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn file_purge_drops_only_the_twins_own_events() {
+        let twin = Uuid::new_v4();
+        let (purged, kept) = records(twin);
+        // The file backend stores no headers, so it never holds binary-mode records.
+        let purged: Vec<_> = purged.into_iter().filter(|r| r.headers.iter().all(|(k, _)| k != "ce_specversion")).collect();
+        let dir = std::env::temp_dir().join(format!("pagi-event-bus-{}", Uuid::new_v4()));
+        let bus = FileLogBus::new(dir.clone()).unwrap();
+        for record in purged.iter().chain(&kept) {
+            bus.produce("events", record).await.unwrap();
+        }
+
+        assert_eq!(bus.purge_twin(twin).await.unwrap(), purged.len() as u64);
+        assert_eq!(bus.purge_twin(twin).await.unwrap(), 0);
+        let text = std::fs::read_to_string(bus.topic_path("events")).unwrap();
+        assert_eq!(text.lines().collect::<Vec<_>>(), kept.iter().map(|r| r.payload.as_str()).collect::<Vec<_>>());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,54 @@
+use serde::Serialize;
+use serde_json::Value;
+use time::OffsetDateTime;
+
+use crate::bus::{BusRecord, EventBus};
+
+/// Why an event ended up on the dead-letter topic.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// The request body was not a valid `EventEnvelope`.
+    Decode,
+    /// A known event type whose payload does not match its typed schema.
+    Validate,
+    /// The bus refused or timed out producing the event.
+    Produce,
+}
+
+#[derive(Debug, Serialize)]
+struct DeadLetter<'a> {
+    stage: Stage,
+    reason: &'a str,
+    received_at: OffsetDateTime,
+    /// The original body: the envelope when it decoded, the raw text otherwise.
+    body: Value,
+}
+
+/// Best-effort: the original failure is what gets reported to the caller, so a
+/// dead-letter produce failure is only logged.
+pub async fn send(bus: &dyn EventBus, topic: &str, stage: Stage, reason: &str, key: &str, body: Value) {
+    let letter = DeadLetter {
+        stage,
+        reason,
+        received_at: OffsetDateTime::now_utc(),
+        body,
+    };
+    let payload = match serde_json::to_string(&letter) {
+        Ok(p) => p,
+        Err(err) => {
+            tracing::warn!(error = %err, "dead-letter serialization failed");
+            return;
+        }
+    };
+
+    tracing::warn!(?stage, %reason, %topic, "event dead-lettered");
+    let record = BusRecord {
+        key: key.to_string(),
+        payload,
+        headers: Vec::new(),
+    };
+    if let Err(err) = bus.produce(topic, &record).await {
+        tracing::warn!(error = %err, %topic, "dead-letter produce failed");
+    }
+}
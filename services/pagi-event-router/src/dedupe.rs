@@ -0,0 +1,85 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Remembers idempotency keys for a sliding window so client retries are accepted once.
+///
+/// A key is *reserved* before producing and *released* if producing fails, so two
+/// concurrent retries of the same event cannot both reach the bus.
+pub struct IdempotencyCache {
+    window: Duration,
+    seen: Mutex<Seen>,
+}
+
+#[derive(Default)]
+struct Seen {
+    keys: HashMap<String, Instant>,
+    /// Reservations oldest first, so expiry only looks at the front. Released keys stay
+    /// queued until they expire; an entry whose time no longer matches `keys` is stale.
+    order: VecDeque<(Instant, String)>,
+}
+
+impl IdempotencyCache {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Mutex::new(Seen::default()),
+        }
+    }
+
+    /// Returns `false` if `key` was already reserved within the window.
+    pub fn reserve(&self, key: &str) -> bool {
+        self.reserve_at(key, Instant::now())
+    }
+
+    fn reserve_at(&self, key: &str, now: Instant) -> bool {
+        let mut seen = self.seen.lock().unwrap_or_else(|p| p.into_inner());
+        while seen.order.front().is_some_and(|(at, _)| now.duration_since(*at) >= self.window) {
+            if let Some((at, expired)) = seen.order.pop_front() {
+                if seen.keys.get(&expired) == Some(&at) {
+                    seen.keys.remove(&expired);
+                }
+            }
+        }
+        if seen.keys.contains_key(key) {
+            return false;
+        }
+        seen.keys.insert(key.to_string(), now);
+        seen.order.push_back((now, key.to_string()));
+        true
+    }
+
+    pub fn release(&self, key: &str) {
+        self.seen.lock().unwrap_or_else(|p| p.into_inner()).keys.remove(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_reserved_for_the_window() {
+        let cache = IdempotencyCache::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(cache.reserve_at("a", start));
+        assert!(!cache.reserve_at("a", start + Duration::from_secs(9)));
+        assert!(cache.reserve_at("b", start + Duration::from_secs(9)));
+        assert!(cache.reserve_at("a", start + Duration::from_secs(10)));
+        assert!(!cache.reserve_at("b", start + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn released_keys_can_be_reserved_again() {
+        let cache = IdempotencyCache::new(Duration::from_secs(10));
+        let start = Instant::now();
+        assert!(cache.reserve_at("a", start));
+        cache.release("a");
+        assert!(cache.reserve_at("a", start + Duration::from_secs(5)));
+        // The first reservation expiring does not drop the second.
+        assert!(!cache.reserve_at("a", start + Duration::from_secs(12)));
+        assert!(cache.reserve_at("a", start + Duration::from_secs(15)));
+    }
+}
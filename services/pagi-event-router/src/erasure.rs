@@ -0,0 +1,57 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use pagi_common::{
+    erasure::{PurgeReport, PurgeTrigger, TwinDeletion},
+    EventEnvelope, PagiError,
+};
+use pagi_http::{auth::Caller, errors::PagiAxumError};
+use std::sync::Arc;
+
+use crate::{publish, AppState};
+
+const SERVICE: &str = "pagi-event-router";
+
+/// `POST /events`: on `twin_deleted`, drop what the router still holds of the twin — its
+/// events in the event log (the erasure events themselves stay as the record of deletion),
+/// the events retained for `/subscribe` resumption, and its records and dead letters on the
+/// memory and file buses — answering with the purge report, which is published like any
+/// other service's. Other events are ignored (`204`).
+pub async fn handle_event(
+    State(state): State<Arc<AppState>>,
+    caller: Caller,
+    Json(ev): Json<EventEnvelope>,
+) -> Result<Response, PagiAxumError> {
+    caller.check_not_twin()?;
+    let Some(deletion) = TwinDeletion::from_event(&ev) else {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+    let twin_id = state.deletions.confirm(&deletion).await?.twin_id;
+
+    let logged = {
+        let state = state.clone();
+        tokio::task::spawn_blocking(move || match &state.log {
+            Some(log) => log.purge_twin(twin_id),
+            None => Ok(0),
+        })
+        .await
+        .map_err(|e| PagiAxumError::from(PagiError::Unknown(e.to_string())))??
+    };
+    let retained = state.hub.purge_twin(twin_id);
+    let records = state.bus.purge_twin(twin_id).await.map_err(|err| {
+        PagiAxumError::with_status(PagiError::plugin_exec(err), StatusCode::INTERNAL_SERVER_ERROR)
+    })?;
+
+    let report = PurgeReport::new(SERVICE, twin_id, PurgeTrigger::TwinDeleted)
+        .with("logged_events", logged)
+        .with("retained_events", retained as u64)
+        .with("bus_records", records);
+    tracing::info!(%twin_id, purged = report.total(), "event store purged for deleted twin");
+    if let Err(err) = publish::publish_own(&state, report.event()).await {
+        tracing::warn!(%twin_id, error = %err, "purge report not published");
+    }
+    Ok(Json(report).into_response())
+}
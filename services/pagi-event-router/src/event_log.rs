@@ -0,0 +1,477 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use pagi_common::{cloudevents::Binding, EventEnvelope, EventType, PagiError};
+use pagi_http::errors::PagiAxumError;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{bus::BusRecord, subscribe::EventFilter, AppState};
+
+const DEFAULT_PAGE: usize = 100;
+const MAX_PAGE: usize = 1000;
+
+/// Lines between the byte offsets kept in memory for seeking to a cursor.
+const CHECKPOINT_EVERY: u64 = 1024;
+
+/// Append-only, queryable log of every event the router accepted.
+///
+/// Stored as JSONL; an event's sequence number (`seq`) is its 0-based line number, which
+/// doubles as the pagination cursor. Queries scan the file from the page's cursor, so they
+/// are meant for debugging and rebuilding derived state, not for hot paths.
+pub struct EventLog {
+    path: PathBuf,
+    index: Mutex<Index>,
+}
+
+#[derive(Debug, Default)]
+struct Index {
+    next_seq: u64,
+    /// File length in bytes.
+    len: u64,
+    /// `checkpoints[i]` is the byte offset of line `i * CHECKPOINT_EVERY`.
+    checkpoints: Vec<u64>,
+}
+
+impl Index {
+    fn push(&mut self, line_len: u64) {
+        if self.next_seq.is_multiple_of(CHECKPOINT_EVERY) {
+            self.checkpoints.push(self.len);
+        }
+        self.next_seq += 1;
+        self.len += line_len;
+    }
+
+    /// The nearest `(seq, byte offset)` at or before `seq`.
+    fn seek(&self, seq: u64) -> (u64, u64) {
+        let i = (seq / CHECKPOINT_EVERY) as usize;
+        match self.checkpoints.get(i).or(self.checkpoints.last()) {
+            Some(&offset) => (i.min(self.checkpoints.len() - 1) as u64 * CHECKPOINT_EVERY, offset),
+            None => (0, 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LoggedEvent {
+    pub seq: u64,
+    pub event: EventEnvelope,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LogQuery {
+    pub filter: EventFilter,
+    pub since: Option<OffsetDateTime>,
+    pub until: Option<OffsetDateTime>,
+    /// Only return events with `seq > after`.
+    pub after: Option<u64>,
+    pub limit: usize,
+}
+
+impl LogQuery {
+    fn matches(&self, ev: &EventEnvelope) -> bool {
+        if self.since.is_some_and(|since| ev.ts < since) {
+            return false;
+        }
+        if self.until.is_some_and(|until| ev.ts >= until) {
+            return false;
+        }
+        self.filter.matches(ev)
+    }
+}
+
+impl EventLog {
+    pub fn open(path: PathBuf) -> std::io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let index = scan(&path)?;
+        tracing::info!(path = %path.display(), events = index.next_seq, "event log opened");
+        Ok(Self {
+            path,
+            index: Mutex::new(index),
+        })
+    }
+
+    pub fn append(&self, ev: &EventEnvelope) -> std::io::Result<u64> {
+        let mut line = serde_json::to_vec(ev)?;
+        line.push(b'\n');
+
+        let mut index = self.lock_index();
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        file.write_all(&line)?;
+        let seq = index.next_seq;
+        index.push(line.len() as u64);
+        Ok(seq)
+    }
+
+    /// Blank out the events of `twin_id`, except the erasure events recording its deletion.
+    /// Blanked lines are kept (as `{}`, which queries skip) so every other event keeps its
+    /// `seq`. Returns how many events were removed.
+    pub fn purge_twin(&self, twin_id: Uuid) -> std::io::Result<u64> {
+        let erasure = [EventType::TwinDeleted.as_str(), EventType::TwinDataPurged.as_str()];
+        let mut index = self.lock_index();
+        let file = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err),
+        };
+        let tmp = self.path.with_extension("jsonl.tmp");
+        let mut out = File::create(&tmp)?;
+        let mut purged = 0;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let of_twin = serde_json::from_str::<EventEnvelope>(&line)
+                .is_ok_and(|ev| ev.twin_id == Some(twin_id) && !erasure.contains(&ev.event_type.as_str()));
+            if of_twin {
+                purged += 1;
+                out.write_all(b"{}\n")?;
+            } else {
+                out.write_all(line.as_bytes())?;
+                out.write_all(b"\n")?;
+            }
+        }
+        out.sync_all()?;
+        if purged == 0 {
+            drop(out);
+            std::fs::remove_file(&tmp)?;
+            return Ok(0);
+        }
+        std::fs::rename(&tmp, &self.path)?;
+        *index = scan(&self.path)?;
+        Ok(purged)
+    }
+
+    fn lock_index(&self) -> std::sync::MutexGuard<'_, Index> {
+        self.index.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    pub fn query(&self, q: &LogQuery) -> std::io::Result<Vec<LoggedEvent>> {
+        let file = match File::open(&self.path) {
+            Ok(f) => f,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let start = q.after.map(|a| a + 1).unwrap_or(0);
+        let (first, offset) = self.lock_index().seek(start);
+        let mut reader = BufReader::new(file);
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut out = Vec::new();
+        for (seq, line) in (first..).zip(reader.lines()) {
+            let Ok(line) = line else {
+                break;
+            };
+            if seq < start {
+                continue;
+            }
+            let Ok(event) = serde_json::from_str::<EventEnvelope>(&line) else {
+                continue;
+            };
+            if q.matches(&event) {
+                out.push(LoggedEvent { seq, event });
+                if out.len() >= q.limit {
+                    break;
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+fn scan(path: &Path) -> std::io::Result<Index> {
+    let mut index = Index::default();
+    match File::open(path) {
+        Ok(f) => {
+            let mut reader = BufReader::new(f);
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match reader.read_until(b'\n', &mut line)? {
+                    0 => break,
+                    n => index.push(n as u64),
+                }
+            }
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err),
+    }
+    Ok(index)
+}
+
+pub fn from_env() -> Result<Option<EventLog>, String> {
+    let enabled = std::env::var("EVENT_STORE")
+        .unwrap_or_else(|_| "false".to_string())
+        .to_lowercase()
+        == "true";
+    if !enabled {
+        return Ok(None);
+    }
+    let dir = std::env::var("EVENT_STORE_DIR").unwrap_or_else(|_| "/data/event-store".to_string());
+    EventLog::open(PathBuf::from(dir).join("events.jsonl"))
+        .map(Some)
+        .map_err(|e| format!("event store: {e}"))
+}
+
+fn parse_ts(field: &str, raw: Option<&str>) -> Result<Option<OffsetDateTime>, PagiAxumError> {
+    let Some(raw) = raw else {
+        return Ok(None);
+    };
+    OffsetDateTime::parse(raw, &Rfc3339).map(Some).map_err(|e| {
+        PagiAxumError::with_status(
+            PagiError::config(format!("{field}: expected RFC 3339 timestamp ({e})")),
+            StatusCode::BAD_REQUEST,
+        )
+    })
+}
+
+fn log_of(state: &AppState) -> Result<&EventLog, PagiAxumError> {
+    state.log.as_ref().ok_or_else(|| {
+        PagiAxumError::with_status(
+            PagiError::config("event store disabled (set EVENT_STORE=true)"),
+            StatusCode::NOT_FOUND,
+        )
+    })
+}
+
+async fn run_query(state: &Arc<AppState>, q: LogQuery) -> Result<Vec<LoggedEvent>, PagiAxumError> {
+    log_of(state)?;
+    let state = state.clone();
+    tokio::task::spawn_blocking(move || match &state.log {
+        Some(log) => log.query(&q),
+        None => Ok(Vec::new()),
+    })
+    .await
+    .map_err(|e| PagiAxumError::from(PagiError::Unknown(e.to_string())))?
+    .map_err(PagiAxumError::from)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    #[serde(default)]
+    pub twin_id: Option<Uuid>,
+    /// Comma-separated list of event types.
+    #[serde(default, rename = "type", alias = "event_type")]
+    pub event_type: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub correlation_id: Option<Uuid>,
+    #[serde(default)]
+    pub since: Option<String>,
+    #[serde(default)]
+    pub until: Option<String>,
+    /// `next_cursor` from the previous page.
+    #[serde(default)]
+    pub cursor: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventsPage {
+    pub events: Vec<LoggedEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
+}
+
+/// `GET /events?twin_id=&type=&correlation_id=&since=&until=&cursor=&limit=`
+pub async fn list_events(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<EventsQuery>,
+) -> Result<Json<EventsPage>, PagiAxumError> {
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE);
+    let query = LogQuery {
+        filter: EventFilter::from_parts(q.event_type.as_deref(), q.twin_id, q.source, q.subject)
+            .with_correlation(q.correlation_id),
+        since: parse_ts("since", q.since.as_deref())?,
+        until: parse_ts("until", q.until.as_deref())?,
+        after: q.cursor,
+        limit,
+    };
+
+    let events = run_query(&state, query).await?;
+    let next_cursor = if events.len() == limit {
+        events.last().map(|e| e.seq)
+    } else {
+        None
+    };
+    Ok(Json(EventsPage { events, next_cursor }))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReplayTarget {
+    /// Re-produce onto a bus topic.
+    Topic { topic: String },
+    /// Deliver each event to a registered webhook, signed and formatted as its live deliveries
+    /// are (its event filters do not apply).
+    Webhook { id: Uuid },
+    /// Re-broadcast to live `/subscribe` clients (their own filters still apply).
+    Subscribers,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayRequest {
+    pub since: String,
+    #[serde(default)]
+    pub until: Option<String>,
+    #[serde(default)]
+    pub twin_id: Option<Uuid>,
+    #[serde(default, alias = "type")]
+    pub event_type: Option<String>,
+    pub target: ReplayTarget,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayResponse {
+    pub replayed: usize,
+    pub failed: usize,
+}
+
+/// `POST /replay`: re-deliver a time range from the event log, oldest first.
+pub async fn replay(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<ReplayRequest>,
+) -> Result<Json<ReplayResponse>, PagiAxumError> {
+    let mut query = LogQuery {
+        filter: EventFilter::from_parts(req.event_type.as_deref(), req.twin_id, None, None),
+        since: parse_ts("since", Some(&req.since))?,
+        until: parse_ts("until", req.until.as_deref())?,
+        after: None,
+        limit: MAX_PAGE,
+    };
+
+    if let ReplayTarget::Webhook { id } = &req.target {
+        if !state.webhooks.exists(*id) {
+            return Err(PagiAxumError::with_status(
+                PagiError::config(format!("webhook {id} not found")),
+                StatusCode::NOT_FOUND,
+            ));
+        }
+    }
+
+    let (mut replayed, mut failed) = (0usize, 0usize);
+    loop {
+        let page = run_query(&state, query.clone()).await?;
+        let Some(last) = page.last().map(|e| e.seq) else {
+            break;
+        };
+
+        for logged in &page {
+            let ok = deliver(&state, &req.target, &logged.event).await;
+            if ok {
+                replayed += 1;
+            } else {
+                failed += 1;
+            }
+        }
+
+        if page.len() < query.limit {
+            break;
+        }
+        query.after = Some(last);
+    }
+
+    tracing::info!(replayed, failed, target = ?req.target, "replay finished");
+    Ok(Json(ReplayResponse { replayed, failed }))
+}
+
+async fn deliver(state: &AppState, target: &ReplayTarget, ev: &EventEnvelope) -> bool {
+    match target {
+        ReplayTarget::Topic { topic } => {
+            let Ok((payload, headers)) = state.bus_format.encode(ev, Binding::Kafka) else {
+                return false;
+            };
+            let key = ev.twin_id.map(|id| id.to_string()).unwrap_or_else(|| ev.id.to_string());
+            match state.bus.produce(topic, &BusRecord { key, payload, headers }).await {
+                Ok(()) => true,
+                Err(err) => {
+                    tracing::warn!(event_id = %ev.id, %topic, error = %err, "replay produce failed");
+                    false
+                }
+            }
+        }
+        ReplayTarget::Webhook { id } => match state.webhooks.send_to(*id, ev).await {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::warn!(event_id = %ev.id, webhook = %id, error = %err, "replay delivery failed");
+                false
+            }
+        },
+        ReplayTarget::Subscribers => {
+            state.hub.publish(ev.clone());
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_seek_to_their_cursor() {
+        let path = std::env::temp_dir().join(format!("pagi-event-log-{}.jsonl", Uuid::new_v4()));
+        let log = EventLog::open(path.clone()).unwrap();
+        let total = 2 * CHECKPOINT_EVERY + 10;
+        for n in 0..total {
+            log.append(&EventEnvelope::new(EventType::ContextBuilt, serde_json::json!({"n": n})))
+                .unwrap();
+        }
+
+        let page = |after: Option<u64>| {
+            let q = LogQuery { after, limit: 3, ..LogQuery::default() };
+            log.query(&q).unwrap().iter().map(|e| (e.seq, e.event.payload["n"].as_u64())).collect::<Vec<_>>()
+        };
+        assert_eq!(page(None), vec![(0, Some(0)), (1, Some(1)), (2, Some(2))]);
+        let after = CHECKPOINT_EVERY + 5;
+        assert_eq!(page(Some(after))[0], (after + 1, Some(after + 1)));
+        assert_eq!(page(Some(total - 2)), vec![(total - 1, Some(total - 1))]);
+        assert!(page(Some(total + 100)).is_empty());
+
+        // Reopening rebuilds the same index.
+        let reopened = EventLog::open(path.clone()).unwrap();
+        assert_eq!(reopened.lock_index().checkpoints, log.lock_index().checkpoints);
+        assert_eq!(reopened.append(&EventEnvelope::new(EventType::ContextBuilt, serde_json::json!({}))).unwrap(), total);
+
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn purging_a_twin_keeps_other_events_in_place() {
+        let path = std::env::temp_dir().join(format!("pagi-event-log-{}.jsonl", Uuid::new_v4()));
+        let log = EventLog::open(path.clone()).unwrap();
+        let (twin, other) = (Uuid::new_v4(), Uuid::new_v4());
+        let event = |twin_id, event_type, n: u64| {
+            let mut ev = EventEnvelope::new(event_type, serde_json::json!({"n": n}));
+            ev.twin_id = Some(twin_id);
+            ev
+        };
+        log.append(&event(twin, EventType::ContextBuilt, 0)).unwrap();
+        log.append(&event(other, EventType::ContextBuilt, 1)).unwrap();
+        log.append(&event(twin, EventType::ContextBuilt, 2)).unwrap();
+        log.append(&event(twin, EventType::TwinDeleted, 3)).unwrap();
+
+        assert_eq!(log.purge_twin(twin).unwrap(), 2);
+        assert_eq!(log.purge_twin(twin).unwrap(), 0);
+        let all = LogQuery { limit: 10, ..LogQuery::default() };
+        let left: Vec<_> = log.query(&all).unwrap().iter().map(|e| (e.seq, e.event.twin_id)).collect();
+        assert_eq!(left, vec![(1, Some(other)), (3, Some(twin))]);
+        // Appends continue after the blanked lines.
+        assert_eq!(log.append(&event(other, EventType::ContextBuilt, 4)).unwrap(), 4);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
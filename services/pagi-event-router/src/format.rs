@@ -0,0 +1,98 @@
+use axum::http::{header::CONTENT_TYPE, HeaderMap};
+use pagi_common::{
+    cloudevents::{self, Binding},
+    CloudEvent, EventEnvelope,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Wire representation of an event leaving the router (bus records, webhooks, replay, subscribers).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventFormat {
+    /// The native [`EventEnvelope`] JSON.
+    #[default]
+    Envelope,
+    /// CloudEvents structured mode (`application/cloudevents+json`).
+    Cloudevents,
+    /// CloudEvents binary mode: attributes in `ce-`/`ce_` headers, `data` as the body.
+    CloudeventsBinary,
+}
+
+impl EventFormat {
+    pub fn parse(raw: &str) -> Option<Self> {
+        serde_json::from_value(Value::String(raw.trim().to_lowercase().replace('-', "_"))).ok()
+    }
+
+    /// Body plus the headers (including `content-type`) to send it with.
+    pub fn encode(self, ev: &EventEnvelope, binding: Binding) -> Result<(String, Vec<(String, String)>), serde_json::Error> {
+        match self {
+            EventFormat::Envelope => Ok((
+                serde_json::to_string(ev)?,
+                vec![("content-type".to_string(), "application/json".to_string())],
+            )),
+            EventFormat::Cloudevents => Ok((
+                serde_json::to_string(&CloudEvent::from(ev))?,
+                vec![("content-type".to_string(), cloudevents::STRUCTURED_CONTENT_TYPE.to_string())],
+            )),
+            EventFormat::CloudeventsBinary => {
+                let ce = CloudEvent::from(ev);
+                let body = match &ce.data {
+                    Some(data) => serde_json::to_string(data)?,
+                    None => String::new(),
+                };
+                Ok((body, ce.binary_headers(binding)))
+            }
+        }
+    }
+
+    /// JSON document for transports without headers (SSE, WebSocket): binary mode falls
+    /// back to structured.
+    pub fn document(self, ev: &EventEnvelope) -> Result<Value, serde_json::Error> {
+        match self {
+            EventFormat::Envelope => serde_json::to_value(ev),
+            EventFormat::Cloudevents | EventFormat::CloudeventsBinary => serde_json::to_value(CloudEvent::from(ev)),
+        }
+    }
+}
+
+fn content_type(headers: &HeaderMap) -> &str {
+    headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).unwrap_or_default()
+}
+
+fn has_media_type(headers: &HeaderMap, media_type: &str) -> bool {
+    content_type(headers)
+        .split(';')
+        .next()
+        .is_some_and(|essence| essence.trim().eq_ignore_ascii_case(media_type))
+}
+
+/// Decode one event from a `/publish` request: CloudEvents binary mode (`ce-specversion`
+/// header), structured mode (`application/cloudevents+json`), or a plain envelope.
+pub fn decode_request(headers: &HeaderMap, body: &[u8]) -> Result<EventEnvelope, String> {
+    if headers.contains_key("ce-specversion") {
+        let ce = CloudEvent::from_binary(
+            Binding::Http,
+            headers.iter().map(|(k, v)| (k.as_str(), v.as_bytes())),
+            body,
+        )
+        .map_err(|e| e.to_string())?;
+        return EventEnvelope::try_from(ce).map_err(|e| e.to_string());
+    }
+    let value: Value = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+    decode_value(value, has_media_type(headers, cloudevents::STRUCTURED_CONTENT_TYPE))
+}
+
+/// Whether a `/publish/batch` body is a CloudEvents batch (`application/cloudevents-batch+json`).
+pub fn is_cloudevents_batch(headers: &HeaderMap) -> bool {
+    has_media_type(headers, cloudevents::BATCH_CONTENT_TYPE)
+}
+
+pub fn decode_value(value: Value, cloudevent: bool) -> Result<EventEnvelope, String> {
+    if cloudevent {
+        let ce: CloudEvent = serde_json::from_value(value).map_err(|e| e.to_string())?;
+        EventEnvelope::try_from(ce).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_value(value).map_err(|e| e.to_string())
+    }
+}
@@ -1,117 +1,136 @@
+mod bus;
+mod dead_letter;
+mod dedupe;
+mod erasure;
+mod event_log;
+mod format;
+mod publish;
+mod routing;
+mod subscribe;
+mod webhooks;
+
 use axum::{
-    extract::State,
     http::StatusCode,
-    routing::{get, post},
-    Json, Router,
-};
-use pagi_common::{PagiError, EventEnvelope};
-use pagi_http::errors::PagiAxumError;
-use rdkafka::{
-    admin::{AdminClient, AdminOptions, NewTopic, TopicReplication},
-    producer::{FutureProducer, FutureRecord},
-    ClientConfig,
+    routing::{get, patch, post},
+    Router,
 };
+use pagi_common::{auth::scopes, erasure::DeletionConfirmer};
+use pagi_http::auth::Auth;
 use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 
-const TOPIC: &str = "core-events";
+const DEFAULT_DEAD_LETTER_TOPIC: &str = "core-events.dlq";
 
-#[derive(Clone)]
 struct AppState {
-    producer: FutureProducer,
+    bus: Box<dyn bus::EventBus>,
+    hub: subscribe::Hub,
+    log: Option<event_log::EventLog>,
+    dedupe: dedupe::IdempotencyCache,
+    dead_letter_topic: String,
+    routes: routing::RoutingTable,
+    bus_format: format::EventFormat,
+    batch_max: usize,
+    webhooks: webhooks::Webhooks,
+    /// Confirms `twin_deleted` events before a twin's events are purged.
+    deletions: DeletionConfirmer,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     pagi_http::tracing::init("pagi-event-router");
 
-    let brokers = std::env::var("KAFKA_BROKERS").unwrap_or_else(|_| "localhost:9092".to_string());
-    tracing::info!(%brokers, "starting in kafka mode (kafka-only)");
+    let bus = bus::from_env()?;
+    tracing::info!(backend = bus.name(), "starting event router");
+
+    let dead_letter_topic =
+        std::env::var("DEAD_LETTER_TOPIC").unwrap_or_else(|_| DEFAULT_DEAD_LETTER_TOPIC.to_string());
+    let idempotency_window = std::env::var("IDEMPOTENCY_WINDOW_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(600);
+
+    let routes = routing::from_env()?;
+    let bus_format = match std::env::var("EVENT_BUS_FORMAT") {
+        Ok(raw) => format::EventFormat::parse(&raw).ok_or_else(|| {
+            format!("unknown EVENT_BUS_FORMAT '{raw}' (expected envelope|cloudevents|cloudevents-binary)")
+        })?,
+        Err(_) => format::EventFormat::default(),
+    };
+    if bus_format == format::EventFormat::CloudeventsBinary && bus.name() == "file" {
+        return Err("EVENT_BUS_FORMAT=cloudevents-binary keeps attributes in record headers, which the file bus \
+                    does not store; use cloudevents instead"
+            .into());
+    }
+    let batch_max = std::env::var("PUBLISH_BATCH_MAX")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(pagi_common::outbox::DEFAULT_PUBLISH_BATCH_MAX);
 
-    let producer: FutureProducer = ClientConfig::new()
-        .set("bootstrap.servers", &brokers)
-        .set("message.timeout.ms", "5000")
-        .create()?;
+    // Create topics if needed (best-effort).
+    routes.ensure_topics(&*bus).await;
+    bus.ensure_topic(&dead_letter_topic, 1).await;
 
-    // Create topic if needed (best-effort).
-    ensure_topic(&brokers).await;
+    // How many recent events `/subscribe` can resume from.
+    let retain: usize = std::env::var("SUBSCRIBE_RETAIN_EVENTS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1024);
 
     let state = Arc::new(AppState {
-        producer,
+        bus,
+        hub: subscribe::Hub::new(retain),
+        log: event_log::from_env()?,
+        dedupe: dedupe::IdempotencyCache::new(Duration::from_secs(idempotency_window)),
+        dead_letter_topic,
+        routes,
+        bus_format,
+        batch_max,
+        webhooks: webhooks::from_env()?,
+        deletions: DeletionConfirmer::from_env(),
     });
 
+    // Replay and webhook changes send events onwards, so they need the publish scope.
+    let auth = Auth::from_env();
+    let read = || auth.require(scopes::EVENTS_READ);
+    let write = || auth.require(scopes::EVENTS_PUBLISH);
     let app = Router::new()
         .route("/healthz", get(health))
-        .route("/publish", post(publish))
+        .route("/publish", post(publish::publish).route_layer(write()))
+        .route("/publish/batch", post(publish::publish_batch).route_layer(write()))
+        .route("/subscribe", get(subscribe::subscribe).route_layer(read()))
+        .route(
+            "/events",
+            get(event_log::list_events)
+                .route_layer(read())
+                .merge(post(erasure::handle_event).route_layer(write())),
+        )
+        .route("/replay", post(event_log::replay).route_layer(write()))
+        .route(
+            "/webhooks",
+            get(webhooks::list)
+                .route_layer(read())
+                .merge(post(webhooks::create).route_layer(write())),
+        )
+        .route(
+            "/webhooks/:id",
+            get(webhooks::get).route_layer(read()).merge(
+                patch(webhooks::update)
+                    .delete(webhooks::delete)
+                    .route_layer(write()),
+            ),
+        )
         .with_state(state)
+        .layer(axum::middleware::from_fn(pagi_http::trace_context::propagate))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive());
 
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 8000).into());
     tracing::info!(%addr, "listening");
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    pagi_http::tls::serve(listener, app).await?;
     Ok(())
 }
 
 async fn health() -> (StatusCode, &'static str) {
     (StatusCode::OK, "ok")
 }
-
-async fn publish(
-    State(state): State<Arc<AppState>>,
-    Json(mut ev): Json<EventEnvelope>,
-) -> Result<StatusCode, PagiAxumError> {
-    if ev.event_type.trim().is_empty() {
-        return Err(PagiAxumError::with_status(
-            PagiError::config("event_type required"),
-            StatusCode::BAD_REQUEST,
-        ));
-    }
-    if ev.source.is_none() {
-        ev.source = Some("pagi-event-router".to_string());
-    }
-
-    let payload = serde_json::to_string(&ev).map_err(|e| {
-        PagiAxumError::with_status(PagiError::Unknown(e.to_string()), StatusCode::BAD_REQUEST)
-    })?;
-
-    let key = ev
-        .twin_id
-        .map(|id| id.to_string())
-        .unwrap_or_else(|| ev.id.to_string());
-
-    let record = FutureRecord::to(TOPIC).payload(&payload).key(&key);
-    match state.producer.send(record, Duration::from_secs(5)).await {
-        Ok(_) => Ok(StatusCode::ACCEPTED),
-        Err((e, _)) => Err(PagiAxumError::with_status(
-            PagiError::plugin_exec(format!("kafka produce failed: {e}")),
-            StatusCode::BAD_GATEWAY,
-        )),
-    }
-}
-
-async fn ensure_topic(brokers: &str) {
-    let admin: AdminClient<_> = match ClientConfig::new().set("bootstrap.servers", brokers).create() {
-        Ok(a) => a,
-        Err(err) => {
-            tracing::warn!(error = %err, "failed to create kafka admin client");
-            return;
-        }
-    };
-
-    let new_topic = NewTopic::new(TOPIC, 1, TopicReplication::Fixed(1));
-    match admin.create_topics([&new_topic], &AdminOptions::new()).await {
-        Ok(results) => {
-            for res in results {
-                match res {
-                    Ok(name) => tracing::info!(%name, "topic ready"),
-                    Err((name, err)) => tracing::info!(%name, error = %err, "topic create skipped/failed"),
-                }
-            }
-        }
-        Err(err) => {
-            tracing::warn!(error = %err, "create_topics failed");
-        }
-    }
-}
@@ -0,0 +1,316 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use futures_util::future::join_all;
+use pagi_common::{
+    cloudevents::{self, Binding},
+    EventEnvelope, EventType, PagiError,
+};
+use pagi_http::errors::PagiAxumError;
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::{bus::BusRecord, dead_letter, format, AppState};
+
+/// Why an event was not produced, and the status reported for it.
+struct Rejection {
+    status: StatusCode,
+    error: PagiError,
+}
+
+impl From<Rejection> for PagiAxumError {
+    fn from(r: Rejection) -> Self {
+        PagiAxumError::with_status(r.error, r.status)
+    }
+}
+
+enum Admitted {
+    /// Already accepted within the idempotency window.
+    Duplicate,
+    Ready {
+        idempotency_key: String,
+        topic: String,
+        record: BusRecord,
+    },
+}
+
+/// `POST /publish`: an [`EventEnvelope`], or a CloudEvent in structured
+/// (`application/cloudevents+json`) or binary (`ce-*` headers) HTTP mode.
+///
+/// - `202 Accepted`: produced
+/// - `200 OK`: duplicate of an event accepted within the idempotency window (not produced again)
+/// - `400`/`422`: malformed or schema-invalid; the body is dead-lettered with the reason
+/// - `502`: the bus failed; the event is dead-lettered and the caller should retry
+pub async fn publish(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, PagiAxumError> {
+    let mut ev = match format::decode_request(&headers, &body) {
+        Ok(ev) => ev,
+        Err(err) => {
+            let raw = Value::String(String::from_utf8_lossy(&body).into_owned());
+            return Err(reject_undecodable(&state, &err, raw).await.into());
+        }
+    };
+
+    let idempotency_key = headers
+        .get("idempotency-key")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+
+    match admit(&state, &mut ev, idempotency_key).await? {
+        Admitted::Duplicate => Ok(StatusCode::OK),
+        Admitted::Ready {
+            idempotency_key,
+            topic,
+            record,
+        } => {
+            let produced = state.bus.produce(&topic, &record).await;
+            settle(&state, ev, &idempotency_key, &record, produced).await?;
+            Ok(StatusCode::ACCEPTED)
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BatchItemResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    /// What `POST /publish` would have returned for this event alone.
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct BatchResponse {
+    pub accepted: usize,
+    pub duplicates: usize,
+    pub failed: usize,
+    /// One entry per input event, in input order.
+    pub results: Vec<BatchItemResult>,
+}
+
+/// `POST /publish/batch`: a JSON array of envelopes, or of structured CloudEvents when sent
+/// as `application/cloudevents-batch+json`.
+///
+/// Every event goes through the same validation, deduplication (by envelope `id`) and
+/// dead-lettering as `POST /publish`; one bad event does not fail the batch. Produces are
+/// issued together, and accepted events reach the log and subscribers in input order.
+pub async fn publish_batch(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(items): Json<Vec<Value>>,
+) -> Result<Json<BatchResponse>, PagiAxumError> {
+    let cloudevents = format::is_cloudevents_batch(&headers);
+    if items.len() > state.batch_max {
+        return Err(PagiAxumError::with_status(
+            PagiError::config(format!("batch of {} exceeds PUBLISH_BATCH_MAX={}", items.len(), state.batch_max)),
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ));
+    }
+
+    let mut results = Vec::with_capacity(items.len());
+    let mut ready = Vec::new();
+    for (index, item) in items.into_iter().enumerate() {
+        let mut ev = match format::decode_value(item.clone(), cloudevents) {
+            Ok(ev) => ev,
+            Err(err) => {
+                results.push(failed(None, reject_undecodable(&state, &err, item).await));
+                continue;
+            }
+        };
+        match admit(&state, &mut ev, None).await {
+            Ok(Admitted::Duplicate) => results.push(BatchItemResult {
+                id: Some(ev.id),
+                status: StatusCode::OK.as_u16(),
+                error: None,
+            }),
+            Ok(Admitted::Ready {
+                idempotency_key,
+                topic,
+                record,
+            }) => {
+                // Placeholder until the produce below settles.
+                results.push(BatchItemResult {
+                    id: Some(ev.id),
+                    status: StatusCode::ACCEPTED.as_u16(),
+                    error: None,
+                });
+                ready.push((index, ev, idempotency_key, topic, record));
+            }
+            Err(rejection) => results.push(failed(Some(ev.id), rejection)),
+        }
+    }
+
+    let produced = join_all(
+        ready
+            .iter()
+            .map(|(_, _, _, topic, record)| state.bus.produce(topic, record)),
+    )
+    .await;
+
+    for ((index, ev, idempotency_key, _, record), outcome) in ready.into_iter().zip(produced) {
+        let id = ev.id;
+        if let Err(rejection) = settle(&state, ev, &idempotency_key, &record, outcome).await {
+            results[index] = failed(Some(id), rejection);
+        }
+    }
+
+    let mut resp = BatchResponse::default();
+    for r in &results {
+        match r.status {
+            202 => resp.accepted += 1,
+            200 => resp.duplicates += 1,
+            _ => resp.failed += 1,
+        }
+    }
+    resp.results = results;
+    Ok(Json(resp))
+}
+
+/// Publish an event raised by the router itself, as `/publish` would.
+pub async fn publish_own(state: &AppState, mut ev: EventEnvelope) -> Result<(), String> {
+    match admit(state, &mut ev, None).await {
+        Ok(Admitted::Ready {
+            idempotency_key,
+            topic,
+            record,
+        }) => {
+            let produced = state.bus.produce(&topic, &record).await;
+            settle(state, ev, &idempotency_key, &record, produced)
+                .await
+                .map_err(|rejection| rejection.error.to_string())
+        }
+        Ok(Admitted::Duplicate) => Ok(()),
+        Err(rejection) => Err(rejection.error.to_string()),
+    }
+}
+
+fn failed(id: Option<Uuid>, rejection: Rejection) -> BatchItemResult {
+    BatchItemResult {
+        id,
+        status: rejection.status.as_u16(),
+        error: Some(rejection.error.to_string()),
+    }
+}
+
+async fn reject_undecodable(state: &AppState, err: &str, raw: Value) -> Rejection {
+    let reason = format!("invalid envelope: {err}");
+    dead_letter::send(&*state.bus, &state.dead_letter_topic, dead_letter::Stage::Decode, &reason, "", raw).await;
+    Rejection {
+        status: StatusCode::BAD_REQUEST,
+        error: PagiError::config(reason),
+    }
+}
+
+/// Validate, deduplicate and route a decoded envelope.
+async fn admit(
+    state: &AppState,
+    ev: &mut EventEnvelope,
+    idempotency_key: Option<String>,
+) -> Result<Admitted, Rejection> {
+    if let Err(reason) = validate(ev) {
+        let key = ev.id.to_string();
+        let body = serde_json::to_value(&*ev).unwrap_or_default();
+        dead_letter::send(&*state.bus, &state.dead_letter_topic, dead_letter::Stage::Validate, &reason, &key, body).await;
+        return Err(Rejection {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            error: PagiError::config(reason),
+        });
+    }
+
+    let idempotency_key = idempotency_key.unwrap_or_else(|| ev.id.to_string());
+    if !state.dedupe.reserve(&idempotency_key) {
+        tracing::debug!(event_id = %ev.id, %idempotency_key, "duplicate publish ignored");
+        return Ok(Admitted::Duplicate);
+    }
+
+    if ev.source.is_none() {
+        ev.source = Some("pagi-event-router".to_string());
+    }
+
+    let (payload, headers) = match state.bus_format.encode(ev, Binding::Kafka) {
+        Ok(encoded) => encoded,
+        Err(e) => {
+            state.dedupe.release(&idempotency_key);
+            return Err(Rejection {
+                status: StatusCode::BAD_REQUEST,
+                error: PagiError::Unknown(e.to_string()),
+            });
+        }
+    };
+
+    let route = state.routes.route(&ev.event_type);
+    Ok(Admitted::Ready {
+        idempotency_key,
+        topic: route.topic.clone(),
+        record: route.record_for(ev, payload, headers),
+    })
+}
+
+/// Record the outcome of a produce: fan out on success, dead-letter on failure.
+async fn settle(
+    state: &AppState,
+    ev: EventEnvelope,
+    idempotency_key: &str,
+    record: &BusRecord,
+    produced: Result<(), String>,
+) -> Result<(), Rejection> {
+    match produced {
+        Ok(()) => {
+            if let Some(log) = &state.log {
+                // The bus already has the event; a log failure only costs queryability.
+                if let Err(err) = log.append(&ev) {
+                    tracing::warn!(event_id = %ev.id, error = %err, "event log append failed");
+                }
+            }
+            state.webhooks.dispatch(&ev);
+            state.hub.publish(ev);
+            Ok(())
+        }
+        Err(e) => {
+            state.dedupe.release(idempotency_key);
+            let body = serde_json::to_value(&ev).unwrap_or_default();
+            dead_letter::send(&*state.bus, &state.dead_letter_topic, dead_letter::Stage::Produce, &e, &record.key, body).await;
+            Err(Rejection {
+                status: StatusCode::BAD_GATEWAY,
+                error: PagiError::plugin_exec(e),
+            })
+        }
+    }
+}
+
+/// Known event types must parse (after upcasting) into the matching [`pagi_common::CoreEvent`].
+/// Unknown types are let through untouched so plugins can publish their own events.
+fn validate(ev: &EventEnvelope) -> Result<(), String> {
+    if ev.event_type.trim().is_empty() {
+        return Err("event_type required".to_string());
+    }
+    // Extensions become CloudEvents attributes on the way out, so they must be valid names.
+    if let Some(name) = ev.extensions.keys().find(|name| !cloudevents::is_valid_attribute_name(name)) {
+        return Err(format!("invalid extension name '{name}' (expected 1-20 of a-z, 0-9)"));
+    }
+    let Some(kind) = EventType::parse(&ev.event_type) else {
+        return Ok(());
+    };
+
+    let core = ev
+        .core_event()
+        .map_err(|e| format!("payload does not match '{}' schema: {e}", ev.event_type))?;
+    if core.kind() != kind {
+        return Err(format!(
+            "payload is tagged '{}' but event_type is '{}'",
+            core.event_type(),
+            ev.event_type
+        ));
+    }
+    Ok(())
+}
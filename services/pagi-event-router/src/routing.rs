@@ -0,0 +1,113 @@
+use pagi_common::EventEnvelope;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::bus::{BusRecord, EventBus};
+
+pub const DEFAULT_TOPIC: &str = "core-events";
+
+/// Which envelope field becomes the bus record key (and therefore the partition).
+///
+/// Falls back to the event `id` when the chosen field is absent.
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyField {
+    #[default]
+    TwinId,
+    Id,
+    Subject,
+    Source,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Route {
+    pub topic: String,
+    #[serde(default = "default_partitions")]
+    pub partitions: i32,
+    #[serde(default)]
+    pub key: KeyField,
+}
+
+fn default_partitions() -> i32 {
+    1
+}
+
+impl Default for Route {
+    fn default() -> Self {
+        Self {
+            topic: DEFAULT_TOPIC.to_string(),
+            partitions: default_partitions(),
+            key: KeyField::default(),
+        }
+    }
+}
+
+impl Route {
+    pub fn record_for(&self, ev: &EventEnvelope, payload: String, headers: Vec<(String, String)>) -> BusRecord {
+        let key = match self.key {
+            KeyField::TwinId => ev.twin_id.map(|id| id.to_string()),
+            KeyField::Id => None,
+            KeyField::Subject => ev.subject.clone(),
+            KeyField::Source => ev.source.clone(),
+        }
+        .unwrap_or_else(|| ev.id.to_string());
+        BusRecord { key, payload, headers }
+    }
+}
+
+/// Event type → topic routing, loaded from a TOML file (`EVENT_ROUTES_FILE`):
+///
+/// ```toml
+/// [default]
+/// topic = "core-events"
+/// partitions = 3
+///
+/// [routes.working_memory_appended]
+/// topic = "core-events.working-memory"
+/// partitions = 6
+/// key = "twin_id"   # twin_id | id | subject | source
+/// ```
+///
+/// Event types without an entry go to `default`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RoutingTable {
+    #[serde(default)]
+    pub default: Route,
+    #[serde(default)]
+    pub routes: HashMap<String, Route>,
+}
+
+impl RoutingTable {
+    pub fn route(&self, event_type: &str) -> &Route {
+        self.routes.get(event_type).unwrap_or(&self.default)
+    }
+
+    /// Every distinct route, for topic creation at startup.
+    pub fn all(&self) -> impl Iterator<Item = &Route> {
+        std::iter::once(&self.default).chain(self.routes.values())
+    }
+
+    /// Create every routed topic (best-effort, like [`EventBus::ensure_topic`]).
+    pub async fn ensure_topics(&self, bus: &dyn EventBus) {
+        let mut partitions: HashMap<&str, i32> = HashMap::new();
+        for route in self.all() {
+            let n = partitions.entry(route.topic.as_str()).or_insert(route.partitions);
+            *n = (*n).max(route.partitions);
+        }
+        for (topic, n) in partitions {
+            bus.ensure_topic(topic, n.max(1)).await;
+        }
+    }
+}
+
+pub fn from_env() -> Result<RoutingTable, String> {
+    let Ok(path) = std::env::var("EVENT_ROUTES_FILE") else {
+        return Ok(RoutingTable::default());
+    };
+    let raw = std::fs::read_to_string(&path).map_err(|e| format!("event routes {path}: {e}"))?;
+    let table: RoutingTable = toml::from_str(&raw).map_err(|e| format!("event routes {path}: {e}"))?;
+    for (event_type, route) in &table.routes {
+        tracing::info!(%event_type, topic = %route.topic, partitions = route.partitions, key = ?route.key, "event route");
+    }
+    Ok(table)
+}
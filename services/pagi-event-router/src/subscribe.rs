@@ -0,0 +1,337 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    http::HeaderMap,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures_util::stream::{self, Stream, StreamExt};
+use pagi_common::EventEnvelope;
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use uuid::Uuid;
+
+use crate::{format::EventFormat, AppState};
+
+/// In-process fan-out of published events to live subscribers.
+///
+/// Keeps a bounded ring of recent events so clients can resume from the last id they saw
+/// (SSE `Last-Event-ID` or `?last_event_id=`). A client whose last id is no longer retained
+/// resumes from the newest event and is sent a `gap` event first.
+pub struct Hub {
+    tx: broadcast::Sender<Arc<EventEnvelope>>,
+    recent: Mutex<VecDeque<Arc<EventEnvelope>>>,
+    retain: usize,
+}
+
+impl Hub {
+    pub fn new(retain: usize) -> Self {
+        let (tx, _) = broadcast::channel(retain.max(16));
+        Self {
+            tx,
+            recent: Mutex::new(VecDeque::with_capacity(retain)),
+            retain,
+        }
+    }
+
+    pub fn publish(&self, ev: EventEnvelope) {
+        let ev = Arc::new(ev);
+        // Push + send under the same lock so `subscribe` never sees a gap or a duplicate.
+        let mut recent = self.recent.lock().unwrap_or_else(|p| p.into_inner());
+        if self.retain > 0 {
+            if recent.len() == self.retain {
+                recent.pop_front();
+            }
+            recent.push_back(ev.clone());
+        }
+        let _ = self.tx.send(ev);
+    }
+
+    /// Forget the retained events of `twin_id`, returning how many there were. Clients resuming
+    /// from one of them get a `gap`.
+    pub fn purge_twin(&self, twin_id: Uuid) -> usize {
+        let mut recent = self.recent.lock().unwrap_or_else(|p| p.into_inner());
+        let before = recent.len();
+        recent.retain(|ev| ev.twin_id != Some(twin_id));
+        before - recent.len()
+    }
+
+    /// Subscribe to live events, returning the retained events published after `last_seen` first.
+    ///
+    /// The backlog is `None` when `last_seen` is no longer retained (or unknown): there is no
+    /// telling what the subscriber missed, so it only gets live events from here on.
+    fn subscribe(
+        &self,
+        last_seen: Option<Uuid>,
+    ) -> (Option<Vec<Arc<EventEnvelope>>>, broadcast::Receiver<Arc<EventEnvelope>>) {
+        let recent = self.recent.lock().unwrap_or_else(|p| p.into_inner());
+        let backlog = match last_seen {
+            None => Some(Vec::new()),
+            Some(id) => recent
+                .iter()
+                .position(|e| e.id == id)
+                .map(|i| recent.iter().skip(i + 1).cloned().collect()),
+        };
+        (backlog, self.tx.subscribe())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SubscribeQuery {
+    /// Comma-separated list of event types.
+    #[serde(default)]
+    pub event_type: Option<String>,
+    #[serde(default)]
+    pub twin_id: Option<Uuid>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub subject: Option<String>,
+    #[serde(default)]
+    pub correlation_id: Option<Uuid>,
+    #[serde(default)]
+    pub last_event_id: Option<Uuid>,
+    /// `envelope` (default) or `cloudevents` (structured).
+    #[serde(default)]
+    pub format: EventFormat,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    event_types: Vec<String>,
+    twin_id: Option<Uuid>,
+    source: Option<String>,
+    subject: Option<String>,
+    correlation_id: Option<Uuid>,
+}
+
+impl EventFilter {
+    pub fn matches(&self, ev: &EventEnvelope) -> bool {
+        if !self.event_types.is_empty() && !self.event_types.iter().any(|t| t == &ev.event_type) {
+            return false;
+        }
+        if self.twin_id.is_some() && ev.twin_id != self.twin_id {
+            return false;
+        }
+        if self.source.is_some() && ev.source != self.source {
+            return false;
+        }
+        if self.subject.is_some() && ev.subject != self.subject {
+            return false;
+        }
+        if self.correlation_id.is_some() && ev.correlation_id != self.correlation_id {
+            return false;
+        }
+        true
+    }
+
+    /// `event_types` is a comma-separated list; empty entries are ignored.
+    pub fn from_parts(
+        event_types: Option<&str>,
+        twin_id: Option<Uuid>,
+        source: Option<String>,
+        subject: Option<String>,
+    ) -> Self {
+        let event_types = event_types
+            .map(|raw| {
+                raw.split(',')
+                    .map(|s| s.trim())
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            event_types,
+            twin_id,
+            source,
+            subject,
+            correlation_id: None,
+        }
+    }
+
+    /// Restrict to the events of one request/trace.
+    pub fn with_correlation(mut self, correlation_id: Option<Uuid>) -> Self {
+        self.correlation_id = correlation_id;
+        self
+    }
+}
+
+impl From<&SubscribeQuery> for EventFilter {
+    fn from(q: &SubscribeQuery) -> Self {
+        Self::from_parts(q.event_type.as_deref(), q.twin_id, q.source.clone(), q.subject.clone())
+            .with_correlation(q.correlation_id)
+    }
+}
+
+/// `GET /subscribe`: WebSocket when the request is an upgrade, Server-Sent Events otherwise.
+pub async fn subscribe(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SubscribeQuery>,
+    headers: HeaderMap,
+    ws: Option<WebSocketUpgrade>,
+) -> Response {
+    let filter = EventFilter::from(&query);
+    let format = query.format;
+    let last_seen = query.last_event_id.or_else(|| {
+        headers
+            .get("last-event-id")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| Uuid::parse_str(v.trim()).ok())
+    });
+
+    let (backlog, rx) = state.hub.subscribe(last_seen);
+    // Tell the client its resume point is gone, so it can fall back to `/events` or `/replay`.
+    let gap = last_seen.filter(|_| backlog.is_none());
+    let backlog: VecDeque<_> = backlog.unwrap_or_default().into_iter().filter(|e| filter.matches(e)).collect();
+
+    match ws {
+        Some(upgrade) => upgrade
+            .on_upgrade(move |socket| stream_ws(socket, gap, backlog, rx, filter, format))
+            .into_response(),
+        None => {
+            let gap = gap.map(|id| Ok(Event::default().event("gap").data(id.to_string())));
+            Sse::new(stream::iter(gap).chain(sse_stream(backlog, rx, filter, format)))
+                .keep_alive(KeepAlive::default())
+                .into_response()
+        }
+    }
+}
+
+enum Next {
+    Event(Arc<EventEnvelope>),
+    Lagged(u64),
+    Closed,
+}
+
+async fn next_matching(
+    backlog: &mut VecDeque<Arc<EventEnvelope>>,
+    rx: &mut broadcast::Receiver<Arc<EventEnvelope>>,
+    filter: &EventFilter,
+) -> Next {
+    if let Some(ev) = backlog.pop_front() {
+        return Next::Event(ev);
+    }
+    loop {
+        match rx.recv().await {
+            Ok(ev) if filter.matches(&ev) => return Next::Event(ev),
+            Ok(_) => continue,
+            Err(RecvError::Lagged(n)) => return Next::Lagged(n),
+            Err(RecvError::Closed) => return Next::Closed,
+        }
+    }
+}
+
+fn sse_stream(
+    backlog: VecDeque<Arc<EventEnvelope>>,
+    rx: broadcast::Receiver<Arc<EventEnvelope>>,
+    filter: EventFilter,
+    format: EventFormat,
+) -> impl Stream<Item = Result<Event, Infallible>> {
+    stream::unfold((backlog, rx, filter), move |(mut backlog, mut rx, filter)| async move {
+        let event = match next_matching(&mut backlog, &mut rx, &filter).await {
+            Next::Event(ev) => format
+                .document(&ev)
+                .ok()
+                .and_then(|doc| Event::default().id(ev.id.to_string()).event(ev.event_type.clone()).json_data(doc).ok())
+                .unwrap_or_else(|| Event::default().comment("unserializable event")),
+            // Slow consumer: tell the client how many events it missed so it can resume/replay.
+            Next::Lagged(skipped) => Event::default().event("lagged").data(skipped.to_string()),
+            Next::Closed => return None,
+        };
+        Some((Ok(event), (backlog, rx, filter)))
+    })
+}
+
+async fn stream_ws(
+    mut socket: WebSocket,
+    gap: Option<Uuid>,
+    mut backlog: VecDeque<Arc<EventEnvelope>>,
+    mut rx: broadcast::Receiver<Arc<EventEnvelope>>,
+    filter: EventFilter,
+    format: EventFormat,
+) {
+    if let Some(id) = gap {
+        let msg = Message::Text(serde_json::json!({"gap": id}).to_string());
+        if socket.send(msg).await.is_err() {
+            return;
+        }
+    }
+    loop {
+        let msg = tokio::select! {
+            next = next_matching(&mut backlog, &mut rx, &filter) => match next {
+                Next::Event(ev) => match format.document(&ev) {
+                    Ok(doc) => Message::Text(doc.to_string()),
+                    Err(_) => continue,
+                },
+                Next::Lagged(skipped) => Message::Text(serde_json::json!({"lagged": skipped}).to_string()),
+                Next::Closed => break,
+            },
+            incoming = socket.recv() => match incoming {
+                // Subscribers are read-only; anything but close/errors is ignored.
+                Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                Some(Ok(_)) => continue,
+            },
+        };
+        if socket.send(msg).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pagi_common::EventType;
+
+    fn publish(hub: &Hub, n: usize) -> Vec<Uuid> {
+        (0..n)
+            .map(|i| {
+                let ev = EventEnvelope::new(EventType::ContextBuilt, serde_json::json!({"n": i}));
+                let id = ev.id;
+                hub.publish(ev);
+                id
+            })
+            .collect()
+    }
+
+    fn ids(backlog: Option<Vec<Arc<EventEnvelope>>>) -> Option<Vec<Uuid>> {
+        backlog.map(|events| events.iter().map(|e| e.id).collect())
+    }
+
+    #[test]
+    fn resumes_after_last_seen() {
+        let hub = Hub::new(8);
+        let sent = publish(&hub, 3);
+        assert_eq!(ids(hub.subscribe(Some(sent[0])).0), Some(sent[1..].to_vec()));
+        assert_eq!(ids(hub.subscribe(Some(sent[2])).0), Some(vec![]));
+    }
+
+    #[test]
+    fn fresh_subscribers_get_live_events_only() {
+        let hub = Hub::new(8);
+        publish(&hub, 2);
+        let (backlog, mut rx) = hub.subscribe(None);
+        assert_eq!(ids(backlog), Some(vec![]));
+        let live = publish(&hub, 1);
+        assert_eq!(rx.try_recv().unwrap().id, live[0]);
+    }
+
+    #[test]
+    fn unknown_or_evicted_last_seen_is_a_gap() {
+        let hub = Hub::new(2);
+        let sent = publish(&hub, 3);
+        assert_eq!(ids(hub.subscribe(Some(sent[0])).0), None);
+        assert_eq!(ids(hub.subscribe(Some(Uuid::new_v4())).0), None);
+        assert_eq!(ids(hub.subscribe(Some(sent[1])).0), Some(vec![sent[2]]));
+    }
+}
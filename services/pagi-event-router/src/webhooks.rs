@@ -0,0 +1,720 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use hmac::{Hmac, Mac};
+use pagi_common::{
+    cloudevents::Binding,
+    trace_context::{CORRELATION_ID_HEADER, TRACEPARENT_HEADER},
+    EventEnvelope, PagiError,
+};
+use pagi_http::errors::PagiAxumError;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{
+    collections::HashMap,
+    path::{Path as FsPath, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{format::EventFormat, AppState};
+
+pub const SIGNATURE_HEADER: &str = "x-pagi-signature";
+pub const TIMESTAMP_HEADER: &str = "x-pagi-timestamp";
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_DISABLE_AFTER: u32 = 20;
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A registered webhook. `secret` is only ever returned by the create call.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subscription {
+    pub id: Uuid,
+    pub url: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub secret: String,
+    /// Empty means every event type.
+    #[serde(default)]
+    pub event_types: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub twin_id: Option<Uuid>,
+    /// Body format; the signature always covers the body as sent.
+    #[serde(default)]
+    pub format: EventFormat,
+    /// Attempts per event before it is given up on.
+    pub max_attempts: u32,
+    /// Consecutive failed attempts after which the subscription is disabled.
+    pub disable_after: u32,
+    pub enabled: bool,
+    /// Kept in memory between changes to the subscription; persisted when it is disabled,
+    /// re-enabled or edited.
+    #[serde(default)]
+    pub consecutive_failures: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl Subscription {
+    fn matches(&self, ev: &EventEnvelope) -> bool {
+        if !self.event_types.is_empty() && !self.event_types.iter().any(|t| t == &ev.event_type) {
+            return false;
+        }
+        self.twin_id.is_none() || ev.twin_id == self.twin_id
+    }
+
+    fn redacted(&self) -> Self {
+        Self {
+            secret: String::new(),
+            ..self.clone()
+        }
+    }
+}
+
+/// `X-Pagi-Signature` value: `sha256=` + hex HMAC-SHA256 of `"{timestamp}.{body}"` keyed by the
+/// subscription secret. Binding the timestamp lets receivers reject replays of old deliveries.
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Persistent webhook registry plus one delivery worker per subscription.
+///
+/// Each worker owns a bounded queue, so a slow or dead endpoint only delays its own
+/// deliveries. Events are delivered in order, retried with exponential backoff, and the
+/// subscription is disabled once it accumulates `disable_after` consecutive failures.
+#[derive(Clone)]
+pub struct Webhooks {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    path: Option<PathBuf>,
+    queue_len: usize,
+    http: reqwest::Client,
+    subs: Mutex<HashMap<Uuid, Entry>>,
+    /// Held while writing the store, so writes of successive snapshots do not interleave.
+    saving: Mutex<()>,
+}
+
+struct Entry {
+    sub: Subscription,
+    tx: mpsc::Sender<Arc<EventEnvelope>>,
+}
+
+impl Webhooks {
+    /// Load persisted subscriptions from `path` and start their workers.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn open(path: Option<PathBuf>, queue_len: usize) -> Result<Self, String> {
+        let saved = match &path {
+            Some(path) => load(path)?,
+            None => Vec::new(),
+        };
+        let this = Self {
+            inner: Arc::new(Inner {
+                path,
+                queue_len: queue_len.max(1),
+                http: reqwest::Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()
+                    .unwrap_or_default(),
+                subs: Mutex::new(HashMap::new()),
+                saving: Mutex::new(()),
+            }),
+        };
+        if !saved.is_empty() {
+            tracing::info!(subscriptions = saved.len(), "webhook subscriptions restored");
+        }
+        for sub in saved {
+            this.insert(sub);
+        }
+        Ok(this)
+    }
+
+    /// Queue `ev` for every enabled subscription it matches. Never blocks.
+    pub fn dispatch(&self, ev: &EventEnvelope) {
+        let subs = self.lock();
+        let mut shared: Option<Arc<EventEnvelope>> = None;
+        for entry in subs.values() {
+            if !entry.sub.enabled || !entry.sub.matches(ev) {
+                continue;
+            }
+            let queued = shared.get_or_insert_with(|| Arc::new(ev.clone())).clone();
+            if entry.tx.try_send(queued).is_err() {
+                tracing::warn!(webhook = %entry.sub.id, event_id = %ev.id, "webhook queue full; event dropped");
+            }
+        }
+    }
+
+    pub fn exists(&self, id: Uuid) -> bool {
+        self.lock().contains_key(&id)
+    }
+
+    /// Deliver `ev` to one subscription now, outside its queue and filters (for replay).
+    pub async fn send_to(&self, id: Uuid, ev: &EventEnvelope) -> Result<(), String> {
+        match self.get(id) {
+            Some(sub) if sub.enabled => self.deliver(&sub, ev).await,
+            Some(_) => Err("webhook is disabled".to_string()),
+            None => Err("webhook not found".to_string()),
+        }
+    }
+
+    /// Register a new subscription and persist it.
+    async fn add(&self, sub: Subscription) {
+        self.insert(sub);
+        self.persist().await;
+    }
+
+    fn insert(&self, sub: Subscription) {
+        let (tx, rx) = mpsc::channel(self.inner.queue_len);
+        let id = sub.id;
+        self.lock().insert(id, Entry { sub, tx });
+        let this = self.clone();
+        tokio::spawn(async move { this.run(id, rx).await });
+    }
+
+    fn get(&self, id: Uuid) -> Option<Subscription> {
+        self.lock().get(&id).map(|e| e.sub.clone())
+    }
+
+    fn list(&self) -> Vec<Subscription> {
+        let mut subs: Vec<_> = self.lock().values().map(|e| e.sub.clone()).collect();
+        subs.sort_by_key(|s| s.id);
+        subs
+    }
+
+    /// Apply `f` to a subscription in memory only.
+    fn modify(&self, id: Uuid, f: impl FnOnce(&mut Subscription)) -> Option<Subscription> {
+        let mut subs = self.lock();
+        let entry = subs.get_mut(&id)?;
+        f(&mut entry.sub);
+        Some(entry.sub.clone())
+    }
+
+    /// Apply `f` to a subscription and persist the result.
+    async fn update(&self, id: Uuid, f: impl FnOnce(&mut Subscription)) -> Option<Subscription> {
+        let updated = self.modify(id, f)?;
+        self.persist().await;
+        Some(updated)
+    }
+
+    async fn remove(&self, id: Uuid) -> bool {
+        // Dropping the sender ends the worker once it drains what it already holds.
+        let removed = self.lock().remove(&id).is_some();
+        if removed {
+            self.persist().await;
+        }
+        removed
+    }
+
+    async fn run(self, id: Uuid, mut rx: mpsc::Receiver<Arc<EventEnvelope>>) {
+        while let Some(ev) = rx.recv().await {
+            let mut backoff = INITIAL_BACKOFF;
+            let mut attempt = 0;
+            // Re-read every attempt so updates, disabling and deletion take effect mid-retry.
+            while let Some(sub) = self.get(id).filter(|s| s.enabled) {
+                attempt += 1;
+
+                match self.deliver(&sub, &ev).await {
+                    Ok(()) => {
+                        if sub.consecutive_failures > 0 {
+                            self.modify(id, |s| {
+                                s.consecutive_failures = 0;
+                                s.last_error = None;
+                            });
+                        }
+                        break;
+                    }
+                    Err(reason) => {
+                        let disabled = self.record_failure(id, &reason).await;
+                        tracing::warn!(webhook = %id, event_id = %ev.id, attempt, %reason, "webhook delivery failed");
+                        if disabled || attempt >= sub.max_attempts {
+                            break;
+                        }
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(MAX_BACKOFF);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn deliver(&self, sub: &Subscription, ev: &EventEnvelope) -> Result<(), String> {
+        let (body, headers) = sub.format.encode(ev, Binding::Http).map_err(|e| e.to_string())?;
+        let timestamp = OffsetDateTime::now_utc().unix_timestamp();
+        let mut req = self
+            .inner
+            .http
+            .post(&sub.url)
+            .header(SIGNATURE_HEADER, sign(&sub.secret, timestamp, body.as_bytes()))
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header("x-pagi-event-id", ev.id.to_string())
+            .header("x-pagi-event-type", ev.event_type.as_str());
+        // Let receivers continue the trace that produced the event.
+        if let Some(traceparent) = &ev.traceparent {
+            req = req.header(TRACEPARENT_HEADER, traceparent.as_str());
+        }
+        if let Some(correlation_id) = ev.correlation_id {
+            req = req.header(CORRELATION_ID_HEADER, correlation_id.to_string());
+        }
+        for (name, value) in headers {
+            req = req.header(name, value);
+        }
+        let resp = req.body(body).send().await.map_err(|e| e.to_string())?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("HTTP {}", resp.status()))
+        }
+    }
+
+    /// Returns whether this failure disabled the subscription, which is then persisted.
+    async fn record_failure(&self, id: Uuid, reason: &str) -> bool {
+        let mut disabled = false;
+        self.modify(id, |s| {
+            s.consecutive_failures += 1;
+            s.last_error = Some(reason.to_string());
+            if s.enabled && s.consecutive_failures >= s.disable_after {
+                s.enabled = false;
+                disabled = true;
+            }
+        });
+        if disabled {
+            tracing::warn!(webhook = %id, %reason, "webhook disabled after repeated failures");
+            self.persist().await;
+        }
+        disabled
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<Uuid, Entry>> {
+        self.inner.subs.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    /// Write the subscriptions as they are when the write starts, off the async runtime.
+    async fn persist(&self) {
+        let Some(path) = self.inner.path.clone() else {
+            return;
+        };
+        let this = self.clone();
+        let saved = tokio::task::spawn_blocking(move || {
+            let _saving = this.inner.saving.lock().unwrap_or_else(|p| p.into_inner());
+            save(&path, &this.list())
+        })
+        .await;
+        let err = match saved {
+            Ok(Ok(())) => return,
+            Ok(Err(err)) => err.to_string(),
+            Err(err) => err.to_string(),
+        };
+        tracing::warn!(path = ?self.inner.path, error = %err, "failed to persist webhook subscriptions");
+    }
+}
+
+fn load(path: &FsPath) -> Result<Vec<Subscription>, String> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| format!("webhook store {}: {e}", path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(format!("webhook store {}: {err}", path.display())),
+    }
+}
+
+/// Write the subscriptions, secrets included, readable by the owner only (`0600` on unix).
+/// The write is atomic, so a crash leaves either the old or the new file.
+fn save(path: &FsPath, subs: &[Subscription]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    // A leftover temp file could carry looser permissions; the mode only applies on create.
+    match std::fs::remove_file(&tmp) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(&tmp)?;
+    std::io::Write::write_all(&mut file, &serde_json::to_vec_pretty(subs)?)?;
+    file.sync_all()?;
+    std::fs::rename(tmp, path)
+}
+
+/// Env:
+/// - `WEBHOOK_STORE`: `false` keeps subscriptions in memory only (default `true`)
+/// - `WEBHOOK_STORE_DIR`: directory for `webhooks.json` (default `/data/webhooks`)
+/// - `WEBHOOK_QUEUE_SIZE`: pending deliveries per subscription before events are dropped (default `1000`)
+pub fn from_env() -> Result<Webhooks, String> {
+    let persist = std::env::var("WEBHOOK_STORE")
+        .unwrap_or_else(|_| "true".to_string())
+        .to_lowercase()
+        != "false";
+    let path = persist.then(|| {
+        let dir = std::env::var("WEBHOOK_STORE_DIR").unwrap_or_else(|_| "/data/webhooks".to_string());
+        PathBuf::from(dir).join("webhooks.json")
+    });
+    let queue_len = std::env::var("WEBHOOK_QUEUE_SIZE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1000);
+    Webhooks::open(path, queue_len)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhook {
+    pub url: String,
+    /// Generated when omitted.
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub event_types: Vec<String>,
+    #[serde(default)]
+    pub twin_id: Option<Uuid>,
+    #[serde(default)]
+    pub format: EventFormat,
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+    #[serde(default)]
+    pub disable_after: Option<u32>,
+}
+
+/// Partial update; absent fields are left unchanged, and `"twin_id": null` removes the twin
+/// filter. Setting `enabled: true` also clears the failure count so a re-enabled webhook gets
+/// a fresh budget.
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhook {
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub event_types: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "present")]
+    pub twin_id: Option<Option<Uuid>>,
+    #[serde(default)]
+    pub format: Option<EventFormat>,
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+    #[serde(default)]
+    pub disable_after: Option<u32>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+impl UpdateWebhook {
+    fn apply(self, s: &mut Subscription) {
+        if let Some(url) = self.url {
+            s.url = url;
+        }
+        if let Some(secret) = self.secret.filter(|s| !s.is_empty()) {
+            s.secret = secret;
+        }
+        if let Some(event_types) = self.event_types {
+            s.event_types = event_types;
+        }
+        if let Some(twin_id) = self.twin_id {
+            s.twin_id = twin_id;
+        }
+        if let Some(format) = self.format {
+            s.format = format;
+        }
+        if let Some(n) = self.max_attempts {
+            s.max_attempts = n.max(1);
+        }
+        if let Some(n) = self.disable_after {
+            s.disable_after = n.max(1);
+        }
+        if let Some(enabled) = self.enabled {
+            if enabled && !s.enabled {
+                s.consecutive_failures = 0;
+                s.last_error = None;
+            }
+            s.enabled = enabled;
+        }
+    }
+}
+
+/// `Some` for a field that is present, even as `null`, so it can be told apart from an absent one.
+fn present<'de, T: Deserialize<'de>, D: serde::Deserializer<'de>>(de: D) -> Result<Option<T>, D::Error> {
+    T::deserialize(de).map(Some)
+}
+
+fn bad_request(msg: impl Into<String>) -> PagiAxumError {
+    PagiAxumError::with_status(PagiError::config(msg.into()), StatusCode::BAD_REQUEST)
+}
+
+fn not_found(id: Uuid) -> PagiAxumError {
+    PagiAxumError::with_status(PagiError::config(format!("webhook {id} not found")), StatusCode::NOT_FOUND)
+}
+
+fn validate_url(url: &str) -> Result<(), PagiAxumError> {
+    match reqwest::Url::parse(url) {
+        Ok(u) if matches!(u.scheme(), "http" | "https") => Ok(()),
+        _ => Err(bad_request(format!("invalid webhook url '{url}' (expected http/https)"))),
+    }
+}
+
+fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("whsec_{}", hex::encode(bytes))
+}
+
+/// `POST /webhooks`
+pub async fn create(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<CreateWebhook>,
+) -> Result<(StatusCode, Json<Subscription>), PagiAxumError> {
+    validate_url(&req.url)?;
+    let sub = Subscription {
+        id: Uuid::new_v4(),
+        url: req.url,
+        secret: req.secret.filter(|s| !s.is_empty()).unwrap_or_else(generate_secret),
+        event_types: req.event_types,
+        twin_id: req.twin_id,
+        format: req.format,
+        max_attempts: req.max_attempts.unwrap_or(DEFAULT_MAX_ATTEMPTS).max(1),
+        disable_after: req.disable_after.unwrap_or(DEFAULT_DISABLE_AFTER).max(1),
+        enabled: true,
+        consecutive_failures: 0,
+        last_error: None,
+    };
+    state.webhooks.add(sub.clone()).await;
+    tracing::info!(webhook = %sub.id, url = %sub.url, "webhook registered");
+    Ok((StatusCode::CREATED, Json(sub)))
+}
+
+/// `GET /webhooks`
+pub async fn list(State(state): State<Arc<AppState>>) -> Json<Vec<Subscription>> {
+    Json(state.webhooks.list().iter().map(Subscription::redacted).collect())
+}
+
+/// `GET /webhooks/:id`
+pub async fn get(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Subscription>, PagiAxumError> {
+    state
+        .webhooks
+        .get(id)
+        .map(|s| Json(s.redacted()))
+        .ok_or_else(|| not_found(id))
+}
+
+/// `PATCH /webhooks/:id`
+pub async fn update(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<UpdateWebhook>,
+) -> Result<Json<Subscription>, PagiAxumError> {
+    if let Some(url) = &req.url {
+        validate_url(url)?;
+    }
+    let updated = state
+        .webhooks
+        .update(id, |s| req.apply(s))
+        .await
+        .ok_or_else(|| not_found(id))?;
+    Ok(Json(updated.redacted()))
+}
+
+/// `DELETE /webhooks/:id`
+pub async fn delete(State(state): State<Arc<AppState>>, Path(id): Path<Uuid>) -> Result<StatusCode, PagiAxumError> {
+    if state.webhooks.remove(id).await {
+        tracing::info!(webhook = %id, "webhook removed");
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(not_found(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Bytes, http::HeaderMap, routing::post, Router};
+    use pagi_common::EventType;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Local endpoint that fails the first `failures` deliveries and records every request.
+    #[derive(Clone, Default)]
+    struct Receiver {
+        failures: Arc<AtomicUsize>,
+        seen: Arc<Mutex<Vec<(HeaderMap, Bytes)>>>,
+    }
+
+    impl Receiver {
+        async fn start(failures: usize) -> (Self, String) {
+            let this = Self::default();
+            this.failures.store(failures, Ordering::SeqCst);
+            let state = this.clone();
+            let app = Router::new().route(
+                "/hook",
+                post(move |headers: HeaderMap, body: Bytes| async move {
+                    state.seen.lock().unwrap().push((headers, body));
+                    let failing = state.failures.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+                    if failing.is_ok() {
+                        StatusCode::INTERNAL_SERVER_ERROR
+                    } else {
+                        StatusCode::NO_CONTENT
+                    }
+                }),
+            );
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/hook", listener.local_addr().unwrap());
+            tokio::spawn(async move { axum::serve(listener, app).await });
+            (this, url)
+        }
+
+        fn requests(&self) -> usize {
+            self.seen.lock().unwrap().len()
+        }
+    }
+
+    fn subscription(url: String, max_attempts: u32, disable_after: u32) -> Subscription {
+        Subscription {
+            id: Uuid::new_v4(),
+            url,
+            secret: "whsec_test".to_string(),
+            event_types: Vec::new(),
+            twin_id: None,
+            format: EventFormat::default(),
+            max_attempts,
+            disable_after,
+            enabled: true,
+            consecutive_failures: 0,
+            last_error: None,
+        }
+    }
+
+    fn event() -> EventEnvelope {
+        EventEnvelope::new(EventType::ContextBuilt, serde_json::json!({}))
+    }
+
+    async fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+        for _ in 0..200 {
+            if done() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        panic!("timed out waiting for {what}");
+    }
+
+    fn temp_store() -> PathBuf {
+        std::env::temp_dir().join(format!("pagi-webhooks-{}.json", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn signatures_match_a_known_vector() {
+        assert_eq!(
+            sign("whsec_test", 1_700_000_000, br#"{"a":1}"#),
+            "sha256=38877139021993b830af32feea6e18a8da83eb2f6e49ee50bd9e4cf4ca4d3789"
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_are_signed_and_retried() {
+        let (receiver, url) = Receiver::start(1).await;
+        let webhooks = Webhooks::open(None, 8).unwrap();
+        let sub = subscription(url, 3, 10);
+        let id = sub.id;
+        webhooks.add(sub).await;
+
+        webhooks.dispatch(&event());
+        wait_for("the retry", || receiver.requests() == 2).await;
+        wait_for("the failure count to reset", || webhooks.get(id).unwrap().consecutive_failures == 0).await;
+
+        let sub = webhooks.get(id).unwrap();
+        assert!(sub.enabled);
+        assert_eq!(sub.last_error, None);
+        for (headers, body) in receiver.seen.lock().unwrap().iter() {
+            let timestamp: i64 = headers[TIMESTAMP_HEADER].to_str().unwrap().parse().unwrap();
+            assert_eq!(headers[SIGNATURE_HEADER].to_str().unwrap(), sign("whsec_test", timestamp, body));
+        }
+    }
+
+    #[tokio::test]
+    async fn repeated_failures_disable_until_re_enabled() {
+        let (receiver, url) = Receiver::start(usize::MAX).await;
+        let path = temp_store();
+        let webhooks = Webhooks::open(Some(path.clone()), 8).unwrap();
+        let sub = subscription(url, 1, 2);
+        let id = sub.id;
+        webhooks.add(sub).await;
+
+        webhooks.dispatch(&event());
+        wait_for("the first failure", || webhooks.get(id).unwrap().consecutive_failures == 1).await;
+        assert!(webhooks.get(id).unwrap().enabled);
+        webhooks.dispatch(&event());
+        wait_for("the subscription to be disabled", || !webhooks.get(id).unwrap().enabled).await;
+        assert_eq!(webhooks.get(id).unwrap().last_error.as_deref(), Some("HTTP 500 Internal Server Error"));
+
+        // Disabled subscriptions receive nothing, and the disabling was persisted.
+        webhooks.dispatch(&event());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(receiver.requests(), 2);
+        assert!(!load(&path).unwrap()[0].enabled);
+
+        let req: UpdateWebhook = serde_json::from_value(serde_json::json!({"enabled": true})).unwrap();
+        webhooks.update(id, |s| req.apply(s)).await.unwrap();
+        receiver.failures.store(0, Ordering::SeqCst);
+        let saved = &load(&path).unwrap()[0];
+        assert!(saved.enabled);
+        assert_eq!((saved.consecutive_failures, saved.last_error.as_deref()), (0, None));
+
+        webhooks.dispatch(&event());
+        wait_for("delivery after re-enabling", || receiver.requests() == 3).await;
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn subscriptions_survive_a_reopen() {
+        let path = temp_store();
+        let webhooks = Webhooks::open(Some(path.clone()), 8).unwrap();
+        let mut sub = subscription("http://127.0.0.1:9/hook".to_string(), 2, 3);
+        sub.event_types = vec!["twin_created".to_string()];
+        sub.twin_id = Some(Uuid::new_v4());
+        let id = sub.id;
+        webhooks.add(sub.clone()).await;
+        webhooks.add(subscription("http://127.0.0.1:9/other".to_string(), 1, 1)).await;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+
+        let reopened = Webhooks::open(Some(path.clone()), 8).unwrap();
+        assert_eq!(reopened.list().len(), 2);
+        let restored = reopened.get(id).unwrap();
+        assert_eq!(
+            serde_json::to_value(&restored).unwrap(),
+            serde_json::to_value(&sub).unwrap()
+        );
+
+        // A null twin filter clears it; an absent one leaves it alone.
+        let req: UpdateWebhook = serde_json::from_value(serde_json::json!({"max_attempts": 4})).unwrap();
+        reopened.update(id, |s| req.apply(s)).await.unwrap();
+        assert_eq!(reopened.get(id).unwrap().twin_id, sub.twin_id);
+        let req: UpdateWebhook = serde_json::from_value(serde_json::json!({"twin_id": null})).unwrap();
+        reopened.update(id, |s| req.apply(s)).await.unwrap();
+        let saved = load(&path).unwrap();
+        let saved = saved.iter().find(|s| s.id == id).unwrap();
+        assert_eq!((saved.twin_id, saved.max_attempts), (None, 4));
+
+        assert!(reopened.remove(id).await);
+        assert!(!reopened.remove(id).await);
+        assert_eq!(load(&path).unwrap().len(), 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -5,13 +5,18 @@ use axum::{
     Json, Router,
 };
 use pagi_common::{
-    publish_event, CoreEvent, EventEnvelope, EventType, InstructionsField, Playbook, PlaybookInstructions,
-    RefinementArtifact, TwinId,
+    auth::scopes,
+    lifecycle::LifecycleGuard, publish_event, CoreEvent, EventEnvelope, InstructionsField, Playbook, PlaybookInstructions,
+    RefinementArtifact, TraceContext, TwinId,
+};
+use pagi_http::{
+    auth::{Auth, Caller},
+    errors::PagiAxumError,
+    trace_context::Trace,
 };
-use pagi_http::errors::PagiAxumError;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc};
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use uuid::Uuid;
 use std::time::Duration;
@@ -23,8 +28,10 @@ struct AppState {
     emotion_state_url: String,
     sensor_actuator_url: String,
     external_gateway_url: String,
-    http: reqwest::Client,
+    http: pagi_http::trace_context::TracedClient,
     ethics: EthicsPolicy,
+    /// Refuses work for suspended, archived and deleted twins.
+    lifecycle: Arc<LifecycleGuard>,
 }
 
 #[derive(Debug, Clone)]
@@ -103,7 +110,7 @@ impl EthicsPolicy {
 }
 
 fn split_list(raw: &str) -> Vec<String> {
-    raw.split(|c| c == ',' || c == '\n' || c == ';')
+    raw.split([',', '\n', ';'])
         .map(|s| s.trim())
         .filter(|s| !s.is_empty())
         .map(|s| s.to_string())
@@ -185,26 +192,29 @@ async fn main() {
         emotion_state_url: std::env::var("EMOTION_STATE_URL").unwrap_or_else(|_| "http://127.0.0.1:8007".to_string()),
         sensor_actuator_url: std::env::var("SENSOR_ACTUATOR_URL").unwrap_or_else(|_| "http://127.0.0.1:8008".to_string()),
         external_gateway_url: std::env::var("EXTERNAL_GATEWAY_URL").unwrap_or_else(|_| "http://127.0.0.1:8010".to_string()),
-        http: reqwest::Client::new(),
+        http: pagi_http::auth::service_client(),
         ethics: EthicsPolicy::from_env(),
+        lifecycle: Arc::new(LifecycleGuard::from_env()),
     };
 
     // Optional: self-update checks via ExternalGateway tool (implemented by the updater plugin).
     // This keeps the core immutable: the executive only *invokes* a tool; it never replaces itself.
     spawn_update_checker(state.clone());
 
+    let auth = Auth::from_env();
     let app = Router::new()
         .route("/healthz", get(healthz))
-        .route("/plan", post(plan))
-        .route("/interact/:twin_id", post(interact))
+        .route("/plan", post(plan).route_layer(auth.require(scopes::AGENT_RUN)))
+        .route("/interact/:twin_id", post(interact).route_layer(auth.require(scopes::AGENT_RUN)))
         .with_state(state)
+        .layer(axum::middleware::from_fn(pagi_http::trace_context::propagate))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive());
 
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 8006).into());
     tracing::info!(%addr, "listening");
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    pagi_http::tls::serve(listener, app).await.unwrap();
 }
 
 fn spawn_update_checker(state: AppState) {
@@ -281,7 +291,16 @@ async fn healthz() -> (StatusCode, &'static str) {
     (StatusCode::OK, "ok")
 }
 
-async fn plan(State(_state): State<AppState>, Json(req): Json<PlanRequest>) -> Json<PlanResponse> {
+async fn plan(
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(req): Json<PlanRequest>,
+) -> Result<Json<PlanResponse>, PagiAxumError> {
+    if let Some(twin_id) = req.twin_id {
+        caller.check_twin(twin_id)?;
+        state.lifecycle.check(twin_id).await?;
+    }
+
     let mut steps = Vec::new();
     steps.push(format!("Clarify goal: {}", req.goal));
     steps.push("Collect relevant memory/context".to_string());
@@ -289,25 +308,42 @@ async fn plan(State(_state): State<AppState>, Json(req): Json<PlanRequest>) -> J
     steps.push("Evaluate result and update state".to_string());
 
     if let Some(twin_id) = req.twin_id {
-        let mut ev = EventEnvelope::new(EventType::PlanCreated, json!({"twin_id": twin_id, "step_count": steps.len()}));
-        ev.twin_id = Some(twin_id);
+        let mut ev = EventEnvelope::new_core(twin_id, CoreEvent::PlanCreated { step_count: steps.len() });
         ev.source = Some("pagi-executive-engine".to_string());
-        let _ = publish_event(ev).await;
+        publish_event(ev);
     }
 
-    Json(PlanResponse { steps })
+    Ok(Json(PlanResponse { steps }))
 }
 
 async fn interact(
     State(state): State<AppState>,
+    Trace(trace): Trace,
+    caller: Caller,
     Path(twin_id): Path<Uuid>,
     Json(req): Json<InteractRequest>,
 ) -> Result<Json<InteractResponse>, PagiAxumError> {
+    // 0) Only the twin itself (or a service) may drive it, and only while its lifecycle accepts work.
+    caller.check_twin(twin_id)?;
+    state.lifecycle.check(twin_id).await?;
+
     // 1) Publish GoalReceived
     let mut goal_ev = EventEnvelope::new_core(twin_id, CoreEvent::GoalReceived { goal: req.goal.clone() });
     goal_ev.source = Some("pagi-executive-engine".to_string());
-    let _ = publish_event(goal_ev).await;
+    let goal_id = goal_ev.id;
+    publish_event(goal_ev);
 
+    // Everything below, including events emitted by the services we call, is caused by the goal.
+    let trace = trace.with_causation(goal_id);
+    trace.scope(run_interaction(state, trace, twin_id, req)).await
+}
+
+async fn run_interaction(
+    state: AppState,
+    trace: TraceContext,
+    twin_id: Uuid,
+    req: InteractRequest,
+) -> Result<Json<InteractResponse>, PagiAxumError> {
     // 1b) Ethics gate (best-effort, env-configured). Refuse early.
     if let Err(refusal) = state.ethics.check_goal(&req.goal) {
         return Ok(Json(InteractResponse {
@@ -392,7 +428,7 @@ async fn interact(
     // 8) Publish PlanGenerated
     let mut plan_ev = EventEnvelope::new_core(twin_id, CoreEvent::PlanGenerated { plan: plan.clone() });
     plan_ev.source = Some("pagi-executive-engine".to_string());
-    let _ = publish_event(plan_ev).await;
+    publish_event(plan_ev);
 
     // 9) Execute a sample tool if available (for demonstration)
     if let Some(sample_tool) = tools_response.tools.first() {
@@ -434,12 +470,12 @@ async fn interact(
 
     // 11) Self-improvement loop (best-effort): reflect and offer artifact to Hive sync plugin via ExternalGateway.
     let artifact = generate_refinement_artifact(twin_id, &req.goal, &plan, &playbook);
-    tokio::spawn(async move {
+    tokio::spawn(trace.scope(async move {
         // Fire-and-forget; do not block user response.
         if let Err(err) = try_push_refinement_artifact(&state, twin_id, artifact).await {
             tracing::debug!(twin_id = %twin_id, error = %err, "refinement artifact push skipped/failed");
         }
-    });
+    }));
 
     Ok(Json(InteractResponse {
         status: "plan_executed".to_string(),
@@ -273,11 +273,14 @@ async fn scan_and_register_plugins(
 }
 
 /// Register a single plugin from its manifest.
+///
+/// Manifests carry no owning twin, so tools are registered globally whether or not
+/// `global_tools` is set.
 async fn register_plugin_from_manifest(
     state: &GatewayState,
     plugin_path: &Path,
     manifest_path: &Path,
-    global_tools: bool,
+    _global_tools: bool,
     keep_libs: &mut HashSet<PathBuf>,
 ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
     // Phase 5: optional signature verification (best-effort/strict) for plugin manifests.
@@ -329,7 +332,7 @@ async fn register_plugin_from_manifest(
                 keep_libs.insert(canonical.clone());
 
                 let tools = shared_lib::register_tools(&canonical)?;
-                let twin_id = if global_tools { global_twin_id() } else { global_twin_id() };
+                let twin_id = global_twin_id();
 
                 let mut registered = 0usize;
                 for mut tool in tools {
@@ -367,7 +370,7 @@ async fn register_plugin_from_manifest(
             if full_wasm.exists() {
                 let canonical = full_wasm.canonicalize().unwrap_or(full_wasm.clone());
                 let tools = wasm_plugin::register_tools(&canonical)?;
-                let twin_id = if global_tools { global_twin_id() } else { global_twin_id() };
+                let twin_id = global_twin_id();
 
                 let mut registered = 0usize;
                 for mut tool in tools {
@@ -404,7 +407,7 @@ async fn register_plugin_from_manifest(
                 let canonical = full_wasm.canonicalize().unwrap_or(full_wasm.clone());
                 let plugin_url = format!("wasm-component://{}", canonical.display());
 
-                let twin_id = if global_tools { global_twin_id() } else { global_twin_id() };
+                let twin_id = global_twin_id();
                 let mut registered = 0usize;
 
                 for tool_def in &manifest.tools {
@@ -487,7 +490,7 @@ async fn register_plugin_from_manifest(
         return Ok(0);
     };
 
-    let twin_id = if global_tools { global_twin_id() } else { global_twin_id() };
+    let twin_id = global_twin_id();
 
     let mut registered = 0usize;
     for tool_def in manifest.tools {
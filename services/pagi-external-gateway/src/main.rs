@@ -5,15 +5,24 @@ mod wasm_plugin;
 mod wasm_component_plugin;
 
 use axum::{
-    extract::{Json, Path, State},
+    extract::{Json, Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
     routing::{get, post},
     Router,
 };
 use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
-use pagi_common::{ErrorCode, PagiError, TwinId};
-use pagi_http::errors::PagiAxumError;
+use pagi_common::{
+    auth::scopes,
+    erasure::{DeletionConfirmer, PurgeReport, PurgeTrigger, TwinDeletion},
+    lifecycle::LifecycleGuard,
+    publish_event, ErrorCode, EventEnvelope, Outbox, PagiError, TwinId,
+};
+use pagi_http::{
+    auth::{Auth, Caller},
+    errors::PagiAxumError,
+    tls::{require_peer, Peer, PeerAllowlist},
+};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use std::{
@@ -28,7 +37,7 @@ use tokio::sync::RwLock;
 use tracing::info;
 use uuid::Uuid;
 
-use redis_registry::{load_all_tools, persist_tool};
+use redis_registry::{load_all_tools, persist_tool, remove_twin_tools};
 
 static METRICS: OnceLock<PrometheusHandle> = OnceLock::new();
 
@@ -63,7 +72,11 @@ pub struct ToolSchema {
 struct GatewayState {
     registry: Arc<RwLock<HashMap<Uuid, HashMap<String, ToolSchema>>>>,
     redis_client: redis::Client,
-    http: reqwest::Client,
+    http: pagi_http::trace_context::TracedClient,
+    /// Refuses tool calls for suspended, archived and deleted twins.
+    lifecycle: Arc<LifecycleGuard>,
+    /// Confirms `twin_deleted` events before a twin's tools are purged.
+    deletions: Arc<DeletionConfirmer>,
 }
 
 fn global_twin_id() -> TwinId {
@@ -107,7 +120,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let state = GatewayState {
         registry: Arc::new(RwLock::new(loaded_registry)),
         redis_client,
-        http: reqwest::Client::new(),
+        http: pagi_http::trace_context::client(),
+        lifecycle: Arc::new(LifecycleGuard::from_env()),
+        deletions: Arc::new(DeletionConfirmer::from_env()),
     };
 
     // Optional: auto-discovery from PLUGIN_DIR
@@ -125,20 +140,30 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         });
     }
 
+    let auth = Auth::from_env();
+    // Services allowed to register tools over mTLS (comma-separated; unset allows any peer).
+    let register_peers = PeerAllowlist::from_env("REGISTER_TOOL_PEERS");
     let app = Router::new()
         .route("/health", get(|| async { "OK" }))
         .route("/healthz", get(|| async { "OK" }))
         .route("/metrics", get(metrics_handler))
-        .route("/register_tool", post(register_tool))
-        .route("/tools", get(list_all_tools))
-        .route("/tools/:twin_id", get(list_tools_for_twin))
-        .route("/execute/:tool_name", post(execute_tool))
-        .with_state(state);
+        .route(
+            "/register_tool",
+            post(register_tool)
+                .route_layer(auth.require(scopes::TOOLS_REGISTER))
+                .route_layer(axum::middleware::from_fn_with_state(register_peers, require_peer)),
+        )
+        .route("/tools", get(list_all_tools).route_layer(auth.require(scopes::TOOLS_READ)))
+        .route("/tools/:twin_id", get(list_tools_for_twin).route_layer(auth.require(scopes::TOOLS_READ)))
+        .route("/execute/:tool_name", post(execute_tool).route_layer(auth.require(scopes::TOOLS_EXECUTE)))
+        .route("/events", post(handle_event).route_layer(auth.require(scopes::TOOLS_REGISTER)))
+        .with_state(state)
+        .layer(axum::middleware::from_fn(pagi_http::trace_context::propagate));
 
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 8010).into());
     let listener = tokio::net::TcpListener::bind(addr).await?;
     info!(%addr, %redis_url, "PAGI-ExternalGateway listening (Redis registry)");
-    axum::serve(listener, app).await?;
+    pagi_http::tls::serve(listener, app).await?;
     Ok(())
 }
 
@@ -150,6 +175,7 @@ struct RegisterPayload {
 
 async fn register_tool(
     State(state): State<GatewayState>,
+    peer: Option<Peer>,
     Json(payload): Json<RegisterPayload>,
 ) -> impl IntoResponse {
     let twin_id = payload.twin_id.unwrap_or_else(global_twin_id);
@@ -157,7 +183,8 @@ async fn register_tool(
 
     match upsert_tool(&state, twin_id, &tool).await {
         Ok(()) => {
-            info!(tool_name = %tool.name, twin_id = ?twin_id, "Registered tool");
+            let registered_by = peer.as_ref().map(|p| p.service.as_str()).unwrap_or("-");
+            info!(tool_name = %tool.name, twin_id = ?twin_id, %registered_by, "Registered tool");
             StatusCode::OK.into_response()
         }
         Err(source) => err_json(
@@ -182,10 +209,26 @@ async fn list_all_tools(State(state): State<GatewayState>) -> impl IntoResponse
     Json(json!({ "tools": all_tools })).into_response()
 }
 
+#[derive(Deserialize)]
+struct TwinToolsQuery {
+    /// `false` lists only the twin's own tools, e.g. for export.
+    #[serde(default = "default_true")]
+    global: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
 async fn list_tools_for_twin(
     Path(twin_uuid): Path<Uuid>,
     State(state): State<GatewayState>,
+    caller: Caller,
+    Query(query): Query<TwinToolsQuery>,
 ) -> impl IntoResponse {
+    if let Err(err) = caller.check_twin(twin_uuid) {
+        return err.into_response();
+    }
     let twin_id = TwinId(twin_uuid);
 
     let reg = state.registry.read().await;
@@ -194,13 +237,63 @@ async fn list_tools_for_twin(
     if let Some(t) = reg.get(&twin_id.0) {
         tools.extend(t.values().cloned());
     }
-    if let Some(global) = reg.get(&Uuid::nil()) {
+    if let Some(global) = reg.get(&Uuid::nil()).filter(|_| query.global) {
         tools.extend(global.values().cloned());
     }
 
     Json(json!({ "twin_id": twin_id, "tools": tools })).into_response()
 }
 
+/// `POST /events`: unregister a twin's own tools (in memory and in Redis) on `twin_deleted`,
+/// answering with the purge report. Other events are ignored (`204`).
+async fn handle_event(
+    State(state): State<GatewayState>,
+    caller: Caller,
+    Json(ev): Json<EventEnvelope>,
+) -> impl IntoResponse {
+    if let Err(err) = caller.check_not_twin() {
+        return err.into_response();
+    }
+    let Some(deletion) = TwinDeletion::from_event(&ev) else {
+        return StatusCode::NO_CONTENT.into_response();
+    };
+    let twin_id = deletion.twin_id;
+    if twin_id.is_nil() {
+        return err_json(StatusCode::BAD_REQUEST, PagiError::config("the global twin cannot be deleted")).into_response();
+    }
+    if let Err(err) = state.deletions.confirm(&deletion).await {
+        return PagiAxumError::from(err).into_response();
+    }
+
+    // Redis first: if it fails, the in-memory registry still matches what a restart reloads.
+    let persisted = match remove_twin_tools(&state.redis_client, twin_id).await {
+        Ok(count) => count,
+        Err(source) => {
+            return err_json(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                PagiError::Redis {
+                    code: ErrorCode::RedisError,
+                    source,
+                },
+            )
+            .into_response()
+        }
+    };
+    let in_memory = state.registry.write().await.remove(&twin_id).map_or(0, |tools| tools.len() as u64);
+    // Redis holds every registration that was persisted; memory may also hold ones whose
+    // persistence failed.
+    let outbox = match Outbox::global().purge_twin(twin_id).await {
+        Ok(count) => count as u64,
+        Err(err) => return PagiAxumError::from(err).into_response(),
+    };
+    let report = PurgeReport::new("pagi-external-gateway", twin_id, PurgeTrigger::TwinDeleted)
+        .with("tools", in_memory.max(persisted))
+        .with("outbox_events", outbox);
+    info!(%twin_id, tools = report.total(), "Unregistered tools of deleted twin");
+    publish_event(report.event());
+    Json(report).into_response()
+}
+
 #[derive(Deserialize)]
 struct ExecutePayload {
     twin_id: TwinId,
@@ -210,11 +303,27 @@ struct ExecutePayload {
 async fn execute_tool(
     Path(tool_name): Path<String>,
     State(state): State<GatewayState>,
+    caller: Caller,
     Json(payload): Json<ExecutePayload>,
 ) -> impl IntoResponse {
     let started = Instant::now();
 
     let twin_uuid = payload.twin_id.0;
+    if let Err(err) = caller.check_twin(twin_uuid) {
+        tracing::warn!(twin_id = %twin_uuid, tool_name = %tool_name, "tool execution refused: token bound to another twin");
+        return err.into_response();
+    }
+    if let Err(refusal) = state.lifecycle.check(twin_uuid).await {
+        metrics::counter!(
+            "pagi_tool_executions_total",
+            "tool" => tool_name.clone(),
+            "status" => "refused"
+        )
+        .increment(1);
+        tracing::warn!(twin_id = %twin_uuid, tool_name = %tool_name, %refusal, "tool execution refused");
+        return PagiAxumError::from(refusal).into_response();
+    }
+
     let tool = {
         let reg = state.registry.read().await;
         reg.get(&twin_uuid)
@@ -351,7 +460,11 @@ async fn execute_tool(
                 .increment(1);
             metrics::histogram!("pagi_tool_execution_duration_seconds", "tool" => tool_name.clone())
                 .record(started.elapsed().as_secs_f64());
-            err_json(StatusCode::BAD_GATEWAY, PagiError::Network { code, source: e }).into_response()
+            let err = match e {
+                reqwest_middleware::Error::Reqwest(source) => PagiError::Network { code, source },
+                other => PagiError::plugin_exec(other.to_string()),
+            };
+            err_json(StatusCode::BAD_GATEWAY, err).into_response()
         }
     }
 }
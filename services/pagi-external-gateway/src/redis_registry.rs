@@ -84,6 +84,16 @@ pub async fn persist_tool(
     Ok(())
 }
 
+/// Remove every tool registered for a twin, returning how many there were.
+pub async fn remove_twin_tools(client: &redis::Client, twin_id: Uuid) -> Result<u64, RedisError> {
+    let mut con = client.get_multiplexed_tokio_connection().await?;
+    let key = twin_key(&twin_id);
+    // The DEL reply is ignored, so the transaction answers with the HLEN count alone.
+    let (count,): (u64,) = redis::pipe().atomic().hlen(&key).del(&key).ignore().query_async(&mut con).await?;
+    info!(%twin_id, tools = count, redis_key = %key, "Removed twin tools from Redis");
+    Ok(count)
+}
+
 /// Remove a tool (optional cleanup)
 #[allow(dead_code)]
 pub async fn remove_tool(
@@ -100,3 +110,101 @@ pub async fn remove_tool(
     con.hdel::<_, _, ()>(&key, tool_name).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::{Arc, Mutex};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+    };
+
+    type Hashes = Arc<Mutex<HashMap<String, HashMap<String, String>>>>;
+
+    /// Just enough of a Redis server for the registry's commands, transactions included.
+    async fn fake_redis() -> (redis::Client, Hashes) {
+        let hashes = Hashes::default();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let served = hashes.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve(socket, served.clone()));
+            }
+        });
+        (redis::Client::open(format!("redis://{addr}")).unwrap(), hashes)
+    }
+
+    async fn serve(socket: tokio::net::TcpStream, hashes: Hashes) {
+        let (read, mut write) = socket.into_split();
+        let mut lines = BufReader::new(read).lines();
+        let mut queued: Option<Vec<String>> = None;
+        while let Ok(Some(header)) = lines.next_line().await {
+            let argc: usize = header.trim_start_matches('*').parse().unwrap();
+            let mut args = Vec::new();
+            for _ in 0..argc {
+                lines.next_line().await.unwrap();
+                args.push(lines.next_line().await.unwrap().unwrap());
+            }
+            let command = args[0].to_uppercase();
+            let reply = match (command.as_str(), queued.as_mut()) {
+                ("MULTI", _) => {
+                    queued = Some(Vec::new());
+                    "+OK\r\n".to_string()
+                }
+                ("EXEC", Some(_)) => {
+                    let replies = queued.take().unwrap();
+                    format!("*{}\r\n{}", replies.len(), replies.concat())
+                }
+                (_, Some(replies)) => {
+                    replies.push(execute(&hashes, &command, &args[1..]));
+                    "+QUEUED\r\n".to_string()
+                }
+                (_, None) => execute(&hashes, &command, &args[1..]),
+            };
+            write.write_all(reply.as_bytes()).await.unwrap();
+        }
+    }
+
+    fn execute(hashes: &Hashes, command: &str, args: &[String]) -> String {
+        let mut hashes = hashes.lock().unwrap();
+        match command {
+            "HSET" => {
+                let hash = hashes.entry(args[0].clone()).or_default();
+                let added = hash.insert(args[1].clone(), args[2].clone()).is_none();
+                format!(":{}\r\n", added as u8)
+            }
+            "HLEN" => format!(":{}\r\n", hashes.get(&args[0]).map_or(0, HashMap::len)),
+            "DEL" => format!(":{}\r\n", hashes.remove(&args[0]).is_some() as u8),
+            _ => "+OK\r\n".to_string(),
+        }
+    }
+
+    fn tool(name: &str) -> ToolSchema {
+        ToolSchema {
+            name: name.to_string(),
+            description: String::new(),
+            plugin_url: "http://plugin".to_string(),
+            endpoint: "/execute".to_string(),
+            parameters: json!({}),
+        }
+    }
+
+    #[tokio::test]
+    async fn removing_a_twins_tools_counts_and_deletes_them() {
+        let (client, hashes) = fake_redis().await;
+        let (twin, other) = (Uuid::new_v4(), Uuid::new_v4());
+        persist_tool(&client, Some(twin), &tool("a")).await.unwrap();
+        persist_tool(&client, Some(twin), &tool("b")).await.unwrap();
+        persist_tool(&client, Some(other), &tool("a")).await.unwrap();
+        persist_tool(&client, None, &tool("global")).await.unwrap();
+
+        assert_eq!(remove_twin_tools(&client, twin).await.unwrap(), 2);
+        assert_eq!(remove_twin_tools(&client, twin).await.unwrap(), 0);
+        let hashes = hashes.lock().unwrap();
+        assert!(!hashes.contains_key(&twin_key(&twin)));
+        assert!(hashes.contains_key(&twin_key(&other)) && hashes.contains_key(GLOBAL_TOOLS_KEY));
+    }
+}
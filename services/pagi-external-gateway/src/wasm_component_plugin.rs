@@ -47,8 +47,5 @@ pub(crate) fn execute_tool(
         .call(&mut store, (payload_str,))
         .map_err(|e| format!("execute trap: {e}"))?;
 
-    match result {
-        Ok(s) => Ok(s),
-        Err(err) => Err(err),
-    }
+    result
 }
@@ -0,0 +1,590 @@
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use multibase::Base;
+use pagi_common::{
+    keystore::{self, Keyring},
+    TwinState, TwinStatus,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::store::{DidService, KeyVersion, TwinQuery, TwinRecord, TwinStore};
+
+pub fn data_dir() -> PathBuf {
+    PathBuf::from(std::env::var("IDENTITY_DATA_DIR").unwrap_or_else(|_| "/data/identity".to_string()))
+}
+
+/// How new twins get their DID (`IDENTITY_DID_METHOD`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DidMethod {
+    /// `did:key`: self-certifying, but bound to one key forever.
+    Key,
+    /// `did:web:{domain}:twins:{twin_id}` by default, or under a path chosen at registration;
+    /// documents are served by this service and the DID survives key rotation.
+    Web { domain: String },
+}
+
+impl DidMethod {
+    pub fn from_env() -> Result<Self, String> {
+        let method = std::env::var("IDENTITY_DID_METHOD").unwrap_or_else(|_| "key".to_string());
+        match method.to_lowercase().as_str() {
+            "key" => Ok(DidMethod::Key),
+            "web" => {
+                let domain = std::env::var("IDENTITY_DID_WEB_DOMAIN")
+                    .ok()
+                    .map(|d| d.trim().trim_end_matches('/').to_string())
+                    .filter(|d| !d.is_empty())
+                    .ok_or_else(|| "IDENTITY_DID_METHOD=web requires IDENTITY_DID_WEB_DOMAIN".to_string())?;
+                // did:web percent-encodes the port separator.
+                Ok(DidMethod::Web {
+                    domain: domain.replace(':', "%3A"),
+                })
+            }
+            other => Err(format!("unknown IDENTITY_DID_METHOD '{other}' (expected key|web)")),
+        }
+    }
+
+    /// The default `did:web` for a twin, resolved from `/twins/{twin_id}/did.json`.
+    pub fn web_did(&self, twin_id: Uuid) -> Option<String> {
+        self.web_did_at(&["twins".to_string(), twin_id.to_string()])
+    }
+
+    /// `did:web` for a document served at `/{path}/did.json`; the empty path is the bare
+    /// domain, served at `/.well-known/did.json`.
+    pub fn web_did_at(&self, path: &[String]) -> Option<String> {
+        match self {
+            DidMethod::Key => None,
+            DidMethod::Web { domain } if path.is_empty() => Some(format!("did:web:{domain}")),
+            DidMethod::Web { domain } => Some(format!("did:web:{domain}:{}", path.join(":"))),
+        }
+    }
+
+    /// Whether this node serves the document of `did` (a `did:web` under its domain).
+    pub fn serves(&self, did: &str) -> bool {
+        match self {
+            DidMethod::Key => false,
+            DidMethod::Web { domain } => did
+                .strip_prefix("did:web:")
+                .and_then(|rest| rest.strip_prefix(domain.as_str()))
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(':')),
+        }
+    }
+
+    /// The `did:web` whose document lives at an HTTP request path, if any.
+    pub fn did_for_request_path(&self, request_path: &str) -> Option<String> {
+        if request_path == "/.well-known/did.json" {
+            return self.web_did_at(&[]);
+        }
+        let path = request_path.strip_prefix('/')?.strip_suffix("/did.json")?;
+        self.web_did_at(&parse_did_path(path).ok()?)
+    }
+}
+
+/// Validate a registration-time `did:web` path (`alice`, `orgs/acme/alice`, or empty for the
+/// bare domain). `twins/...` is reserved for the default DIDs.
+pub fn parse_did_path(raw: &str) -> Result<Vec<String>, String> {
+    let trimmed = raw.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        return Ok(Vec::new());
+    }
+    let segments: Vec<String> = trimmed.split('/').map(str::to_string).collect();
+    for segment in &segments {
+        let valid = !segment.is_empty()
+            && segment != "."
+            && segment != ".."
+            && segment.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'));
+        if !valid {
+            return Err(format!("invalid did:web path segment '{segment}'"));
+        }
+    }
+    if segments[0] == "twins" || segments[0] == ".well-known" {
+        return Err(format!("did:web path '{trimmed}' is reserved"));
+    }
+    Ok(segments)
+}
+
+/// multibase(base58btc, multicodec(ed25519-pub) || pubkey), as used by `did:key` and
+/// `publicKeyMultibase`.
+pub fn public_key_multibase(verifying_key: &VerifyingKey) -> String {
+    // Multicodec prefix for Ed25519 public key is 0xed 0x01.
+    let public_bytes = verifying_key.to_bytes();
+    let mut codec_and_key = Vec::with_capacity(2 + public_bytes.len());
+    codec_and_key.push(0xed);
+    codec_and_key.push(0x01);
+    codec_and_key.extend_from_slice(&public_bytes);
+    multibase::encode(Base::Base58Btc, codec_and_key)
+}
+
+/// Verification method id for the `n`th (1-based) key of a rotatable DID.
+fn web_key_id(did: &str, n: usize) -> String {
+    format!("{did}#key-{n}")
+}
+
+/// A fresh DID and its first key: `web_did` when given, otherwise the key's `did:key`.
+fn mint(web_did: Option<String>, verifying_key: &VerifyingKey, now: OffsetDateTime) -> (String, KeyVersion) {
+    let method_id = public_key_multibase(verifying_key);
+    let (did, id) = match web_did {
+        Some(did) => {
+            let id = web_key_id(&did, 1);
+            (did, id)
+        }
+        None => {
+            let did = format!("did:key:{method_id}");
+            let id = format!("{did}#{method_id}");
+            (did, id)
+        }
+    };
+    let key = KeyVersion {
+        id,
+        public_key_multibase: method_id,
+        created_at: now,
+        retired_at: None,
+    };
+    (did, key)
+}
+
+/// DID document for a twin. Every key it has had is listed as a verification method so
+/// signatures made before a rotation still verify, retired ones with a `revoked` time; only
+/// the current key can authenticate or make new assertions.
+pub fn did_document(twin: &TwinRecord) -> Value {
+    let did = &twin.did;
+    let keys = &twin.keys;
+    let current: Vec<&str> = keys.iter().filter(|k| k.retired_at.is_none()).map(|k| k.id.as_str()).collect();
+    let mut doc = json!({
+        "@context": "https://www.w3.org/ns/did/v1",
+        "id": did,
+        "verificationMethod": keys.iter().map(|k| {
+            let mut method = json!({
+                "id": k.id,
+                "type": "Ed25519VerificationKey2020",
+                "controller": did,
+                "publicKeyMultibase": k.public_key_multibase,
+            });
+            if let Some(retired_at) = k.retired_at.and_then(|at| at.format(&Rfc3339).ok()) {
+                method["revoked"] = json!(retired_at);
+            }
+            method
+        }).collect::<Vec<_>>(),
+        "authentication": current,
+        "assertionMethod": current,
+    });
+    if !twin.previous_dids.is_empty() {
+        doc["alsoKnownAs"] = json!(twin.previous_dids);
+    }
+    if !twin.services.is_empty() {
+        doc["service"] = twin
+            .services
+            .iter()
+            .map(|s| {
+                json!({
+                    "id": format!("{did}#{}", s.id.trim_start_matches('#')),
+                    "type": s.kind,
+                    "serviceEndpoint": s.service_endpoint,
+                })
+            })
+            .collect();
+    }
+    doc
+}
+
+/// Check service entries before they are published; only `did:web` documents can carry them,
+/// since a `did:key` document is derived from the key alone.
+pub fn validate_services(did: &str, services: &[DidService]) -> Result<(), String> {
+    if services.is_empty() {
+        return Ok(());
+    }
+    if !did.starts_with("did:web:") {
+        return Err(format!("{did}: only did:web documents can publish services"));
+    }
+    let mut seen = HashSet::new();
+    for service in services {
+        let fragment = service.id.trim_start_matches('#');
+        if fragment.is_empty() || service.kind.trim().is_empty() || service.service_endpoint.trim().is_empty() {
+            return Err("service id, type and service_endpoint are required".to_string());
+        }
+        if !seen.insert(fragment) {
+            return Err(format!("duplicate service id '{fragment}'"));
+        }
+    }
+    Ok(())
+}
+
+/// Generate a signing key for the twin, persist it (sealed when a KEK is configured), and
+/// return its DID (`web_did`, or a `did:key`) and first key.
+pub fn create_and_persist_did(
+    keyring: &Keyring,
+    keys_dir: &Path,
+    web_did: Option<String>,
+    twin_uuid: Uuid,
+) -> Result<(String, KeyVersion), String> {
+    use rand_core::OsRng;
+
+    let mut rng = OsRng;
+    let signing_key = SigningKey::generate(&mut rng);
+
+    // Persist the private key for later signing.
+    keyring.write_key(keys_dir, twin_uuid, &signing_key.to_bytes())?;
+
+    Ok(mint(web_did, &signing_key.verifying_key(), OffsetDateTime::now_utc()))
+}
+
+/// The DID a twin keeps across a key rotation: its own if rotatable, or its `did:web`
+/// when `did:web` is configured and the twin still has a `did:key`. `None` when the key
+/// cannot be rotated.
+pub fn rotation_did(method: &DidMethod, twin: &TwinRecord) -> Option<String> {
+    if twin.did.starts_with("did:web:") {
+        return Some(twin.did.clone());
+    }
+    method.web_did(twin.twin_id)
+}
+
+/// Retire the current key and make `verifying_key` current under `did`. Moving to a new
+/// DID re-homes the key history under it and records the old DID.
+pub fn apply_rotation(twin: &mut TwinRecord, did: &str, verifying_key: &VerifyingKey, now: OffsetDateTime) {
+    for key in twin.keys.iter_mut().filter(|k| k.retired_at.is_none()) {
+        key.retired_at = Some(now);
+    }
+    if twin.did != did {
+        rehome(twin, did);
+    }
+    twin.keys.push(KeyVersion {
+        id: web_key_id(did, twin.keys.len() + 1),
+        public_key_multibase: public_key_multibase(verifying_key),
+        created_at: now,
+        retired_at: None,
+    });
+    twin.did_document = did_document(twin);
+}
+
+/// Move the twin to `did`, re-homing its key history under it and recording the old DID in
+/// `alsoKnownAs`. The caller regenerates the DID document.
+pub fn rehome(twin: &mut TwinRecord, did: &str) {
+    twin.previous_dids.push(std::mem::replace(&mut twin.did, did.to_string()));
+    for (i, key) in twin.keys.iter_mut().enumerate() {
+        key.id = web_key_id(did, i + 1);
+    }
+}
+
+/// The DID an imported twin takes on this node: a `did:web` served elsewhere moves under
+/// this node's domain when it has one, since its document would otherwise stay with the
+/// old node. `None` keeps the twin's DID.
+pub fn import_did(method: &DidMethod, twin: &TwinRecord) -> Option<String> {
+    if !twin.did.starts_with("did:web:") || method.serves(&twin.did) {
+        return None;
+    }
+    method.web_did(twin.twin_id)
+}
+
+/// Replace the twin's signing key file with a fresh key, returning its public half. The
+/// registry must then be updated with [`apply_rotation`], or the old key put back with
+/// [`restore_key`]; if neither happens, startup [`reconcile`] adopts the key file.
+pub fn generate_rotated_key(keyring: &Keyring, keys_dir: &Path, twin_id: Uuid) -> Result<VerifyingKey, String> {
+    use rand_core::OsRng;
+
+    let signing_key = SigningKey::generate(&mut OsRng);
+    keyring.write_key(keys_dir, twin_id, &signing_key.to_bytes())?;
+    Ok(signing_key.verifying_key())
+}
+
+/// Undo [`generate_rotated_key`] after the registry refused the new key: write `previous` back,
+/// or destroy the new key file if the twin had none.
+pub fn restore_key(keyring: &Keyring, keys_dir: &Path, twin_id: Uuid, previous: Option<&[u8; 32]>) -> Result<(), String> {
+    match previous {
+        Some(secret) => keyring.write_key(keys_dir, twin_id, secret),
+        None => erase_key(keys_dir, twin_id).map(|_| ()),
+    }
+}
+
+/// Destroy a twin's key file: overwrite it with zeros, flush, then unlink it. Returns whether
+/// there was a file to destroy.
+pub fn erase_key(keys_dir: &Path, twin_id: Uuid) -> Result<bool, String> {
+    use std::io::Write as _;
+
+    let path = keystore::key_path(keys_dir, twin_id);
+    let len = match std::fs::metadata(&path) {
+        Ok(meta) => meta.len(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(format!("key {path:?}: {e}")),
+    };
+    let wipe = || -> std::io::Result<()> {
+        let mut file = std::fs::OpenOptions::new().write(true).open(&path)?;
+        file.write_all(&vec![0u8; len as usize])?;
+        file.sync_all()?;
+        std::fs::remove_file(&path)
+    };
+    wipe().map_err(|e| format!("erasing key {path:?}: {e}"))?;
+    Ok(true)
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Rewrapped {
+    /// Re-sealed under the current KEK (previously plaintext or under the previous KEK).
+    pub rewrapped: usize,
+    /// Already sealed under the current KEK.
+    pub unchanged: usize,
+    /// Twin id → reason, for files that could not be opened or written.
+    pub failed: BTreeMap<Uuid, String>,
+}
+
+/// Re-seal every key file under the current KEK. Each file is replaced atomically, so
+/// signing keeps working throughout for readers that hold both KEKs.
+pub fn rewrap_all(keyring: &Keyring, keys_dir: &Path) -> Result<Rewrapped, String> {
+    let mut report = Rewrapped::default();
+    for id in keystore::key_files(keys_dir)? {
+        match keyring.rewrap(keys_dir, id) {
+            Ok(true) => report.rewrapped += 1,
+            Ok(false) => report.unchanged += 1,
+            Err(err) => {
+                tracing::warn!(twin_id = %id, error = %err, "key re-wrap failed");
+                report.failed.insert(id, err);
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[derive(Debug, Default)]
+pub struct Reconciled {
+    /// Key files with no registry entry; re-registered with a default state.
+    pub recovered: usize,
+    /// Registry entries whose current key did not match their key file; the key file wins.
+    pub repaired: usize,
+    /// Registry entries without a key file; their DID can no longer sign.
+    pub missing_keys: usize,
+}
+
+/// Startup recovery: make the registry agree with the key files on disk.
+pub fn reconcile(
+    store: &dyn TwinStore,
+    keyring: &Keyring,
+    keys_dir: &Path,
+    method: &DidMethod,
+) -> Result<Reconciled, String> {
+    let mut report = Reconciled::default();
+    let keys = keystore::key_files(keys_dir)?;
+
+    for id in &keys {
+        let key = match keyring.read_key(keys_dir, *id) {
+            Ok(secret) => SigningKey::from_bytes(&secret).verifying_key(),
+            Err(err) => {
+                tracing::warn!(twin_id = %id, error = %err, "unreadable key file skipped");
+                continue;
+            }
+        };
+        let now = OffsetDateTime::now_utc();
+        let Some(record) = store.get(*id)? else {
+            let (did, first) = mint(method.web_did(*id), &key, now);
+            let mut twin = TwinRecord {
+                twin_id: *id,
+                state: TwinState {
+                    note: Some("recovered from key file".to_string()),
+                    ..TwinState::default()
+                },
+                version: 1,
+                transitions: Vec::new(),
+                did,
+                did_document: Value::Null,
+                keys: vec![first],
+                previous_dids: Vec::new(),
+                services: Vec::new(),
+                erasure: None,
+                created_at: now,
+                updated_at: now,
+            };
+            twin.did_document = did_document(&twin);
+            store.insert(&twin)?;
+            tracing::warn!(twin_id = %id, "twin recovered from key file");
+            report.recovered += 1;
+            continue;
+        };
+
+        let multibase = public_key_multibase(&key);
+        let current = record.keys.iter().rev().find(|k| k.retired_at.is_none());
+        if current.is_some_and(|k| k.public_key_multibase == multibase) {
+            continue;
+        }
+        if record.keys.is_empty() && record.did == format!("did:key:{multibase}") {
+            // Registered before key history existed: backfill it from the key file.
+            store.update(*id, &mut |r| {
+                let (_, first) = mint(None, &key, r.created_at);
+                r.keys = vec![first];
+                r.did_document = did_document(r);
+            })?;
+            continue;
+        }
+
+        // The key file changed without the registry following: an interrupted rotation,
+        // or a key restored by hand.
+        store.update(*id, &mut |r| {
+            if r.did.starts_with("did:web:") {
+                let did = r.did.clone();
+                apply_rotation(r, &did, &key, now);
+            } else {
+                let (did, first) = mint(None, &key, now);
+                r.did = did;
+                r.keys = vec![first];
+                r.did_document = did_document(r);
+            }
+        })?;
+        tracing::warn!(twin_id = %id, did = %record.did, "registry key repaired from key file");
+        report.repaired += 1;
+    }
+
+    let known: HashSet<Uuid> = keys.into_iter().collect();
+    let mut after = None;
+    loop {
+        let page = store.list(&TwinQuery {
+            status: None,
+            after,
+            limit: 500,
+        })?;
+        let Some((last, _)) = page.last() else {
+            break;
+        };
+        after = Some(*last);
+        for (_, record) in &page {
+            // Deleted twins had their key file erased.
+            if !known.contains(&record.twin_id) && record.state.status != TwinStatus::Deleted {
+                tracing::warn!(twin_id = %record.twin_id, did = %record.did, "twin has no key file");
+                report.missing_keys += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::MemoryStore;
+
+    fn keys_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("pagi-identity-keys-{}", Uuid::new_v4()))
+    }
+
+    fn register(store: &dyn TwinStore, keyring: &Keyring, dir: &Path, web_did: Option<String>) -> TwinRecord {
+        let id = Uuid::new_v4();
+        let (did, key) = create_and_persist_did(keyring, dir, web_did, id).unwrap();
+        let now = OffsetDateTime::now_utc();
+        let mut twin = TwinRecord {
+            twin_id: id,
+            state: TwinState::default(),
+            version: 1,
+            transitions: Vec::new(),
+            did,
+            did_document: Value::Null,
+            keys: vec![key],
+            previous_dids: Vec::new(),
+            services: Vec::new(),
+            erasure: None,
+            created_at: now,
+            updated_at: now,
+        };
+        twin.did_document = did_document(&twin);
+        store.insert(&twin).unwrap();
+        twin
+    }
+
+    #[test]
+    fn reconcile_makes_the_registry_follow_the_key_files() {
+        let (store, keyring, dir) = (MemoryStore::default(), Keyring::new(None, None), keys_dir());
+        let method = DidMethod::Web { domain: "example.com".to_string() };
+
+        let intact = register(&store, &keyring, &dir, None);
+        let rotated = register(&store, &keyring, &dir, method.web_did(Uuid::new_v4()));
+        let keyless = register(&store, &keyring, &dir, None);
+        // A rotation that replaced the key file but never reached the registry.
+        let new_key = generate_rotated_key(&keyring, &dir, rotated.twin_id).unwrap();
+        std::fs::remove_file(keystore::key_path(&dir, keyless.twin_id)).unwrap();
+        // A key file whose registry entry was lost.
+        let orphan = Uuid::new_v4();
+        keyring.write_key(&dir, orphan, &[9u8; 32]).unwrap();
+
+        let report = reconcile(&store, &keyring, &dir, &method).unwrap();
+        assert_eq!((report.recovered, report.repaired, report.missing_keys), (1, 1, 1));
+
+        assert_eq!(store.get(intact.twin_id).unwrap().unwrap().version, 1);
+        let repaired = store.get(rotated.twin_id).unwrap().unwrap();
+        assert_eq!(repaired.did, rotated.did);
+        assert_eq!(repaired.keys.len(), 2);
+        assert!(repaired.keys[0].retired_at.is_some());
+        assert_eq!(repaired.keys[1].public_key_multibase, public_key_multibase(&new_key));
+        let recovered = store.get(orphan).unwrap().unwrap();
+        assert_eq!(recovered.did, method.web_did(orphan).unwrap());
+
+        // Reconciling again finds nothing left to do.
+        let again = reconcile(&store, &keyring, &dir, &method).unwrap();
+        assert_eq!((again.recovered, again.repaired, again.missing_keys), (0, 0, 1));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotation_moves_a_did_key_twin_to_its_did_web() {
+        let (store, keyring, dir) = (MemoryStore::default(), Keyring::new(None, None), keys_dir());
+        let method = DidMethod::Web { domain: "example.com".to_string() };
+        let mut twin = register(&store, &keyring, &dir, None);
+        let old_did = twin.did.clone();
+        assert!(old_did.starts_with("did:key:"));
+
+        let did = rotation_did(&method, &twin).unwrap();
+        let new_key = generate_rotated_key(&keyring, &dir, twin.twin_id).unwrap();
+        let now = OffsetDateTime::now_utc();
+        apply_rotation(&mut twin, &did, &new_key, now);
+
+        assert_eq!(twin.did, did);
+        assert_eq!(twin.previous_dids, vec![old_did.clone()]);
+        assert_eq!(twin.keys.len(), 2);
+        assert_eq!(twin.keys[0].retired_at, Some(now));
+        assert_eq!(twin.keys[0].id, format!("{did}#key-1"));
+        assert_eq!(twin.keys[1].id, format!("{did}#key-2"));
+        assert_eq!(twin.keys[1].public_key_multibase, public_key_multibase(&new_key));
+        // Both keys stay listed so old signatures verify; only the new one may sign.
+        let doc = &twin.did_document;
+        assert_eq!(doc["verificationMethod"].as_array().unwrap().len(), 2);
+        assert_eq!(doc["verificationMethod"][0]["revoked"], json!(now.format(&Rfc3339).unwrap()));
+        assert!(doc["verificationMethod"][1].get("revoked").is_none());
+        assert_eq!(doc["assertionMethod"], json!([format!("{did}#key-2")]));
+        assert_eq!(doc["authentication"], json!([format!("{did}#key-2")]));
+        assert_eq!(doc["alsoKnownAs"], json!([old_did]));
+
+        // A second rotation keeps the DID and retires only the current key.
+        let later = now + time::Duration::seconds(1);
+        let third = generate_rotated_key(&keyring, &dir, twin.twin_id).unwrap();
+        assert_eq!(rotation_did(&method, &twin).as_deref(), Some(did.as_str()));
+        apply_rotation(&mut twin, &did, &third, later);
+        assert_eq!(twin.did, did);
+        assert_eq!(twin.previous_dids.len(), 1);
+        assert_eq!(twin.keys[0].retired_at, Some(now));
+        assert_eq!(twin.keys[1].retired_at, Some(later));
+        assert_eq!(twin.did_document["verificationMethod"].as_array().unwrap().len(), 3);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn a_refused_rotation_puts_the_old_key_back() {
+        let (store, keyring, dir) = (MemoryStore::default(), Keyring::new(None, None), keys_dir());
+        let twin = register(&store, &keyring, &dir, None);
+        let previous = keyring.read_key(&dir, twin.twin_id).unwrap();
+
+        generate_rotated_key(&keyring, &dir, twin.twin_id).unwrap();
+        assert_ne!(keyring.read_key(&dir, twin.twin_id).unwrap(), previous);
+        restore_key(&keyring, &dir, twin.twin_id, Some(&previous)).unwrap();
+        assert_eq!(keyring.read_key(&dir, twin.twin_id).unwrap(), previous);
+
+        // Nothing left for reconciliation to adopt.
+        let report = reconcile(&store, &keyring, &dir, &DidMethod::Key).unwrap();
+        assert_eq!((report.recovered, report.repaired, report.missing_keys), (0, 0, 0));
+
+        // A twin that had no key file is left without one.
+        let keyless = Uuid::new_v4();
+        generate_rotated_key(&keyring, &dir, keyless).unwrap();
+        restore_key(&keyring, &dir, keyless, None).unwrap();
+        assert!(!keystore::key_path(&dir, keyless).exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
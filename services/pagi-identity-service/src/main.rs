@@ -1,35 +1,82 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    routing::{get, patch, post},
+    extract::{DefaultBodyLimit, Path, Query, State},
+    http::{header, HeaderMap, StatusCode, Uri},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
 use ed25519_dalek::SigningKey;
-use multibase::Base;
-use pagi_common::{publish_event, EventEnvelope, EventType, TwinId, TwinState};
+use pagi_common::{
+    auth::{scopes, Role, TokenRequest, TokenResponse},
+    bundle::{BundleContents, TwinBundle},
+    did::DidResolver,
+    erasure::{PurgeReport, PurgeTrigger},
+    keystore::Keyring,
+    publish_event, CoreEvent, EventEnvelope, TwinId, TwinState, TwinStatus,
+};
+use pagi_http::auth::{bearer_token, Auth, Caller};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
-use tokio::sync::RwLock;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use uuid::Uuid;
 
+mod keys;
+mod migrate;
+mod store;
+mod tokens;
+
+use store::{
+    DidService, ErasureOutcome, ErasureReport, StatusTransition, TwinQuery, TwinRecord, TwinStore, Updated,
+};
+use tokens::Requester;
+
+const DEFAULT_PAGE: usize = 100;
+const MAX_PAGE: usize = 1000;
+/// Largest twin bundle accepted by `POST /twins/import`.
+const MAX_BUNDLE_BYTES: usize = 64 * 1024 * 1024;
+/// Header carrying the passphrase that seals the twin key in a bundle.
+const BUNDLE_PASSPHRASE_HEADER: &str = "x-bundle-passphrase";
+/// Erasure report section for the twin's key file.
+const SIGNING_KEY_SECTION: &str = "signing_key";
+
 #[derive(Clone)]
 struct AppState {
-    twins: Arc<RwLock<HashMap<Uuid, TwinState>>>,
-    identities: Arc<RwLock<HashMap<Uuid, TwinIdentity>>>,
-}
-
-#[derive(Debug, Clone, Serialize)]
-struct TwinIdentity {
-    did: String,
-    did_document: serde_json::Value,
+    store: Arc<dyn TwinStore>,
+    keyring: Arc<Keyring>,
+    keys_dir: PathBuf,
+    did_method: keys::DidMethod,
+    /// Published as a `DIDCommMessaging` service in new `did:web` documents.
+    didcomm_endpoint: Option<String>,
+    /// Serializes key rotations so the key file and the registry advance together.
+    rotation: Arc<Mutex<()>>,
+    issuer: Arc<tokens::Issuer>,
+    issuer_dir: PathBuf,
+    /// Verifies tokens presented to `POST /tokens`.
+    issuer_auth: Auth,
+    /// `AUTH_CLIENT_SECRET`: lets services obtain service tokens from `POST /tokens`.
+    client_secret: Option<String>,
+    /// `IDENTITY_ADMIN_SECRET`: lets operators obtain tokens of any role, including admin.
+    admin_secret: Option<String>,
+    /// Services whose twin data goes into export bundles and is erased on deletion.
+    data: Arc<migrate::DataServices>,
+    /// Resolves the source DIDs of imported twins.
+    did_resolver: DidResolver,
 }
 
 #[derive(Debug, Deserialize)]
 struct CreateTwinRequest {
+    /// Status `registered` (the default) or `active`.
+    #[serde(default)]
+    pub initial_state: Option<RequestedState>,
+    /// `did:web` path for the twin (`alice` → `did:web:{domain}:alice`, empty → the bare
+    /// domain); defaults to `twins/{twin_id}`.
     #[serde(default)]
-    pub initial_state: Option<TwinState>,
+    pub did_path: Option<String>,
+    /// Service endpoints for the DID document; defaults to the DIDComm endpoint, if configured.
+    #[serde(default)]
+    pub services: Option<Vec<DidService>>,
 }
 
 #[derive(Debug, Serialize)]
@@ -42,150 +89,961 @@ struct CreateTwinResponse {
 
 #[derive(Debug, Deserialize)]
 struct UpdateStateRequest {
-    pub state: TwinState,
+    pub state: RequestedState,
+    /// Why the status is changing, as free text; recorded with the transition.
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// A [`TwinState`] as sent by clients: the status is checked strictly rather than read
+/// leniently like stored states.
+#[derive(Debug, Deserialize)]
+struct RequestedState {
+    pub status: String,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+impl RequestedState {
+    fn parse(self) -> Result<TwinState, StatusCode> {
+        let Some(status) = TwinStatus::parse(&self.status) else {
+            tracing::warn!(status = %self.status, "unknown twin status");
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        };
+        Ok(TwinState { status, note: self.note })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteTwinQuery {
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateServicesRequest {
+    pub services: Vec<DidService>,
+}
+
+#[derive(Debug, Serialize)]
+struct ImportResponse {
+    pub twin_id: TwinId,
+    pub did: String,
+    /// The DID the twin had on the exporting node, when it moved under this node's domain.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous_did: Option<String>,
+    pub exported_by: String,
+    pub sections: std::collections::BTreeMap<String, migrate::SectionOutcome>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListTwinsQuery {
+    #[serde(default)]
+    pub status: Option<String>,
+    /// `next_cursor` from the previous page.
+    #[serde(default)]
+    pub cursor: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+struct TwinsPage {
+    pub twins: Vec<TwinRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
 }
 
 #[tokio::main]
-async fn main() {
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     pagi_http::tracing::init("pagi-identity-service");
 
+    let data_dir = keys::data_dir();
+    let keys_dir = data_dir.join("keys");
+    let keyring = Arc::new(Keyring::from_env()?);
+    if !keyring.is_sealing() {
+        tracing::warn!("IDENTITY_KEK is not set: twin signing keys are stored unencrypted");
+    }
+    let did_method = keys::DidMethod::from_env()?;
+    let didcomm_endpoint = std::env::var("IDENTITY_DIDCOMM_ENDPOINT")
+        .ok()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty());
+    let issuer_dir = data_dir.join("issuer");
+    let issuer = Arc::new(tokens::Issuer::load_or_create(&keyring, &issuer_dir)?);
+    let issuer_auth = Auth::with_keys(issuer.verifying_keys(), Some(issuer.name.clone()));
+    // This service is the issuer, so it verifies with its own key rather than `AUTH_JWKS_URL`.
+    let auth = if pagi_http::auth::required_from_env() {
+        issuer_auth.clone()
+    } else {
+        tracing::error!("AUTH_REQUIRED=false: twin registry routes are not authenticated");
+        Auth::disabled()
+    };
+    let client_secret = std::env::var("AUTH_CLIENT_SECRET").ok().filter(|s| !s.trim().is_empty());
+    if client_secret.is_none() {
+        tracing::warn!("AUTH_CLIENT_SECRET is not set: services cannot obtain tokens from POST /tokens");
+    }
+    let admin_secret = std::env::var("IDENTITY_ADMIN_SECRET").ok().filter(|s| !s.trim().is_empty());
+    if admin_secret.is_some() && admin_secret == client_secret {
+        return Err("IDENTITY_ADMIN_SECRET must differ from AUTH_CLIENT_SECRET".into());
+    }
+    let store: Arc<dyn TwinStore> = store::from_env(&data_dir)?.into();
+    let report = keys::reconcile(&*store, &keyring, &keys_dir, &did_method)?;
+    tracing::info!(
+        backend = store.name(),
+        recovered = report.recovered,
+        repaired = report.repaired,
+        missing_keys = report.missing_keys,
+        "twin registry ready"
+    );
+
     let state = AppState {
-        twins: Arc::new(RwLock::new(HashMap::new())),
-        identities: Arc::new(RwLock::new(HashMap::new())),
+        store,
+        keyring,
+        keys_dir,
+        did_method,
+        didcomm_endpoint,
+        rotation: Arc::new(Mutex::new(())),
+        issuer,
+        issuer_dir,
+        issuer_auth,
+        client_secret,
+        admin_secret,
+        data: Arc::new(migrate::DataServices::from_env()),
+        did_resolver: DidResolver::from_env(pagi_common::tls::client()),
     };
+    let read = || auth.require(scopes::TWINS_READ);
+    let write = || auth.require(scopes::TWINS_WRITE);
 
     let app = Router::new()
         .route("/healthz", get(healthz))
-        .route("/twins", post(create_twin))
-        .route("/twins/:id", get(get_twin))
+        .route(
+            "/twins",
+            post(create_twin).route_layer(write()).merge(get(list_twins).route_layer(read())),
+        )
+        .route(
+            "/twins/import",
+            post(import_twin)
+                .route_layer(write())
+                .layer(DefaultBodyLimit::max(MAX_BUNDLE_BYTES)),
+        )
+        .route(
+            "/twins/:id",
+            get(get_twin).route_layer(read()).merge(delete(delete_twin).route_layer(write())),
+        )
+        .route("/twins/:id/erasure", get(get_erasure).route_layer(read()))
+        .route("/twins/:id/export", get(export_twin).route_layer(write()))
         .route("/twins/:id/did", get(get_did))
-        .route("/twins/:id/state", patch(update_state))
+        .route("/twins/:id/did.json", get(get_did))
+        .route("/twins/:id/keys/rotate", post(rotate_twin_key).route_layer(write()))
+        .route("/twins/:id/services", put(update_services).route_layer(write()))
+        .route("/twins/:id/state", patch(update_state).route_layer(write()))
+        .route("/keys/rotate", post(rotate_keys).route_layer(write()))
+        .route("/tokens", post(issue_token))
+        .route("/.well-known/jwks.json", get(jwks))
+        // did:web documents at custom paths: /.well-known/did.json and /{path}/did.json.
+        .fallback(get_web_did)
         .with_state(state)
+        .layer(axum::middleware::from_fn(pagi_http::trace_context::propagate))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive());
 
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 8002).into());
     tracing::info!(%addr, "listening");
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    pagi_http::tls::serve(listener, app).await?;
+    Ok(())
+}
+
+fn internal(err: String) -> StatusCode {
+    tracing::error!(error = %err, "twin registry error");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Run a registry call on the blocking pool: the SQLite backend does synchronous I/O.
+async fn registry<T: Send + 'static>(
+    state: &AppState,
+    f: impl FnOnce(&dyn TwinStore) -> Result<T, String> + Send + 'static,
+) -> Result<T, StatusCode> {
+    let store = state.store.clone();
+    tokio::task::spawn_blocking(move || f(&*store))
+        .await
+        .map_err(|e| internal(e.to_string()))?
+        .map_err(internal)
 }
 
 async fn healthz() -> (StatusCode, &'static str) {
     (StatusCode::OK, "ok")
 }
 
-async fn create_twin(State(state): State<AppState>, Json(req): Json<CreateTwinRequest>) -> (StatusCode, Json<CreateTwinResponse>) {
+async fn create_twin(
+    State(state): State<AppState>,
+    Json(req): Json<CreateTwinRequest>,
+) -> Result<(StatusCode, Json<CreateTwinResponse>), StatusCode> {
     let id = Uuid::new_v4();
-    let twin_state = req.initial_state.unwrap_or_default();
-    state.twins.write().await.insert(id, twin_state.clone());
+    let twin_state = match req.initial_state {
+        Some(requested) => requested.parse()?,
+        None => TwinState::default(),
+    };
+    if !matches!(twin_state.status, TwinStatus::Registered | TwinStatus::Active) {
+        tracing::warn!(status = %twin_state.status, "twins start registered or active");
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let web_did = match req.did_path.as_deref() {
+        None => state.did_method.web_did(id),
+        Some(_) if state.did_method == keys::DidMethod::Key => {
+            tracing::warn!("did_path requires IDENTITY_DID_METHOD=web");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Some(raw) => {
+            let path = keys::parse_did_path(raw).map_err(|err| {
+                tracing::warn!(error = %err, "rejected did_path");
+                StatusCode::BAD_REQUEST
+            })?;
+            let did = state.did_method.web_did_at(&path);
+            if let Some(did) = did.clone() {
+                if registry(&state, move |s| s.find_by_did(&did)).await?.is_some() {
+                    return Err(StatusCode::CONFLICT);
+                }
+            }
+            did
+        }
+    };
+    let services = match (req.services, &web_did, &state.didcomm_endpoint) {
+        (Some(services), _, _) => services,
+        (None, Some(_), Some(endpoint)) => vec![DidService {
+            id: "didcomm".to_string(),
+            kind: "DIDCommMessaging".to_string(),
+            service_endpoint: endpoint.clone(),
+        }],
+        (None, _, _) => Vec::new(),
+    };
+    keys::validate_services(web_did.as_deref().unwrap_or("did:key"), &services).map_err(|err| {
+        tracing::warn!(error = %err, "rejected DID services");
+        StatusCode::BAD_REQUEST
+    })?;
 
-    // Generate DID (did:key using Ed25519) + persist private key to disk.
-    let (did, did_document) = match create_and_persist_did(id) {
-        Ok(v) => v,
+    // Generate DID (did:key or did:web, Ed25519) + persist private key to disk.
+    let now = OffsetDateTime::now_utc();
+    let mut twin = TwinRecord {
+        twin_id: id,
+        state: twin_state.clone(),
+        version: 1,
+        transitions: Vec::new(),
+        did: String::new(),
+        did_document: serde_json::Value::Null,
+        keys: Vec::new(),
+        previous_dids: Vec::new(),
+        services: Vec::new(),
+        erasure: None,
+        created_at: now,
+        updated_at: now,
+    };
+    match keys::create_and_persist_did(&state.keyring, &state.keys_dir, web_did, id) {
+        Ok((did, key)) => {
+            twin.did = did;
+            twin.keys = vec![key];
+            twin.services = services;
+            twin.did_document = keys::did_document(&twin);
+        }
         Err(err) => {
             tracing::error!(twin_id = %id, error = %err, "failed to create DID");
-            (format!("did:key:unavailable:{}", id), json!({"error": err}))
+            twin.did = format!("did:key:unavailable:{}", id);
+            twin.did_document = json!({"error": err});
         }
-    };
-    state
-        .identities
-        .write()
-        .await
-        .insert(id, TwinIdentity { did: did.clone(), did_document: did_document.clone() });
+    }
+    let record = twin.clone();
+    registry(&state, move |s| s.insert(&record)).await?;
 
-    let mut ev = EventEnvelope::new(
-        EventType::TwinRegistered,
-        json!({"twin_id": id, "state": twin_state}),
-    );
-    ev.twin_id = Some(id);
+    let mut ev = EventEnvelope::new_core(id, CoreEvent::TwinRegistered { state: twin_state.clone() });
     ev.source = Some("pagi-identity-service".to_string());
-    let _ = publish_event(ev).await;
+    publish_event(ev);
 
-    (
+    Ok((
         StatusCode::CREATED,
         Json(CreateTwinResponse {
             twin_id: TwinId(id),
             state: twin_state,
-            did,
-            did_document,
+            did: twin.did,
+            did_document: twin.did_document,
         }),
-    )
+    ))
+}
+
+/// `GET /twins?status=&cursor=&limit=`: twins in registration order.
+async fn list_twins(
+    State(state): State<AppState>,
+    caller: Caller,
+    Query(q): Query<ListTwinsQuery>,
+) -> Result<Json<TwinsPage>, StatusCode> {
+    if caller.0.as_ref().is_some_and(|c| c.role == Role::Twin) {
+        tracing::warn!("twin tokens cannot list the registry");
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE);
+    let status = match q.status.as_deref().filter(|s| !s.is_empty()) {
+        Some(raw) => Some(TwinStatus::parse(raw).ok_or(StatusCode::BAD_REQUEST)?),
+        None => None,
+    };
+    let query = TwinQuery {
+        status,
+        after: q.cursor,
+        limit,
+    };
+    let page = registry(&state, move |s| s.list(&query)).await?;
+    let next_cursor = if page.len() == limit {
+        page.last().map(|(seq, _)| *seq)
+    } else {
+        None
+    };
+    Ok(Json(TwinsPage {
+        twins: page.into_iter().map(|(_, twin)| twin).collect(),
+        next_cursor,
+    }))
 }
 
 async fn get_did(State(state): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<serde_json::Value>, StatusCode> {
-    let Some(ident) = state.identities.read().await.get(&id).cloned() else {
+    let Some(twin) = registry(&state, move |s| s.get(id)).await? else {
         return Err(StatusCode::NOT_FOUND);
     };
-    Ok(Json(ident.did_document))
+    served_document(twin)
 }
 
-fn create_and_persist_did(twin_uuid: Uuid) -> Result<(String, serde_json::Value), String> {
-    use rand_core::OsRng;
+/// Fallback: serve the `did:web` document for the request path, e.g. `/.well-known/did.json`
+/// for `did:web:{domain}` and `/orgs/acme/did.json` for `did:web:{domain}:orgs:acme`.
+async fn get_web_did(State(state): State<AppState>, uri: Uri) -> Result<Json<serde_json::Value>, StatusCode> {
+    let Some(did) = state.did_method.did_for_request_path(uri.path()) else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let Some(twin) = registry(&state, move |s| s.find_by_did(&did)).await? else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    served_document(twin)
+}
 
-    let mut rng = OsRng;
-    let signing_key = SigningKey::generate(&mut rng);
-    let verifying_key = signing_key.verifying_key();
+/// A deleted twin's DID is deactivated: resolvers get `410 Gone`.
+fn served_document(twin: TwinRecord) -> Result<Json<serde_json::Value>, StatusCode> {
+    if twin.state.status == TwinStatus::Deleted {
+        return Err(StatusCode::GONE);
+    }
+    Ok(Json(twin.did_document))
+}
 
-    let public_bytes = verifying_key.to_bytes();
-    let secret_bytes = signing_key.to_bytes();
+/// A twin's state with the DIDs it has had, which services erasing a deleted twin purge by.
+#[derive(Debug, Serialize)]
+struct TwinView {
+    #[serde(flatten)]
+    state: TwinState,
+    did: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    previous_dids: Vec<String>,
+}
 
-    // did:key method-specific-id is multibase(base58btc, multicodec(ed25519-pub) || pubkey)
-    // Multicodec prefix for Ed25519 public key is 0xed 0x01.
-    let mut codec_and_key = Vec::with_capacity(2 + public_bytes.len());
-    codec_and_key.push(0xed);
-    codec_and_key.push(0x01);
-    codec_and_key.extend_from_slice(&public_bytes);
+/// `GET /twins/:id`: the twin's state, with its version as the `ETag`.
+async fn get_twin(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+) -> Result<([(header::HeaderName, String); 1], Json<TwinView>), StatusCode> {
+    if caller.0.as_ref().is_some_and(|c| !c.allows_twin(id)) {
+        tracing::warn!(twin_id = %id, "token is bound to another twin");
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let Some(twin) = registry(&state, move |s| s.get(id)).await? else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let view = TwinView {
+        state: twin.state,
+        did: twin.did,
+        previous_dids: twin.previous_dids,
+    };
+    Ok(([(header::ETAG, etag(twin.version))], Json(view)))
+}
 
-    let method_id = multibase::encode(Base::Base58Btc, codec_and_key);
-    let did = format!("did:key:{method_id}");
+fn etag(version: u64) -> String {
+    format!("\"{version}\"")
+}
 
-    let vm_id = format!("{}#{}", did, method_id);
-    let did_document = json!({
-        "@context": "https://www.w3.org/ns/did/v1",
-        "id": did,
-        "verificationMethod": [{
-            "id": vm_id,
-            "type": "Ed25519VerificationKey2020",
-            "controller": did,
-            "publicKeyMultibase": method_id,
-        }],
-        "authentication": [vm_id],
-        "assertionMethod": [vm_id],
+/// The version an `If-Match` header requires, if any. `*` matches any version, and so does
+/// a missing header.
+fn if_match(headers: &HeaderMap) -> Result<Option<u64>, StatusCode> {
+    let Some(raw) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    let raw = raw.to_str().map_err(|_| StatusCode::BAD_REQUEST)?.trim();
+    if raw == "*" {
+        return Ok(None);
+    }
+    raw.trim_start_matches("W/")
+        .trim_matches('"')
+        .parse()
+        .map(Some)
+        // Not a version this service ever issued.
+        .map_err(|_| StatusCode::PRECONDITION_FAILED)
+}
+
+/// `PATCH /twins/:id/state`: replace the note and, when the status changes, make a
+/// lifecycle transition.
+///
+/// - `409`: the lifecycle does not allow the transition (e.g. anything out of `deleted`)
+/// - `412`: `If-Match` does not name the current version
+/// - `422`: unknown status
+async fn update_state(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(req): Json<UpdateStateRequest>,
+) -> Result<(StatusCode, [(header::HeaderName, String); 1], Json<TwinState>), StatusCode> {
+    let expected = if_match(&headers)?;
+    let next = req.state.parse()?;
+    let Some(current) = registry(&state, move |s| s.get(id)).await? else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    if expected.is_some_and(|v| v != current.version) {
+        return Err(StatusCode::PRECONDITION_FAILED);
+    }
+    let from = current.state.status;
+    if !from.can_transition_to(next.status) {
+        tracing::warn!(twin_id = %id, %from, to = %next.status, "lifecycle transition refused");
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let transition = (from != next.status).then(|| StatusTransition {
+        from,
+        to: next.status,
+        reason: req.reason.clone(),
+        actor: actor(&caller),
+        at: OffsetDateTime::now_utc(),
     });
+    // Conditional on the version validated above, so a concurrent transition cannot slip in.
+    let (applied, recorded) = (next.clone(), transition.clone());
+    let updated = registry(&state, move |s| {
+        s.update_versioned(id, Some(current.version), &mut |twin| {
+            twin.state = applied.clone();
+            twin.transitions.extend(recorded.clone());
+        })
+    })
+    .await?;
+    let twin = match updated {
+        Updated::Applied(twin) => *twin,
+        Updated::NotFound => return Err(StatusCode::NOT_FOUND),
+        Updated::Stale if expected.is_some() => return Err(StatusCode::PRECONDITION_FAILED),
+        Updated::Stale => return Err(StatusCode::CONFLICT),
+    };
 
-    // Persist the private key for later signing.
-    // NOTE: This is intentionally minimal. Phase 5 should encrypt-at-rest.
-    let data_dir = std::env::var("IDENTITY_DATA_DIR").unwrap_or_else(|_| "/data/identity".to_string());
-    let keys_dir = std::path::PathBuf::from(data_dir).join("keys");
-    std::fs::create_dir_all(&keys_dir).map_err(|e| e.to_string())?;
-    let key_path = keys_dir.join(format!("{twin_uuid}.ed25519"));
-    std::fs::write(&key_path, secret_bytes).map_err(|e| e.to_string())?;
+    if let Some(t) = &transition {
+        tracing::info!(twin_id = %id, from = %t.from, to = %t.to, actor = ?t.actor, reason = ?t.reason, "twin lifecycle transition");
+    }
+    let mut ev = EventEnvelope::new_core(
+        id,
+        CoreEvent::TwinStateUpdated {
+            state: twin.state.clone(),
+            previous_status: transition.as_ref().map(|t| t.from),
+            reason: transition.as_ref().and(req.reason),
+            actor: actor(&caller),
+        },
+    );
+    ev.source = Some("pagi-identity-service".to_string());
+    publish_event(ev);
 
-    Ok((did_document["id"].as_str().unwrap_or_default().to_string(), did_document))
+    // Deleting through the lifecycle erases the twin's data just like `DELETE /twins/:id`.
+    let twin = match transition.filter(|t| t.to == TwinStatus::Deleted) {
+        Some(t) => erase_twin(&state, twin, t.reason, t.actor).await?.0,
+        None => twin,
+    };
+    Ok((StatusCode::OK, [(header::ETAG, etag(twin.version))], Json(twin.state)))
 }
 
-async fn get_twin(State(state): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<TwinState>, StatusCode> {
-    let Some(st) = state.twins.read().await.get(&id).cloned() else {
+/// `DELETE /twins/:id`: delete the twin and erase its data across the node.
+///
+/// The twin moves to `deleted` (recorded with the `reason` query parameter and the caller),
+/// its key file is destroyed and a `twin_deleted` event is delivered to every configured
+/// data service (see [`migrate::DataServices::erase`]) before being published. The record
+/// stays behind as a tombstone holding the [`ErasureReport`]. Calling again on a deleted
+/// twin retries the sections that have not been confirmed.
+///
+/// - `200`: every section confirmed
+/// - `202`: some sections failed (see the report); retry later
+/// - `409`: the lifecycle does not allow deletion (archive active and suspended twins first)
+async fn delete_twin(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(id): Path<Uuid>,
+    Query(query): Query<DeleteTwinQuery>,
+) -> Result<(StatusCode, Json<ErasureReport>), StatusCode> {
+    let Some(current) = registry(&state, move |s| s.get(id)).await? else {
         return Err(StatusCode::NOT_FOUND);
     };
-    Ok(Json(st))
+    let from = current.state.status;
+    let actor = actor(&caller);
+    let twin = if from == TwinStatus::Deleted {
+        current
+    } else {
+        if !from.can_transition_to(TwinStatus::Deleted) {
+            tracing::warn!(twin_id = %id, %from, "twin cannot be deleted from its status");
+            return Err(StatusCode::CONFLICT);
+        }
+        let transition = StatusTransition {
+            from,
+            to: TwinStatus::Deleted,
+            reason: query.reason.clone(),
+            actor: actor.clone(),
+            at: OffsetDateTime::now_utc(),
+        };
+        let recorded = transition.clone();
+        let updated = registry(&state, move |s| {
+            s.update_versioned(id, Some(current.version), &mut |twin| {
+                twin.state.status = TwinStatus::Deleted;
+                twin.transitions.push(recorded.clone());
+            })
+        })
+        .await?;
+        let twin = match updated {
+            Updated::Applied(twin) => *twin,
+            Updated::NotFound => return Err(StatusCode::NOT_FOUND),
+            Updated::Stale => return Err(StatusCode::CONFLICT),
+        };
+        tracing::info!(twin_id = %id, %from, to = %TwinStatus::Deleted, ?actor, reason = ?query.reason, "twin lifecycle transition");
+        let mut ev = EventEnvelope::new_core(
+            id,
+            CoreEvent::TwinStateUpdated {
+                state: twin.state.clone(),
+                previous_status: Some(from),
+                reason: query.reason.clone(),
+                actor: actor.clone(),
+            },
+        );
+        ev.source = Some("pagi-identity-service".to_string());
+        publish_event(ev);
+        twin
+    };
+
+    let (_, report) = erase_twin(&state, twin, query.reason, actor).await?;
+    let status = if report.completed_at.is_some() {
+        StatusCode::OK
+    } else {
+        StatusCode::ACCEPTED
+    };
+    Ok((status, Json(report)))
 }
 
-async fn update_state(
+/// Who a lifecycle change is recorded as made by: the caller's token, as `{role}:{sub}`
+/// (nobody when authentication is off).
+fn actor(caller: &Caller) -> Option<String> {
+    let claims = caller.0.as_ref()?;
+    let role = match claims.role {
+        Role::Twin => "twin",
+        Role::Service => "service",
+        Role::Admin => "admin",
+    };
+    Some(format!("{role}:{}", claims.sub))
+}
+
+/// Erase a deleted twin's key file and the sections its data services have not confirmed
+/// yet, and record the outcome on the twin. `twin_deleted` is published on the first attempt.
+async fn erase_twin(
+    state: &AppState,
+    twin: TwinRecord,
+    reason: Option<String>,
+    actor: Option<String>,
+) -> Result<(TwinRecord, ErasureReport), StatusCode> {
+    let id = twin.twin_id;
+    let first_attempt = twin.erasure.is_none();
+    let mut report = twin.erasure.clone().unwrap_or_else(|| ErasureReport {
+        requested_at: OffsetDateTime::now_utc(),
+        completed_at: None,
+        sections: Default::default(),
+    });
+    let allow_skipped = state.data.allow_skipped;
+    if !report.is_done(SIGNING_KEY_SECTION, allow_skipped) {
+        let outcome = match keys::erase_key(&state.keys_dir, id) {
+            Ok(existed) => ErasureOutcome::Purged(
+                PurgeReport::new("pagi-identity-service", id, PurgeTrigger::TwinDeleted).with("key_files", existed as u64),
+            ),
+            Err(err) => {
+                tracing::error!(twin_id = %id, error = %err, "twin key file not erased");
+                ErasureOutcome::Failed(err)
+            }
+        };
+        report.sections.insert(SIGNING_KEY_SECTION.to_string(), outcome);
+    }
+
+    let mut ev = EventEnvelope::new_core(
+        id,
+        CoreEvent::TwinDeleted {
+            did: twin.did.clone(),
+            previous_dids: twin.previous_dids.clone(),
+            reason,
+            actor,
+        },
+    );
+    ev.source = Some("pagi-identity-service".to_string());
+    let pending: Vec<&str> = migrate::DATA_SECTIONS.iter().copied().filter(|s| !report.is_done(s, allow_skipped)).collect();
+    let outcomes = state.data.erase(&ev, &pending).await;
+    report.sections.extend(outcomes);
+    if report.completed_at.is_none() && report.sections.values().all(|o| o.is_done(allow_skipped)) {
+        report.completed_at = Some(OffsetDateTime::now_utc());
+    }
+
+    let erasure = report.clone();
+    let Some(twin) = registry(state, move |s| s.update(id, &mut |t| t.erasure = Some(erasure.clone()))).await? else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    if first_attempt {
+        publish_event(ev);
+    }
+    let failed: Vec<&String> = report.sections.iter().filter(|(_, o)| !o.is_done(allow_skipped)).map(|(s, _)| s).collect();
+    tracing::info!(twin_id = %id, complete = report.completed_at.is_some(), ?failed, "twin erasure");
+    Ok((twin, report))
+}
+
+/// `GET /twins/:id/erasure`: the erasure report of a deleted twin (`404` for other twins).
+async fn get_erasure(State(state): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<ErasureReport>, StatusCode> {
+    let Some(twin) = registry(&state, move |s| s.get(id)).await? else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    twin.erasure.map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+/// `PUT /twins/:id/services`: replace the service endpoints in a `did:web` twin's DID
+/// document, returning the document. `did:key` documents cannot carry services (`409`).
+async fn update_services(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
-    Json(req): Json<UpdateStateRequest>,
-) -> Result<(StatusCode, Json<TwinState>), StatusCode> {
-    let mut guard = state.twins.write().await;
-    let Some(entry) = guard.get_mut(&id) else {
+    Json(req): Json<UpdateServicesRequest>,
+) -> Result<Json<serde_json::Value>, StatusCode> {
+    let Some(twin) = registry(&state, move |s| s.get(id)).await? else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    if !twin.did.starts_with("did:web:") || twin.state.status == TwinStatus::Deleted {
+        tracing::warn!(twin_id = %id, did = %twin.did, status = %twin.state.status, "DID document cannot publish services");
+        return Err(StatusCode::CONFLICT);
+    }
+    keys::validate_services(&twin.did, &req.services).map_err(|err| {
+        tracing::warn!(twin_id = %id, error = %err, "rejected DID services");
+        StatusCode::BAD_REQUEST
+    })?;
+
+    let services = req.services;
+    let updated = registry(&state, move |s| {
+        s.update(id, &mut |t| {
+            t.services = services.clone();
+            t.did_document = keys::did_document(t);
+        })
+    })
+    .await?;
+    let Some(updated) = updated else {
         return Err(StatusCode::NOT_FOUND);
     };
-    *entry = req.state.clone();
+    Ok(Json(updated.did_document))
+}
+
+/// `POST /twins/:id/keys/rotate`: replace the twin's signing key.
+///
+/// The DID stays the same for `did:web` twins. A `did:key` twin moves to its `did:web` (the
+/// old DID is kept in `alsoKnownAs`) when `IDENTITY_DID_METHOD=web`, and is refused with
+/// `409` otherwise. Retired keys stay in the DID document for verifying older signatures.
+async fn rotate_twin_key(State(state): State<AppState>, Path(id): Path<Uuid>) -> Result<Json<TwinRecord>, StatusCode> {
+    let _rotating = state.rotation.lock().await;
+    let Some(twin) = registry(&state, move |s| s.get(id)).await? else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    if twin.state.status == TwinStatus::Deleted {
+        return Err(StatusCode::CONFLICT);
+    }
+    let Some(did) = keys::rotation_did(&state.did_method, &twin) else {
+        tracing::warn!(twin_id = %id, did = %twin.did, "did:key cannot rotate; set IDENTITY_DID_METHOD=web");
+        return Err(StatusCode::CONFLICT);
+    };
 
-    let mut ev = EventEnvelope::new(EventType::TwinStateUpdated, json!({"twin_id": id, "state": entry}));
-    ev.twin_id = Some(id);
+    // Startup reconciliation trusts the key file, so it must not keep a key the registry refused.
+    let previous_key = state.keyring.read_key(&state.keys_dir, id).ok();
+    let verifying_key = keys::generate_rotated_key(&state.keyring, &state.keys_dir, id).map_err(internal)?;
+    let now = OffsetDateTime::now_utc();
+    let rotated = registry(&state, move |s| {
+        s.update(id, &mut |t| keys::apply_rotation(t, &did, &verifying_key, now))
+    })
+    .await;
+    let rotated = match rotated {
+        Ok(Some(rotated)) => rotated,
+        refused => {
+            if let Err(err) = keys::restore_key(&state.keyring, &state.keys_dir, id, previous_key.as_ref()) {
+                tracing::error!(twin_id = %id, error = %err, "failed to restore the key file after a refused rotation");
+            }
+            return Err(refused.err().unwrap_or(StatusCode::NOT_FOUND));
+        }
+    };
+
+    let previous = rotated.keys.len().checked_sub(2).map(|i| rotated.keys[i].id.clone());
+    let current = rotated.keys.last().map(|k| k.id.clone()).unwrap_or_default();
+    tracing::info!(twin_id = %id, did = %rotated.did, verification_method = %current, "twin key rotated");
+    let mut ev = EventEnvelope::new_core(
+        id,
+        CoreEvent::TwinKeyRotated {
+            did: rotated.did.clone(),
+            verification_method: current,
+            previous_verification_method: previous,
+            previous_did: (twin.did != rotated.did).then(|| twin.did.clone()),
+        },
+    );
+    ev.source = Some("pagi-identity-service".to_string());
+    publish_event(ev);
+
+    Ok(Json(rotated))
+}
+
+/// `POST /keys/rotate`: re-seal every key file under the current KEK.
+///
+/// Rotation: deploy `IDENTITY_KEK=<new>` and `IDENTITY_KEK_PREVIOUS=<old>` to this service and
+/// the signing plugins, call this endpoint, then drop `IDENTITY_KEK_PREVIOUS`.
+async fn rotate_keys(State(state): State<AppState>) -> Result<Json<keys::Rewrapped>, StatusCode> {
+    if !state.keyring.is_sealing() {
+        tracing::warn!("key rotation requested but IDENTITY_KEK is not set");
+        return Err(StatusCode::CONFLICT);
+    }
+    // A twin key rotation racing the re-wrap could have its fresh key file overwritten
+    // with the old secret.
+    let _rotating = state.rotation.lock().await;
+    let keyring = state.keyring.clone();
+    let (keys_dir, issuer_dir) = (state.keys_dir.clone(), state.issuer_dir.clone());
+    let report = tokio::task::spawn_blocking(move || {
+        let mut report = keys::rewrap_all(&keyring, &keys_dir)?;
+        // The token issuer key is sealed the same way (listed under the nil id).
+        let issuer = keys::rewrap_all(&keyring, &issuer_dir)?;
+        report.rewrapped += issuer.rewrapped;
+        report.unchanged += issuer.unchanged;
+        report.failed.extend(issuer.failed);
+        Ok::<_, String>(report)
+    })
+    .await
+    .map_err(|e| internal(e.to_string()))?
+    .map_err(internal)?;
+    tracing::info!(rewrapped = report.rewrapped, unchanged = report.unchanged, failed = report.failed.len(), "key files re-wrapped");
+    Ok(Json(report))
+}
+
+/// The export passphrase from `X-Bundle-Passphrase`; `400` when missing or too short.
+fn bundle_passphrase(headers: &HeaderMap) -> Result<String, StatusCode> {
+    let passphrase = headers
+        .get(BUNDLE_PASSPHRASE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if passphrase.chars().count() < migrate::MIN_PASSPHRASE_LEN {
+        tracing::warn!("bundle passphrase missing or shorter than {} characters", migrate::MIN_PASSPHRASE_LEN);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(passphrase.to_string())
+}
+
+/// `GET /twins/:id/export`: the twin as a signed bundle for `POST /twins/import` on another
+/// node. The twin's signing key is sealed under the `X-Bundle-Passphrase` header; the other
+/// sections come from the configured data services (see [`migrate::DataServices`]).
+///
+/// The twin stays registered here: suspend or archive it once the import has succeeded.
+///
+/// - `409`: the twin is deleted or has no current key
+/// - `502`: a data service could not be read
+async fn export_twin(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<([(header::HeaderName, String); 1], Json<TwinBundle>), StatusCode> {
+    let passphrase = bundle_passphrase(&headers)?;
+    let Some(twin) = registry(&state, move |s| s.get(id)).await? else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+    if twin.state.status == TwinStatus::Deleted {
+        tracing::warn!(twin_id = %id, "deleted twins cannot be exported");
+        return Err(StatusCode::CONFLICT);
+    }
+    let Some(current) = twin.keys.iter().rev().find(|k| k.retired_at.is_none()) else {
+        tracing::warn!(twin_id = %id, "twin has no current signing key");
+        return Err(StatusCode::CONFLICT);
+    };
+    let secret = state.keyring.read_key(&state.keys_dir, id).map_err(internal)?;
+    let signing_key = SigningKey::from_bytes(&secret);
+    if keys::public_key_multibase(&signing_key.verifying_key()) != current.public_key_multibase {
+        return Err(internal(format!("key file of twin {id} does not match its current key")));
+    }
+
+    let sections = state.data.export(&twin).await.map_err(|err| {
+        tracing::warn!(twin_id = %id, error = %err, "twin export failed");
+        StatusCode::BAD_GATEWAY
+    })?;
+    let contents = BundleContents {
+        twin_id: id,
+        exported_at: OffsetDateTime::now_utc(),
+        exported_by: state.issuer.name.clone(),
+        identity: serde_json::to_value(&twin).map_err(|e| internal(e.to_string()))?,
+        sealed_key: migrate::seal_key(&passphrase, id, &secret).map_err(internal)?,
+        sections,
+    };
+    let bundle = TwinBundle::seal(&contents, &signing_key, &current.id).map_err(internal)?;
+    tracing::info!(twin_id = %id, sections = ?contents.sections.keys().collect::<Vec<_>>(), "twin exported");
+    Ok((
+        [(header::CONTENT_DISPOSITION, format!("attachment; filename=\"twin-{id}.json\""))],
+        Json(bundle),
+    ))
+}
+
+/// `POST /twins/import`: register a twin from a bundle made by `GET /twins/:id/export`, with
+/// the same `X-Bundle-Passphrase`, then restore its sections to this node's data services.
+///
+/// The sealed key must open with the passphrase and be the twin's current key, the bundle must
+/// be signed by it, and the twin's DID must vouch for it: a `did:key` by encoding it, a
+/// `did:web` by listing it in the document its source node serves. A `did:web` twin whose document this node does not serve moves under
+/// this node's domain (keeping the old DID in `alsoKnownAs`) when `IDENTITY_DID_METHOD=web`.
+///
+/// - `409`: the twin or its DID is already registered here, or the twin is deleted
+/// - `422`: unsupported bundle, wrong passphrase, or integrity check failed
+async fn import_twin(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(bundle): Json<TwinBundle>,
+) -> Result<(StatusCode, Json<ImportResponse>), StatusCode> {
+    let passphrase = bundle_passphrase(&headers)?;
+    let rejected = |err: String| {
+        tracing::warn!(error = %err, "twin bundle rejected");
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    let contents = bundle.unverified_contents().map_err(rejected)?;
+    let mut twin: store::TwinRecord = serde_json::from_value(contents.identity.clone())
+        .map_err(|e| rejected(format!("identity section: {e}")))?;
+    let id = twin.twin_id;
+    if id != contents.twin_id {
+        return Err(rejected(format!("bundle for {} carries twin {id}", contents.twin_id)));
+    }
+    let secret = migrate::open_key(&passphrase, id, &contents.sealed_key).map_err(rejected)?;
+    let signing_key = SigningKey::from_bytes(&secret);
+    bundle.verify(&signing_key.verifying_key()).map_err(rejected)?;
+    let current = twin.keys.iter().rev().find(|k| k.retired_at.is_none());
+    if !current.is_some_and(|k| {
+        k.id == bundle.signature.verification_method
+            && k.public_key_multibase == keys::public_key_multibase(&signing_key.verifying_key())
+    }) {
+        return Err(rejected("bundle key is not the twin's current key".to_string()));
+    }
+    migrate::check_source_did(&state.did_resolver, &twin.did, &signing_key.verifying_key())
+        .await
+        .map_err(rejected)?;
+    if twin.state.status == TwinStatus::Deleted {
+        tracing::warn!(twin_id = %id, "deleted twins cannot be imported");
+        return Err(StatusCode::CONFLICT);
+    }
+
+    // Key file and registry entry are written together, as in a rotation.
+    let registering = state.rotation.lock().await;
+    if registry(&state, move |s| s.get(id)).await?.is_some() {
+        tracing::warn!(twin_id = %id, "imported twin is already registered");
+        return Err(StatusCode::CONFLICT);
+    }
+    let previous_did = keys::import_did(&state.did_method, &twin).map(|did| {
+        let old = twin.did.clone();
+        keys::rehome(&mut twin, &did);
+        old
+    });
+    let did = twin.did.clone();
+    if registry(&state, move |s| s.find_by_did(&did)).await?.is_some() {
+        tracing::warn!(twin_id = %id, did = %twin.did, "imported twin's DID is already registered");
+        return Err(StatusCode::CONFLICT);
+    }
+    twin.did_document = keys::did_document(&twin);
+    twin.updated_at = OffsetDateTime::now_utc();
+
+    state.keyring.write_key(&state.keys_dir, id, &secret).map_err(internal)?;
+    let record = twin.clone();
+    if let Err(status) = registry(&state, move |s| s.insert(&record)).await {
+        let _ = std::fs::remove_file(pagi_common::keystore::key_path(&state.keys_dir, id));
+        return Err(status);
+    }
+    drop(registering);
+
+    let sections = state.data.import(id, &contents.sections).await;
+    tracing::info!(twin_id = %id, did = %twin.did, exported_by = %contents.exported_by, "twin imported");
+    let mut ev = EventEnvelope::new_core(id, CoreEvent::TwinRegistered { state: twin.state.clone() });
     ev.source = Some("pagi-identity-service".to_string());
-    let _ = publish_event(ev).await;
+    publish_event(ev);
+
+    Ok((
+        StatusCode::CREATED,
+        Json(ImportResponse {
+            twin_id: TwinId(id),
+            did: twin.did,
+            previous_did,
+            exported_by: contents.exported_by,
+            sections,
+        }),
+    ))
+}
+
+/// `GET /.well-known/jwks.json`: the public key services verify bearer tokens with.
+async fn jwks(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(state.issuer.jwks())
+}
+
+/// `POST /tokens`: issue a bearer token.
+///
+/// The caller authenticates with `Authorization: Bearer` and one of (see [`Requester`]):
+/// the client secret (`AUTH_CLIENT_SECRET`), which only gets service tokens; the admin secret
+/// (`IDENTITY_ADMIN_SECRET`); or a token carrying `tokens:issue`, where only admin tokens get
+/// admin tokens. Twin tokens are only issued for registered twins whose status accepts work.
+///
+/// - `400`: inconsistent role, subject, twin or scopes
+/// - `401`: missing or unknown credentials
+/// - `403`: the credential may not issue the role, or the twin does not accept work
+async fn issue_token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<TokenRequest>,
+) -> Result<Json<TokenResponse>, StatusCode> {
+    let Some(presented) = bearer_token(&headers) else {
+        return Err(StatusCode::UNAUTHORIZED);
+    };
+    let matches = |secret: &Option<String>| secret.as_deref().is_some_and(|s| tokens::secret_matches(presented, s));
+    let requester = if matches(&state.admin_secret) {
+        Requester::AdminSecret
+    } else if matches(&state.client_secret) {
+        Requester::ClientSecret
+    } else {
+        match state.issuer_auth.verify(presented).await {
+            Ok(Some(claims)) if claims.has_scope(scopes::TOKENS_ISSUE) => Requester::Token(claims.role),
+            Ok(_) => {
+                tracing::warn!("token request with a token lacking tokens:issue");
+                return Err(StatusCode::FORBIDDEN);
+            }
+            Err(err) => {
+                tracing::warn!(error = ?err, "token request with unknown credentials");
+                return Err(StatusCode::UNAUTHORIZED);
+            }
+        }
+    };
+    if !requester.may_issue(req.role) {
+        tracing::warn!(?requester, role = ?req.role, "credential may not issue this role");
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let claims = state.issuer.claims(&req).map_err(|err| {
+        tracing::warn!(error = %err, "rejected token request");
+        StatusCode::BAD_REQUEST
+    })?;
+    if let Some(twin_id) = req.twin_id {
+        let Some(twin) = registry(&state, move |s| s.get(twin_id)).await? else {
+            return Err(StatusCode::NOT_FOUND);
+        };
+        if !twin.state.status.accepts_work() {
+            tracing::warn!(%twin_id, status = %twin.state.status, "no tokens for inactive twins");
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
 
-    Ok((StatusCode::OK, Json(entry.clone())))
+    let access_token = state.issuer.sign(&claims).map_err(internal)?;
+    tracing::info!(sub = %claims.sub, role = ?claims.role, jti = %claims.jti, "token issued");
+    Ok(Json(TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: claims.exp - claims.iat,
+        scope: claims.scope,
+    }))
 }
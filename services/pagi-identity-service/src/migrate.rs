@@ -0,0 +1,288 @@
+//! Twin data held by other services: moving it between nodes as a
+//! [`TwinBundle`](pagi_common::bundle::TwinBundle), and erasing it when the twin is deleted.
+//!
+//! The registry record and signing key come from this service; the other sections are
+//! fetched from, restored to and purged by the services configured here. A service that is
+//! not configured is left out of exports and skipped on import and erasure; a skipped section
+//! leaves the erasure incomplete unless `IDENTITY_ERASURE_ALLOW_SKIPPED=true`.
+
+use std::collections::BTreeMap;
+
+use ed25519_dalek::VerifyingKey;
+use pagi_common::{bundle::sections, did::DidResolver, erasure::PurgeReport, keystore::Keyring, EventEnvelope};
+use pagi_http::{auth::service_client, trace_context::TracedClient};
+use serde::Serialize;
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::{
+    keys,
+    store::{ErasureOutcome, TwinRecord},
+};
+
+/// Shortest export passphrase accepted.
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// The event router's event log, retained events and bus records. Erased but never exported.
+pub const EVENTS_SECTION: &str = "events";
+
+/// Sections purged by [`DataServices::erase`].
+pub const DATA_SECTIONS: &[&str] =
+    &[sections::WORKING_MEMORY, sections::EMOTION, sections::TOOLS, sections::MAILBOX, EVENTS_SECTION];
+
+/// Services holding twin data.
+///
+/// - `WORKING_MEMORY_URL`: working memory items
+/// - `EMOTION_STATE_URL`: emotion state
+/// - `EXTERNAL_GATEWAY_URL`: twin-scoped tools
+/// - `DIDCOMM_PLUGIN_URL`: pending DIDComm messages for the twin's DIDs
+/// - `EVENT_ROUTER_URL`: the twin's events (erasure only)
+/// - `IDENTITY_ERASURE_ALLOW_SKIPPED`: `true` counts sections without a configured service as
+///   erased (default: `false`, the erasure stays incomplete)
+pub struct DataServices {
+    http: TracedClient,
+    working_memory: Option<String>,
+    emotion: Option<String>,
+    gateway: Option<String>,
+    didcomm: Option<String>,
+    event_router: Option<String>,
+    pub allow_skipped: bool,
+}
+
+/// What happened to a bundle section on import.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SectionOutcome {
+    Restored,
+    /// No service for the section is configured on this node.
+    Skipped,
+    Failed(String),
+}
+
+impl DataServices {
+    pub fn from_env() -> Self {
+        let url = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .map(|v| v.trim().trim_end_matches('/').to_string())
+                .filter(|v| !v.is_empty())
+        };
+        Self {
+            http: service_client(),
+            working_memory: url("WORKING_MEMORY_URL"),
+            emotion: url("EMOTION_STATE_URL"),
+            gateway: url("EXTERNAL_GATEWAY_URL"),
+            didcomm: url("DIDCOMM_PLUGIN_URL"),
+            // The outbox accepts the full `/publish` endpoint too.
+            event_router: url("EVENT_ROUTER_URL").map(|u| u.trim_end_matches("/publish").to_string()),
+            allow_skipped: std::env::var("IDENTITY_ERASURE_ALLOW_SKIPPED")
+                .is_ok_and(|v| v.trim().eq_ignore_ascii_case("true")),
+        }
+    }
+
+    /// Collect the twin's sections. Any configured service failing fails the export, so a
+    /// bundle never silently misses data.
+    pub async fn export(&self, twin: &TwinRecord) -> Result<BTreeMap<String, Value>, String> {
+        let id = twin.twin_id;
+        let mut out = BTreeMap::new();
+        if let Some(base) = &self.working_memory {
+            let items = self.memory_items(base, id).await;
+            out.insert(sections::WORKING_MEMORY.to_string(), items.map_err(|e| format!("working memory: {e}"))?);
+        }
+        if let Some(base) = &self.emotion {
+            let emotion = self.fetch(self.http.get(format!("{base}/emotion/{id}"))).await;
+            out.insert(sections::EMOTION.to_string(), emotion.map_err(|e| format!("emotion state: {e}"))?);
+        }
+        if let Some(base) = &self.gateway {
+            let listed = self
+                .fetch(self.http.get(format!("{base}/tools/{id}?global=false")))
+                .await
+                .map_err(|e| format!("tools: {e}"))?;
+            out.insert(sections::TOOLS.to_string(), listed.get("tools").cloned().unwrap_or(json!([])));
+        }
+        if let Some(base) = &self.didcomm {
+            let dids: Vec<&String> = std::iter::once(&twin.did).chain(&twin.previous_dids).collect();
+            let mailbox = self
+                .fetch(self.http.post(format!("{base}/mailbox/export")).json(&json!({ "dids": dids })))
+                .await;
+            out.insert(sections::MAILBOX.to_string(), mailbox.map_err(|e| format!("mailbox: {e}"))?);
+        }
+        Ok(out)
+    }
+
+    /// Restore each section to its service; failures are reported per section, since the
+    /// twin itself is already registered by then.
+    pub async fn import(&self, twin_id: Uuid, data: &BTreeMap<String, Value>) -> BTreeMap<String, SectionOutcome> {
+        let mut outcomes = BTreeMap::new();
+        for (name, section) in data {
+            let outcome = match self.restore(twin_id, name, section).await {
+                None => SectionOutcome::Skipped,
+                Some(Ok(())) => SectionOutcome::Restored,
+                Some(Err(err)) => {
+                    tracing::warn!(%twin_id, section = %name, error = %err, "bundle section not restored");
+                    SectionOutcome::Failed(err)
+                }
+            };
+            outcomes.insert(name.clone(), outcome);
+        }
+        outcomes
+    }
+
+    /// Deliver a `twin_deleted` event to the `POST /events` endpoint of each service holding
+    /// one of the `pending` sections, collecting their purge reports.
+    pub async fn erase(&self, ev: &EventEnvelope, pending: &[&str]) -> BTreeMap<String, ErasureOutcome> {
+        let services = [
+            (sections::WORKING_MEMORY, &self.working_memory),
+            (sections::EMOTION, &self.emotion),
+            (sections::TOOLS, &self.gateway),
+            (sections::MAILBOX, &self.didcomm),
+            (EVENTS_SECTION, &self.event_router),
+        ];
+        let mut outcomes = BTreeMap::new();
+        for (name, base) in services.into_iter().filter(|(name, _)| pending.contains(name)) {
+            let outcome = match base {
+                None => ErasureOutcome::Skipped,
+                Some(base) => match self.fetch(self.http.post(format!("{base}/events")).json(ev)).await {
+                    Ok(report) => match serde_json::from_value::<PurgeReport>(report) {
+                        Ok(report) => ErasureOutcome::Purged(report),
+                        Err(e) => ErasureOutcome::Failed(format!("unexpected purge report: {e}")),
+                    },
+                    Err(err) => ErasureOutcome::Failed(err),
+                },
+            };
+            if let ErasureOutcome::Failed(err) = &outcome {
+                tracing::warn!(twin_id = ?ev.twin_id, section = %name, error = %err, "twin data not erased");
+            }
+            outcomes.insert(name.to_string(), outcome);
+        }
+        outcomes
+    }
+
+    /// All working memory items, oldest first. Pages run from the newest items backwards.
+    async fn memory_items(&self, base: &str, twin_id: Uuid) -> Result<Value, String> {
+        let mut pages = Vec::new();
+        let mut cursor: Option<u64> = None;
+        loop {
+            let url = match cursor {
+                Some(cursor) => format!("{base}/memory/{twin_id}?limit=1000&cursor={cursor}"),
+                None => format!("{base}/memory/{twin_id}?limit=1000"),
+            };
+            let mut page = self.fetch(self.http.get(url)).await?;
+            let mut items = match page.get_mut("items").map(Value::take) {
+                Some(Value::Array(items)) => items,
+                _ => return Err("unexpected memory page".to_string()),
+            };
+            // Scores are recomputed by the importing node.
+            for item in &mut items {
+                if let Some(item) = item.as_object_mut() {
+                    item.remove("importance");
+                }
+            }
+            pages.push(items);
+            cursor = page.get("next_cursor").and_then(Value::as_u64);
+            if cursor.is_none() {
+                break;
+            }
+        }
+        Ok(Value::Array(pages.into_iter().rev().flatten().collect()))
+    }
+
+    async fn restore(&self, twin_id: Uuid, name: &str, section: &Value) -> Option<Result<(), String>> {
+        Some(match name {
+            sections::WORKING_MEMORY => {
+                let base = self.working_memory.as_ref()?;
+                self.send(self.http.put(format!("{base}/memory/{twin_id}")).json(section)).await
+            }
+            sections::EMOTION => {
+                let base = self.emotion.as_ref()?;
+                self.send(self.http.put(format!("{base}/emotion/{twin_id}")).json(section)).await
+            }
+            sections::TOOLS => {
+                let base = self.gateway.as_ref()?;
+                let mut result = Ok(());
+                for tool in section.as_array().into_iter().flatten() {
+                    let payload = json!({ "twin_id": twin_id, "tool": tool });
+                    result = self.send(self.http.post(format!("{base}/register_tool")).json(&payload)).await;
+                    if result.is_err() {
+                        break;
+                    }
+                }
+                result
+            }
+            sections::MAILBOX => {
+                let base = self.didcomm.as_ref()?;
+                self.send(self.http.post(format!("{base}/mailbox/import")).json(section)).await
+            }
+            _ => return None,
+        })
+    }
+
+    async fn fetch(&self, req: reqwest_middleware::RequestBuilder) -> Result<Value, String> {
+        let resp = req.send().await.map_err(|e| e.to_string())?;
+        let resp = resp.error_for_status().map_err(|e| e.to_string())?;
+        resp.json().await.map_err(|e| e.to_string())
+    }
+
+    async fn send(&self, req: reqwest_middleware::RequestBuilder) -> Result<(), String> {
+        let resp = req.send().await.map_err(|e| e.to_string())?;
+        resp.error_for_status().map(drop).map_err(|e| e.to_string())
+    }
+}
+
+/// Seal the twin's secret key under the export passphrase (key file v1 format, bound to
+/// the twin id).
+pub fn seal_key(passphrase: &str, twin_id: Uuid, secret: &[u8; 32]) -> Result<Value, String> {
+    let sealed = Keyring::new(Some(passphrase.as_bytes().to_vec()), None).seal(twin_id, secret)?;
+    serde_json::from_slice(&sealed).map_err(|e| e.to_string())
+}
+
+/// Check that `key` is controlled by `did`, the DID a bundle says the twin had on its source
+/// node: a `did:key` must encode it, and a `did:web` document must list it as a current
+/// assertion key. A bundle's own signature only proves it was signed with the key it carries.
+pub async fn check_source_did(resolver: &DidResolver, did: &str, key: &VerifyingKey) -> Result<(), String> {
+    if let Some(method_id) = did.strip_prefix("did:key:") {
+        if method_id != keys::public_key_multibase(key) {
+            return Err(format!("{did} does not encode the bundle key"));
+        }
+        return Ok(());
+    }
+    if !did.starts_with("did:web:") {
+        return Err(format!("unsupported DID method: {did}"));
+    }
+    let keys = resolver.ed25519_keys(did).await.map_err(|e| format!("resolving {did}: {e}"))?;
+    if !keys.contains(key.as_bytes()) {
+        return Err(format!("{did} does not list the bundle key as a current key"));
+    }
+    Ok(())
+}
+
+/// Open a key sealed by [`seal_key`]; fails on a wrong passphrase or a key sealed for
+/// another twin.
+pub fn open_key(passphrase: &str, twin_id: Uuid, sealed: &Value) -> Result<[u8; 32], String> {
+    if !sealed.is_object() {
+        return Err("bundle key is not sealed".to_string());
+    }
+    let raw = serde_json::to_vec(sealed).map_err(|e| e.to_string())?;
+    Keyring::new(Some(passphrase.as_bytes().to_vec()), None)
+        .open(twin_id, &raw)
+        .map(|(secret, _)| secret)
+        .map_err(|_| "bundle key could not be opened with the passphrase".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    #[tokio::test]
+    async fn did_key_sources_must_encode_the_bundle_key() {
+        let resolver = DidResolver::new(reqwest::Client::new(), false);
+        let key = SigningKey::from_bytes(&[5u8; 32]).verifying_key();
+        let other = SigningKey::from_bytes(&[6u8; 32]).verifying_key();
+        let did = format!("did:key:{}", keys::public_key_multibase(&key));
+
+        assert!(check_source_did(&resolver, &did, &key).await.is_ok());
+        assert!(check_source_did(&resolver, &did, &other).await.is_err());
+        assert!(check_source_did(&resolver, "did:example:123", &key).await.is_err());
+    }
+}
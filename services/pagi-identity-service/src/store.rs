@@ -0,0 +1,556 @@
+use pagi_common::{erasure::PurgeReport, TwinState, TwinStatus};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Mutex,
+};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+/// Everything the registry knows about a twin. The private key stays in its key file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TwinRecord {
+    pub twin_id: Uuid,
+    pub state: TwinState,
+    /// Bumped on every change; served as the ETag for optimistic concurrency.
+    #[serde(default)]
+    pub version: u64,
+    /// Lifecycle transitions, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transitions: Vec<StatusTransition>,
+    pub did: String,
+    pub did_document: serde_json::Value,
+    /// Every signing key the twin has had, oldest first; the last one is current.
+    #[serde(default)]
+    pub keys: Vec<KeyVersion>,
+    /// DIDs the twin was known by before a rotation changed its DID (`alsoKnownAs`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous_dids: Vec<String>,
+    /// Service endpoints published in a `did:web` document (e.g. the DIDComm `/receive` URL).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<DidService>,
+    /// Set once the twin is deleted: what was erased, and where.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub erasure: Option<ErasureReport>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+/// One of a twin's Ed25519 public keys, as a DID verification method.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KeyVersion {
+    /// Verification method id (`{did}#...`).
+    pub id: String,
+    pub public_key_multibase: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    /// Set once rotated out; the key stays in the DID document so older signatures verify.
+    #[serde(default, with = "time::serde::rfc3339::option", skip_serializing_if = "Option::is_none")]
+    pub retired_at: Option<OffsetDateTime>,
+}
+
+/// One lifecycle status change, with who made it and why.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatusTransition {
+    pub from: TwinStatus,
+    pub to: TwinStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub at: OffsetDateTime,
+}
+
+/// Proof of a deleted twin's erasure, by section of twin data (the sections of a twin
+/// bundle, plus this service's `signing_key`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErasureReport {
+    #[serde(with = "time::serde::rfc3339")]
+    pub requested_at: OffsetDateTime,
+    /// Set once every section is purged (skipped ones only count when the operator allows it).
+    #[serde(default, with = "time::serde::rfc3339::option", skip_serializing_if = "Option::is_none")]
+    pub completed_at: Option<OffsetDateTime>,
+    pub sections: BTreeMap<String, ErasureOutcome>,
+}
+
+impl ErasureReport {
+    /// Whether `section` has been purged, or skipped and `allow_skipped`.
+    pub fn is_done(&self, section: &str, allow_skipped: bool) -> bool {
+        self.sections.get(section).is_some_and(|o| o.is_done(allow_skipped))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErasureOutcome {
+    /// The service's own report.
+    Purged(PurgeReport),
+    /// No service for the section is configured on this node.
+    Skipped,
+    Failed(String),
+}
+
+impl ErasureOutcome {
+    /// A skipped section only counts as erased when the operator allows it, since no service
+    /// confirmed that nothing was held.
+    pub fn is_done(&self, allow_skipped: bool) -> bool {
+        match self {
+            ErasureOutcome::Purged(_) => true,
+            ErasureOutcome::Skipped => allow_skipped,
+            ErasureOutcome::Failed(_) => false,
+        }
+    }
+}
+
+/// A DID document `service` entry.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DidService {
+    /// Fragment (`didcomm` or `#didcomm`); the document id is `{did}#{fragment}`.
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub service_endpoint: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TwinQuery {
+    pub status: Option<TwinStatus>,
+    /// Only return twins registered after this sequence number.
+    pub after: Option<u64>,
+    pub limit: usize,
+}
+
+/// Twin registry storage.
+///
+/// Backends (`IDENTITY_STORE`):
+/// - `sqlite` (default): embedded database at `IDENTITY_DATA_DIR/registry.db`
+/// - `memory`: lost on restart; for local development and tests
+///
+/// Every twin gets a sequence number in registration order, used as the list cursor.
+pub trait TwinStore: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Insert a new twin; fails if the id is already registered.
+    fn insert(&self, record: &TwinRecord) -> Result<(), String>;
+
+    fn get(&self, id: Uuid) -> Result<Option<TwinRecord>, String>;
+
+    /// The twin currently identified by `did`.
+    fn find_by_did(&self, did: &str) -> Result<Option<TwinRecord>, String>;
+
+    /// Apply `f` atomically and bump `version` and `updated_at`, provided the record is still
+    /// at `expected_version` (when given).
+    fn update_versioned(
+        &self,
+        id: Uuid,
+        expected_version: Option<u64>,
+        f: &mut dyn FnMut(&mut TwinRecord),
+    ) -> Result<Updated, String>;
+
+    /// Unconditional [`TwinStore::update_versioned`]; `None` when the twin does not exist.
+    fn update(&self, id: Uuid, f: &mut dyn FnMut(&mut TwinRecord)) -> Result<Option<TwinRecord>, String> {
+        match self.update_versioned(id, None, f)? {
+            Updated::Applied(record) => Ok(Some(*record)),
+            Updated::NotFound | Updated::Stale => Ok(None),
+        }
+    }
+
+    /// Twins in registration order, paired with their sequence number.
+    fn list(&self, query: &TwinQuery) -> Result<Vec<(u64, TwinRecord)>, String>;
+}
+
+pub enum Updated {
+    Applied(Box<TwinRecord>),
+    NotFound,
+    /// Someone else changed the twin first.
+    Stale,
+}
+
+fn apply(record: &mut TwinRecord, f: &mut dyn FnMut(&mut TwinRecord)) {
+    f(record);
+    record.version += 1;
+    record.updated_at = OffsetDateTime::now_utc();
+}
+
+pub fn from_env(data_dir: &Path) -> Result<Box<dyn TwinStore>, String> {
+    let backend = std::env::var("IDENTITY_STORE").unwrap_or_else(|_| "sqlite".to_string());
+    match backend.to_lowercase().as_str() {
+        "sqlite" => {
+            std::fs::create_dir_all(data_dir).map_err(|e| format!("identity data dir {}: {e}", data_dir.display()))?;
+            Ok(Box::new(SqliteStore::open(&data_dir.join("registry.db"))?))
+        }
+        "memory" => Ok(Box::new(MemoryStore::default())),
+        other => Err(format!("unknown IDENTITY_STORE backend '{other}' (expected sqlite|memory)")),
+    }
+}
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| format!("registry {}: {e}", path.display()))?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             CREATE TABLE IF NOT EXISTS twins (
+                 seq     INTEGER PRIMARY KEY AUTOINCREMENT,
+                 twin_id TEXT NOT NULL UNIQUE,
+                 status  TEXT NOT NULL,
+                 record  TEXT NOT NULL,
+                 did     TEXT
+             );
+             CREATE INDEX IF NOT EXISTS twins_status ON twins (status, seq);",
+        )
+        .map_err(|e| format!("registry {}: {e}", path.display()))?;
+        // Registries created before DIDs were looked up by value lack the column.
+        let has_did: bool = conn
+            .query_row("SELECT COUNT(*) FROM pragma_table_info('twins') WHERE name = 'did'", [], |row| row.get(0))
+            .map_err(|e| format!("registry {}: {e}", path.display()))?;
+        if !has_did {
+            conn.execute_batch(
+                "ALTER TABLE twins ADD COLUMN did TEXT;
+                 UPDATE twins SET did = json_extract(record, '$.did');",
+            )
+            .map_err(|e| format!("registry {}: {e}", path.display()))?;
+        }
+        // Free-form statuses from before the lifecycle read as `suspended` (see `TwinStatus`);
+        // make the column agree so status filters find them.
+        conn.execute_batch(
+            "CREATE UNIQUE INDEX IF NOT EXISTS twins_did ON twins (did);
+             UPDATE twins SET status = 'suspended'
+              WHERE status NOT IN ('registered', 'active', 'suspended', 'archived', 'deleted');",
+        )
+        .map_err(|e| format!("registry {}: {e}", path.display()))?;
+        tracing::info!(path = %path.display(), "twin registry: sqlite");
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|p| p.into_inner())
+    }
+}
+
+fn decode(raw: &str) -> Result<TwinRecord, String> {
+    serde_json::from_str(raw).map_err(|e| format!("corrupt twin record: {e}"))
+}
+
+impl TwinStore for SqliteStore {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn insert(&self, record: &TwinRecord) -> Result<(), String> {
+        let raw = serde_json::to_string(record).map_err(|e| e.to_string())?;
+        self.conn()
+            .execute(
+                "INSERT INTO twins (twin_id, status, record, did) VALUES (?1, ?2, ?3, ?4)",
+                params![record.twin_id.to_string(), record.state.status.as_str(), raw, record.did],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn get(&self, id: Uuid) -> Result<Option<TwinRecord>, String> {
+        let raw: Option<String> = self
+            .conn()
+            .query_row("SELECT record FROM twins WHERE twin_id = ?1", [id.to_string()], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        raw.as_deref().map(decode).transpose()
+    }
+
+    fn find_by_did(&self, did: &str) -> Result<Option<TwinRecord>, String> {
+        let raw: Option<String> = self
+            .conn()
+            .query_row("SELECT record FROM twins WHERE did = ?1", [did], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        raw.as_deref().map(decode).transpose()
+    }
+
+    fn update_versioned(
+        &self,
+        id: Uuid,
+        expected_version: Option<u64>,
+        f: &mut dyn FnMut(&mut TwinRecord),
+    ) -> Result<Updated, String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let raw: Option<String> = tx
+            .query_row("SELECT record FROM twins WHERE twin_id = ?1", [id.to_string()], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let Some(raw) = raw else {
+            return Ok(Updated::NotFound);
+        };
+        let mut record = decode(&raw)?;
+        if expected_version.is_some_and(|v| v != record.version) {
+            return Ok(Updated::Stale);
+        }
+        apply(&mut record, f);
+        let raw = serde_json::to_string(&record).map_err(|e| e.to_string())?;
+        tx.execute(
+            "UPDATE twins SET status = ?2, record = ?3, did = ?4 WHERE twin_id = ?1",
+            params![id.to_string(), record.state.status.as_str(), raw, record.did],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(Updated::Applied(Box::new(record)))
+    }
+
+    fn list(&self, query: &TwinQuery) -> Result<Vec<(u64, TwinRecord)>, String> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare(
+                "SELECT seq, record FROM twins
+                 WHERE seq > ?1 AND (?2 IS NULL OR status = ?2)
+                 ORDER BY seq LIMIT ?3",
+            )
+            .map_err(|e| e.to_string())?;
+        let after = query.after.unwrap_or(0) as i64;
+        let rows = stmt
+            .query_map(params![after, query.status.map(|s| s.as_str()), query.limit as i64], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.map(|row| {
+            let (seq, raw) = row.map_err(|e| e.to_string())?;
+            Ok((seq as u64, decode(&raw)?))
+        })
+        .collect()
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    inner: Mutex<MemoryInner>,
+}
+
+#[derive(Default)]
+struct MemoryInner {
+    next_seq: u64,
+    seqs: HashMap<Uuid, u64>,
+    twins: BTreeMap<u64, TwinRecord>,
+}
+
+impl TwinStore for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn insert(&self, record: &TwinRecord) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap_or_else(|p| p.into_inner());
+        if inner.seqs.contains_key(&record.twin_id) {
+            return Err(format!("twin {} already registered", record.twin_id));
+        }
+        if inner.twins.values().any(|r| r.did == record.did) {
+            return Err(format!("{} already registered", record.did));
+        }
+        inner.next_seq += 1;
+        let seq = inner.next_seq;
+        inner.seqs.insert(record.twin_id, seq);
+        inner.twins.insert(seq, record.clone());
+        Ok(())
+    }
+
+    fn get(&self, id: Uuid) -> Result<Option<TwinRecord>, String> {
+        let inner = self.inner.lock().unwrap_or_else(|p| p.into_inner());
+        Ok(inner.seqs.get(&id).and_then(|seq| inner.twins.get(seq)).cloned())
+    }
+
+    fn find_by_did(&self, did: &str) -> Result<Option<TwinRecord>, String> {
+        let inner = self.inner.lock().unwrap_or_else(|p| p.into_inner());
+        Ok(inner.twins.values().find(|r| r.did == did).cloned())
+    }
+
+    fn update_versioned(
+        &self,
+        id: Uuid,
+        expected_version: Option<u64>,
+        f: &mut dyn FnMut(&mut TwinRecord),
+    ) -> Result<Updated, String> {
+        let mut inner = self.inner.lock().unwrap_or_else(|p| p.into_inner());
+        let Some(seq) = inner.seqs.get(&id).copied() else {
+            return Ok(Updated::NotFound);
+        };
+        let Some(record) = inner.twins.get_mut(&seq) else {
+            return Ok(Updated::NotFound);
+        };
+        if expected_version.is_some_and(|v| v != record.version) {
+            return Ok(Updated::Stale);
+        }
+        apply(record, f);
+        Ok(Updated::Applied(Box::new(record.clone())))
+    }
+
+    fn list(&self, query: &TwinQuery) -> Result<Vec<(u64, TwinRecord)>, String> {
+        let inner = self.inner.lock().unwrap_or_else(|p| p.into_inner());
+        Ok(inner
+            .twins
+            .range(query.after.unwrap_or(0) + 1..)
+            .filter(|(_, r)| query.status.is_none_or(|s| r.state.status == s))
+            .take(query.limit)
+            .map(|(seq, r)| (*seq, r.clone()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(did: &str, status: TwinStatus) -> TwinRecord {
+        let now = OffsetDateTime::now_utc();
+        TwinRecord {
+            twin_id: Uuid::new_v4(),
+            state: TwinState { status, note: None },
+            version: 1,
+            transitions: Vec::new(),
+            did: did.to_string(),
+            did_document: serde_json::Value::Null,
+            keys: Vec::new(),
+            previous_dids: Vec::new(),
+            services: Vec::new(),
+            erasure: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn sqlite() -> (SqliteStore, std::path::PathBuf) {
+        let path = std::env::temp_dir().join(format!("pagi-registry-{}.db", Uuid::new_v4()));
+        (SqliteStore::open(&path).unwrap(), path)
+    }
+
+    fn backends() -> Vec<(Box<dyn TwinStore>, Option<std::path::PathBuf>)> {
+        let (store, path) = sqlite();
+        vec![(Box::new(store), Some(path)), (Box::new(MemoryStore::default()), None)]
+    }
+
+    #[test]
+    fn records_are_found_by_id_and_did() {
+        for (store, path) in backends() {
+            let twin = record("did:key:z6Mkone", TwinStatus::Active);
+            store.insert(&twin).unwrap();
+            assert!(store.insert(&twin).is_err(), "{}: duplicate id", store.name());
+            assert!(store.insert(&record("did:key:z6Mkone", TwinStatus::Active)).is_err(), "{}: duplicate DID", store.name());
+
+            assert_eq!(store.get(twin.twin_id).unwrap().map(|r| r.did), Some(twin.did.clone()));
+            assert_eq!(store.find_by_did(&twin.did).unwrap().map(|r| r.twin_id), Some(twin.twin_id));
+            assert!(store.get(Uuid::new_v4()).unwrap().is_none());
+            assert!(store.find_by_did("did:key:z6Mkother").unwrap().is_none());
+            if let Some(path) = path {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    #[test]
+    fn versioned_updates_refuse_stale_versions() {
+        for (store, path) in backends() {
+            let twin = record("did:key:z6Mkversioned", TwinStatus::Registered);
+            store.insert(&twin).unwrap();
+
+            let activate = &mut |r: &mut TwinRecord| r.state.status = TwinStatus::Active;
+            let Updated::Applied(updated) = store.update_versioned(twin.twin_id, Some(1), activate).unwrap() else {
+                panic!("{}: update at the current version refused", store.name());
+            };
+            assert_eq!((updated.version, updated.state.status), (2, TwinStatus::Active));
+            assert!(matches!(store.update_versioned(twin.twin_id, Some(1), activate).unwrap(), Updated::Stale));
+            assert!(matches!(store.update_versioned(Uuid::new_v4(), None, activate).unwrap(), Updated::NotFound));
+
+            // The status column follows the record, so filters see the change.
+            let active = store.list(&TwinQuery { status: Some(TwinStatus::Active), after: None, limit: 10 }).unwrap();
+            assert_eq!(active.len(), 1);
+            if let Some(path) = path {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    #[test]
+    fn list_pages_in_registration_order() {
+        for (store, path) in backends() {
+            let twins: Vec<_> = (0..5)
+                .map(|i| {
+                    let status = if i % 2 == 0 { TwinStatus::Active } else { TwinStatus::Suspended };
+                    let twin = record(&format!("did:key:z6Mk{i}"), status);
+                    store.insert(&twin).unwrap();
+                    twin.twin_id
+                })
+                .collect();
+
+            let mut after = None;
+            let mut seen = Vec::new();
+            loop {
+                let page = store.list(&TwinQuery { status: None, after, limit: 2 }).unwrap();
+                let Some((last, _)) = page.last() else {
+                    break;
+                };
+                after = Some(*last);
+                seen.extend(page.into_iter().map(|(_, r)| r.twin_id));
+            }
+            assert_eq!(seen, twins, "{}", store.name());
+
+            let active = store.list(&TwinQuery { status: Some(TwinStatus::Active), after: None, limit: 2 }).unwrap();
+            assert_eq!(active.iter().map(|(_, r)| r.twin_id).collect::<Vec<_>>(), vec![twins[0], twins[2]]);
+            let rest = store
+                .list(&TwinQuery { status: Some(TwinStatus::Active), after: Some(active[1].0), limit: 2 })
+                .unwrap();
+            assert_eq!(rest.iter().map(|(_, r)| r.twin_id).collect::<Vec<_>>(), vec![twins[4]]);
+            if let Some(path) = path {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+
+    #[test]
+    fn skipped_sections_only_count_as_erased_when_allowed() {
+        let purged = PurgeReport::new("pagi-working-memory", Uuid::new_v4(), pagi_common::erasure::PurgeTrigger::TwinDeleted);
+        let report = ErasureReport {
+            requested_at: OffsetDateTime::now_utc(),
+            completed_at: None,
+            sections: BTreeMap::from([
+                ("working_memory".to_string(), ErasureOutcome::Purged(purged)),
+                ("emotion".to_string(), ErasureOutcome::Skipped),
+                ("tools".to_string(), ErasureOutcome::Failed("HTTP 500".to_string())),
+            ]),
+        };
+        assert!(report.is_done("working_memory", false));
+        assert!(!report.is_done("emotion", false));
+        assert!(report.is_done("emotion", true));
+        assert!(!report.is_done("tools", true));
+        assert!(!report.is_done("mailbox", true));
+    }
+
+    #[test]
+    fn legacy_statuses_reopen_as_suspended() {
+        let (store, path) = sqlite();
+        let twin = record("did:key:z6Mklegacy", TwinStatus::Active);
+        store.insert(&twin).unwrap();
+        let mut raw = serde_json::to_value(&twin).unwrap();
+        raw["state"]["status"] = "online".into();
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE twins SET status = 'online', record = ?2 WHERE twin_id = ?1",
+                params![twin.twin_id.to_string(), raw.to_string()],
+            )
+            .unwrap();
+        drop(store);
+
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.get(twin.twin_id).unwrap().unwrap().state.status, TwinStatus::Suspended);
+        let suspended = store.list(&TwinQuery { status: Some(TwinStatus::Suspended), after: None, limit: 10 }).unwrap();
+        assert_eq!(suspended.len(), 1);
+        let _ = std::fs::remove_file(path);
+    }
+}
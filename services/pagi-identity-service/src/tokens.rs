@@ -0,0 +1,174 @@
+//! Bearer token issuance: EdDSA JWTs signed with the service's issuer key.
+//!
+//! The issuer key is an Ed25519 key file under `{IDENTITY_DATA_DIR}/issuer`, sealed by the
+//! same [`Keyring`] as the twin keys. Its public half is published at
+//! `/.well-known/jwks.json`, where `pagi_http::auth` picks it up.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use pagi_common::{
+    auth::{unix_now, Claims, Role, TokenRequest},
+    keystore::Keyring,
+};
+use serde_json::{json, Value};
+use std::{collections::HashMap, path::Path};
+use uuid::Uuid;
+
+/// Key file id of the issuer key in its directory.
+pub const ISSUER_KEY_ID: Uuid = Uuid::nil();
+
+/// Upper bound for requested token lifetimes.
+const MAX_TTL_SECS: u64 = 3600;
+
+pub struct Issuer {
+    signing_key: SigningKey,
+    kid: String,
+    /// `iss` claim (`IDENTITY_TOKEN_ISSUER`, default `pagi-identity-service`).
+    pub name: String,
+    /// Lifetime of tokens that do not ask for one (`IDENTITY_TOKEN_TTL_SECS`, default 900).
+    pub default_ttl: u64,
+}
+
+impl Issuer {
+    /// Load the issuer key from `dir`, generating it on first start.
+    pub fn load_or_create(keyring: &Keyring, dir: &Path) -> Result<Self, String> {
+        let signing_key = if pagi_common::keystore::key_path(dir, ISSUER_KEY_ID).exists() {
+            SigningKey::from_bytes(&keyring.read_key(dir, ISSUER_KEY_ID)?)
+        } else {
+            use rand_core::OsRng;
+
+            let key = SigningKey::generate(&mut OsRng);
+            keyring.write_key(dir, ISSUER_KEY_ID, &key.to_bytes())?;
+            tracing::info!("generated token issuer key");
+            key
+        };
+        let default_ttl = std::env::var("IDENTITY_TOKEN_TTL_SECS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(900)
+            .clamp(1, MAX_TTL_SECS);
+        Ok(Self {
+            kid: key_id(&signing_key.verifying_key()),
+            signing_key,
+            name: std::env::var("IDENTITY_TOKEN_ISSUER").unwrap_or_else(|_| "pagi-identity-service".to_string()),
+            default_ttl,
+        })
+    }
+
+    /// Keys for verifying this issuer's tokens locally, by `kid`.
+    pub fn verifying_keys(&self) -> HashMap<String, VerifyingKey> {
+        HashMap::from([(self.kid.clone(), self.signing_key.verifying_key())])
+    }
+
+    /// The JWK Set published at `/.well-known/jwks.json`.
+    pub fn jwks(&self) -> Value {
+        json!({
+            "keys": [{
+                "kty": "OKP",
+                "crv": "Ed25519",
+                "alg": "EdDSA",
+                "use": "sig",
+                "kid": self.kid,
+                "x": URL_SAFE_NO_PAD.encode(self.signing_key.verifying_key().as_bytes()),
+            }]
+        })
+    }
+
+    /// Claims for a validated request: the role's default scopes unless narrowed, and the
+    /// requested lifetime capped at an hour.
+    pub fn claims(&self, req: &TokenRequest) -> Result<Claims, String> {
+        let defaults = req.role.default_scopes();
+        let scopes: Vec<&str> = match &req.scopes {
+            Some(requested) => {
+                if let Some(extra) = requested.iter().find(|s| !defaults.contains(&s.as_str())) {
+                    return Err(format!("scope {extra} is not available to the {:?} role", req.role));
+                }
+                requested.iter().map(String::as_str).collect()
+            }
+            None => defaults.to_vec(),
+        };
+        let subject = match (req.role, req.twin_id, &req.subject) {
+            (Role::Twin, None, _) => return Err("twin tokens require twin_id".to_string()),
+            (Role::Twin, Some(_), Some(_)) => return Err("twin tokens take their subject from twin_id".to_string()),
+            (Role::Twin, Some(twin_id), None) => twin_id.to_string(),
+            (_, Some(_), _) => return Err("only twin tokens are bound to a twin".to_string()),
+            (_, None, Some(subject)) if !subject.trim().is_empty() => subject.trim().to_string(),
+            (_, None, _) => return Err("service and admin tokens require a subject".to_string()),
+        };
+
+        let now = unix_now();
+        let ttl = req.ttl_secs.unwrap_or(self.default_ttl).clamp(1, MAX_TTL_SECS);
+        Ok(Claims {
+            iss: self.name.clone(),
+            sub: subject,
+            iat: now,
+            exp: now + ttl,
+            jti: Uuid::new_v4(),
+            role: req.role,
+            twin_id: req.twin_id,
+            scope: scopes.join(" "),
+        })
+    }
+
+    /// Encode and sign `claims` as a compact JWS.
+    pub fn sign(&self, claims: &Claims) -> Result<String, String> {
+        let header = json!({"alg": "EdDSA", "typ": "JWT", "kid": self.kid});
+        let encode = |v: &Value| URL_SAFE_NO_PAD.encode(v.to_string());
+        let payload = serde_json::to_value(claims).map_err(|e| e.to_string())?;
+        let signing_input = format!("{}.{}", encode(&header), encode(&payload));
+        let sig = self.signing_key.sign(signing_input.as_bytes());
+        Ok(format!("{signing_input}.{}", URL_SAFE_NO_PAD.encode(sig.to_bytes())))
+    }
+}
+
+/// Stable key id: the first 16 characters of the base64url public key.
+fn key_id(verifying_key: &VerifyingKey) -> String {
+    URL_SAFE_NO_PAD.encode(verifying_key.as_bytes())[..16].to_string()
+}
+
+/// What authenticated a `POST /tokens` request, which decides the roles it may be issued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Requester {
+    /// The shared `AUTH_CLIENT_SECRET` every service holds: service tokens only.
+    ClientSecret,
+    /// `IDENTITY_ADMIN_SECRET`: any role, to bootstrap the first admin token.
+    AdminSecret,
+    /// A bearer token carrying `tokens:issue`; only admin tokens can issue admin tokens.
+    Token(Role),
+}
+
+impl Requester {
+    pub fn may_issue(self, role: Role) -> bool {
+        match self {
+            Requester::ClientSecret => role == Role::Service,
+            Requester::AdminSecret => true,
+            Requester::Token(caller) => role != Role::Admin || caller == Role::Admin,
+        }
+    }
+}
+
+/// Compare a presented client secret in constant time.
+pub fn secret_matches(presented: &str, expected: &str) -> bool {
+    let (a, b) = (presented.as_bytes(), expected.as_bytes());
+    let mut diff = a.len() ^ b.len();
+    for i in 0..a.len().max(b.len()) {
+        diff |= (a.get(i).copied().unwrap_or(0) ^ b.get(i).copied().unwrap_or(0)) as usize;
+    }
+    diff == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_admin_credentials_issue_admin_tokens() {
+        assert!(Requester::ClientSecret.may_issue(Role::Service));
+        assert!(!Requester::ClientSecret.may_issue(Role::Admin));
+        assert!(!Requester::ClientSecret.may_issue(Role::Twin));
+        assert!(Requester::AdminSecret.may_issue(Role::Admin));
+        assert!(Requester::Token(Role::Admin).may_issue(Role::Admin));
+        assert!(Requester::Token(Role::Service).may_issue(Role::Twin));
+        assert!(!Requester::Token(Role::Service).may_issue(Role::Admin));
+    }
+}
@@ -4,10 +4,12 @@ use axum::{
     routing::{get, post},
     Json, Router,
 };
-use pagi_common::{publish_event, EventEnvelope, EventType};
-use pagi_http::errors::PagiAxumError;
+use pagi_common::{auth::scopes, publish_event, CoreEvent, EventEnvelope};
+use pagi_http::{
+    auth::{Auth, Caller},
+    errors::PagiAxumError,
+};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use std::net::SocketAddr;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use uuid::Uuid;
@@ -38,17 +40,19 @@ async fn main() {
     let state = AppState {
     };
 
+    let auth = Auth::from_env();
     let app = Router::new()
         .route("/healthz", get(healthz))
-        .route("/infer", post(infer))
+        .route("/infer", post(infer).route_layer(auth.require(scopes::AGENT_RUN)))
         .with_state(state)
+        .layer(axum::middleware::from_fn(pagi_http::trace_context::propagate))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive());
 
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 8005).into());
     tracing::info!(%addr, "listening");
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    pagi_http::tls::serve(listener, app).await.unwrap();
 }
 
 async fn healthz() -> (StatusCode, &'static str) {
@@ -57,15 +61,18 @@ async fn healthz() -> (StatusCode, &'static str) {
 
 async fn infer(
     State(_state): State<AppState>,
+    caller: Caller,
     Json(req): Json<InferRequest>,
 ) -> Result<Json<InferResponse>, PagiAxumError> {
-    let mut ev = EventEnvelope::new(
-        EventType::InferenceRequested,
-        json!({"twin_id": req.twin_id, "has_context": req.context.is_some()}),
+    caller.check_twin(req.twin_id)?;
+    let mut ev = EventEnvelope::new_core(
+        req.twin_id,
+        CoreEvent::InferenceRequested {
+            has_context: req.context.is_some(),
+        },
     );
-    ev.twin_id = Some(req.twin_id);
     ev.source = Some("pagi-inference-gateway".to_string());
-    let _ = publish_event(ev).await;
+    publish_event(ev);
 
     // MVP mock model adapter: returns a deterministic response.
     let output = if let Some(ctx) = &req.context {
@@ -74,13 +81,15 @@ async fn infer(
         format!("[mock-model] Input:\n{}", req.input)
     };
 
-    let mut ev = EventEnvelope::new(
-        EventType::InferenceCompleted,
-        json!({"twin_id": req.twin_id, "output_len": output.len()}),
+    let mut ev = EventEnvelope::new_core(
+        req.twin_id,
+        CoreEvent::InferenceCompleted {
+            model: Some("mock".to_string()),
+            output_len: output.len(),
+        },
     );
-    ev.twin_id = Some(req.twin_id);
     ev.source = Some("pagi-inference-gateway".to_string());
-    let _ = publish_event(ev).await;
+    publish_event(ev);
 
     Ok(Json(InferResponse {
         twin_id: req.twin_id,
@@ -4,9 +4,9 @@ use axum::{
     routing::{get, post},
     Json, Router,
 };
-use pagi_common::{publish_event, EventEnvelope, EventType};
+use pagi_common::{auth::scopes, publish_event, CoreEvent, EventEnvelope};
+use pagi_http::auth::Auth;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use std::net::SocketAddr;
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 
@@ -34,17 +34,19 @@ async fn main() {
     let state = AppState {
     };
 
+    let auth = Auth::from_env();
     let app = Router::new()
         .route("/healthz", get(healthz))
-        .route("/act", post(act))
+        .route("/act", post(act).route_layer(auth.require(scopes::AGENT_RUN)))
         .with_state(state)
+        .layer(axum::middleware::from_fn(pagi_http::trace_context::propagate))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive());
 
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 8008).into());
     tracing::info!(%addr, "listening");
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    pagi_http::tls::serve(listener, app).await.unwrap();
 }
 
 async fn healthz() -> (StatusCode, &'static str) {
@@ -52,9 +54,12 @@ async fn healthz() -> (StatusCode, &'static str) {
 }
 
 async fn act(State(_state): State<AppState>, Json(req): Json<ActionRequest>) -> (StatusCode, Json<ActionResponse>) {
-    let mut ev = EventEnvelope::new(EventType::ActionRequested, json!({"tool": req.tool, "args": req.args}));
+    let mut ev = EventEnvelope::from_core(CoreEvent::ActionRequested {
+        tool: req.tool.clone(),
+        args: req.args.clone(),
+    });
     ev.source = Some("pagi-sensor-actuator".to_string());
-    let _ = publish_event(ev).await;
+    publish_event(ev);
 
     // Intentionally minimal: actions are not executed in MVP.
     (
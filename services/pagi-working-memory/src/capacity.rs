@@ -0,0 +1,486 @@
+use serde::{Deserialize, Serialize};
+
+use uuid::Uuid;
+
+use crate::{
+    item::{MemoryItem, MemorySource, SourceKind},
+    SERVICE,
+};
+
+/// Role of the item that summarize-on-overflow folds older items into.
+pub const SUMMARY_ROLE: &str = "summary";
+
+/// Longest excerpt of a single item kept in a summary.
+const SUMMARY_EXCERPT_CHARS: usize = 200;
+
+/// Largest summary item, in bytes.
+const SUMMARY_MAX_BYTES: usize = 4096;
+
+/// How much working memory a twin may hold.
+///
+/// The node default comes from the environment; a twin can override it with
+/// `PUT /memory/:twin_id/policy`.
+/// - `WORKING_MEMORY_MAX_ITEMS`: maximum items per twin (default: 1000, `0`: unlimited)
+/// - `WORKING_MEMORY_MAX_BYTES`: maximum role and content bytes per twin (default: 1 MiB, `0`: unlimited)
+/// - `WORKING_MEMORY_OVERFLOW`: `evict` (drop items, default) or `summarize` (fold them into a
+///   single `summary` item)
+/// - `WORKING_MEMORY_EVICTION_ORDER`: `oldest_first` (default) or `importance` (ascending
+///   [importance](crate::importance), older items first among equals)
+/// - `WORKING_MEMORY_MAX_PINNED`: share of `max_items` and `max_bytes`, in percent, that pinned
+///   items may take (default: 50)
+/// - `WORKING_MEMORY_MAX_QUARANTINED`: items held in quarantine per twin; the oldest are dropped
+///   beyond it (default: 100)
+///
+/// Pinned items within that share and the newest item never leave; pins beyond it (left by a
+/// lower limit or an import) leave like any other item, the oldest pins first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapacityPolicy {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_items: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_bytes: Option<usize>,
+    #[serde(default)]
+    pub overflow: Overflow,
+    #[serde(default)]
+    pub eviction_order: EvictionOrder,
+    #[serde(default = "default_max_pinned")]
+    pub max_pinned: u8,
+    #[serde(default = "default_max_quarantined")]
+    pub max_quarantined: usize,
+}
+
+fn default_max_pinned() -> u8 {
+    50
+}
+
+fn default_max_quarantined() -> usize {
+    100
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Overflow {
+    #[default]
+    Evict,
+    Summarize,
+}
+
+/// Which items leave first when a twin is over its policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EvictionOrder {
+    #[default]
+    OldestFirst,
+    Importance,
+}
+
+/// What [`CapacityPolicy::enforce`] did to make the items fit.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Enforced {
+    /// Nothing had to (or could) leave.
+    Fits,
+    /// These items were dropped, oldest first.
+    Evicted(Vec<MemoryItem>),
+    /// These items (including any earlier summary), oldest first, were folded into one summary
+    /// item.
+    Summarized(Vec<MemoryItem>),
+}
+
+/// What a twin's items take up, as counted against its policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub items: usize,
+    pub bytes: usize,
+}
+
+impl Usage {
+    /// The usage after adding `item`.
+    pub fn with(self, item: &MemoryItem) -> Self {
+        Self {
+            items: self.items + 1,
+            bytes: self.bytes + item_bytes(item),
+        }
+    }
+
+    /// The usage after removing `item`.
+    pub fn without(self, item: &MemoryItem) -> Self {
+        Self {
+            items: self.items.saturating_sub(1),
+            bytes: self.bytes.saturating_sub(item_bytes(item)),
+        }
+    }
+}
+
+impl Enforced {
+    /// Items that left working memory.
+    pub fn departed(&self) -> &[MemoryItem] {
+        match self {
+            Enforced::Fits => &[],
+            Enforced::Evicted(items) | Enforced::Summarized(items) => items,
+        }
+    }
+}
+
+impl CapacityPolicy {
+    pub fn from_env() -> Result<Self, String> {
+        let limit = |name: &str, default: usize| -> Result<Option<usize>, String> {
+            let value = match std::env::var(name) {
+                Ok(raw) if !raw.trim().is_empty() => raw
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("{name} must be a number, got {raw:?}"))?,
+                _ => default,
+            };
+            Ok(Some(value).filter(|v| *v > 0))
+        };
+        let overflow = match std::env::var("WORKING_MEMORY_OVERFLOW") {
+            Ok(raw) if !raw.trim().is_empty() => match raw.trim().to_lowercase().as_str() {
+                "evict" => Overflow::Evict,
+                "summarize" => Overflow::Summarize,
+                other => return Err(format!("unknown WORKING_MEMORY_OVERFLOW '{other}' (expected evict|summarize)")),
+            },
+            _ => Overflow::Evict,
+        };
+        let eviction_order = match std::env::var("WORKING_MEMORY_EVICTION_ORDER") {
+            Ok(raw) if !raw.trim().is_empty() => match raw.trim().to_lowercase().as_str() {
+                "oldest_first" => EvictionOrder::OldestFirst,
+                "importance" => EvictionOrder::Importance,
+                other => {
+                    return Err(format!(
+                        "unknown WORKING_MEMORY_EVICTION_ORDER '{other}' (expected oldest_first|importance)"
+                    ))
+                }
+            },
+            _ => EvictionOrder::OldestFirst,
+        };
+        let max_pinned = match std::env::var("WORKING_MEMORY_MAX_PINNED") {
+            Ok(raw) if !raw.trim().is_empty() => raw
+                .trim()
+                .parse()
+                .map_err(|_| format!("WORKING_MEMORY_MAX_PINNED must be a percentage, got {raw:?}"))?,
+            _ => default_max_pinned(),
+        };
+        let max_quarantined = match std::env::var("WORKING_MEMORY_MAX_QUARANTINED") {
+            Ok(raw) if !raw.trim().is_empty() => raw
+                .trim()
+                .parse()
+                .map_err(|_| format!("WORKING_MEMORY_MAX_QUARANTINED must be a number, got {raw:?}"))?,
+            _ => default_max_quarantined(),
+        };
+        let policy = Self {
+            max_items: limit("WORKING_MEMORY_MAX_ITEMS", 1000)?,
+            max_bytes: limit("WORKING_MEMORY_MAX_BYTES", 1 << 20)?,
+            overflow,
+            eviction_order,
+            max_pinned,
+            max_quarantined,
+        };
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_items == Some(0) || self.max_bytes == Some(0) {
+            return Err("limits must be positive (omit a limit for unlimited)".to_string());
+        }
+        if self.overflow == Overflow::Summarize && self.max_items.is_some_and(|max| max < 2) {
+            return Err("summarize needs room for the summary and the newest item (max_items >= 2)".to_string());
+        }
+        if self.max_pinned > 100 {
+            return Err("max_pinned is a percentage (0 to 100)".to_string());
+        }
+        if self.max_quarantined == 0 {
+            return Err("max_quarantined must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether a single item can ever be stored under this policy.
+    pub fn admits(&self, item: &MemoryItem) -> bool {
+        self.max_bytes.is_none_or(|max| item_bytes(item) <= max)
+    }
+
+    /// Whether items taking up `usage` are within the policy, so [`Self::enforce`] would not
+    /// change them.
+    pub fn holds(&self, usage: Usage) -> bool {
+        self.within(usage.items, usage.bytes)
+    }
+
+    /// Whether pinned items taking up `pinned` are within the share they may take.
+    pub fn pins_fit(&self, pinned: Usage) -> bool {
+        let share = |max: usize| max * usize::from(self.max_pinned) / 100;
+        self.max_items.is_none_or(|max| pinned.items <= share(max))
+            && self.max_bytes.is_none_or(|max| pinned.bytes <= share(max))
+    }
+
+    /// Bring `items` (oldest first) within the policy, removing unpinned items in the policy's
+    /// eviction order (`score` ranks them for [`EvictionOrder::Importance`]). The newest item is
+    /// always kept.
+    pub fn enforce(&self, items: &mut Vec<MemoryItem>, score: impl Fn(&MemoryItem) -> f32) -> Enforced {
+        if self.fits(items) {
+            return Enforced::Fits;
+        }
+        // Pins are kept newest first while they fit their share.
+        let newest = items.len() - 1;
+        let mut kept = vec![false; items.len()];
+        kept[newest] = true;
+        let mut pinned = Usage::default();
+        for i in (0..newest).rev().filter(|i| items[*i].pinned) {
+            if self.pins_fit(pinned.with(&items[i])) {
+                pinned = pinned.with(&items[i]);
+                kept[i] = true;
+            }
+        }
+        // Oldest first, or least important first with the older first among equals.
+        let mut candidates: Vec<(f32, usize)> = items
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !kept[*i])
+            .map(|(i, item)| match self.eviction_order {
+                EvictionOrder::OldestFirst => (0.0, i),
+                EvictionOrder::Importance => (score(item), i),
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        let summarizing = self.overflow == Overflow::Summarize;
+        let budget = self.max_bytes.map_or(SUMMARY_MAX_BYTES, |max| SUMMARY_MAX_BYTES.min(max / 4));
+        if summarizing {
+            // Earlier summaries are always folded, their lines carrying over into the new one.
+            candidates.sort_by_key(|(_, i)| items[*i].role != SUMMARY_ROLE);
+        }
+
+        let mut count = items.len();
+        let mut bytes: usize = items.iter().map(item_bytes).sum();
+        let mut leaving = vec![false; items.len()];
+        for (_, i) in candidates {
+            let room = if summarizing {
+                // What stays must leave room for the summary.
+                items[i].role != SUMMARY_ROLE && self.within(count + 1, bytes + budget)
+            } else {
+                self.within(count, bytes)
+            };
+            if room {
+                break;
+            }
+            leaving[i] = true;
+            count -= 1;
+            bytes -= item_bytes(&items[i]);
+        }
+        if !leaving.contains(&true) {
+            return Enforced::Fits;
+        }
+
+        let mut departed = Vec::new();
+        let mut leaves = leaving.into_iter();
+        items.retain(|item| {
+            let leaves = leaves.next().unwrap_or_default();
+            if leaves {
+                departed.push(item.clone());
+            }
+            !leaves
+        });
+        if !summarizing {
+            return Enforced::Evicted(departed);
+        }
+        let budget = self.max_bytes.map_or(budget, |max| budget.min(max.saturating_sub(bytes)));
+        if let Some(summary) = summarize(&departed, budget) {
+            items.insert(0, summary);
+        }
+        Enforced::Summarized(departed)
+    }
+
+    fn fits(&self, items: &[MemoryItem]) -> bool {
+        self.within(items.len(), items.iter().map(item_bytes).sum())
+    }
+
+    fn within(&self, count: usize, bytes: usize) -> bool {
+        self.max_items.is_none_or(|max| count <= max) && self.max_bytes.is_none_or(|max| bytes <= max)
+    }
+}
+
+/// Size counted against `max_bytes`.
+pub fn item_bytes(item: &MemoryItem) -> usize {
+    item.role.len() + item.content.len()
+}
+
+/// One `- role: excerpt` line per folded item, carrying over the lines of an earlier summary.
+/// The oldest lines are dropped to stay within `budget` bytes.
+fn summarize(folded: &[MemoryItem], budget: usize) -> Option<MemoryItem> {
+    let newest = folded.last()?;
+    let mut lines: Vec<String> = Vec::new();
+    for item in folded {
+        if item.role == SUMMARY_ROLE {
+            lines.extend(item.content.lines().map(str::to_string));
+        } else {
+            lines.push(format!("- {}: {}", item.role, excerpt(&item.content)));
+        }
+    }
+    let budget = budget.saturating_sub(SUMMARY_ROLE.len());
+    let mut content = String::new();
+    for line in lines.iter().rev() {
+        if content.len() + line.len() + 1 > budget {
+            break;
+        }
+        content.insert_str(0, &format!("{line}\n"));
+    }
+    let content = content.trim_end().to_string();
+    if content.is_empty() {
+        return None;
+    }
+    Some(MemoryItem {
+        id: Uuid::new_v4(),
+        role: SUMMARY_ROLE.to_string(),
+        content,
+        created_at: newest.created_at,
+        updated_at: None,
+        source: Some(MemorySource {
+            kind: SourceKind::Service,
+            name: Some(SERVICE.to_string()),
+        }),
+        tags: Vec::new(),
+        metadata: Default::default(),
+        pinned: false,
+        retrievals: 0,
+        last_retrieved_at: None,
+    })
+}
+
+fn excerpt(content: &str) -> String {
+    let flat = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    match flat.char_indices().nth(SUMMARY_EXCERPT_CHARS) {
+        Some((end, _)) => format!("{}…", &flat[..end]),
+        None => flat,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(role: &str, content: &str) -> MemoryItem {
+        serde_json::from_value(json!({"role": role, "content": content})).unwrap()
+    }
+
+    fn contents(items: &[MemoryItem]) -> Vec<&str> {
+        items.iter().map(|item| item.content.as_str()).collect()
+    }
+
+    fn policy(max_items: Option<usize>, max_bytes: Option<usize>, overflow: Overflow) -> CapacityPolicy {
+        CapacityPolicy { max_items, max_bytes, overflow, eviction_order: EvictionOrder::OldestFirst, max_pinned: 50, max_quarantined: 100 }
+    }
+
+    fn even(_: &MemoryItem) -> f32 {
+        0.5
+    }
+
+    #[test]
+    fn items_within_the_policy_fit() {
+        let policy = policy(Some(3), None, Overflow::Evict);
+        let mut items = vec![item("user", "one"), item("user", "two")];
+        assert_eq!(policy.enforce(&mut items, even), Enforced::Fits);
+        assert_eq!(items.len(), 2);
+        assert!(policy.holds(Usage { items: 2, bytes: 14 }.with(&items[0])));
+        assert!(!policy.holds(Usage { items: 3, bytes: 21 }.with(&items[0])));
+    }
+
+    /// Tool output matters least.
+    fn by_role(item: &MemoryItem) -> f32 {
+        if item.role == "tool" {
+            0.1
+        } else {
+            0.2
+        }
+    }
+
+    #[test]
+    fn eviction_drops_the_oldest_unpinned_items_by_default() {
+        let policy = policy(Some(3), None, Overflow::Evict);
+        let mut items = vec![item("system", "rules"), item("user", "hi"), item("tool", "output"), item("user", "new")];
+        items[0].pinned = true;
+        let Enforced::Evicted(evicted) = policy.enforce(&mut items, by_role) else {
+            panic!("nothing evicted");
+        };
+        assert_eq!(contents(&evicted), ["hi"]);
+        assert_eq!(contents(&items), ["rules", "output", "new"]);
+    }
+
+    #[test]
+    fn eviction_by_importance_drops_the_least_important_unpinned_items() {
+        let policy = CapacityPolicy { eviction_order: EvictionOrder::Importance, ..policy(Some(3), None, Overflow::Evict) };
+        let mut items = vec![item("system", "rules"), item("user", "hi"), item("tool", "output"), item("user", "new")];
+        items[0].pinned = true;
+        let Enforced::Evicted(evicted) = policy.enforce(&mut items, by_role) else {
+            panic!("nothing evicted");
+        };
+        assert_eq!(contents(&evicted), ["output"]);
+        assert_eq!(contents(&items), ["rules", "hi", "new"]);
+        // Among equals, the older leaves first.
+        items.push(item("user", "newer"));
+        policy.enforce(&mut items, by_role);
+        assert_eq!(contents(&items), ["rules", "new", "newer"]);
+    }
+
+    #[test]
+    fn pins_beyond_their_share_leave_oldest_first() {
+        // Half of four items: two pins are kept.
+        let items_policy = policy(Some(4), None, Overflow::Evict);
+        let mut items: Vec<MemoryItem> = ["one", "two", "three"].iter().map(|c| item("user", c)).collect();
+        items.iter_mut().for_each(|item| item.pinned = true);
+        items.extend([item("tool", "output"), item("user", "new")]);
+        let Enforced::Evicted(evicted) = items_policy.enforce(&mut items, by_role) else {
+            panic!("nothing evicted");
+        };
+        assert_eq!(contents(&evicted), ["one"]);
+        assert_eq!(contents(&items), ["two", "three", "output", "new"]);
+
+        assert!(items_policy.pins_fit(Usage { items: 2, bytes: 0 }));
+        assert!(!items_policy.pins_fit(Usage { items: 2, bytes: 0 }.with(&items[0])));
+        let bytes = CapacityPolicy { max_pinned: 25, ..policy(None, Some(64), Overflow::Evict) };
+        assert!(bytes.pins_fit(Usage { items: 10, bytes: 16 }));
+        assert!(!bytes.pins_fit(Usage { items: 1, bytes: 17 }));
+    }
+
+    #[test]
+    fn byte_limits_count_role_and_content() {
+        // "user" + "aaaa" = 8 bytes each.
+        let policy = policy(None, Some(16), Overflow::Evict);
+        let mut items = vec![item("user", "aaaa"), item("user", "bbbb"), item("user", "cccc")];
+        assert_eq!(policy.enforce(&mut items, even).departed().len(), 1);
+        assert_eq!(contents(&items), ["bbbb", "cccc"]);
+
+        assert!(policy.admits(&item("user", "012345678901")));
+        assert!(!policy.admits(&item("user", "0123456789012")));
+        assert_eq!(Usage::default().with(&item("user", "é")), Usage { items: 1, bytes: 6 });
+    }
+
+    #[test]
+    fn summarizing_folds_the_oldest_items_into_a_summary() {
+        let policy = policy(Some(3), None, Overflow::Summarize);
+        let mut items: Vec<MemoryItem> = (1..=4).map(|n| item("user", &format!("message {n}"))).collect();
+        let Enforced::Summarized(folded) = policy.enforce(&mut items, even) else {
+            panic!("nothing summarized");
+        };
+        assert_eq!(contents(&folded), ["message 1", "message 2"]);
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0].role, SUMMARY_ROLE);
+        assert_eq!(items[0].content, "- user: message 1\n- user: message 2");
+        assert_eq!(contents(&items[1..]), ["message 3", "message 4"]);
+
+        // A later summary carries the earlier one's lines over.
+        items.push(item("user", "message 5"));
+        policy.enforce(&mut items, even);
+        assert_eq!(items[0].content, "- user: message 1\n- user: message 2\n- user: message 3");
+        assert_eq!(contents(&items[1..]), ["message 4", "message 5"]);
+    }
+
+    #[test]
+    fn policies_are_validated() {
+        assert!(policy(Some(0), None, Overflow::Evict).validate().is_err());
+        assert!(policy(None, Some(0), Overflow::Evict).validate().is_err());
+        assert!(policy(Some(1), None, Overflow::Summarize).validate().is_err());
+        assert!(policy(Some(1), None, Overflow::Evict).validate().is_ok());
+        assert!(CapacityPolicy { max_pinned: 101, ..policy(Some(1), None, Overflow::Evict) }.validate().is_err());
+        assert!(CapacityPolicy { max_quarantined: 0, ..policy(Some(1), None, Overflow::Evict) }.validate().is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! Importance of working memory items: how much an item is still worth keeping in context.
+//!
+//! An item's score is its role's weight times how much of it is still remembered, following a
+//! forgetting curve from the item's last reinforcement (when it was stored or last retrieved into
+//! a context). Each retrieval makes the memory more stable, stretching the curve. Pinned items
+//! always score `1`.
+//!
+//! Configuration:
+//! - `WORKING_MEMORY_ROLE_WEIGHTS`: `role=weight` pairs in `[0, 1]` (default:
+//!   `system=1.0,user=0.8,summary=0.7,assistant=0.6,tool=0.5`; other roles weigh `0.5`)
+//! - `WORKING_MEMORY_FORGETTING_CURVE`: `exponential` (default), `power` (slower, long-tailed
+//!   decay) or `none` (scores depend on role and pins only)
+//! - `WORKING_MEMORY_HALF_LIFE_SECS`: time for an unreinforced item to lose half its score
+//!   (default: 604800, a week)
+//! - `WORKING_MEMORY_RETRIEVAL_BOOST`: how much each retrieval stretches the half-life (default:
+//!   `1.0`, i.e. a once-retrieved item decays half as fast)
+//! - `WORKING_MEMORY_PRUNE_BELOW`: items scoring below this are forgotten by a sweep every
+//!   `RETENTION_SWEEP_SECS`, and consolidated into long-term memory (unset: never)
+
+use std::{collections::HashMap, time::Duration};
+
+use time::OffsetDateTime;
+
+use crate::item::MemoryItem;
+
+const DEFAULT_ROLE_WEIGHTS: &str = "system=1.0,user=0.8,summary=0.7,assistant=0.6,tool=0.5";
+const DEFAULT_ROLE_WEIGHT: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForgettingCurve {
+    /// Halves every half-life.
+    Exponential,
+    /// `1 / (1 + t / half_life)`: also half at one half-life, then a long tail.
+    Power,
+    None,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportanceModel {
+    role_weights: HashMap<String, f32>,
+    curve: ForgettingCurve,
+    half_life: Duration,
+    retrieval_boost: f32,
+    pub prune_below: Option<f32>,
+    pub sweep_every: Duration,
+}
+
+impl ImportanceModel {
+    pub fn from_env() -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|raw| !raw.trim().is_empty());
+        let number = |name: &str| -> Result<Option<f64>, String> {
+            var(name)
+                .map(|raw| {
+                    raw.trim()
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|v| v.is_finite() && *v >= 0.0)
+                        .ok_or_else(|| format!("{name} must be a non-negative number, got {raw:?}"))
+                })
+                .transpose()
+        };
+
+        let weights = var("WORKING_MEMORY_ROLE_WEIGHTS").unwrap_or_else(|| DEFAULT_ROLE_WEIGHTS.to_string());
+        let mut role_weights = HashMap::new();
+        for pair in weights.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let parsed = pair
+                .split_once('=')
+                .and_then(|(role, weight)| Some((role.trim(), weight.trim().parse::<f32>().ok()?)))
+                .filter(|(role, weight)| !role.is_empty() && (0.0..=1.0).contains(weight));
+            let Some((role, weight)) = parsed else {
+                return Err(format!("WORKING_MEMORY_ROLE_WEIGHTS: expected role=weight with a weight in [0, 1], got {pair:?}"));
+            };
+            role_weights.insert(role.to_string(), weight);
+        }
+
+        let curve = match var("WORKING_MEMORY_FORGETTING_CURVE").map(|raw| raw.trim().to_lowercase()) {
+            None => ForgettingCurve::Exponential,
+            Some(curve) => match curve.as_str() {
+                "exponential" => ForgettingCurve::Exponential,
+                "power" => ForgettingCurve::Power,
+                "none" => ForgettingCurve::None,
+                other => {
+                    return Err(format!(
+                        "unknown WORKING_MEMORY_FORGETTING_CURVE '{other}' (expected exponential|power|none)"
+                    ))
+                }
+            },
+        };
+        let half_life = number("WORKING_MEMORY_HALF_LIFE_SECS")?.unwrap_or(7.0 * 24.0 * 3600.0);
+        if half_life <= 0.0 {
+            return Err("WORKING_MEMORY_HALF_LIFE_SECS must be positive".to_string());
+        }
+        let prune_below = number("WORKING_MEMORY_PRUNE_BELOW")?.map(|v| v as f32);
+        if prune_below.is_some_and(|v| v >= 1.0) {
+            return Err("WORKING_MEMORY_PRUNE_BELOW must be below 1 (pinned items score 1)".to_string());
+        }
+        Ok(Self {
+            role_weights,
+            curve,
+            half_life: Duration::from_secs_f64(half_life),
+            retrieval_boost: number("WORKING_MEMORY_RETRIEVAL_BOOST")?.unwrap_or(1.0) as f32,
+            prune_below: prune_below.filter(|v| *v > 0.0),
+            sweep_every: Duration::from_secs(number("RETENTION_SWEEP_SECS")?.unwrap_or(300.0).max(1.0) as u64),
+        })
+    }
+
+    /// The item's importance at `now`, in `[0, 1]`.
+    pub fn score(&self, item: &MemoryItem, now: OffsetDateTime) -> f32 {
+        if item.pinned {
+            return 1.0;
+        }
+        let weight = self.role_weights.get(&item.role).copied().unwrap_or(DEFAULT_ROLE_WEIGHT);
+        let reinforced = item.last_retrieved_at.map_or(item.created_at, |at| at.max(item.created_at));
+        let elapsed = (now - reinforced).as_seconds_f64().max(0.0);
+        let stability = self.half_life.as_secs_f64() * (1.0 + self.retrieval_boost as f64 * item.retrievals as f64);
+        let t = elapsed / stability;
+        let retention = match self.curve {
+            ForgettingCurve::Exponential => 0.5f64.powf(t),
+            ForgettingCurve::Power => 1.0 / (1.0 + t),
+            ForgettingCurve::None => 1.0,
+        };
+        weight * retention as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const DAY: f64 = 24.0 * 3600.0;
+
+    fn model(curve: ForgettingCurve) -> ImportanceModel {
+        ImportanceModel {
+            role_weights: HashMap::from([("user".to_string(), 0.8)]),
+            curve,
+            half_life: Duration::from_secs_f64(DAY),
+            retrieval_boost: 1.0,
+            prune_below: None,
+            sweep_every: Duration::from_secs(300),
+        }
+    }
+
+    fn item(role: &str, created_at: OffsetDateTime) -> MemoryItem {
+        let mut item: MemoryItem = serde_json::from_value(json!({"role": role, "content": "x"})).unwrap();
+        item.created_at = created_at;
+        item
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn scores_halve_every_half_life() {
+        let now = OffsetDateTime::now_utc();
+        let model = model(ForgettingCurve::Exponential);
+        assert!(close(model.score(&item("user", now), now), 0.8));
+        assert!(close(model.score(&item("user", now - time::Duration::days(1)), now), 0.4));
+        assert!(close(model.score(&item("user", now - time::Duration::days(2)), now), 0.2));
+        // Unknown roles weigh the default.
+        assert!(close(model.score(&item("other", now), now), DEFAULT_ROLE_WEIGHT));
+    }
+
+    #[test]
+    fn retrievals_reinforce_and_pins_keep_full_importance() {
+        let now = OffsetDateTime::now_utc();
+        let model = model(ForgettingCurve::Exponential);
+        let mut retrieved = item("user", now - time::Duration::days(4));
+        retrieved.retrievals = 1;
+        retrieved.last_retrieved_at = Some(now - time::Duration::days(2));
+        // Two days since the retrieval, on a half-life stretched to two days.
+        assert!(close(model.score(&retrieved, now), 0.4));
+
+        let mut pinned = item("user", now - time::Duration::days(365));
+        pinned.pinned = true;
+        assert_eq!(model.score(&pinned, now), 1.0);
+    }
+
+    #[test]
+    fn curves_differ_in_their_tails() {
+        let now = OffsetDateTime::now_utc();
+        let old = item("user", now - time::Duration::days(3));
+        assert!(close(model(ForgettingCurve::Exponential).score(&old, now), 0.1));
+        assert!(close(model(ForgettingCurve::Power).score(&old, now), 0.2));
+        assert!(close(model(ForgettingCurve::None).score(&old, now), 0.8));
+    }
+}
@@ -0,0 +1,197 @@
+use pagi_common::auth::{Claims, Role};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryItem {
+    /// Assigned on append unless the caller brings its own (e.g. on import).
+    #[serde(default = "Uuid::new_v4")]
+    pub id: Uuid,
+    pub role: String,
+    pub content: String,
+    /// When the item was stored, set on append (imports keep theirs); retention is measured
+    /// from here.
+    #[serde(default = "OffsetDateTime::now_utc", with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "time::serde::rfc3339::option"
+    )]
+    pub updated_at: Option<OffsetDateTime>,
+    /// Who wrote the item; taken from the caller's token when not given.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<MemorySource>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub metadata: Map<String, Value>,
+    /// Pinned items keep full importance (see [`crate::importance`]).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Times the item was retrieved into a context.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub retrievals: u32,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "time::serde::rfc3339::option"
+    )]
+    pub last_retrieved_at: Option<OffsetDateTime>,
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemorySource {
+    pub kind: SourceKind,
+    /// Service or tool name, or the user's subject.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    Service,
+    Tool,
+    User,
+}
+
+impl MemorySource {
+    /// Twin tokens write on behalf of their user; anything else is a service.
+    pub fn from_claims(claims: &Claims) -> Self {
+        let kind = match claims.role {
+            Role::Twin => SourceKind::User,
+            Role::Service | Role::Admin => SourceKind::Service,
+        };
+        Self {
+            kind,
+            name: Some(claims.sub.clone()),
+        }
+    }
+
+    /// The source an appended item is stored with: services and operators may give one, twin
+    /// tokens always write as their user.
+    pub fn for_append(claims: Option<&Claims>, given: Option<Self>) -> Option<Self> {
+        match (claims, given) {
+            (Some(claims), _) if claims.role == Role::Twin => Some(Self::from_claims(claims)),
+            (_, Some(given)) => Some(given),
+            (claims, None) => claims.map(Self::from_claims),
+        }
+    }
+}
+
+/// `PATCH /memory/:twin_id/items/:item_id` body. `role`, `content`, `tags` and `pinned`
+/// replace the current values; `metadata` is merged key by key, a `null` value removing the key.
+#[derive(Debug, Default, Deserialize)]
+pub struct ItemPatch {
+    pub role: Option<String>,
+    pub content: Option<String>,
+    pub tags: Option<Vec<String>>,
+    pub metadata: Option<Map<String, Value>>,
+    pub pinned: Option<bool>,
+}
+
+impl ItemPatch {
+    pub fn apply(self, item: &mut MemoryItem) {
+        if let Some(role) = self.role {
+            item.role = role;
+        }
+        if let Some(content) = self.content {
+            item.content = content;
+        }
+        if let Some(tags) = self.tags {
+            item.tags = tags;
+        }
+        if let Some(pinned) = self.pinned {
+            item.pinned = pinned;
+        }
+        for (key, value) in self.metadata.unwrap_or_default() {
+            if value.is_null() {
+                item.metadata.remove(&key);
+            } else {
+                item.metadata.insert(key, value);
+            }
+        }
+        item.updated_at = Some(OffsetDateTime::now_utc());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn appended_items_get_an_id_and_timestamp() {
+        let before = OffsetDateTime::now_utc();
+        let item: MemoryItem = serde_json::from_value(json!({"role": "user", "content": "hi"})).unwrap();
+        assert!(item.created_at >= before);
+        assert!(!item.pinned && item.retrievals == 0 && item.source.is_none());
+
+        // Empty fields stay out of the stored form.
+        let stored = serde_json::to_value(&item).unwrap();
+        let keys: Vec<&str> = stored.as_object().unwrap().keys().map(String::as_str).collect();
+        assert_eq!(keys.len(), 4, "{keys:?}");
+    }
+
+    #[test]
+    fn patches_replace_fields_and_merge_metadata() {
+        let mut item: MemoryItem = serde_json::from_value(json!({
+            "role": "user",
+            "content": "hi",
+            "tags": ["a"],
+            "metadata": {"keep": 1, "drop": 2},
+        }))
+        .unwrap();
+        let patch: ItemPatch = serde_json::from_value(json!({
+            "content": "hello",
+            "pinned": true,
+            "metadata": {"drop": null, "add": "x"},
+        }))
+        .unwrap();
+        patch.apply(&mut item);
+        assert_eq!((item.role.as_str(), item.content.as_str()), ("user", "hello"));
+        assert_eq!(item.tags, ["a"]);
+        assert!(item.pinned);
+        assert_eq!(Value::Object(item.metadata), json!({"keep": 1, "add": "x"}));
+        assert!(item.updated_at.is_some());
+    }
+
+    #[test]
+    fn twin_tokens_write_as_the_user() {
+        let mut claims: Claims = serde_json::from_value(json!({
+            "iss": "pagi", "sub": "twin-1", "iat": 0, "exp": 0, "jti": Uuid::new_v4(), "role": "twin",
+        }))
+        .unwrap();
+        assert_eq!(MemorySource::from_claims(&claims).kind, SourceKind::User);
+        claims.role = Role::Service;
+        assert_eq!(MemorySource::from_claims(&claims).kind, SourceKind::Service);
+        assert_eq!(MemorySource::from_claims(&claims).name.as_deref(), Some("twin-1"));
+    }
+
+    #[test]
+    fn only_services_choose_an_appended_items_source() {
+        let mut claims: Claims = serde_json::from_value(json!({
+            "iss": "pagi", "sub": "twin-1", "iat": 0, "exp": 0, "jti": Uuid::new_v4(), "role": "twin",
+        }))
+        .unwrap();
+        let tool = MemorySource {
+            kind: SourceKind::Tool,
+            name: Some("crawler".to_string()),
+        };
+        let source = MemorySource::for_append(Some(&claims), Some(tool.clone())).unwrap();
+        assert_eq!((source.kind, source.name.as_deref()), (SourceKind::User, Some("twin-1")));
+
+        claims.role = Role::Service;
+        assert_eq!(MemorySource::for_append(Some(&claims), Some(tool.clone())), Some(tool.clone()));
+        assert_eq!(MemorySource::for_append(Some(&claims), None).unwrap().kind, SourceKind::Service);
+        assert_eq!(MemorySource::for_append(None, Some(tool.clone())), Some(tool));
+        assert_eq!(MemorySource::for_append(None, None), None);
+    }
+}
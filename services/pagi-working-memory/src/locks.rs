@@ -0,0 +1,54 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use uuid::Uuid;
+
+/// Write locks per twin: read-modify-write of one twin's memory (append, replace, policy
+/// changes, consolidation) is serialized without holding up writes to other twins.
+#[derive(Default)]
+pub struct TwinLocks {
+    locks: Mutex<HashMap<Uuid, Arc<AsyncMutex<()>>>>,
+}
+
+impl TwinLocks {
+    pub async fn lock(&self, twin_id: Uuid) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap_or_else(|p| p.into_inner());
+            // Locks nobody holds or waits for are dropped, so the map only grows with
+            // concurrent writers.
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(twin_id).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn twins_are_locked_separately() {
+        let locks = TwinLocks::default();
+        let (a, b) = (Uuid::new_v4(), Uuid::new_v4());
+        let held = locks.lock(a).await;
+        tokio::time::timeout(Duration::from_secs(1), locks.lock(b)).await.expect("other twin blocked");
+        assert!(tokio::time::timeout(Duration::from_millis(50), locks.lock(a)).await.is_err());
+        drop(held);
+        tokio::time::timeout(Duration::from_secs(1), locks.lock(a)).await.expect("released lock still held");
+    }
+
+    #[tokio::test]
+    async fn released_locks_are_dropped() {
+        let locks = TwinLocks::default();
+        for _ in 0..10 {
+            drop(locks.lock(Uuid::new_v4()).await);
+        }
+        let _held = locks.lock(Uuid::new_v4()).await;
+        assert_eq!(locks.locks.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,511 @@
+//! Long-term memory: where items go when they leave working memory (capacity eviction and
+//! summaries, retention), so a twin remembers beyond its working set.
+//!
+//! The twin's playbook picks the [`Consolidation`] (`memory.long_term_storage`):
+//! - `episodic`: one record per distinct item; repeats bump `occurrences` instead of adding records
+//! - `semantic`: departing items are staged as episodic records (marked `staged`), and a
+//!   background worker has the inference gateway summarize them into a semantic record, removing
+//!   the staged episodes. Episodes stay staged (and are retried on the next consolidation) while
+//!   the gateway fails. Unstaged episodes, kept while the twin consolidated episodically, are
+//!   never summarized away.
+
+use std::{
+    collections::{BTreeSet, HashSet},
+    sync::Arc,
+};
+
+use pagi_common::{publish_event, CoreEvent, EventEnvelope};
+use pagi_http::trace_context::TracedClient;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use crate::{
+    capacity::SUMMARY_ROLE,
+    item::MemoryItem,
+    locks::TwinLocks,
+    store::MemoryStore,
+    SERVICE,
+};
+
+/// Most staged episodes summarized in one inference call.
+const SUMMARY_BATCH: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordKind {
+    Episodic,
+    Semantic,
+}
+
+impl RecordKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecordKind::Episodic => "episodic",
+            RecordKind::Semantic => "semantic",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LongTermRecord {
+    pub id: Uuid,
+    pub kind: RecordKind,
+    /// Role of the consolidated item (episodic records).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    pub content: String,
+    /// When the record was written.
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    /// The period the source items were stored in.
+    #[serde(with = "time::serde::rfc3339")]
+    pub first_seen: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_seen: OffsetDateTime,
+    /// Source items this record stands for, counting repeats.
+    pub occurrences: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub source_items: Vec<Uuid>,
+    /// An episode waiting to be folded into a semantic record.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub staged: bool,
+}
+
+impl LongTermRecord {
+    fn episode(item: &MemoryItem, staged: bool) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            kind: RecordKind::Episodic,
+            role: Some(item.role.clone()),
+            content: item.content.clone(),
+            created_at: OffsetDateTime::now_utc(),
+            first_seen: item.created_at,
+            last_seen: item.created_at,
+            occurrences: 1,
+            tags: item.tags.clone(),
+            source_items: vec![item.id],
+            staged,
+        }
+    }
+
+    fn absorb(&mut self, item: &MemoryItem) {
+        self.occurrences += 1;
+        self.first_seen = self.first_seen.min(item.created_at);
+        self.last_seen = self.last_seen.max(item.created_at);
+        for tag in &item.tags {
+            if !self.tags.contains(tag) {
+                self.tags.push(tag.clone());
+            }
+        }
+        self.source_items.push(item.id);
+    }
+
+    /// Episodic records with the same key are the same memory: role and content, ignoring case
+    /// and whitespace.
+    pub fn dedup_key(&self) -> Option<String> {
+        let role = self.role.as_deref()?;
+        Some(dedup_key(role, &self.content))
+    }
+}
+
+fn dedup_key(role: &str, content: &str) -> String {
+    let content = content.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    format!("{}\u{1f}{content}", role.to_lowercase())
+}
+
+/// What happens to items leaving working memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Consolidation {
+    /// They are dropped.
+    #[default]
+    None,
+    Episodic,
+    Semantic,
+}
+
+impl Consolidation {
+    /// Playbook `memory.long_term_storage` values.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name.trim().to_lowercase().as_str() {
+            "" | "none" => Ok(Consolidation::None),
+            "episodic" => Ok(Consolidation::Episodic),
+            "semantic" | "summary" | "summaries" => Ok(Consolidation::Semantic),
+            other => Err(format!("unknown long_term_storage '{other}' (expected none|episodic|semantic)")),
+        }
+    }
+
+    /// `WORKING_MEMORY_CONSOLIDATION`: for twins whose playbook does not choose (default: `none`).
+    pub fn from_env() -> Result<Self, String> {
+        Self::parse(&std::env::var("WORKING_MEMORY_CONSOLIDATION").unwrap_or_default())
+            .map_err(|e| format!("WORKING_MEMORY_CONSOLIDATION: {e}"))
+    }
+}
+
+pub struct Consolidator {
+    store: Arc<dyn MemoryStore>,
+    wake: mpsc::UnboundedSender<Uuid>,
+}
+
+impl Consolidator {
+    /// Start the summary worker. `INFERENCE_GATEWAY_URL` is where summaries are generated.
+    ///
+    /// Must be called from within a Tokio runtime.
+    pub fn spawn(store: Arc<dyn MemoryStore>, locks: Arc<TwinLocks>, http: TracedClient) -> Self {
+        let inference_url =
+            std::env::var("INFERENCE_GATEWAY_URL").unwrap_or_else(|_| "http://127.0.0.1:8005".to_string());
+        let (wake, mut woken) = mpsc::unbounded_channel::<Uuid>();
+        let worker = Summarizer {
+            store: store.clone(),
+            locks,
+            http,
+            infer_url: format!("{}/infer", inference_url.trim_end_matches('/')),
+        };
+        tokio::spawn(async move {
+            while let Some(twin_id) = woken.recv().await {
+                // Coalesce wake-ups queued while the previous summary was generated.
+                let mut twins = HashSet::from([twin_id]);
+                while let Ok(twin_id) = woken.try_recv() {
+                    twins.insert(twin_id);
+                }
+                for twin_id in twins {
+                    loop {
+                        match worker.summarize(twin_id).await {
+                            Ok(true) => continue,
+                            Ok(false) => break,
+                            Err(err) => {
+                                tracing::warn!(%twin_id, error = %err, "long-term summary not generated; episodes stay staged");
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+        Self { store, wake }
+    }
+
+    /// Move `items` that left working memory into the long-term tier. Call with the twin's
+    /// write lock held, off the async runtime (the store blocks).
+    pub fn consolidate(&self, twin_id: Uuid, policy: Consolidation, items: &[MemoryItem]) -> Result<(), String> {
+        if policy == Consolidation::None {
+            return Ok(());
+        }
+        // Capacity summaries only restate items that were consolidated when they were folded.
+        let items: Vec<&MemoryItem> = items.iter().filter(|item| item.role != SUMMARY_ROLE).collect();
+        if items.is_empty() {
+            return Ok(());
+        }
+        // Semantic consolidation only stages (and repeats only raise) episodes of its own, so
+        // episodes kept earlier are not summarized away.
+        let staged = policy == Consolidation::Semantic;
+        let mut records = 0;
+        for item in &items {
+            let key = dedup_key(&item.role, &item.content);
+            let record = match self.store.find_episode(twin_id, &key, staged)? {
+                Some(mut record) => {
+                    record.absorb(item);
+                    record
+                }
+                None => {
+                    records += 1;
+                    LongTermRecord::episode(item, staged)
+                }
+            };
+            self.store.put_record(twin_id, &record)?;
+        }
+        tracing::debug!(%twin_id, items = items.len(), records, "working memory consolidated");
+        match policy {
+            Consolidation::Semantic => {
+                let _ = self.wake.send(twin_id);
+            }
+            _ => publish_consolidated(twin_id, RecordKind::Episodic, items.len() as u64, records),
+        }
+        Ok(())
+    }
+}
+
+struct Summarizer {
+    store: Arc<dyn MemoryStore>,
+    locks: Arc<TwinLocks>,
+    http: TracedClient,
+    infer_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct InferResponse {
+    output: String,
+}
+
+impl Summarizer {
+    /// Summarize up to [`SUMMARY_BATCH`] staged episodes, oldest first; `true` when more are staged.
+    async fn summarize(&self, twin_id: Uuid) -> Result<bool, String> {
+        let episodes = self.blocking(move |store| store.staged(twin_id, SUMMARY_BATCH)).await?;
+        if episodes.is_empty() {
+            return Ok(false);
+        }
+
+        let mut context = String::from("# Memories to consolidate\n");
+        for episode in &episodes {
+            let role = episode.role.as_deref().unwrap_or("unknown");
+            match episode.occurrences {
+                1 => context.push_str(&format!("- {role}: {}\n", episode.content)),
+                n => context.push_str(&format!("- {role} ({n}x): {}\n", episode.content)),
+            }
+        }
+        let resp = self
+            .http
+            .post(&self.infer_url)
+            .json(&json!({
+                "twin_id": twin_id,
+                "input": "Summarize these memories into durable facts about the user, their preferences and \
+                          commitments. Be concise; omit small talk.",
+                "context": context,
+            }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?
+            .error_for_status()
+            .map_err(|e| e.to_string())?;
+        let summary = resp.json::<InferResponse>().await.map_err(|e| e.to_string())?.output;
+        if summary.trim().is_empty() {
+            return Err("inference gateway returned an empty summary".to_string());
+        }
+
+        let summary = summary.trim().to_string();
+        let batch = episodes.len();
+        let record = {
+            let _writing = self.locks.lock(twin_id).await;
+            self.blocking(move |store| {
+                // Episodes that changed while the summary was generated (a repeat raised them)
+                // stay staged for the next summary; vanished ones mean the twin was erased.
+                let ids: Vec<Uuid> = episodes.iter().map(|e| e.id).collect();
+                let current = store.get_records(twin_id, &ids)?;
+                let unchanged: Vec<LongTermRecord> =
+                    episodes.into_iter().filter(|episode| current.contains(episode)).collect();
+                if unchanged.is_empty() {
+                    return Ok(None);
+                }
+                let record = semantic_record(summary, &unchanged);
+                let ids: Vec<Uuid> = unchanged.iter().map(|e| e.id).collect();
+                store.delete_records(twin_id, &ids)?;
+                store.put_record(twin_id, &record)?;
+                Ok(Some((record, ids.len())))
+            })
+            .await?
+        };
+        let Some((record, episodes)) = record else {
+            return Ok(false);
+        };
+        tracing::info!(%twin_id, episodes, record_id = %record.id, "long-term summary stored");
+        publish_consolidated(twin_id, RecordKind::Semantic, record.occurrences as u64, 1);
+        Ok(batch == SUMMARY_BATCH)
+    }
+
+    /// Run store calls on the blocking pool: the SQLite backend does synchronous I/O.
+    async fn blocking<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&dyn MemoryStore) -> Result<T, String> + Send + 'static,
+    ) -> Result<T, String> {
+        let store = self.store.clone();
+        tokio::task::spawn_blocking(move || f(&*store)).await.map_err(|e| e.to_string())?
+    }
+}
+
+/// The semantic record standing for `episodes` (not empty).
+fn semantic_record(summary: String, episodes: &[LongTermRecord]) -> LongTermRecord {
+    let now = OffsetDateTime::now_utc();
+    LongTermRecord {
+        id: Uuid::new_v4(),
+        kind: RecordKind::Semantic,
+        role: None,
+        content: summary,
+        created_at: now,
+        first_seen: episodes.iter().map(|e| e.first_seen).min().unwrap_or(now),
+        last_seen: episodes.iter().map(|e| e.last_seen).max().unwrap_or(now),
+        occurrences: episodes.iter().map(|e| e.occurrences).sum(),
+        tags: episodes
+            .iter()
+            .flat_map(|e| e.tags.iter().cloned())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect(),
+        source_items: episodes.iter().flat_map(|e| e.source_items.iter().copied()).collect(),
+        staged: false,
+    }
+}
+
+fn publish_consolidated(twin_id: Uuid, tier: RecordKind, items: u64, records: u64) {
+    let mut ev = EventEnvelope::new_core(
+        twin_id,
+        CoreEvent::WorkingMemoryConsolidated {
+            tier: tier.as_str().to_string(),
+            items,
+            records,
+        },
+    );
+    ev.source = Some(SERVICE.to_string());
+    publish_event(ev);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{InMemoryStore, ItemQuery};
+
+    fn item(role: &str, content: &str) -> MemoryItem {
+        serde_json::from_value(json!({"role": role, "content": content})).unwrap()
+    }
+
+    /// A consolidator without the summary worker.
+    fn consolidator(store: &Arc<dyn MemoryStore>) -> Consolidator {
+        let (wake, _) = mpsc::unbounded_channel();
+        Consolidator {
+            store: store.clone(),
+            wake,
+        }
+    }
+
+    /// A summarizer using an inference gateway that answers `output`, running `during` first
+    /// (to change the store while the summary is generated).
+    async fn summarizer(
+        store: &Arc<dyn MemoryStore>,
+        output: &'static str,
+        during: impl Fn() + Clone + Send + Sync + 'static,
+    ) -> Summarizer {
+        let app = axum::Router::new().route(
+            "/infer",
+            axum::routing::post(move || {
+                let during = during.clone();
+                async move {
+                    during();
+                    axum::Json(json!({ "output": output }))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        Summarizer {
+            store: store.clone(),
+            locks: Arc::new(TwinLocks::default()),
+            http: pagi_http::trace_context::client(),
+            infer_url: format!("http://{addr}/infer"),
+        }
+    }
+
+    fn episodes(store: &Arc<dyn MemoryStore>, twin: Uuid, kind: RecordKind) -> Vec<LongTermRecord> {
+        let records = store.records(twin, kind, &ItemQuery::default()).unwrap();
+        records.into_iter().map(|(_, record)| record).collect()
+    }
+
+    #[test]
+    fn playbooks_name_the_consolidation() {
+        assert_eq!(Consolidation::parse("").unwrap(), Consolidation::None);
+        assert_eq!(Consolidation::parse(" Episodic ").unwrap(), Consolidation::Episodic);
+        assert_eq!(Consolidation::parse("summaries").unwrap(), Consolidation::Semantic);
+        assert!(Consolidation::parse("forever").is_err());
+    }
+
+    #[tokio::test]
+    async fn repeated_items_share_an_episode() {
+        let store: Arc<dyn MemoryStore> = Arc::new(InMemoryStore::default());
+        let consolidator = consolidator(&store);
+        let twin = Uuid::new_v4();
+        let mut summary = item(SUMMARY_ROLE, "- user: likes tea");
+        summary.tags = vec!["ignored".to_string()];
+        let mut repeat = item("user", "likes   TEA");
+        repeat.tags = vec!["drinks".to_string()];
+        let items = [item("user", "Likes tea"), repeat, item("assistant", "likes tea"), summary];
+        consolidator.consolidate(twin, Consolidation::Episodic, &items).unwrap();
+
+        let records = store.records(twin, RecordKind::Episodic, &ItemQuery::default()).unwrap();
+        let records: Vec<&LongTermRecord> = records.iter().map(|(_, record)| record).collect();
+        assert_eq!(records.len(), 2, "{records:?}");
+        assert_eq!((records[0].content.as_str(), records[0].occurrences), ("Likes tea", 2));
+        assert_eq!(records[0].source_items, [items[0].id, items[1].id]);
+        assert_eq!(records[0].tags, ["drinks"]);
+        assert_eq!(records[1].role.as_deref(), Some("assistant"));
+    }
+
+    #[tokio::test]
+    async fn semantic_consolidation_leaves_earlier_episodes_alone() {
+        let store: Arc<dyn MemoryStore> = Arc::new(InMemoryStore::default());
+        let consolidator = consolidator(&store);
+        let twin = Uuid::new_v4();
+        consolidator.consolidate(twin, Consolidation::Episodic, &[item("user", "likes tea")]).unwrap();
+        consolidator
+            .consolidate(twin, Consolidation::Semantic, &[item("user", "likes tea"), item("user", "likes tea")])
+            .unwrap();
+
+        let staged = store.staged(twin, 10).unwrap();
+        assert_eq!(staged.len(), 1);
+        assert_eq!(staged[0].occurrences, 2);
+        let kept: Vec<u32> = episodes(&store, twin, RecordKind::Episodic)
+            .into_iter()
+            .filter(|record| !record.staged)
+            .map(|record| record.occurrences)
+            .collect();
+        assert_eq!(kept, [1]);
+    }
+
+    #[tokio::test]
+    async fn summaries_replace_only_unchanged_staged_episodes() {
+        let store: Arc<dyn MemoryStore> = Arc::new(InMemoryStore::default());
+        let twin = Uuid::new_v4();
+        let history = LongTermRecord::episode(&item("user", "likes tea"), false);
+        let tea = LongTermRecord::episode(&item("user", "likes tea"), true);
+        let coffee = LongTermRecord::episode(&item("user", "likes coffee"), true);
+        for record in [&history, &tea, &coffee] {
+            store.put_record(twin, record).unwrap();
+        }
+        let writer = store.clone();
+        let repeat = item("user", "likes coffee");
+        let summarizer = summarizer(&store, "The user likes tea.", move || {
+            let mut coffee = writer.find_episode(twin, &dedup_key("user", "likes coffee"), true).unwrap().unwrap();
+            if coffee.occurrences == 1 {
+                coffee.absorb(&repeat);
+                writer.put_record(twin, &coffee).unwrap();
+            }
+        })
+        .await;
+
+        assert!(!summarizer.summarize(twin).await.unwrap());
+        let semantic = episodes(&store, twin, RecordKind::Semantic);
+        assert_eq!(semantic.len(), 1);
+        assert_eq!(semantic[0].content, "The user likes tea.");
+        assert_eq!(semantic[0].source_items, tea.source_items);
+        // The repeated episode waits for the next summary; the unstaged one is kept.
+        let staged = store.staged(twin, 10).unwrap();
+        assert_eq!((staged.len(), staged[0].id, staged[0].occurrences), (1, coffee.id, 2));
+        let ids: Vec<Uuid> = episodes(&store, twin, RecordKind::Episodic).iter().map(|r| r.id).collect();
+        assert_eq!(ids, [history.id, coffee.id]);
+    }
+
+    #[tokio::test]
+    async fn summaries_of_erased_twins_are_dropped() {
+        let store: Arc<dyn MemoryStore> = Arc::new(InMemoryStore::default());
+        let twin = Uuid::new_v4();
+        store.put_record(twin, &LongTermRecord::episode(&item("user", "likes tea"), true)).unwrap();
+        let writer = store.clone();
+        let summarizer = summarizer(&store, "The user likes tea.", move || {
+            writer.remove(twin).unwrap();
+        })
+        .await;
+
+        assert!(!summarizer.summarize(twin).await.unwrap());
+        assert!(episodes(&store, twin, RecordKind::Semantic).is_empty());
+    }
+
+    #[tokio::test]
+    async fn nothing_is_kept_without_consolidation() {
+        let store: Arc<dyn MemoryStore> = Arc::new(InMemoryStore::default());
+        let twin = Uuid::new_v4();
+        consolidator(&store).consolidate(twin, Consolidation::None, &[item("user", "hi")]).unwrap();
+        assert!(store.records(twin, RecordKind::Episodic, &ItemQuery::default()).unwrap().is_empty());
+    }
+}
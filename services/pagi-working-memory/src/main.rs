@@ -1,81 +1,1200 @@
+mod capacity;
+mod importance;
+mod item;
+mod locks;
+mod long_term;
+mod retrieval;
+mod schema;
+mod store;
+
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
-    routing::{get, post},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
     Json, Router,
 };
-use pagi_common::{publish_event, EventEnvelope, EventType};
+use pagi_common::{
+    auth::scopes,
+    erasure::{DeletionConfirmer, PurgeReport, PurgeTrigger, Retention, TwinDeletion},
+    publish_event,
+    swarm::PlaybookMemory,
+    CoreEvent, EventEnvelope, Outbox, PagiError,
+};
+use pagi_http::{
+    auth::{Auth, Caller},
+    errors::PagiAxumError,
+};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
-use tokio::sync::RwLock;
+use std::{collections::BTreeSet, net::SocketAddr, sync::Arc};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
 use uuid::Uuid;
 
+use capacity::{CapacityPolicy, Enforced};
+use importance::ImportanceModel;
+use item::{ItemPatch, MemoryItem, MemorySource};
+use locks::TwinLocks;
+use long_term::{Consolidation, Consolidator, LongTermRecord, RecordKind};
+use retrieval::{Hit, MemoryIndex, SearchQuery, Strategy};
+use schema::{MemorySchema, OnViolation, Quarantined};
+use store::{ItemQuery, MemoryStore};
+
 #[derive(Clone)]
 struct AppState {
-    mem: Arc<RwLock<HashMap<Uuid, Vec<MemoryItem>>>>,
+    store: Arc<dyn MemoryStore>,
+    /// Node default, for twins without their own policy.
+    capacity: Arc<CapacityPolicy>,
+    /// Serializes read-modify-write of each twin's items (append, replace, policy changes).
+    locks: Arc<TwinLocks>,
+    index: Arc<MemoryIndex>,
+    consolidator: Arc<Consolidator>,
+    /// Node default, for twins whose playbook does not choose.
+    consolidation: Consolidation,
+    importance: Arc<ImportanceModel>,
+    deletions: Arc<DeletionConfirmer>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct MemoryItem {
-    pub role: String,
-    pub content: String,
+#[derive(Debug, Deserialize)]
+struct AppendRequest {
+    pub item: MemoryItem,
 }
 
 #[derive(Debug, Deserialize)]
-struct AppendRequest {
+struct ListQuery {
+    #[serde(default)]
+    pub since: Option<String>,
+    #[serde(default)]
+    pub until: Option<String>,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+    /// `next_cursor` from the previous page.
+    #[serde(default)]
+    pub cursor: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// `recent` (default) or `importance`.
+    #[serde(default)]
+    pub sort: Option<String>,
+    #[serde(default)]
+    pub min_importance: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct ScoredItem {
+    #[serde(flatten)]
     pub item: MemoryItem,
+    /// See [`ImportanceModel::score`].
+    pub importance: f32,
+}
+
+#[derive(Debug, Serialize)]
+struct MemoryPage {
+    pub items: Vec<ScoredItem>,
+    /// Pass as `cursor` for the next (older) page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct LongTermPage {
+    pub records: Vec<LongTermRecord>,
+    /// Pass as `cursor` for the next (older) page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RetrievedRequest {
+    pub item_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+struct RetrievedResponse {
+    /// Items found and reinforced.
+    pub updated: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct PageQuery {
+    #[serde(default)]
+    pub cursor: Option<u64>,
+    #[serde(default)]
+    pub limit: Option<usize>,
 }
 
+#[derive(Debug, Serialize)]
+struct QuarantinePage {
+    pub items: Vec<Quarantined>,
+    /// Pass as `cursor` for the next (older) page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct SchemaResponse {
+    /// `registered` (`PUT .../schema`) or `playbook` (`memory.schema`).
+    pub origin: &'static str,
+    pub schema: MemorySchema,
+}
+
+#[derive(Debug, Deserialize)]
+struct SearchRequest {
+    pub query: String,
+    #[serde(default)]
+    pub top_k: Option<usize>,
+    #[serde(default)]
+    pub strategy: Strategy,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct SearchResponse {
+    pub strategy: Strategy,
+    pub embedder: &'static str,
+    pub results: Vec<Hit>,
+}
+
+const DEFAULT_TOP_K: usize = 10;
+const MAX_TOP_K: usize = 100;
+
+const DEFAULT_PAGE: usize = 100;
+const MAX_PAGE: usize = 1000;
+
+const SERVICE: &str = "pagi-working-memory";
+
 #[tokio::main]
-async fn main() {
-    pagi_http::tracing::init("pagi-working-memory");
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    pagi_http::tracing::init(SERVICE);
 
+    let store = store::from_env()?;
+    let (twins, items) = store.stats()?;
+    tracing::info!(backend = store.name(), twins, items, "working memory loaded");
+    let capacity = CapacityPolicy::from_env()?;
+    tracing::info!(?capacity, "default capacity policy");
+    let index = MemoryIndex::from_env()?;
+    tracing::info!(embedder = index.embedder(), max_twins = index.max_twins(), "working memory search enabled");
+    let consolidation = Consolidation::from_env()?;
+    tracing::info!(?consolidation, "default long-term consolidation");
+    let importance = ImportanceModel::from_env()?;
+    tracing::info!(?importance, "importance scoring");
+    let store: Arc<dyn MemoryStore> = Arc::from(store);
+    let locks = Arc::new(TwinLocks::default());
+    let consolidator = Consolidator::spawn(store.clone(), locks.clone(), pagi_http::auth::service_client());
     let state = AppState {
-        mem: Arc::new(RwLock::new(HashMap::new())),
+        store,
+        capacity: Arc::new(capacity),
+        locks,
+        index: Arc::new(index),
+        consolidator: Arc::new(consolidator),
+        consolidation,
+        importance: Arc::new(importance),
+        deletions: Arc::new(DeletionConfirmer::from_env()),
     };
 
+    let retention = Retention::from_env("WORKING_MEMORY_MAX_AGE_SECS")?;
+    if let Some(max_age) = retention.max_age {
+        tracing::info!(max_age_secs = max_age.as_secs(), "working memory retention enabled");
+    }
+    let sweeping = state.clone();
+    retention.spawn(move |cutoff| {
+        let state = sweeping.clone();
+        async move { sweep_expired(&state, cutoff).await }
+    });
+    let long_term_retention = Retention::from_env("LONG_TERM_MEMORY_MAX_AGE_SECS")?;
+    if let Some(max_age) = long_term_retention.max_age {
+        tracing::info!(max_age_secs = max_age.as_secs(), "long-term memory retention enabled");
+    }
+    let sweeping = state.clone();
+    long_term_retention.spawn(move |cutoff| {
+        let state = sweeping.clone();
+        async move { sweep_expired_records(&state, cutoff).await }
+    });
+    if let Some(threshold) = state.importance.prune_below {
+        tracing::info!(threshold, "forgetting of unimportant working memory items enabled");
+        let forgetting = state.clone();
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(forgetting.importance.sweep_every);
+            tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tick.tick().await;
+                forget(&forgetting, threshold).await;
+            }
+        });
+    }
+
+    let auth = Auth::from_env();
     let app = Router::new()
         .route("/healthz", get(healthz))
-        .route("/memory/:twin_id", get(get_memory))
-        .route("/memory/:twin_id/append", post(append_memory))
+        .route(
+            "/memory/:twin_id",
+            get(get_memory)
+                .route_layer(auth.require(scopes::MEMORY_READ))
+                .merge(put(replace_memory).route_layer(auth.require(scopes::MEMORY_WRITE))),
+        )
+        .route("/memory/:twin_id/append", post(append_memory).route_layer(auth.require(scopes::MEMORY_WRITE)))
+        .route("/memory/:twin_id/search", post(search_memory).route_layer(auth.require(scopes::MEMORY_READ)))
+        .route(
+            "/memory/:twin_id/retrieved",
+            post(record_retrievals).route_layer(auth.require(scopes::MEMORY_WRITE)),
+        )
+        .route(
+            "/memory/:twin_id/items/:item_id",
+            get(get_item)
+                .route_layer(auth.require(scopes::MEMORY_READ))
+                .merge(
+                    patch(update_item)
+                        .delete(delete_item)
+                        .route_layer(auth.require(scopes::MEMORY_WRITE)),
+                ),
+        )
+        .route(
+            "/memory/:twin_id/policy",
+            get(get_policy)
+                .route_layer(auth.require(scopes::MEMORY_READ))
+                .merge(put(set_policy).route_layer(auth.require(scopes::MEMORY_WRITE))),
+        )
+        .route(
+            "/memory/:twin_id/playbook",
+            get(get_playbook)
+                .route_layer(auth.require(scopes::MEMORY_READ))
+                .merge(put(set_playbook).route_layer(auth.require(scopes::MEMORY_WRITE))),
+        )
+        .route(
+            "/memory/:twin_id/long_term/:kind",
+            get(get_long_term).route_layer(auth.require(scopes::MEMORY_READ)),
+        )
+        .route(
+            "/memory/:twin_id/long_term/:kind/:record_id",
+            delete(delete_long_term).route_layer(auth.require(scopes::MEMORY_WRITE)),
+        )
+        .route(
+            "/memory/:twin_id/schema",
+            get(get_schema)
+                .route_layer(auth.require(scopes::MEMORY_READ))
+                .merge(
+                    put(set_schema)
+                        .delete(clear_schema)
+                        .route_layer(auth.require(scopes::MEMORY_WRITE)),
+                ),
+        )
+        .route(
+            "/memory/:twin_id/quarantine",
+            get(get_quarantine).route_layer(auth.require(scopes::MEMORY_READ)),
+        )
+        .route(
+            "/memory/:twin_id/quarantine/:item_id",
+            delete(discard_quarantined).route_layer(auth.require(scopes::MEMORY_WRITE)),
+        )
+        .route(
+            "/memory/:twin_id/quarantine/:item_id/release",
+            post(release_quarantined).route_layer(auth.require(scopes::MEMORY_WRITE)),
+        )
+        .route("/events", post(handle_event).route_layer(auth.require(scopes::MEMORY_WRITE)))
         .with_state(state)
+        .layer(axum::middleware::from_fn(pagi_http::trace_context::propagate))
         .layer(TraceLayer::new_for_http())
         .layer(CorsLayer::permissive());
 
     let addr: SocketAddr = pagi_http::config::bind_addr(([0, 0, 0, 0], 8003).into());
     tracing::info!(%addr, "listening");
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    pagi_http::tls::serve(listener, app).await?;
+    Ok(())
 }
 
 async fn healthz() -> (StatusCode, &'static str) {
     (StatusCode::OK, "ok")
 }
 
-async fn get_memory(State(state): State<AppState>, Path(twin_id): Path<Uuid>) -> Json<Vec<MemoryItem>> {
-    let guard = state.mem.read().await;
-    let items = guard.get(&twin_id).cloned().unwrap_or_default();
-    Json(items)
+/// `GET /memory/:twin_id?since=&until=&role=&tag=&cursor=&limit=&sort=&min_importance=`: the
+/// newest matching items, oldest first; `next_cursor` pages back to older ones. With
+/// `sort=importance`, the `limit` most important matching items instead (not paged).
+async fn get_memory(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+    Query(q): Query<ListQuery>,
+) -> Result<Json<MemoryPage>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE);
+    let by_importance = match q.sort.as_deref() {
+        None | Some("recent") => false,
+        Some("importance") => true,
+        Some(other) => return Err(bad_request(format!("unknown sort '{other}' (expected recent|importance)"))),
+    };
+    if by_importance && q.cursor.is_some() {
+        return Err(bad_request("cursor pages by recency only".to_string()));
+    }
+    let query = ItemQuery {
+        since: parse_ts("since", q.since.as_deref())?,
+        until: parse_ts("until", q.until.as_deref())?,
+        role: q.role,
+        tag: q.tag,
+        before: q.cursor,
+        limit: (!by_importance).then_some(limit),
+    };
+    let page = blocking(&state, move |state| state.store.query(twin_id, &query).map_err(internal)).await?;
+    let next_cursor = if !by_importance && page.len() == limit {
+        page.first().map(|(seq, _)| *seq)
+    } else {
+        None
+    };
+
+    let now = OffsetDateTime::now_utc();
+    let mut scored: Vec<(u64, ScoredItem)> = page
+        .into_iter()
+        .map(|(seq, item)| {
+            let importance = state.importance.score(&item, now);
+            (seq, ScoredItem { item, importance })
+        })
+        .filter(|(_, scored)| q.min_importance.is_none_or(|min| scored.importance >= min))
+        .collect();
+    if by_importance {
+        // Most important first (equal scores favour newer items), then back to oldest first.
+        scored.sort_by(|a, b| b.1.importance.total_cmp(&a.1.importance).then(b.0.cmp(&a.0)));
+        scored.truncate(limit);
+        scored.sort_by_key(|(seq, _)| *seq);
+    }
+    Ok(Json(MemoryPage {
+        items: scored.into_iter().map(|(_, scored)| scored).collect(),
+        next_cursor,
+    }))
+}
+
+/// `POST /memory/:twin_id/retrieved`: record that items were put into a context, which
+/// reinforces them against forgetting (see [`importance`]).
+async fn record_retrievals(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+    Json(req): Json<RetrievedRequest>,
+) -> Result<Json<RetrievedResponse>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let now = OffsetDateTime::now_utc();
+    let _writing = state.locks.lock(twin_id).await;
+    let updated = blocking(&state, move |state| {
+        let mut updated = 0;
+        for item_id in req.item_ids.into_iter().collect::<BTreeSet<_>>() {
+            let Some(mut item) = state.store.get(twin_id, item_id).map_err(internal)? else {
+                continue;
+            };
+            item.retrievals = item.retrievals.saturating_add(1);
+            item.last_retrieved_at = Some(now);
+            state.store.update(twin_id, &item).map_err(internal)?;
+            state.index.upsert(twin_id, &item);
+            updated += 1;
+        }
+        Ok(updated)
+    })
+    .await?;
+    tracing::debug!(%twin_id, updated, "working memory retrievals recorded");
+    Ok(Json(RetrievedResponse { updated }))
+}
+
+async fn get_item(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path((twin_id, item_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<MemoryItem>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    match blocking(&state, move |state| state.store.get(twin_id, item_id).map_err(internal)).await? {
+        Some(item) => Ok(Json(item)),
+        None => Err(item_not_found(item_id)),
+    }
+}
+
+/// `PATCH /memory/:twin_id/items/:item_id`: edit an item in place (see [`ItemPatch`]).
+async fn update_item(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path((twin_id, item_id)): Path<(Uuid, Uuid)>,
+    Json(patch): Json<ItemPatch>,
+) -> Result<Json<MemoryItem>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let _writing = state.locks.lock(twin_id).await;
+    let item = blocking(&state, move |state| {
+        let Some(stored) = state.store.get(twin_id, item_id).map_err(internal)? else {
+            return Err(item_not_found(item_id));
+        };
+        let mut item = stored.clone();
+        patch.apply(&mut item);
+        // Edits cannot be quarantined; they are rejected whatever the schema's `on_violation`.
+        if let Some((_, schema)) = schema_for(state, twin_id)? {
+            let violations = schema.violations(&item);
+            if !violations.is_empty() {
+                publish_rejected(twin_id, &item, &violations, OnViolation::Reject);
+                return Err(schema_violation(&violations));
+            }
+        }
+        let policy = capacity_for(state, twin_id)?;
+        if !policy.admits(&item) {
+            return Err(too_large(&policy));
+        }
+        if item.pinned {
+            let mut pinned = state.store.pinned_usage(twin_id).map_err(internal)?;
+            if stored.pinned {
+                pinned = pinned.without(&stored);
+            }
+            if !policy.pins_fit(pinned.with(&item)) {
+                return Err(too_many_pins(&policy));
+            }
+        }
+        state.store.update(twin_id, &item).map_err(internal)?;
+        state.index.upsert(twin_id, &item);
+
+        // A longer item can push the twin over its byte limit. The edit is committed, so a
+        // failure only leaves the twin over its limit until its next write.
+        let departed = enforce_stored(state, twin_id, &policy).unwrap_or_else(|err| {
+            tracing::warn!(%twin_id, error = ?err, "working memory capacity not enforced");
+            0
+        });
+        tracing::info!(%twin_id, %item_id, departed, "working memory item updated");
+        Ok(item)
+    })
+    .await?;
+
+    let mut ev = EventEnvelope::new_core(
+        twin_id,
+        CoreEvent::WorkingMemoryUpdated {
+            item: serde_json::to_value(&item).unwrap_or_default(),
+        },
+    );
+    ev.source = Some(SERVICE.to_string());
+    publish_event(ev);
+
+    Ok(Json(item))
+}
+
+async fn delete_item(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path((twin_id, item_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let deleted = {
+        let _writing = state.locks.lock(twin_id).await;
+        blocking(&state, move |state| state.store.delete(twin_id, item_id).map_err(internal)).await?
+    };
+    if !deleted {
+        return Err(item_not_found(item_id));
+    }
+    state.index.remove_item(twin_id, item_id);
+    tracing::info!(%twin_id, %item_id, "working memory item deleted");
+
+    let mut ev = EventEnvelope::new_core(twin_id, CoreEvent::WorkingMemoryDeleted { item_id });
+    ev.source = Some(SERVICE.to_string());
+    publish_event(ev);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `PUT /memory/:twin_id`: replace a twin's items, e.g. when a twin is imported from another node.
+/// The twin's capacity policy applies to the new items.
+async fn replace_memory(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+    Json(mut items): Json<Vec<MemoryItem>>,
+) -> Result<Json<Vec<MemoryItem>>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let _writing = state.locks.lock(twin_id).await;
+    let items = blocking(&state, move |state| {
+        let policy = capacity_for(state, twin_id)?;
+        let now = OffsetDateTime::now_utc();
+        let enforced = policy.enforce(&mut items, |item| state.importance.score(item, now));
+        consolidate(state, twin_id, enforced.departed())?;
+        state.store.replace(twin_id, &items).map_err(internal)?;
+        state.index.invalidate(twin_id);
+        tracing::info!(%twin_id, items = items.len(), departed = enforced.departed().len(), "working memory replaced");
+        Ok(items)
+    })
+    .await?;
+    Ok(Json(items))
 }
 
+/// `POST /memory/:twin_id/append`. Items not matching the twin's schema are rejected (`422`)
+/// or quarantined (`202`, answering with the [`Quarantined`] entry).
 async fn append_memory(
     State(state): State<AppState>,
+    caller: Caller,
     Path(twin_id): Path<Uuid>,
-    Json(req): Json<AppendRequest>,
-) -> (StatusCode, Json<Vec<MemoryItem>>) {
-    let mut guard = state.mem.write().await;
-    let entry = guard.entry(twin_id).or_default();
-    entry.push(req.item.clone());
-
-    let mut ev = EventEnvelope::new(
-        EventType::WorkingMemoryAppended,
-        json!({"twin_id": twin_id, "item": req.item}),
+    Json(mut req): Json<AppendRequest>,
+) -> Result<Response, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    req.item.source = MemorySource::for_append(caller.0.as_ref(), req.item.source.take());
+    // Importance is earned here, not claimed: only imports (`PUT`) keep their history.
+    req.item.created_at = OffsetDateTime::now_utc();
+    req.item.retrievals = 0;
+    req.item.last_retrieved_at = None;
+    let _writing = state.locks.lock(twin_id).await;
+    blocking(&state, move |state| {
+        if let Some((_, schema)) = schema_for(state, twin_id)? {
+            let violations = schema.violations(&req.item);
+            if !violations.is_empty() {
+                publish_rejected(twin_id, &req.item, &violations, schema.on_violation);
+                if schema.on_violation == OnViolation::Reject {
+                    return Err(schema_violation(&violations));
+                }
+                let entry = Quarantined {
+                    item: req.item,
+                    violations,
+                    quarantined_at: OffsetDateTime::now_utc(),
+                };
+                let keep = capacity_for(state, twin_id)?.max_quarantined;
+                let dropped = state.store.quarantine(twin_id, &entry, keep).map_err(internal)?;
+                tracing::info!(%twin_id, item_id = %entry.item.id, violations = ?entry.violations, "working memory item quarantined");
+                if dropped > 0 {
+                    tracing::warn!(%twin_id, dropped, max_quarantined = keep, "oldest quarantined working memory items dropped");
+                }
+                return Ok((StatusCode::ACCEPTED, Json(entry)).into_response());
+            }
+        }
+        append_item(state, twin_id, &req.item)?;
+        Ok((StatusCode::OK, Json(req.item)).into_response())
+    })
+    .await
+}
+
+/// Store `item` as the twin's newest, applying its capacity policy. Call with the twin's write
+/// lock held, off the async runtime.
+fn append_item(state: &AppState, twin_id: Uuid, item: &MemoryItem) -> Result<(), PagiAxumError> {
+    let policy = capacity_for(state, twin_id)?;
+    if !policy.admits(item) {
+        return Err(too_large(&policy));
+    }
+    if item.pinned && !policy.pins_fit(state.store.pinned_usage(twin_id).map_err(internal)?.with(item)) {
+        return Err(too_many_pins(&policy));
+    }
+    if state.store.get(twin_id, item.id).map_err(internal)?.is_some() {
+        return Err(PagiAxumError::with_status(
+            PagiError::config(format!("memory item {} already exists", item.id)),
+            StatusCode::CONFLICT,
+        ));
+    }
+    // Only a twin at capacity has its items loaded, to choose what makes room.
+    if policy.holds(state.store.usage(twin_id).map_err(internal)?.with(item)) {
+        state.store.append(twin_id, item, &[]).map_err(internal)?;
+        state.index.upsert(twin_id, item);
+        publish_appended(twin_id, item);
+        return Ok(());
+    }
+    let mut items = state.store.list(twin_id).map_err(internal)?;
+    items.push(item.clone());
+    let now = OffsetDateTime::now_utc();
+    let enforced = policy.enforce(&mut items, |item| state.importance.score(item, now));
+    // Departing items reach long-term memory before they leave, and nothing fails once the
+    // append is committed.
+    consolidate(state, twin_id, enforced.departed())?;
+    match &enforced {
+        Enforced::Fits => state.store.append(twin_id, item, &[]),
+        Enforced::Evicted(evicted) => {
+            tracing::debug!(%twin_id, evicted = evicted.len(), "working memory over capacity");
+            let ids: Vec<Uuid> = evicted.iter().map(|item| item.id).collect();
+            state.store.append(twin_id, item, &ids)
+        }
+        Enforced::Summarized(folded) => {
+            tracing::debug!(%twin_id, summarized = folded.len(), "working memory over capacity");
+            state.store.replace(twin_id, &items)
+        }
+    }
+    .map_err(internal)?;
+    if enforced == Enforced::Fits {
+        state.index.upsert(twin_id, item);
+    } else {
+        state.index.invalidate(twin_id);
+    }
+    publish_appended(twin_id, item);
+    Ok(())
+}
+
+/// Apply `policy` to the twin's stored items, loading them only when they are over it; returns
+/// how many items left. Departing items are consolidated before they are removed, so on error
+/// they are still in working memory. Call with the twin's write lock held, off the async runtime.
+fn enforce_stored(state: &AppState, twin_id: Uuid, policy: &CapacityPolicy) -> Result<usize, PagiAxumError> {
+    if policy.holds(state.store.usage(twin_id).map_err(internal)?) {
+        return Ok(0);
+    }
+    let mut items = state.store.list(twin_id).map_err(internal)?;
+    let now = OffsetDateTime::now_utc();
+    let enforced = policy.enforce(&mut items, |item| state.importance.score(item, now));
+    if enforced == Enforced::Fits {
+        return Ok(0);
+    }
+    consolidate(state, twin_id, enforced.departed())?;
+    state.store.replace(twin_id, &items).map_err(internal)?;
+    state.index.invalidate(twin_id);
+    Ok(enforced.departed().len())
+}
+
+fn publish_appended(twin_id: Uuid, item: &MemoryItem) {
+    let mut ev = EventEnvelope::new_core(
+        twin_id,
+        CoreEvent::WorkingMemoryAppended {
+            item: serde_json::to_value(item).unwrap_or_default(),
+        },
     );
-    ev.twin_id = Some(twin_id);
-    ev.source = Some("pagi-working-memory".to_string());
-    let _ = publish_event(ev).await;
+    ev.source = Some(SERVICE.to_string());
+    publish_event(ev);
+}
+
+/// `POST /memory/:twin_id/search`: the twin's items best matching `query`, best first.
+async fn search_memory(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+    Json(req): Json<SearchRequest>,
+) -> Result<Json<SearchResponse>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let query = SearchQuery {
+        text: req.query,
+        top_k: req.top_k.unwrap_or(DEFAULT_TOP_K).clamp(1, MAX_TOP_K),
+        strategy: req.strategy,
+        role: req.role,
+        tag: req.tag,
+    };
+    let strategy = query.strategy;
+    let results = blocking(&state, move |state| {
+        state.index.search(twin_id, &query, || state.store.list(twin_id)).map_err(internal)
+    })
+    .await?;
+    Ok(Json(SearchResponse {
+        strategy,
+        embedder: state.index.embedder(),
+        results,
+    }))
+}
+
+/// `GET /memory/:twin_id/policy`: the capacity policy in effect for the twin.
+async fn get_policy(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+) -> Result<Json<CapacityPolicy>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    Ok(Json(blocking(&state, move |state| capacity_for(state, twin_id)).await?))
+}
+
+/// `PUT /memory/:twin_id/policy`: set the twin's own capacity policy, applying it to the
+/// items already held.
+async fn set_policy(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+    Json(policy): Json<CapacityPolicy>,
+) -> Result<Json<CapacityPolicy>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    policy
+        .validate()
+        .map_err(|e| PagiAxumError::with_status(PagiError::config(e), StatusCode::UNPROCESSABLE_ENTITY))?;
+    let _writing = state.locks.lock(twin_id).await;
+    blocking(&state, move |state| {
+        state.store.set_policy(twin_id, &policy).map_err(internal)?;
+        let departed = enforce_stored(state, twin_id, &policy).unwrap_or_else(|err| {
+            tracing::warn!(%twin_id, error = ?err, "working memory capacity not enforced");
+            0
+        });
+        tracing::info!(%twin_id, ?policy, departed, "working memory capacity policy set");
+        Ok(Json(policy))
+    })
+    .await
+}
+
+/// `GET /memory/:twin_id/playbook`: the `memory` section of the twin's playbook, as last
+/// registered (empty when none was).
+async fn get_playbook(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+) -> Result<Json<PlaybookMemory>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let memory = blocking(&state, move |state| state.store.playbook(twin_id).map_err(internal)).await?;
+    Ok(Json(memory.unwrap_or_default()))
+}
+
+/// `PUT /memory/:twin_id/playbook`: register the `memory` section of the twin's playbook;
+/// `long_term_storage` selects where departing items are consolidated.
+async fn set_playbook(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+    Json(memory): Json<PlaybookMemory>,
+) -> Result<Json<PlaybookMemory>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let consolidation = Consolidation::parse(memory.long_term_storage.as_deref().unwrap_or_default())
+        .and_then(|consolidation| MemorySchema::from_playbook(&memory).map(|_| consolidation))
+        .map_err(|e| PagiAxumError::with_status(PagiError::config(e), StatusCode::UNPROCESSABLE_ENTITY))?;
+    let _writing = state.locks.lock(twin_id).await;
+    blocking(&state, move |state| {
+        state.store.set_playbook(twin_id, &memory).map_err(internal)?;
+        tracing::info!(%twin_id, ?consolidation, "working memory playbook set");
+        Ok(Json(memory))
+    })
+    .await
+}
+
+/// `GET /memory/:twin_id/long_term/:kind?since=&until=&role=&tag=&cursor=&limit=`: the newest
+/// matching `episodic` or `semantic` records, oldest first; `since`/`until` apply to when a
+/// record was last seen.
+async fn get_long_term(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path((twin_id, kind)): Path<(Uuid, String)>,
+    Query(q): Query<ListQuery>,
+) -> Result<Json<LongTermPage>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let kind = record_kind(&kind)?;
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE);
+    let query = ItemQuery {
+        since: parse_ts("since", q.since.as_deref())?,
+        until: parse_ts("until", q.until.as_deref())?,
+        role: q.role,
+        tag: q.tag,
+        before: q.cursor,
+        limit: Some(limit),
+    };
+    let page = blocking(&state, move |state| state.store.records(twin_id, kind, &query).map_err(internal)).await?;
+    let next_cursor = if page.len() == limit {
+        page.first().map(|(seq, _)| *seq)
+    } else {
+        None
+    };
+    Ok(Json(LongTermPage {
+        records: page.into_iter().map(|(_, record)| record).collect(),
+        next_cursor,
+    }))
+}
+
+async fn delete_long_term(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path((twin_id, kind, record_id)): Path<(Uuid, String, Uuid)>,
+) -> Result<StatusCode, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    record_kind(&kind)?;
+    let deleted = {
+        let _writing = state.locks.lock(twin_id).await;
+        blocking(&state, move |state| state.store.delete_records(twin_id, &[record_id]).map_err(internal)).await?
+    };
+    if deleted == 0 {
+        return Err(PagiAxumError::with_status(
+            PagiError::config(format!("long-term record {record_id} not found")),
+            StatusCode::NOT_FOUND,
+        ));
+    }
+    tracing::info!(%twin_id, %record_id, "long-term memory record deleted");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /memory/:twin_id/schema`: the schema appended items are checked against.
+async fn get_schema(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+) -> Result<Json<SchemaResponse>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    match blocking(&state, move |state| schema_for(state, twin_id)).await? {
+        Some((origin, schema)) => Ok(Json(SchemaResponse { origin, schema })),
+        None => Err(no_schema(twin_id)),
+    }
+}
+
+/// `PUT /memory/:twin_id/schema`: register a schema for the twin, taking precedence over its
+/// playbook's `memory.schema`. Items already held are not re-checked.
+async fn set_schema(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+    Json(schema): Json<MemorySchema>,
+) -> Result<Json<MemorySchema>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    schema
+        .validate()
+        .map_err(|e| PagiAxumError::with_status(PagiError::config(e), StatusCode::UNPROCESSABLE_ENTITY))?;
+    let _writing = state.locks.lock(twin_id).await;
+    blocking(&state, move |state| {
+        state.store.set_schema(twin_id, &schema).map_err(internal)?;
+        tracing::info!(%twin_id, ?schema, "working memory schema registered");
+        Ok(Json(schema))
+    })
+    .await
+}
+
+/// `DELETE /memory/:twin_id/schema`: drop the registered schema (the playbook's applies again).
+async fn clear_schema(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+) -> Result<StatusCode, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let cleared = {
+        let _writing = state.locks.lock(twin_id).await;
+        blocking(&state, move |state| state.store.clear_schema(twin_id).map_err(internal)).await?
+    };
+    if !cleared {
+        return Err(no_schema(twin_id));
+    }
+    tracing::info!(%twin_id, "working memory schema cleared");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /memory/:twin_id/quarantine?cursor=&limit=`: the newest quarantined items, oldest first.
+async fn get_quarantine(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path(twin_id): Path<Uuid>,
+    Query(q): Query<PageQuery>,
+) -> Result<Json<QuarantinePage>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let limit = q.limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE);
+    let cursor = q.cursor;
+    let page = blocking(&state, move |state| state.store.quarantined(twin_id, cursor, limit).map_err(internal)).await?;
+    let next_cursor = if page.len() == limit {
+        page.first().map(|(seq, _)| *seq)
+    } else {
+        None
+    };
+    Ok(Json(QuarantinePage {
+        items: page.into_iter().map(|(_, entry)| entry).collect(),
+        next_cursor,
+    }))
+}
+
+async fn discard_quarantined(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path((twin_id, item_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let discarded = {
+        let _writing = state.locks.lock(twin_id).await;
+        blocking(&state, move |state| state.store.take_quarantined(twin_id, item_id).map_err(internal)).await?
+    };
+    if discarded.is_none() {
+        return Err(item_not_found(item_id));
+    }
+    tracing::info!(%twin_id, %item_id, "quarantined working memory item discarded");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /memory/:twin_id/quarantine/:item_id/release`: append a quarantined item as it is,
+/// without checking it against the schema again.
+async fn release_quarantined(
+    State(state): State<AppState>,
+    caller: Caller,
+    Path((twin_id, item_id)): Path<(Uuid, Uuid)>,
+) -> Result<Json<MemoryItem>, PagiAxumError> {
+    caller.check_twin(twin_id)?;
+    let _writing = state.locks.lock(twin_id).await;
+    blocking(&state, move |state| {
+        let Some(entry) = state.store.take_quarantined(twin_id, item_id).map_err(internal)? else {
+            return Err(item_not_found(item_id));
+        };
+        if let Err(err) = append_item(state, twin_id, &entry.item) {
+            let keep = capacity_for(state, twin_id)?.max_quarantined;
+            state.store.quarantine(twin_id, &entry, keep).map_err(internal)?;
+            return Err(err);
+        }
+        tracing::info!(%twin_id, %item_id, "quarantined working memory item released");
+        Ok(Json(entry.item))
+    })
+    .await
+}
+
+/// `POST /events`: purge a twin's items on `twin_deleted`, answering with the
+/// [`PurgeReport`]. Other events are ignored (`204`).
+async fn handle_event(
+    State(state): State<AppState>,
+    caller: Caller,
+    Json(ev): Json<EventEnvelope>,
+) -> Result<Response, PagiAxumError> {
+    caller.check_not_twin()?;
+    let Some(deletion) = TwinDeletion::from_event(&ev) else {
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    };
+    let deletion = state.deletions.confirm(&deletion).await?;
+    let twin_id = deletion.twin_id;
+    let removed = {
+        let _writing = state.locks.lock(twin_id).await;
+        blocking(&state, move |state| state.store.remove(twin_id).map_err(internal)).await?
+    };
+    state.index.invalidate(deletion.twin_id);
+    let report = PurgeReport::new(SERVICE, deletion.twin_id, PurgeTrigger::TwinDeleted)
+        .with("items", removed.items as u64)
+        .with("long_term", removed.long_term as u64)
+        .with("quarantined", removed.quarantined as u64)
+        .with("outbox_events", Outbox::global().purge_twin(deletion.twin_id).await? as u64);
+    tracing::info!(twin_id = %deletion.twin_id, ?removed, "working memory purged for deleted twin");
+    publish_event(report.event());
+    Ok(Json(report).into_response())
+}
+
+/// Drop items stored or quarantined before `cutoff` (see [`Retention`]), consolidating
+/// expired items into the long-term tier.
+async fn sweep_expired(state: &AppState, cutoff: OffsetDateTime) {
+    let swept = blocking(state, move |state| {
+        let quarantined = state.store.expire_quarantine(cutoff).map_err(internal)?;
+        Ok((state.store.twins().map_err(internal)?, quarantined))
+    })
+    .await;
+    let (twins, mut quarantined) = match swept {
+        Ok(swept) => swept,
+        Err(err) => {
+            tracing::error!(error = ?err, "working memory retention sweep failed");
+            return;
+        }
+    };
+    for twin_id in twins {
+        let expired = {
+            let _writing = state.locks.lock(twin_id).await;
+            blocking(state, move |state| {
+                let items = state.store.expired(twin_id, cutoff).map_err(internal)?;
+                if items.is_empty() {
+                    return Ok(items);
+                }
+                // Kept until consolidated; the next sweep retries.
+                consolidate(state, twin_id, &items)?;
+                let ids: Vec<Uuid> = items.iter().map(|item| item.id).collect();
+                state.store.delete_items(twin_id, &ids).map_err(internal)?;
+                state.index.invalidate(twin_id);
+                Ok(items)
+            })
+            .await
+        };
+        let items = match expired {
+            Ok(items) if items.is_empty() => continue,
+            Ok(items) => items,
+            Err(err) => {
+                tracing::error!(%twin_id, error = ?err, "working memory retention sweep failed");
+                continue;
+            }
+        };
+        let held = quarantined.remove(&twin_id).unwrap_or_default();
+        tracing::info!(%twin_id, items = items.len(), quarantined = held, "expired working memory items removed");
+        publish_event(
+            PurgeReport::new(SERVICE, twin_id, PurgeTrigger::Retention)
+                .with("items", items.len() as u64)
+                .with("quarantined", held)
+                .event(),
+        );
+    }
+    for (twin_id, held) in quarantined {
+        tracing::info!(%twin_id, quarantined = held, "expired quarantined working memory items removed");
+        publish_event(PurgeReport::new(SERVICE, twin_id, PurgeTrigger::Retention).with("quarantined", held).event());
+    }
+}
+
+/// Forget unpinned items whose importance fell below `threshold`, consolidating them into the
+/// long-term tier.
+async fn forget(state: &AppState, threshold: f32) {
+    let twins = match blocking(state, |state| state.store.twins().map_err(internal)).await {
+        Ok(twins) => twins,
+        Err(err) => {
+            tracing::error!(error = ?err, "working memory forgetting sweep failed");
+            return;
+        }
+    };
+    for twin_id in twins {
+        let forgotten = {
+            let _writing = state.locks.lock(twin_id).await;
+            blocking(state, move |state| {
+                let now = OffsetDateTime::now_utc();
+                let forgotten: Vec<MemoryItem> = state
+                    .store
+                    .list(twin_id)
+                    .map_err(internal)?
+                    .into_iter()
+                    .filter(|item| state.importance.score(item, now) < threshold)
+                    .collect();
+                if forgotten.is_empty() {
+                    return Ok(0);
+                }
+                // Kept until consolidated; the next sweep retries.
+                consolidate(state, twin_id, &forgotten)?;
+                let ids: Vec<Uuid> = forgotten.iter().map(|item| item.id).collect();
+                state.store.delete_items(twin_id, &ids).map_err(internal)?;
+                for id in ids {
+                    state.index.remove_item(twin_id, id);
+                }
+                Ok(forgotten.len())
+            })
+            .await
+        };
+        let forgotten = match forgotten {
+            Ok(0) => continue,
+            Ok(forgotten) => forgotten,
+            Err(err) => {
+                tracing::error!(%twin_id, error = ?err, "working memory forgetting sweep failed");
+                continue;
+            }
+        };
+        tracing::info!(%twin_id, items = forgotten, "unimportant working memory items forgotten");
+        publish_event(
+            PurgeReport::new(SERVICE, twin_id, PurgeTrigger::Retention)
+                .with("forgotten", forgotten as u64)
+                .event(),
+        );
+    }
+}
+
+/// Drop long-term records last seen before `cutoff`. Each expiry is a single delete, so it
+/// does not take the twins' write locks; a record consolidated into concurrently is written
+/// back as seen now.
+async fn sweep_expired_records(state: &AppState, cutoff: OffsetDateTime) {
+    let expired = blocking(state, move |state| state.store.expire_records(cutoff).map_err(internal)).await;
+    let expired = match expired {
+        Ok(expired) => expired,
+        Err(err) => {
+            tracing::error!(error = ?err, "long-term memory retention sweep failed");
+            return;
+        }
+    };
+    for (twin_id, records) in expired {
+        tracing::info!(%twin_id, records, "expired long-term memory records removed");
+        publish_event(PurgeReport::new(SERVICE, twin_id, PurgeTrigger::Retention).with("long_term", records).event());
+    }
+}
+
+/// Run store work on the blocking pool: the SQLite backend does synchronous I/O.
+async fn blocking<T: Send + 'static>(
+    state: &AppState,
+    f: impl FnOnce(&AppState) -> Result<T, PagiAxumError> + Send + 'static,
+) -> Result<T, PagiAxumError> {
+    let state = state.clone();
+    tokio::task::spawn_blocking(move || f(&state))
+        .await
+        .map_err(|e| internal(e.to_string()))?
+}
+
+/// Consolidate items that left the twin's working memory, as its playbook (or the node
+/// default) chooses. Call with the twin's write lock held, off the async runtime.
+fn consolidate(state: &AppState, twin_id: Uuid, departed: &[MemoryItem]) -> Result<(), PagiAxumError> {
+    if departed.is_empty() {
+        return Ok(());
+    }
+    let chosen = state.store.playbook(twin_id).map_err(internal)?.and_then(|memory| memory.long_term_storage);
+    let policy = match chosen.as_deref().map(Consolidation::parse) {
+        Some(Ok(policy)) => policy,
+        Some(Err(err)) => {
+            tracing::warn!(%twin_id, error = %err, "ignoring playbook long_term_storage");
+            state.consolidation
+        }
+        None => state.consolidation,
+    };
+    state.consolidator.consolidate(twin_id, policy, departed).map_err(internal)
+}
+
+/// The twin's own capacity policy, or the node default.
+fn capacity_for(state: &AppState, twin_id: Uuid) -> Result<CapacityPolicy, PagiAxumError> {
+    let policy = state.store.policy(twin_id).map_err(internal)?;
+    Ok(policy.unwrap_or_else(|| state.capacity.as_ref().clone()))
+}
+
+fn parse_ts(field: &str, raw: Option<&str>) -> Result<Option<OffsetDateTime>, PagiAxumError> {
+    let Some(raw) = raw else {
+        return Ok(None);
+    };
+    OffsetDateTime::parse(raw, &Rfc3339).map(Some).map_err(|e| {
+        PagiAxumError::with_status(
+            PagiError::config(format!("{field}: expected RFC 3339 timestamp ({e})")),
+            StatusCode::BAD_REQUEST,
+        )
+    })
+}
+
+/// The twin's registered schema, or the one from its playbook.
+fn schema_for(state: &AppState, twin_id: Uuid) -> Result<Option<(&'static str, MemorySchema)>, PagiAxumError> {
+    if let Some(schema) = state.store.schema(twin_id).map_err(internal)? {
+        return Ok(Some(("registered", schema)));
+    }
+    let Some(memory) = state.store.playbook(twin_id).map_err(internal)? else {
+        return Ok(None);
+    };
+    match MemorySchema::from_playbook(&memory) {
+        Ok(schema) => Ok(schema.map(|schema| ("playbook", schema))),
+        Err(err) => {
+            tracing::warn!(%twin_id, error = %err, "ignoring playbook memory schema");
+            Ok(None)
+        }
+    }
+}
+
+fn publish_rejected(twin_id: Uuid, item: &MemoryItem, violations: &[String], action: OnViolation) {
+    tracing::warn!(%twin_id, item_id = %item.id, ?violations, "working memory item does not match the schema");
+    let mut ev = EventEnvelope::new_core(
+        twin_id,
+        CoreEvent::WorkingMemoryRejected {
+            item: serde_json::to_value(item).unwrap_or_default(),
+            violations: violations.to_vec(),
+            action: action.as_str().to_string(),
+        },
+    );
+    ev.source = Some(SERVICE.to_string());
+    publish_event(ev);
+}
+
+fn bad_request(message: String) -> PagiAxumError {
+    PagiAxumError::with_status(PagiError::config(message), StatusCode::BAD_REQUEST)
+}
+
+fn schema_violation(violations: &[String]) -> PagiAxumError {
+    PagiAxumError::with_status(
+        PagiError::config(format!("item does not match the twin's memory schema: {}", violations.join("; "))),
+        StatusCode::UNPROCESSABLE_ENTITY,
+    )
+}
+
+fn no_schema(twin_id: Uuid) -> PagiAxumError {
+    PagiAxumError::with_status(
+        PagiError::config(format!("no memory schema registered for twin {twin_id}")),
+        StatusCode::NOT_FOUND,
+    )
+}
+
+fn record_kind(raw: &str) -> Result<RecordKind, PagiAxumError> {
+    match raw {
+        "episodic" => Ok(RecordKind::Episodic),
+        "semantic" => Ok(RecordKind::Semantic),
+        other => Err(PagiAxumError::with_status(
+            PagiError::config(format!("unknown long-term store '{other}' (expected episodic|semantic)")),
+            StatusCode::NOT_FOUND,
+        )),
+    }
+}
+
+fn item_not_found(item_id: Uuid) -> PagiAxumError {
+    PagiAxumError::with_status(
+        PagiError::config(format!("memory item {item_id} not found")),
+        StatusCode::NOT_FOUND,
+    )
+}
+
+fn too_large(policy: &CapacityPolicy) -> PagiAxumError {
+    PagiAxumError::with_status(
+        PagiError::config(format!(
+            "item exceeds the twin's working memory limit of {} bytes",
+            policy.max_bytes.unwrap_or_default()
+        )),
+        StatusCode::PAYLOAD_TOO_LARGE,
+    )
+}
+
+fn too_many_pins(policy: &CapacityPolicy) -> PagiAxumError {
+    PagiAxumError::with_status(
+        PagiError::config(format!(
+            "pinned items may take at most {}% of the twin's working memory limits",
+            policy.max_pinned
+        )),
+        StatusCode::CONFLICT,
+    )
+}
 
-    (StatusCode::OK, Json(entry.clone()))
+fn internal(err: String) -> PagiAxumError {
+    tracing::error!(error = %err, "working memory store error");
+    PagiError::Unknown(err).into()
 }
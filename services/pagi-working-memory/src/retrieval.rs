@@ -0,0 +1,552 @@
+//! Search over a twin's working memory: BM25 over item terms combined with cosine similarity
+//! of item embeddings.
+//!
+//! Each twin's index is built from the store on its first search and kept up to date by the
+//! write paths (see [`MemoryIndex::upsert`], [`MemoryIndex::remove_item`]); writes that
+//! rearrange many items (eviction, summaries, replacement, retention) drop it so the next
+//! search rebuilds it. At most `WORKING_MEMORY_INDEXED_TWINS` indexes (default: 1000) are
+//! kept; the least recently searched or written is dropped to make room.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::item::MemoryItem;
+
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+/// Share of the hybrid score taken from BM25 (the rest from embeddings).
+const HYBRID_LEXICAL_WEIGHT: f32 = 0.5;
+
+const DEFAULT_INDEXED_TWINS: usize = 1000;
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "do", "for", "from", "has", "have", "i", "if", "in", "is",
+    "it", "me", "my", "of", "on", "or", "so", "that", "the", "this", "to", "was", "we", "what", "with", "you", "your",
+];
+
+/// Turns text into a vector; vectors are compared by cosine similarity.
+///
+/// Providers (`WORKING_MEMORY_EMBEDDER`):
+/// - `hashing` (default): [`HashingEmbedder`], pure Rust and offline
+pub trait Embedder: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// L2-normalized (or all-zero for text without features).
+    fn embed(&self, text: &str) -> Vec<f32>;
+}
+
+pub fn embedder_from_env() -> Result<Box<dyn Embedder>, String> {
+    let provider = std::env::var("WORKING_MEMORY_EMBEDDER").unwrap_or_else(|_| "hashing".to_string());
+    match provider.to_lowercase().as_str() {
+        "hashing" => {
+            let dims = match std::env::var("WORKING_MEMORY_EMBEDDING_DIMS") {
+                Ok(raw) if !raw.trim().is_empty() => raw
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|d| *d > 0)
+                    .ok_or_else(|| format!("WORKING_MEMORY_EMBEDDING_DIMS must be a positive number, got {raw:?}"))?,
+                _ => 256,
+            };
+            Ok(Box::new(HashingEmbedder { dims }))
+        }
+        other => Err(format!("unknown WORKING_MEMORY_EMBEDDER '{other}' (expected hashing)")),
+    }
+}
+
+/// Signed feature hashing of words and their character trigrams into `dims` buckets. The
+/// trigrams let related word forms ("deploy", "deployment") land close together.
+pub struct HashingEmbedder {
+    pub dims: usize,
+}
+
+impl Embedder for HashingEmbedder {
+    fn name(&self) -> &'static str {
+        "hashing"
+    }
+
+    fn embed(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0f32; self.dims];
+        let mut add = |feature: &str, weight: f32| {
+            let hash = fnv1a(feature.as_bytes());
+            let sign = if hash >> 63 == 0 { 1.0 } else { -1.0 };
+            vector[(hash % self.dims as u64) as usize] += sign * weight;
+        };
+        for token in tokenize(text) {
+            add(&token, 1.0);
+            let padded: Vec<char> = format!("#{token}#").chars().collect();
+            for gram in padded.windows(3) {
+                add(&gram.iter().collect::<String>(), 0.5);
+            }
+        }
+        let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|v| *v /= norm);
+        }
+        vector
+    }
+}
+
+/// FNV-1a, so vectors do not depend on the process or toolchain.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+/// Lowercased alphanumeric words of two or more characters, without stopwords.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 2)
+        .map(str::to_lowercase)
+        .filter(|word| !STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// How results are ranked. The aliases are the names playbooks use for
+/// `memory.retrieval_strategy`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// BM25 only.
+    #[serde(alias = "bm25", alias = "keyword")]
+    Lexical,
+    /// Embedding similarity only.
+    #[serde(alias = "embedding", alias = "vector")]
+    Semantic,
+    #[default]
+    Hybrid,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    pub text: String,
+    pub top_k: usize,
+    pub strategy: Strategy,
+    pub role: Option<String>,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Hit {
+    pub item: MemoryItem,
+    /// What results are ranked by: BM25 for `lexical`, cosine for `semantic`, and for `hybrid`
+    /// the weighted sum of both, BM25 scaled to the best match.
+    pub score: f32,
+    pub lexical: f32,
+    pub semantic: f32,
+}
+
+struct Doc {
+    item: MemoryItem,
+    terms: HashMap<String, u32>,
+    len: u32,
+    vector: Vec<f32>,
+}
+
+#[derive(Default)]
+struct TwinIndex {
+    docs: HashMap<Uuid, Doc>,
+    /// Documents containing each term.
+    df: HashMap<String, u32>,
+    total_len: u64,
+}
+
+impl TwinIndex {
+    fn insert(&mut self, doc: Doc) {
+        self.remove(doc.item.id);
+        for term in doc.terms.keys() {
+            *self.df.entry(term.clone()).or_default() += 1;
+        }
+        self.total_len += doc.len as u64;
+        self.docs.insert(doc.item.id, doc);
+    }
+
+    fn remove(&mut self, item_id: Uuid) {
+        let Some(doc) = self.docs.remove(&item_id) else {
+            return;
+        };
+        for term in doc.terms.keys() {
+            if let Some(df) = self.df.get_mut(term) {
+                *df -= 1;
+                if *df == 0 {
+                    self.df.remove(term);
+                }
+            }
+        }
+        self.total_len -= doc.len as u64;
+    }
+
+    fn bm25(&self, doc: &Doc, query_terms: &[String]) -> f32 {
+        let n = self.docs.len() as f32;
+        let avg_len = (self.total_len as f32 / n).max(1.0);
+        query_terms
+            .iter()
+            .filter_map(|term| {
+                let tf = *doc.terms.get(term)? as f32;
+                let df = *self.df.get(term)? as f32;
+                let idf = (1.0 + (n - df + 0.5) / (df + 0.5)).ln();
+                Some(idf * tf * (BM25_K1 + 1.0) / (tf + BM25_K1 * (1.0 - BM25_B + BM25_B * doc.len as f32 / avg_len)))
+            })
+            .sum()
+    }
+}
+
+pub struct MemoryIndex {
+    embedder: Box<dyn Embedder>,
+    max_twins: usize,
+    twins: Mutex<Twins>,
+}
+
+#[derive(Default)]
+struct Twins {
+    indexes: HashMap<Uuid, Cached>,
+    /// Twins whose index is being built outside the lock, counting writes to them meanwhile.
+    building: HashMap<Uuid, Build>,
+    tick: u64,
+}
+
+struct Cached {
+    index: Arc<Mutex<TwinIndex>>,
+    used: u64,
+}
+
+#[derive(Default)]
+struct Build {
+    builders: usize,
+    writes: u64,
+}
+
+impl Twins {
+    fn touch(&mut self, twin_id: Uuid) -> Option<Arc<Mutex<TwinIndex>>> {
+        self.tick += 1;
+        let cached = self.indexes.get_mut(&twin_id)?;
+        cached.used = self.tick;
+        Some(cached.index.clone())
+    }
+
+    /// A write the twin's cached index cannot follow, or that lands while it is being built.
+    fn written(&mut self, twin_id: Uuid) {
+        if let Some(build) = self.building.get_mut(&twin_id) {
+            build.writes += 1;
+        }
+    }
+
+    fn finish_build(&mut self, twin_id: Uuid) -> u64 {
+        let Some(build) = self.building.get_mut(&twin_id) else {
+            return 0;
+        };
+        build.builders -= 1;
+        let writes = build.writes;
+        if build.builders == 0 {
+            self.building.remove(&twin_id);
+        }
+        writes
+    }
+}
+
+impl MemoryIndex {
+    pub fn new(embedder: Box<dyn Embedder>, max_twins: usize) -> Self {
+        Self {
+            embedder,
+            max_twins: max_twins.max(1),
+            twins: Mutex::new(Twins::default()),
+        }
+    }
+
+    /// The embedder from [`embedder_from_env`], keeping `WORKING_MEMORY_INDEXED_TWINS` indexes.
+    pub fn from_env() -> Result<Self, String> {
+        let max_twins = match std::env::var("WORKING_MEMORY_INDEXED_TWINS") {
+            Ok(raw) if !raw.trim().is_empty() => raw
+                .trim()
+                .parse()
+                .ok()
+                .filter(|n| *n > 0)
+                .ok_or_else(|| format!("WORKING_MEMORY_INDEXED_TWINS must be a positive number, got {raw:?}"))?,
+            _ => DEFAULT_INDEXED_TWINS,
+        };
+        Ok(Self::new(embedder_from_env()?, max_twins))
+    }
+
+    pub fn embedder(&self) -> &'static str {
+        self.embedder.name()
+    }
+
+    pub fn max_twins(&self) -> usize {
+        self.max_twins
+    }
+
+    fn twins(&self) -> MutexGuard<'_, Twins> {
+        self.twins.lock().unwrap_or_else(|p| p.into_inner())
+    }
+
+    fn doc(&self, item: &MemoryItem) -> Doc {
+        let tokens = tokenize(&item.content);
+        let mut terms: HashMap<String, u32> = HashMap::new();
+        for token in &tokens {
+            *terms.entry(token.clone()).or_default() += 1;
+        }
+        Doc {
+            item: item.clone(),
+            terms,
+            len: tokens.len() as u32,
+            vector: self.embedder.embed(&item.content),
+        }
+    }
+
+    /// Index a new or edited item. Twins without an index are left to be built on search.
+    pub fn upsert(&self, twin_id: Uuid, item: &MemoryItem) {
+        let doc = self.doc(item);
+        let cached = {
+            let mut twins = self.twins();
+            twins.written(twin_id);
+            twins.touch(twin_id)
+        };
+        if let Some(index) = cached {
+            lock(&index).insert(doc);
+        }
+    }
+
+    pub fn remove_item(&self, twin_id: Uuid, item_id: Uuid) {
+        let cached = {
+            let mut twins = self.twins();
+            twins.written(twin_id);
+            twins.indexes.get(&twin_id).map(|cached| cached.index.clone())
+        };
+        if let Some(index) = cached {
+            lock(&index).remove(item_id);
+        }
+    }
+
+    /// Drop the twin's index; it is rebuilt from the store on the next search.
+    pub fn invalidate(&self, twin_id: Uuid) {
+        let mut twins = self.twins();
+        twins.written(twin_id);
+        twins.indexes.remove(&twin_id);
+    }
+
+    /// The twin's index, built from `load` when it has none. Loading and embedding happen
+    /// without holding the lock; a build that raced a write to the twin serves this search
+    /// but is not kept.
+    fn index(
+        &self,
+        twin_id: Uuid,
+        load: impl FnOnce() -> Result<Vec<MemoryItem>, String>,
+    ) -> Result<Arc<Mutex<TwinIndex>>, String> {
+        let started = {
+            let mut twins = self.twins();
+            if let Some(index) = twins.touch(twin_id) {
+                return Ok(index);
+            }
+            let build = twins.building.entry(twin_id).or_default();
+            build.builders += 1;
+            build.writes
+        };
+        let built = load().map(|items| {
+            let mut index = TwinIndex::default();
+            for item in &items {
+                index.insert(self.doc(item));
+            }
+            Arc::new(Mutex::new(index))
+        });
+
+        let mut twins = self.twins();
+        let writes = twins.finish_build(twin_id);
+        let built = built?;
+        if let Some(index) = twins.touch(twin_id) {
+            // Another search finished building first.
+            return Ok(index);
+        }
+        if writes == started {
+            if twins.indexes.len() >= self.max_twins {
+                let lru = twins.indexes.iter().min_by_key(|(_, cached)| cached.used).map(|(id, _)| *id);
+                if let Some(lru) = lru {
+                    twins.indexes.remove(&lru);
+                }
+            }
+            let used = twins.tick;
+            twins.indexes.insert(twin_id, Cached { index: built.clone(), used });
+        }
+        Ok(built)
+    }
+
+    /// The `top_k` best matches, best first. `load` supplies the twin's items when its index
+    /// has to be built.
+    pub fn search(
+        &self,
+        twin_id: Uuid,
+        query: &SearchQuery,
+        load: impl FnOnce() -> Result<Vec<MemoryItem>, String>,
+    ) -> Result<Vec<Hit>, String> {
+        let query_terms = tokenize(&query.text);
+        let query_vector = self.embedder.embed(&query.text);
+        let index = self.index(twin_id, load)?;
+        let index = lock(&index);
+        let candidates = index.docs.values().filter(|doc| {
+            query.role.as_ref().is_none_or(|role| &doc.item.role == role)
+                && query.tag.as_ref().is_none_or(|tag| doc.item.tags.contains(tag))
+        });
+        let mut hits: Vec<Hit> = candidates
+            .map(|doc| Hit {
+                item: doc.item.clone(),
+                score: 0.0,
+                lexical: index.bm25(doc, &query_terms),
+                semantic: cosine(&query_vector, &doc.vector).max(0.0),
+            })
+            .collect();
+        drop(index);
+
+        let best_lexical = hits.iter().map(|hit| hit.lexical).fold(0.0, f32::max);
+        for hit in &mut hits {
+            hit.score = match query.strategy {
+                Strategy::Lexical => hit.lexical,
+                Strategy::Semantic => hit.semantic,
+                Strategy::Hybrid => {
+                    let lexical = if best_lexical > 0.0 { hit.lexical / best_lexical } else { 0.0 };
+                    HYBRID_LEXICAL_WEIGHT * lexical + (1.0 - HYBRID_LEXICAL_WEIGHT) * hit.semantic
+                }
+            };
+        }
+        hits.retain(|hit| hit.score > 0.0);
+        // Best first; equal scores favour newer items.
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(b.item.created_at.cmp(&a.item.created_at)));
+        hits.truncate(query.top_k);
+        Ok(hits)
+    }
+}
+
+fn lock(index: &Mutex<TwinIndex>) -> MutexGuard<'_, TwinIndex> {
+    index.lock().unwrap_or_else(|p| p.into_inner())
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(role: &str, content: &str) -> MemoryItem {
+        serde_json::from_value(json!({"role": role, "content": content})).unwrap()
+    }
+
+    fn index() -> MemoryIndex {
+        MemoryIndex::new(Box::new(HashingEmbedder { dims: 256 }), 10)
+    }
+
+    fn query(text: &str, strategy: Strategy) -> SearchQuery {
+        SearchQuery {
+            text: text.to_string(),
+            top_k: 10,
+            strategy,
+            ..Default::default()
+        }
+    }
+
+    fn found(hits: &[Hit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.item.content.as_str()).collect()
+    }
+
+    #[test]
+    fn tokens_skip_stopwords_and_single_characters() {
+        assert_eq!(tokenize("What is the Deploy-plan for a 2nd try?"), ["deploy", "plan", "2nd", "try"]);
+    }
+
+    #[test]
+    fn embeddings_are_normalized_and_stable() {
+        let embedder = HashingEmbedder { dims: 64 };
+        let vector = embedder.embed("deploy the service");
+        assert!((vector.iter().map(|v| v * v).sum::<f32>() - 1.0).abs() < 1e-5);
+        assert_eq!(vector, embedder.embed("deploy the service"));
+        assert!(embedder.embed("the a").iter().all(|v| *v == 0.0));
+        // Related word forms share trigrams.
+        let related = cosine(&embedder.embed("deploy"), &embedder.embed("deployment"));
+        let unrelated = cosine(&embedder.embed("deploy"), &embedder.embed("banana"));
+        assert!(related > unrelated, "{related} <= {unrelated}");
+    }
+
+    #[test]
+    fn lexical_search_ranks_matching_items() {
+        let index = index();
+        let twin = Uuid::new_v4();
+        let items = vec![
+            item("user", "I like green tea in the morning"),
+            item("user", "The deployment failed on friday"),
+            item("assistant", "Tea and coffee are both fine"),
+        ];
+        let hits = index.search(twin, &query("green tea", Strategy::Lexical), || Ok(items.clone())).unwrap();
+        assert_eq!(found(&hits), ["I like green tea in the morning", "Tea and coffee are both fine"]);
+
+        let mut role = query("tea", Strategy::Hybrid);
+        role.role = Some("assistant".to_string());
+        let hits = index.search(twin, &role, || panic!("index rebuilt")).unwrap();
+        assert_eq!(found(&hits), ["Tea and coffee are both fine"]);
+    }
+
+    #[test]
+    fn the_index_follows_item_changes() {
+        let index = index();
+        let twin = Uuid::new_v4();
+        let tea = item("user", "green tea");
+        index.search(twin, &query("tea", Strategy::Lexical), || Ok(vec![tea.clone()])).unwrap();
+
+        let coffee = item("user", "black coffee");
+        index.upsert(twin, &coffee);
+        let hits = index.search(twin, &query("coffee", Strategy::Lexical), || panic!("index rebuilt")).unwrap();
+        assert_eq!(found(&hits), ["black coffee"]);
+
+        index.remove_item(twin, tea.id);
+        assert!(index.search(twin, &query("tea", Strategy::Lexical), || panic!("index rebuilt")).unwrap().is_empty());
+
+        index.invalidate(twin);
+        let hits = index.search(twin, &query("tea", Strategy::Lexical), || Ok(vec![tea.clone()])).unwrap();
+        assert_eq!(found(&hits), ["green tea"]);
+    }
+
+    #[test]
+    fn builds_racing_a_write_are_not_kept() {
+        let index = index();
+        let twin = Uuid::new_v4();
+        let tea = item("user", "green tea");
+        // The write lands while the items are loaded (and the index lock is not held).
+        let hits = index
+            .search(twin, &query("tea", Strategy::Lexical), || {
+                index.upsert(twin, &item("user", "iced tea"));
+                Ok(vec![tea.clone()])
+            })
+            .unwrap();
+        assert_eq!(found(&hits), ["green tea"]);
+
+        let stored = vec![tea.clone(), item("user", "iced tea")];
+        let hits = index.search(twin, &query("tea", Strategy::Lexical), || Ok(stored.clone())).unwrap();
+        assert_eq!(hits.len(), 2);
+        index.search(twin, &query("tea", Strategy::Lexical), || panic!("index not kept")).unwrap();
+    }
+
+    #[test]
+    fn the_least_recently_used_index_is_dropped() {
+        let index = MemoryIndex::new(Box::new(HashingEmbedder { dims: 64 }), 2);
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let search = |twin: Uuid, load: &dyn Fn() -> Vec<MemoryItem>| {
+            index.search(twin, &query("tea", Strategy::Lexical), || Ok(load())).unwrap();
+        };
+        search(a, &|| vec![item("user", "tea")]);
+        search(b, &|| vec![item("user", "tea")]);
+        search(a, &|| panic!("a rebuilt"));
+        search(c, &|| vec![item("user", "tea")]);
+        search(a, &|| panic!("a rebuilt"));
+        search(c, &|| panic!("c rebuilt"));
+        let rebuilt = std::cell::Cell::new(false);
+        search(b, &|| {
+            rebuilt.set(true);
+            Vec::new()
+        });
+        assert!(rebuilt.get(), "b kept beyond max_twins");
+    }
+}
@@ -0,0 +1,247 @@
+//! Per-twin memory schemas: what appended items must look like.
+//!
+//! A twin's schema is registered with `PUT /memory/:twin_id/schema`, or comes from the
+//! `memory.schema` section of its playbook (registered schemas win):
+//!
+//! ```toml
+//! [memory.schema]
+//! roles = ["user", "assistant", "tool"]
+//! required = ["tags", "metadata.source_url"]
+//! on_violation = "quarantine"
+//!
+//! [memory.schema.fields]
+//! "metadata.source_url" = "string"
+//! "metadata.confidence" = "number"
+//! ```
+//!
+//! Fields are dotted paths into the item, rooted at `role`, `content`, `tags`, `metadata` or
+//! `source`.
+
+use std::collections::BTreeMap;
+
+use pagi_common::swarm::PlaybookMemory;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use time::OffsetDateTime;
+
+use crate::item::MemoryItem;
+
+const ITEM_FIELDS: &[&str] = &["role", "content", "tags", "metadata", "source"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemorySchema {
+    /// Allowed roles (empty: any).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    /// Fields that must be present and not null, empty text or an empty list.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub required: Vec<String>,
+    /// Types of fields, checked when present.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fields: BTreeMap<String, FieldType>,
+    #[serde(default)]
+    pub on_violation: OnViolation,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Array,
+    Object,
+}
+
+/// What happens to an item that does not match the schema.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnViolation {
+    /// The append fails with `422`.
+    #[default]
+    Reject,
+    /// The item is set aside in the twin's quarantine, out of working memory, for review.
+    Quarantine,
+}
+
+impl OnViolation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OnViolation::Reject => "rejected",
+            OnViolation::Quarantine => "quarantined",
+        }
+    }
+}
+
+/// An item held back by its twin's schema.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quarantined {
+    pub item: MemoryItem,
+    pub violations: Vec<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub quarantined_at: OffsetDateTime,
+}
+
+impl MemorySchema {
+    /// The playbook's `memory.schema` section; `None` when it is empty.
+    pub fn from_playbook(memory: &PlaybookMemory) -> Result<Option<Self>, String> {
+        if memory.schema.is_empty() {
+            return Ok(None);
+        }
+        let raw = serde_json::to_value(&memory.schema).map_err(|e| e.to_string())?;
+        let schema: Self = serde_json::from_value(raw).map_err(|e| format!("memory.schema: {e}"))?;
+        schema.validate().map_err(|e| format!("memory.schema: {e}"))?;
+        Ok(Some(schema))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for path in self.required.iter().chain(self.fields.keys()) {
+            let root = path.split('.').next().unwrap_or_default();
+            if !ITEM_FIELDS.contains(&root) || path.split('.').any(str::is_empty) {
+                return Err(format!("unknown field '{path}' (fields start with {})", ITEM_FIELDS.join("|")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Everything wrong with `item`; empty when it matches.
+    pub fn violations(&self, item: &MemoryItem) -> Vec<String> {
+        let mut violations = Vec::new();
+        if !self.roles.is_empty() && !self.roles.contains(&item.role) {
+            violations.push(format!("role '{}' is not one of {}", item.role, self.roles.join(", ")));
+        }
+        let doc = serde_json::to_value(item).unwrap_or_default();
+        for path in &self.required {
+            if lookup(&doc, path).is_none_or(is_blank) {
+                violations.push(format!("{path} is required"));
+            }
+        }
+        for (path, expected) in &self.fields {
+            let Some(value) = lookup(&doc, path).filter(|v| !v.is_null()) else {
+                continue;
+            };
+            if !expected.matches(value) {
+                violations.push(format!("{path} must be {}", expected.describe()));
+            }
+        }
+        violations
+    }
+}
+
+impl FieldType {
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            FieldType::String => value.is_string(),
+            FieldType::Number => value.is_number(),
+            FieldType::Integer => value.is_i64() || value.is_u64(),
+            FieldType::Boolean => value.is_boolean(),
+            FieldType::Array => value.is_array(),
+            FieldType::Object => value.is_object(),
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            FieldType::String => "a string",
+            FieldType::Number => "a number",
+            FieldType::Integer => "an integer",
+            FieldType::Boolean => "a boolean",
+            FieldType::Array => "an array",
+            FieldType::Object => "an object",
+        }
+    }
+}
+
+fn lookup<'a>(doc: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(doc, |value, key| value.get(key))
+}
+
+fn is_blank(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.trim().is_empty(),
+        Value::Array(items) => items.is_empty(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn item(value: Value) -> MemoryItem {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn schema(value: Value) -> MemorySchema {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn matching_items_have_no_violations() {
+        let schema = schema(json!({
+            "roles": ["user"],
+            "required": ["tags", "metadata.source_url"],
+            "fields": {"metadata.source_url": "string", "metadata.confidence": "number"},
+        }));
+        let ok = item(json!({
+            "role": "user",
+            "content": "hi",
+            "tags": ["web"],
+            "metadata": {"source_url": "https://example.com"},
+        }));
+        assert!(schema.violations(&ok).is_empty());
+        assert_eq!(schema.on_violation, OnViolation::Reject);
+    }
+
+    #[test]
+    fn every_violation_is_reported() {
+        let schema = schema(json!({
+            "roles": ["user", "assistant"],
+            "required": ["tags", "metadata.source_url"],
+            "fields": {"metadata.confidence": "number", "metadata.count": "integer"},
+        }));
+        let bad = item(json!({
+            "role": "tool",
+            "content": "hi",
+            "tags": [],
+            "metadata": {"source_url": "  ", "confidence": "high", "count": 1.5},
+        }));
+        assert_eq!(
+            schema.violations(&bad),
+            [
+                "role 'tool' is not one of user, assistant",
+                "tags is required",
+                "metadata.source_url is required",
+                "metadata.confidence must be a number",
+                "metadata.count must be an integer",
+            ]
+        );
+    }
+
+    #[test]
+    fn schemas_only_name_item_fields() {
+        assert!(schema(json!({"required": ["metadata.x", "source.kind"]})).validate().is_ok());
+        assert!(schema(json!({"required": ["owner"]})).validate().is_err());
+        assert!(schema(json!({"fields": {"metadata..x": "string"}})).validate().is_err());
+        assert!(serde_json::from_value::<MemorySchema>(json!({"role": ["user"]})).is_err());
+    }
+
+    #[test]
+    fn playbooks_bring_their_schema() {
+        let memory: PlaybookMemory = serde_json::from_value(json!({
+            "schema": {"roles": ["user"], "on_violation": "quarantine"},
+        }))
+        .unwrap();
+        let schema = MemorySchema::from_playbook(&memory).unwrap().unwrap();
+        assert_eq!(schema.roles, ["user"]);
+        assert_eq!(schema.on_violation, OnViolation::Quarantine);
+
+        assert!(MemorySchema::from_playbook(&PlaybookMemory::default()).unwrap().is_none());
+        let broken: PlaybookMemory = serde_json::from_value(json!({"schema": {"required": ["owner"]}})).unwrap();
+        assert!(MemorySchema::from_playbook(&broken).is_err());
+    }
+}
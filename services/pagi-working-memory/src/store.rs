@@ -0,0 +1,1514 @@
+use pagi_common::swarm::PlaybookMemory;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::Mutex,
+};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    capacity::{item_bytes, CapacityPolicy, Usage},
+    item::MemoryItem,
+    long_term::{LongTermRecord, RecordKind},
+    schema::{MemorySchema, Quarantined},
+};
+
+/// Working memory storage.
+///
+/// Backends (`WORKING_MEMORY_STORE`):
+/// - `sqlite` (default): embedded database at `WORKING_MEMORY_DATA_DIR/memory.db`
+/// - `memory`: lost on restart; for local development and tests
+///
+/// Items are kept per twin in the order they were stored, oldest first, each with a sequence
+/// number that serves as the pagination cursor. Capacity is enforced by the caller (see
+/// [`CapacityPolicy::enforce`]); the store only applies the outcome.
+pub trait MemoryStore: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// The newest `limit` items matching `query`, oldest first, with their sequence numbers.
+    fn query(&self, twin_id: Uuid, query: &ItemQuery) -> Result<Vec<(u64, MemoryItem)>, String>;
+
+    /// All of the twin's items, oldest first.
+    fn list(&self, twin_id: Uuid) -> Result<Vec<MemoryItem>, String> {
+        let items = self.query(twin_id, &ItemQuery::default())?;
+        Ok(items.into_iter().map(|(_, item)| item).collect())
+    }
+
+    fn get(&self, twin_id: Uuid, item_id: Uuid) -> Result<Option<MemoryItem>, String>;
+
+    /// How many items the twin holds and their size, without loading them.
+    fn usage(&self, twin_id: Uuid) -> Result<Usage, String>;
+
+    /// The same for the twin's pinned items.
+    fn pinned_usage(&self, twin_id: Uuid) -> Result<Usage, String>;
+
+    /// Overwrite the stored item with the same id, keeping its place; `false` if there is none.
+    fn update(&self, twin_id: Uuid, item: &MemoryItem) -> Result<bool, String>;
+
+    /// `false` if there is no such item.
+    fn delete(&self, twin_id: Uuid, item_id: Uuid) -> Result<bool, String>;
+
+    /// Delete these items in one transaction; returns how many existed.
+    fn delete_items(&self, twin_id: Uuid, item_ids: &[Uuid]) -> Result<usize, String>;
+
+    /// Store `item` as the newest and drop the `evict` items, atomically.
+    fn append(&self, twin_id: Uuid, item: &MemoryItem, evict: &[Uuid]) -> Result<(), String>;
+
+    /// Replace all of the twin's items.
+    fn replace(&self, twin_id: Uuid, items: &[MemoryItem]) -> Result<(), String>;
+
+    /// Remove everything held for the twin, including its long-term records, quarantine and
+    /// settings.
+    fn remove(&self, twin_id: Uuid) -> Result<Removed, String>;
+
+    /// The twin's items stored before `cutoff`, oldest first.
+    fn expired(&self, twin_id: Uuid, cutoff: OffsetDateTime) -> Result<Vec<MemoryItem>, String>;
+
+    /// The twin's own capacity policy, if it has one.
+    fn policy(&self, twin_id: Uuid) -> Result<Option<CapacityPolicy>, String>;
+
+    fn set_policy(&self, twin_id: Uuid, policy: &CapacityPolicy) -> Result<(), String>;
+
+    /// The `memory` section of the twin's playbook, if one was registered.
+    fn playbook(&self, twin_id: Uuid) -> Result<Option<PlaybookMemory>, String>;
+
+    fn set_playbook(&self, twin_id: Uuid, memory: &PlaybookMemory) -> Result<(), String>;
+
+    /// The newest `limit` long-term records of `kind` matching `query` (`since`/`until` apply
+    /// to `last_seen`), oldest first, with their sequence numbers.
+    fn records(&self, twin_id: Uuid, kind: RecordKind, query: &ItemQuery) -> Result<Vec<(u64, LongTermRecord)>, String>;
+
+    /// The episodic record with this [`LongTermRecord::dedup_key`], among the staged or the
+    /// unstaged ones.
+    fn find_episode(&self, twin_id: Uuid, dedup_key: &str, staged: bool) -> Result<Option<LongTermRecord>, String>;
+
+    /// The oldest `limit` episodes staged for a semantic summary, oldest first.
+    fn staged(&self, twin_id: Uuid, limit: usize) -> Result<Vec<LongTermRecord>, String>;
+
+    /// The records with these ids, as stored now; missing ones are left out.
+    fn get_records(&self, twin_id: Uuid, record_ids: &[Uuid]) -> Result<Vec<LongTermRecord>, String>;
+
+    /// Store a new record, or overwrite the one with the same id in place.
+    fn put_record(&self, twin_id: Uuid, record: &LongTermRecord) -> Result<(), String>;
+
+    /// Returns how many of the records existed.
+    fn delete_records(&self, twin_id: Uuid, record_ids: &[Uuid]) -> Result<usize, String>;
+
+    /// Remove long-term records last seen before `cutoff`; returns the number removed per twin.
+    fn expire_records(&self, cutoff: OffsetDateTime) -> Result<BTreeMap<Uuid, u64>, String>;
+
+    /// The schema registered for the twin, if any.
+    fn schema(&self, twin_id: Uuid) -> Result<Option<MemorySchema>, String>;
+
+    fn set_schema(&self, twin_id: Uuid, schema: &MemorySchema) -> Result<(), String>;
+
+    /// `false` if the twin had no registered schema.
+    fn clear_schema(&self, twin_id: Uuid) -> Result<bool, String>;
+
+    /// Hold an item back for review as the newest, replacing any quarantined item with the same
+    /// id, and drop the twin's oldest entries beyond the newest `keep`; returns how many were dropped.
+    fn quarantine(&self, twin_id: Uuid, entry: &Quarantined, keep: usize) -> Result<usize, String>;
+
+    /// The newest `limit` quarantined items older than the `before` cursor, oldest first, with
+    /// their sequence numbers.
+    fn quarantined(&self, twin_id: Uuid, before: Option<u64>, limit: usize) -> Result<Vec<(u64, Quarantined)>, String>;
+
+    /// Remove and return a quarantined item.
+    fn take_quarantined(&self, twin_id: Uuid, item_id: Uuid) -> Result<Option<Quarantined>, String>;
+
+    /// Remove items quarantined before `cutoff`; returns the number removed per twin.
+    fn expire_quarantine(&self, cutoff: OffsetDateTime) -> Result<BTreeMap<Uuid, u64>, String>;
+
+    /// Twins with items.
+    fn twins(&self) -> Result<Vec<Uuid>, String>;
+
+    /// Twins with items, and the items held.
+    fn stats(&self) -> Result<(usize, usize), String>;
+}
+
+/// What [`MemoryStore::remove`] removed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Removed {
+    pub items: usize,
+    pub long_term: usize,
+    pub quarantined: usize,
+}
+
+/// Filters for [`MemoryStore::query`]. `since` is inclusive, `until` exclusive.
+#[derive(Debug, Clone, Default)]
+pub struct ItemQuery {
+    pub since: Option<OffsetDateTime>,
+    pub until: Option<OffsetDateTime>,
+    pub role: Option<String>,
+    pub tag: Option<String>,
+    /// Only items stored before this sequence number (the previous page's cursor).
+    pub before: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+impl ItemQuery {
+    fn matches(&self, seq: u64, item: &MemoryItem) -> bool {
+        self.matches_parts(seq, item.created_at, Some(&item.role), &item.tags)
+    }
+
+    fn matches_record(&self, seq: u64, record: &LongTermRecord) -> bool {
+        self.matches_parts(seq, record.last_seen, record.role.as_ref(), &record.tags)
+    }
+
+    fn matches_parts(&self, seq: u64, at: OffsetDateTime, role: Option<&String>, tags: &[String]) -> bool {
+        self.since.is_none_or(|since| at >= since)
+            && self.until.is_none_or(|until| at < until)
+            && self.role.as_ref().is_none_or(|wanted| role == Some(wanted))
+            && self.tag.as_ref().is_none_or(|tag| tags.contains(tag))
+            && self.before.is_none_or(|before| seq < before)
+    }
+}
+
+pub fn from_env() -> Result<Box<dyn MemoryStore>, String> {
+    let backend = std::env::var("WORKING_MEMORY_STORE").unwrap_or_else(|_| "sqlite".to_string());
+    match backend.to_lowercase().as_str() {
+        "sqlite" => {
+            let dir = std::env::var("WORKING_MEMORY_DATA_DIR").unwrap_or_else(|_| "/data/working-memory".to_string());
+            let dir = Path::new(&dir);
+            std::fs::create_dir_all(dir).map_err(|e| format!("working memory data dir {}: {e}", dir.display()))?;
+            Ok(Box::new(SqliteStore::open(&dir.join("memory.db"))?))
+        }
+        "memory" => Ok(Box::new(InMemoryStore::default())),
+        other => Err(format!("unknown WORKING_MEMORY_STORE backend '{other}' (expected sqlite|memory)")),
+    }
+}
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| format!("working memory {}: {e}", path.display()))?;
+        // `secure_delete` zeroes removed rows on disk, so erased and expired items do not
+        // linger in free pages.
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+             PRAGMA secure_delete = ON;
+             CREATE TABLE IF NOT EXISTS items (
+                 seq        INTEGER PRIMARY KEY AUTOINCREMENT,
+                 twin_id    TEXT NOT NULL,
+                 created_at INTEGER NOT NULL,
+                 item       TEXT NOT NULL,
+                 item_id    TEXT NOT NULL DEFAULT '',
+                 bytes      INTEGER NOT NULL DEFAULT 0
+             );
+             CREATE INDEX IF NOT EXISTS items_twin ON items (twin_id, seq);
+             CREATE INDEX IF NOT EXISTS items_created ON items (created_at);
+             CREATE TABLE IF NOT EXISTS policies (
+                 twin_id TEXT PRIMARY KEY,
+                 policy  TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS playbooks (
+                 twin_id TEXT PRIMARY KEY,
+                 memory  TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS long_term (
+                 seq       INTEGER PRIMARY KEY AUTOINCREMENT,
+                 twin_id   TEXT NOT NULL,
+                 record_id TEXT NOT NULL UNIQUE,
+                 kind      TEXT NOT NULL,
+                 dedup_key TEXT,
+                 seen_at   INTEGER NOT NULL,
+                 record    TEXT NOT NULL,
+                 staged    INTEGER NOT NULL DEFAULT 0
+             );
+             CREATE INDEX IF NOT EXISTS long_term_twin ON long_term (twin_id, kind, seq);
+             CREATE INDEX IF NOT EXISTS long_term_dedup ON long_term (twin_id, dedup_key);
+             CREATE INDEX IF NOT EXISTS long_term_seen ON long_term (seen_at);
+             CREATE TABLE IF NOT EXISTS schemas (
+                 twin_id TEXT PRIMARY KEY,
+                 schema  TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS quarantine (
+                 seq            INTEGER PRIMARY KEY AUTOINCREMENT,
+                 twin_id        TEXT NOT NULL,
+                 item_id        TEXT NOT NULL,
+                 quarantined_at INTEGER NOT NULL,
+                 entry          TEXT NOT NULL,
+                 UNIQUE (twin_id, item_id)
+             );
+             CREATE INDEX IF NOT EXISTS quarantine_at ON quarantine (quarantined_at);",
+        )
+        .map_err(|e| format!("working memory {}: {e}", path.display()))?;
+        assign_item_ids(&conn).map_err(|e| format!("working memory {}: {e}", path.display()))?;
+        count_item_bytes(&conn).map_err(|e| format!("working memory {}: {e}", path.display()))?;
+        mark_staged_records(&conn).map_err(|e| format!("working memory {}: {e}", path.display()))?;
+        conn.execute_batch(
+            "CREATE INDEX IF NOT EXISTS items_id ON items (twin_id, item_id);
+             CREATE INDEX IF NOT EXISTS long_term_staged ON long_term (twin_id, staged, seq);",
+        )
+        .map_err(|e| format!("working memory {}: {e}", path.display()))?;
+        tracing::info!(path = %path.display(), "working memory: sqlite");
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|p| p.into_inner())
+    }
+}
+
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool, String> {
+    let count: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM pragma_table_info(?1) WHERE name = ?2",
+            [table, column],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(count > 0)
+}
+
+/// Databases from before items had ids: add the column and give every item a stable id.
+fn assign_item_ids(conn: &Connection) -> Result<(), String> {
+    if !has_column(conn, "items", "item_id")? {
+        conn.execute_batch("ALTER TABLE items ADD COLUMN item_id TEXT NOT NULL DEFAULT ''")
+            .map_err(|e| e.to_string())?;
+    }
+    let pending: Vec<(i64, String)> = {
+        let mut stmt = conn
+            .prepare("SELECT seq, item FROM items WHERE item_id = ''")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    if pending.is_empty() {
+        return Ok(());
+    }
+    for (seq, raw) in &pending {
+        let item = decode(raw)?;
+        let raw = serde_json::to_string(&item).map_err(|e| e.to_string())?;
+        conn.execute(
+            "UPDATE items SET item = ?1, item_id = ?2 WHERE seq = ?3",
+            params![raw, item.id.to_string(), seq],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tracing::info!(items = pending.len(), "working memory: assigned ids to existing items");
+    Ok(())
+}
+
+/// Databases from before item sizes were kept: add the column and size the existing items
+/// (UTF-8 bytes of role and content, as [`item_bytes`] counts them).
+fn count_item_bytes(conn: &Connection) -> Result<(), String> {
+    if has_column(conn, "items", "bytes")? {
+        return Ok(());
+    }
+    conn.execute_batch(
+        "ALTER TABLE items ADD COLUMN bytes INTEGER NOT NULL DEFAULT 0;
+         UPDATE items SET bytes = length(CAST(json_extract(item, '$.role') AS BLOB))
+                                + length(CAST(json_extract(item, '$.content') AS BLOB));",
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Databases from before staged episodes were marked: add the column. Existing episodes are
+/// left unstaged, so they are kept as they are rather than summarized.
+fn mark_staged_records(conn: &Connection) -> Result<(), String> {
+    if has_column(conn, "long_term", "staged")? {
+        return Ok(());
+    }
+    conn.execute_batch("ALTER TABLE long_term ADD COLUMN staged INTEGER NOT NULL DEFAULT 0")
+        .map_err(|e| e.to_string())
+}
+
+/// Nanoseconds since the epoch, as stored in `created_at`.
+fn unix_nanos(at: OffsetDateTime) -> i64 {
+    at.unix_timestamp_nanos() as i64
+}
+
+fn insert(conn: &Connection, twin_id: Uuid, item: &MemoryItem) -> Result<(), String> {
+    let raw = serde_json::to_string(item).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO items (twin_id, created_at, item, item_id, bytes) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            twin_id.to_string(),
+            unix_nanos(item.created_at),
+            raw,
+            item.id.to_string(),
+            item_bytes(item) as i64
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn delete_items(conn: &Connection, twin_id: Uuid, item_ids: &[Uuid]) -> Result<usize, String> {
+    let mut stmt = conn
+        .prepare_cached("DELETE FROM items WHERE twin_id = ?1 AND item_id = ?2")
+        .map_err(|e| e.to_string())?;
+    let mut deleted = 0;
+    for id in item_ids {
+        deleted += stmt
+            .execute(params![twin_id.to_string(), id.to_string()])
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(deleted)
+}
+
+fn decode(raw: &str) -> Result<MemoryItem, String> {
+    serde_json::from_str(raw).map_err(|e| format!("corrupt memory item: {e}"))
+}
+
+fn decode_record(raw: &str) -> Result<LongTermRecord, String> {
+    serde_json::from_str(raw).map_err(|e| format!("corrupt long-term record: {e}"))
+}
+
+fn decode_quarantined(raw: &str) -> Result<Quarantined, String> {
+    serde_json::from_str(raw).map_err(|e| format!("corrupt quarantined item: {e}"))
+}
+
+/// Removed counts per twin, from `(twin_id, count)` rows.
+fn counts_by_twin(stmt: &mut rusqlite::Statement<'_>, cutoff: i64) -> Result<BTreeMap<Uuid, u64>, String> {
+    let rows = stmt
+        .query_map([cutoff], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)))
+        .map_err(|e| e.to_string())?;
+    let mut counts = BTreeMap::new();
+    for row in rows {
+        let (twin_id, count) = row.map_err(|e| e.to_string())?;
+        let twin_id = Uuid::parse_str(&twin_id).map_err(|e| format!("corrupt twin id: {e}"))?;
+        counts.insert(twin_id, count as u64);
+    }
+    Ok(counts)
+}
+
+impl MemoryStore for SqliteStore {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn query(&self, twin_id: Uuid, query: &ItemQuery) -> Result<Vec<(u64, MemoryItem)>, String> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT seq, item FROM (
+                     SELECT seq, item FROM items
+                     WHERE twin_id = ?1
+                       AND (?2 IS NULL OR created_at >= ?2)
+                       AND (?3 IS NULL OR created_at < ?3)
+                       AND (?4 IS NULL OR json_extract(item, '$.role') = ?4)
+                       AND (?5 IS NULL OR EXISTS (SELECT 1 FROM json_each(item, '$.tags') WHERE value = ?5))
+                       AND (?6 IS NULL OR seq < ?6)
+                     ORDER BY seq DESC LIMIT ?7
+                 ) ORDER BY seq",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(
+                params![
+                    twin_id.to_string(),
+                    query.since.map(unix_nanos),
+                    query.until.map(unix_nanos),
+                    query.role,
+                    query.tag,
+                    query.before.map(|seq| seq as i64),
+                    query.limit.map_or(-1, |l| l as i64),
+                ],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        rows.map(|row| {
+            let (seq, raw) = row.map_err(|e| e.to_string())?;
+            Ok((seq as u64, decode(&raw)?))
+        })
+        .collect()
+    }
+
+    fn get(&self, twin_id: Uuid, item_id: Uuid) -> Result<Option<MemoryItem>, String> {
+        let raw: Option<String> = self
+            .conn()
+            .query_row(
+                "SELECT item FROM items WHERE twin_id = ?1 AND item_id = ?2",
+                params![twin_id.to_string(), item_id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        raw.as_deref().map(decode).transpose()
+    }
+
+    fn usage(&self, twin_id: Uuid) -> Result<Usage, String> {
+        self.conn()
+            .query_row(
+                "SELECT COUNT(*), COALESCE(SUM(bytes), 0) FROM items WHERE twin_id = ?1",
+                [twin_id.to_string()],
+                |row| {
+                    Ok(Usage {
+                        items: row.get::<_, i64>(0)? as usize,
+                        bytes: row.get::<_, i64>(1)? as usize,
+                    })
+                },
+            )
+            .map_err(|e| e.to_string())
+    }
+
+    fn pinned_usage(&self, twin_id: Uuid) -> Result<Usage, String> {
+        self.conn()
+            .query_row(
+                "SELECT COUNT(*), COALESCE(SUM(bytes), 0) FROM items
+                 WHERE twin_id = ?1 AND json_extract(item, '$.pinned') = 1",
+                [twin_id.to_string()],
+                |row| {
+                    Ok(Usage {
+                        items: row.get::<_, i64>(0)? as usize,
+                        bytes: row.get::<_, i64>(1)? as usize,
+                    })
+                },
+            )
+            .map_err(|e| e.to_string())
+    }
+
+    fn update(&self, twin_id: Uuid, item: &MemoryItem) -> Result<bool, String> {
+        let raw = serde_json::to_string(item).map_err(|e| e.to_string())?;
+        let updated = self
+            .conn()
+            .execute(
+                "UPDATE items SET item = ?1, created_at = ?2, bytes = ?3 WHERE twin_id = ?4 AND item_id = ?5",
+                params![
+                    raw,
+                    unix_nanos(item.created_at),
+                    item_bytes(item) as i64,
+                    twin_id.to_string(),
+                    item.id.to_string()
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(updated > 0)
+    }
+
+    fn delete(&self, twin_id: Uuid, item_id: Uuid) -> Result<bool, String> {
+        let deleted = self
+            .conn()
+            .execute(
+                "DELETE FROM items WHERE twin_id = ?1 AND item_id = ?2",
+                params![twin_id.to_string(), item_id.to_string()],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(deleted > 0)
+    }
+
+    fn delete_items(&self, twin_id: Uuid, item_ids: &[Uuid]) -> Result<usize, String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let deleted = delete_items(&tx, twin_id, item_ids)?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(deleted)
+    }
+
+    fn append(&self, twin_id: Uuid, item: &MemoryItem, evict: &[Uuid]) -> Result<(), String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        delete_items(&tx, twin_id, evict)?;
+        insert(&tx, twin_id, item)?;
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    fn replace(&self, twin_id: Uuid, items: &[MemoryItem]) -> Result<(), String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM items WHERE twin_id = ?1", [twin_id.to_string()])
+            .map_err(|e| e.to_string())?;
+        for item in items {
+            insert(&tx, twin_id, item)?;
+        }
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    fn remove(&self, twin_id: Uuid) -> Result<Removed, String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let twin = twin_id.to_string();
+        let delete = |table: &str| {
+            tx.execute(&format!("DELETE FROM {table} WHERE twin_id = ?1"), [&twin])
+                .map_err(|e| e.to_string())
+        };
+        let removed = Removed {
+            items: delete("items")?,
+            long_term: delete("long_term")?,
+            quarantined: delete("quarantine")?,
+        };
+        for table in ["policies", "playbooks", "schemas"] {
+            delete(table)?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(removed)
+    }
+
+    fn expired(&self, twin_id: Uuid, cutoff: OffsetDateTime) -> Result<Vec<MemoryItem>, String> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare("SELECT item FROM items WHERE twin_id = ?1 AND created_at < ?2 ORDER BY seq")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![twin_id.to_string(), unix_nanos(cutoff)], |row| row.get::<_, String>(0))
+            .map_err(|e| e.to_string())?;
+        rows.map(|row| decode(&row.map_err(|e| e.to_string())?)).collect()
+    }
+
+    fn policy(&self, twin_id: Uuid) -> Result<Option<CapacityPolicy>, String> {
+        let raw: Option<String> = self
+            .conn()
+            .query_row("SELECT policy FROM policies WHERE twin_id = ?1", [twin_id.to_string()], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        raw.map(|raw| serde_json::from_str(&raw).map_err(|e| format!("corrupt capacity policy: {e}")))
+            .transpose()
+    }
+
+    fn set_policy(&self, twin_id: Uuid, policy: &CapacityPolicy) -> Result<(), String> {
+        let raw = serde_json::to_string(policy).map_err(|e| e.to_string())?;
+        self.conn()
+            .execute(
+                "INSERT INTO policies (twin_id, policy) VALUES (?1, ?2)
+                 ON CONFLICT (twin_id) DO UPDATE SET policy = excluded.policy",
+                params![twin_id.to_string(), raw],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn playbook(&self, twin_id: Uuid) -> Result<Option<PlaybookMemory>, String> {
+        let raw: Option<String> = self
+            .conn()
+            .query_row("SELECT memory FROM playbooks WHERE twin_id = ?1", [twin_id.to_string()], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        raw.map(|raw| serde_json::from_str(&raw).map_err(|e| format!("corrupt playbook memory: {e}")))
+            .transpose()
+    }
+
+    fn set_playbook(&self, twin_id: Uuid, memory: &PlaybookMemory) -> Result<(), String> {
+        let raw = serde_json::to_string(memory).map_err(|e| e.to_string())?;
+        self.conn()
+            .execute(
+                "INSERT INTO playbooks (twin_id, memory) VALUES (?1, ?2)
+                 ON CONFLICT (twin_id) DO UPDATE SET memory = excluded.memory",
+                params![twin_id.to_string(), raw],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn records(&self, twin_id: Uuid, kind: RecordKind, query: &ItemQuery) -> Result<Vec<(u64, LongTermRecord)>, String> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT seq, record FROM (
+                     SELECT seq, record FROM long_term
+                     WHERE twin_id = ?1 AND kind = ?2
+                       AND (?3 IS NULL OR seen_at >= ?3)
+                       AND (?4 IS NULL OR seen_at < ?4)
+                       AND (?5 IS NULL OR json_extract(record, '$.role') = ?5)
+                       AND (?6 IS NULL OR EXISTS (SELECT 1 FROM json_each(record, '$.tags') WHERE value = ?6))
+                       AND (?7 IS NULL OR seq < ?7)
+                     ORDER BY seq DESC LIMIT ?8
+                 ) ORDER BY seq",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(
+                params![
+                    twin_id.to_string(),
+                    kind.as_str(),
+                    query.since.map(unix_nanos),
+                    query.until.map(unix_nanos),
+                    query.role,
+                    query.tag,
+                    query.before.map(|seq| seq as i64),
+                    query.limit.map_or(-1, |l| l as i64),
+                ],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        rows.map(|row| {
+            let (seq, raw) = row.map_err(|e| e.to_string())?;
+            Ok((seq as u64, decode_record(&raw)?))
+        })
+        .collect()
+    }
+
+    fn find_episode(&self, twin_id: Uuid, dedup_key: &str, staged: bool) -> Result<Option<LongTermRecord>, String> {
+        let raw: Option<String> = self
+            .conn()
+            .query_row(
+                "SELECT record FROM long_term
+                 WHERE twin_id = ?1 AND dedup_key = ?2 AND kind = 'episodic' AND staged = ?3",
+                params![twin_id.to_string(), dedup_key, staged],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        raw.as_deref().map(decode_record).transpose()
+    }
+
+    fn staged(&self, twin_id: Uuid, limit: usize) -> Result<Vec<LongTermRecord>, String> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached("SELECT record FROM long_term WHERE twin_id = ?1 AND staged = 1 ORDER BY seq LIMIT ?2")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![twin_id.to_string(), limit as i64], |row| row.get::<_, String>(0))
+            .map_err(|e| e.to_string())?;
+        rows.map(|row| decode_record(&row.map_err(|e| e.to_string())?)).collect()
+    }
+
+    fn get_records(&self, twin_id: Uuid, record_ids: &[Uuid]) -> Result<Vec<LongTermRecord>, String> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached("SELECT record FROM long_term WHERE twin_id = ?1 AND record_id = ?2")
+            .map_err(|e| e.to_string())?;
+        let mut records = Vec::new();
+        for id in record_ids {
+            let raw: Option<String> = stmt
+                .query_row(params![twin_id.to_string(), id.to_string()], |row| row.get(0))
+                .optional()
+                .map_err(|e| e.to_string())?;
+            if let Some(raw) = raw {
+                records.push(decode_record(&raw)?);
+            }
+        }
+        Ok(records)
+    }
+
+    fn put_record(&self, twin_id: Uuid, record: &LongTermRecord) -> Result<(), String> {
+        let raw = serde_json::to_string(record).map_err(|e| e.to_string())?;
+        self.conn()
+            .execute(
+                "INSERT INTO long_term (twin_id, record_id, kind, dedup_key, seen_at, record, staged)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+                 ON CONFLICT (record_id) DO UPDATE SET
+                     dedup_key = excluded.dedup_key, seen_at = excluded.seen_at, record = excluded.record,
+                     staged = excluded.staged",
+                params![
+                    twin_id.to_string(),
+                    record.id.to_string(),
+                    record.kind.as_str(),
+                    record.dedup_key(),
+                    unix_nanos(record.last_seen),
+                    raw,
+                    record.staged
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn delete_records(&self, twin_id: Uuid, record_ids: &[Uuid]) -> Result<usize, String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let mut deleted = 0;
+        for id in record_ids {
+            deleted += tx
+                .execute(
+                    "DELETE FROM long_term WHERE twin_id = ?1 AND record_id = ?2",
+                    params![twin_id.to_string(), id.to_string()],
+                )
+                .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(deleted)
+    }
+
+    fn expire_records(&self, cutoff: OffsetDateTime) -> Result<BTreeMap<Uuid, u64>, String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let expired = {
+            let mut stmt = tx
+                .prepare("SELECT twin_id, COUNT(*) FROM long_term WHERE seen_at < ?1 GROUP BY twin_id")
+                .map_err(|e| e.to_string())?;
+            counts_by_twin(&mut stmt, unix_nanos(cutoff))?
+        };
+        tx.execute("DELETE FROM long_term WHERE seen_at < ?1", [unix_nanos(cutoff)])
+            .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(expired)
+    }
+
+    fn schema(&self, twin_id: Uuid) -> Result<Option<MemorySchema>, String> {
+        let raw: Option<String> = self
+            .conn()
+            .query_row("SELECT schema FROM schemas WHERE twin_id = ?1", [twin_id.to_string()], |row| row.get(0))
+            .optional()
+            .map_err(|e| e.to_string())?;
+        raw.map(|raw| serde_json::from_str(&raw).map_err(|e| format!("corrupt memory schema: {e}")))
+            .transpose()
+    }
+
+    fn set_schema(&self, twin_id: Uuid, schema: &MemorySchema) -> Result<(), String> {
+        let raw = serde_json::to_string(schema).map_err(|e| e.to_string())?;
+        self.conn()
+            .execute(
+                "INSERT INTO schemas (twin_id, schema) VALUES (?1, ?2)
+                 ON CONFLICT (twin_id) DO UPDATE SET schema = excluded.schema",
+                params![twin_id.to_string(), raw],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    fn clear_schema(&self, twin_id: Uuid) -> Result<bool, String> {
+        let removed = self
+            .conn()
+            .execute("DELETE FROM schemas WHERE twin_id = ?1", [twin_id.to_string()])
+            .map_err(|e| e.to_string())?;
+        Ok(removed > 0)
+    }
+
+    fn quarantine(&self, twin_id: Uuid, entry: &Quarantined, keep: usize) -> Result<usize, String> {
+        let raw = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        tx.execute(
+            "DELETE FROM quarantine WHERE twin_id = ?1 AND item_id = ?2",
+            params![twin_id.to_string(), entry.item.id.to_string()],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.execute(
+            "INSERT INTO quarantine (twin_id, item_id, quarantined_at, entry) VALUES (?1, ?2, ?3, ?4)",
+            params![twin_id.to_string(), entry.item.id.to_string(), unix_nanos(entry.quarantined_at), raw],
+        )
+        .map_err(|e| e.to_string())?;
+        let dropped = tx
+            .execute(
+                "DELETE FROM quarantine WHERE twin_id = ?1 AND seq NOT IN (
+                     SELECT seq FROM quarantine WHERE twin_id = ?1 ORDER BY seq DESC LIMIT ?2
+                 )",
+                params![twin_id.to_string(), keep as i64],
+            )
+            .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(dropped)
+    }
+
+    fn quarantined(&self, twin_id: Uuid, before: Option<u64>, limit: usize) -> Result<Vec<(u64, Quarantined)>, String> {
+        let conn = self.conn();
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT seq, entry FROM (
+                     SELECT seq, entry FROM quarantine
+                     WHERE twin_id = ?1 AND (?2 IS NULL OR seq < ?2)
+                     ORDER BY seq DESC LIMIT ?3
+                 ) ORDER BY seq",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(
+                params![twin_id.to_string(), before.map(|seq| seq as i64), limit as i64],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+            )
+            .map_err(|e| e.to_string())?;
+        rows.map(|row| {
+            let (seq, raw) = row.map_err(|e| e.to_string())?;
+            Ok((seq as u64, decode_quarantined(&raw)?))
+        })
+        .collect()
+    }
+
+    fn take_quarantined(&self, twin_id: Uuid, item_id: Uuid) -> Result<Option<Quarantined>, String> {
+        let raw: Option<String> = self
+            .conn()
+            .query_row(
+                "DELETE FROM quarantine WHERE twin_id = ?1 AND item_id = ?2 RETURNING entry",
+                params![twin_id.to_string(), item_id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        raw.as_deref().map(decode_quarantined).transpose()
+    }
+
+    fn expire_quarantine(&self, cutoff: OffsetDateTime) -> Result<BTreeMap<Uuid, u64>, String> {
+        let mut conn = self.conn();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let expired = {
+            let mut stmt = tx
+                .prepare("SELECT twin_id, COUNT(*) FROM quarantine WHERE quarantined_at < ?1 GROUP BY twin_id")
+                .map_err(|e| e.to_string())?;
+            counts_by_twin(&mut stmt, unix_nanos(cutoff))?
+        };
+        tx.execute("DELETE FROM quarantine WHERE quarantined_at < ?1", [unix_nanos(cutoff)])
+            .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(expired)
+    }
+
+    fn twins(&self) -> Result<Vec<Uuid>, String> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT DISTINCT twin_id FROM items").map_err(|e| e.to_string())?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0)).map_err(|e| e.to_string())?;
+        rows.map(|row| {
+            let twin_id = row.map_err(|e| e.to_string())?;
+            Uuid::parse_str(&twin_id).map_err(|e| format!("corrupt twin id: {e}"))
+        })
+        .collect()
+    }
+
+    fn stats(&self) -> Result<(usize, usize), String> {
+        self.conn()
+            .query_row("SELECT COUNT(DISTINCT twin_id), COUNT(*) FROM items", [], |row| {
+                Ok((row.get::<_, i64>(0)? as usize, row.get::<_, i64>(1)? as usize))
+            })
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Default)]
+pub struct InMemoryStore {
+    inner: Mutex<InMemoryInner>,
+}
+
+#[derive(Default)]
+struct InMemoryInner {
+    items: HashMap<Uuid, Vec<(u64, MemoryItem)>>,
+    records: HashMap<Uuid, Vec<(u64, LongTermRecord)>>,
+    quarantine: HashMap<Uuid, Vec<(u64, Quarantined)>>,
+    policies: HashMap<Uuid, CapacityPolicy>,
+    playbooks: HashMap<Uuid, PlaybookMemory>,
+    schemas: HashMap<Uuid, MemorySchema>,
+    next_seq: u64,
+}
+
+impl InMemoryInner {
+    fn seq(&mut self) -> u64 {
+        self.next_seq += 1;
+        self.next_seq
+    }
+
+    fn push(&mut self, twin_id: Uuid, item: MemoryItem) {
+        let seq = self.seq();
+        self.items.entry(twin_id).or_default().push((seq, item));
+    }
+}
+
+impl InMemoryStore {
+    fn inner(&self) -> std::sync::MutexGuard<'_, InMemoryInner> {
+        self.inner.lock().unwrap_or_else(|p| p.into_inner())
+    }
+}
+
+impl MemoryStore for InMemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn query(&self, twin_id: Uuid, query: &ItemQuery) -> Result<Vec<(u64, MemoryItem)>, String> {
+        let inner = self.inner();
+        let items = inner.items.get(&twin_id).map(Vec::as_slice).unwrap_or_default();
+        let mut matched: Vec<(u64, MemoryItem)> = items
+            .iter()
+            .rev()
+            .filter(|(seq, item)| query.matches(*seq, item))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        matched.reverse();
+        Ok(matched)
+    }
+
+    fn get(&self, twin_id: Uuid, item_id: Uuid) -> Result<Option<MemoryItem>, String> {
+        let inner = self.inner();
+        let items = inner.items.get(&twin_id).map(Vec::as_slice).unwrap_or_default();
+        Ok(items.iter().find(|(_, item)| item.id == item_id).map(|(_, item)| item.clone()))
+    }
+
+    fn usage(&self, twin_id: Uuid) -> Result<Usage, String> {
+        let inner = self.inner();
+        let items = inner.items.get(&twin_id).map(Vec::as_slice).unwrap_or_default();
+        Ok(Usage {
+            items: items.len(),
+            bytes: items.iter().map(|(_, item)| item_bytes(item)).sum(),
+        })
+    }
+
+    fn pinned_usage(&self, twin_id: Uuid) -> Result<Usage, String> {
+        let inner = self.inner();
+        let items = inner.items.get(&twin_id).map(Vec::as_slice).unwrap_or_default();
+        Ok(items
+            .iter()
+            .filter(|(_, item)| item.pinned)
+            .fold(Usage::default(), |usage, (_, item)| usage.with(item)))
+    }
+
+    fn update(&self, twin_id: Uuid, item: &MemoryItem) -> Result<bool, String> {
+        let mut inner = self.inner();
+        let stored = inner
+            .items
+            .get_mut(&twin_id)
+            .and_then(|items| items.iter_mut().find(|(_, stored)| stored.id == item.id));
+        Ok(match stored {
+            Some((_, stored)) => {
+                *stored = item.clone();
+                true
+            }
+            None => false,
+        })
+    }
+
+    fn delete(&self, twin_id: Uuid, item_id: Uuid) -> Result<bool, String> {
+        let mut inner = self.inner();
+        let Some(items) = inner.items.get_mut(&twin_id) else {
+            return Ok(false);
+        };
+        let before = items.len();
+        items.retain(|(_, item)| item.id != item_id);
+        let deleted = items.len() < before;
+        if items.is_empty() {
+            inner.items.remove(&twin_id);
+        }
+        Ok(deleted)
+    }
+
+    fn delete_items(&self, twin_id: Uuid, item_ids: &[Uuid]) -> Result<usize, String> {
+        let mut inner = self.inner();
+        let Some(items) = inner.items.get_mut(&twin_id) else {
+            return Ok(0);
+        };
+        let before = items.len();
+        items.retain(|(_, item)| !item_ids.contains(&item.id));
+        let deleted = before - items.len();
+        if items.is_empty() {
+            inner.items.remove(&twin_id);
+        }
+        Ok(deleted)
+    }
+
+    fn append(&self, twin_id: Uuid, item: &MemoryItem, evict: &[Uuid]) -> Result<(), String> {
+        let mut inner = self.inner();
+        if let Some(items) = inner.items.get_mut(&twin_id) {
+            items.retain(|(_, stored)| !evict.contains(&stored.id));
+        }
+        inner.push(twin_id, item.clone());
+        Ok(())
+    }
+
+    fn replace(&self, twin_id: Uuid, items: &[MemoryItem]) -> Result<(), String> {
+        let mut inner = self.inner();
+        inner.items.remove(&twin_id);
+        for item in items {
+            inner.push(twin_id, item.clone());
+        }
+        Ok(())
+    }
+
+    fn remove(&self, twin_id: Uuid) -> Result<Removed, String> {
+        let mut inner = self.inner();
+        inner.policies.remove(&twin_id);
+        inner.playbooks.remove(&twin_id);
+        inner.schemas.remove(&twin_id);
+        Ok(Removed {
+            items: inner.items.remove(&twin_id).map_or(0, |items| items.len()),
+            long_term: inner.records.remove(&twin_id).map_or(0, |records| records.len()),
+            quarantined: inner.quarantine.remove(&twin_id).map_or(0, |entries| entries.len()),
+        })
+    }
+
+    fn expired(&self, twin_id: Uuid, cutoff: OffsetDateTime) -> Result<Vec<MemoryItem>, String> {
+        let inner = self.inner();
+        let items = inner.items.get(&twin_id).map(Vec::as_slice).unwrap_or_default();
+        Ok(items.iter().filter(|(_, item)| item.created_at < cutoff).map(|(_, item)| item.clone()).collect())
+    }
+
+    fn policy(&self, twin_id: Uuid) -> Result<Option<CapacityPolicy>, String> {
+        Ok(self.inner().policies.get(&twin_id).cloned())
+    }
+
+    fn set_policy(&self, twin_id: Uuid, policy: &CapacityPolicy) -> Result<(), String> {
+        self.inner().policies.insert(twin_id, policy.clone());
+        Ok(())
+    }
+
+    fn playbook(&self, twin_id: Uuid) -> Result<Option<PlaybookMemory>, String> {
+        Ok(self.inner().playbooks.get(&twin_id).cloned())
+    }
+
+    fn set_playbook(&self, twin_id: Uuid, memory: &PlaybookMemory) -> Result<(), String> {
+        self.inner().playbooks.insert(twin_id, memory.clone());
+        Ok(())
+    }
+
+    fn records(&self, twin_id: Uuid, kind: RecordKind, query: &ItemQuery) -> Result<Vec<(u64, LongTermRecord)>, String> {
+        let inner = self.inner();
+        let records = inner.records.get(&twin_id).map(Vec::as_slice).unwrap_or_default();
+        let mut matched: Vec<(u64, LongTermRecord)> = records
+            .iter()
+            .rev()
+            .filter(|(seq, record)| record.kind == kind && query.matches_record(*seq, record))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect();
+        matched.reverse();
+        Ok(matched)
+    }
+
+    fn find_episode(&self, twin_id: Uuid, dedup_key: &str, staged: bool) -> Result<Option<LongTermRecord>, String> {
+        let inner = self.inner();
+        let records = inner.records.get(&twin_id).map(Vec::as_slice).unwrap_or_default();
+        Ok(records
+            .iter()
+            .map(|(_, record)| record)
+            .find(|record| {
+                record.kind == RecordKind::Episodic
+                    && record.staged == staged
+                    && record.dedup_key().as_deref() == Some(dedup_key)
+            })
+            .cloned())
+    }
+
+    fn staged(&self, twin_id: Uuid, limit: usize) -> Result<Vec<LongTermRecord>, String> {
+        let inner = self.inner();
+        let records = inner.records.get(&twin_id).map(Vec::as_slice).unwrap_or_default();
+        Ok(records
+            .iter()
+            .map(|(_, record)| record)
+            .filter(|record| record.staged)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    fn get_records(&self, twin_id: Uuid, record_ids: &[Uuid]) -> Result<Vec<LongTermRecord>, String> {
+        let inner = self.inner();
+        let records = inner.records.get(&twin_id).map(Vec::as_slice).unwrap_or_default();
+        Ok(record_ids
+            .iter()
+            .filter_map(|id| records.iter().find(|(_, record)| record.id == *id))
+            .map(|(_, record)| record.clone())
+            .collect())
+    }
+
+    fn put_record(&self, twin_id: Uuid, record: &LongTermRecord) -> Result<(), String> {
+        let mut inner = self.inner();
+        let seq = inner.seq();
+        let records = inner.records.entry(twin_id).or_default();
+        match records.iter_mut().find(|(_, stored)| stored.id == record.id) {
+            Some((_, stored)) => *stored = record.clone(),
+            None => records.push((seq, record.clone())),
+        }
+        Ok(())
+    }
+
+    fn delete_records(&self, twin_id: Uuid, record_ids: &[Uuid]) -> Result<usize, String> {
+        let mut inner = self.inner();
+        let Some(records) = inner.records.get_mut(&twin_id) else {
+            return Ok(0);
+        };
+        let before = records.len();
+        records.retain(|(_, record)| !record_ids.contains(&record.id));
+        Ok(before - records.len())
+    }
+
+    fn expire_records(&self, cutoff: OffsetDateTime) -> Result<BTreeMap<Uuid, u64>, String> {
+        let mut inner = self.inner();
+        let mut expired = BTreeMap::new();
+        for (twin_id, records) in inner.records.iter_mut() {
+            let before = records.len();
+            records.retain(|(_, record)| record.last_seen >= cutoff);
+            if records.len() < before {
+                expired.insert(*twin_id, (before - records.len()) as u64);
+            }
+        }
+        inner.records.retain(|_, records| !records.is_empty());
+        Ok(expired)
+    }
+
+    fn schema(&self, twin_id: Uuid) -> Result<Option<MemorySchema>, String> {
+        Ok(self.inner().schemas.get(&twin_id).cloned())
+    }
+
+    fn set_schema(&self, twin_id: Uuid, schema: &MemorySchema) -> Result<(), String> {
+        self.inner().schemas.insert(twin_id, schema.clone());
+        Ok(())
+    }
+
+    fn clear_schema(&self, twin_id: Uuid) -> Result<bool, String> {
+        Ok(self.inner().schemas.remove(&twin_id).is_some())
+    }
+
+    fn quarantine(&self, twin_id: Uuid, entry: &Quarantined, keep: usize) -> Result<usize, String> {
+        let mut inner = self.inner();
+        let seq = inner.seq();
+        let entries = inner.quarantine.entry(twin_id).or_default();
+        entries.retain(|(_, held)| held.item.id != entry.item.id);
+        entries.push((seq, entry.clone()));
+        let dropped = entries.len().saturating_sub(keep);
+        entries.drain(..dropped);
+        Ok(dropped)
+    }
+
+    fn quarantined(&self, twin_id: Uuid, before: Option<u64>, limit: usize) -> Result<Vec<(u64, Quarantined)>, String> {
+        let inner = self.inner();
+        let entries = inner.quarantine.get(&twin_id).map(Vec::as_slice).unwrap_or_default();
+        let mut page: Vec<(u64, Quarantined)> = entries
+            .iter()
+            .rev()
+            .filter(|(seq, _)| before.is_none_or(|before| *seq < before))
+            .take(limit)
+            .cloned()
+            .collect();
+        page.reverse();
+        Ok(page)
+    }
+
+    fn take_quarantined(&self, twin_id: Uuid, item_id: Uuid) -> Result<Option<Quarantined>, String> {
+        let mut inner = self.inner();
+        let Some(entries) = inner.quarantine.get_mut(&twin_id) else {
+            return Ok(None);
+        };
+        let Some(pos) = entries.iter().position(|(_, held)| held.item.id == item_id) else {
+            return Ok(None);
+        };
+        Ok(Some(entries.remove(pos).1))
+    }
+
+    fn expire_quarantine(&self, cutoff: OffsetDateTime) -> Result<BTreeMap<Uuid, u64>, String> {
+        let mut inner = self.inner();
+        let mut expired = BTreeMap::new();
+        for (twin_id, entries) in inner.quarantine.iter_mut() {
+            let before = entries.len();
+            entries.retain(|(_, held)| held.quarantined_at >= cutoff);
+            if entries.len() < before {
+                expired.insert(*twin_id, (before - entries.len()) as u64);
+            }
+        }
+        inner.quarantine.retain(|_, entries| !entries.is_empty());
+        Ok(expired)
+    }
+
+    fn twins(&self) -> Result<Vec<Uuid>, String> {
+        Ok(self.inner().items.keys().copied().collect())
+    }
+
+    fn stats(&self) -> Result<(usize, usize), String> {
+        let inner = self.inner();
+        Ok((inner.items.len(), inner.items.values().map(Vec::len).sum()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use time::Duration;
+
+    fn item(role: &str, content: &str) -> MemoryItem {
+        serde_json::from_value(json!({"role": role, "content": content})).unwrap()
+    }
+
+    fn contents(items: &[MemoryItem]) -> Vec<&str> {
+        items.iter().map(|item| item.content.as_str()).collect()
+    }
+
+    fn backends() -> Vec<(Box<dyn MemoryStore>, Option<std::path::PathBuf>)> {
+        let path = std::env::temp_dir().join(format!("pagi-working-memory-{}.db", Uuid::new_v4()));
+        vec![
+            (Box::new(SqliteStore::open(&path).unwrap()), Some(path)),
+            (Box::new(InMemoryStore::default()), None),
+        ]
+    }
+
+    fn cleanup(path: Option<std::path::PathBuf>) {
+        if let Some(path) = path {
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+            }
+        }
+    }
+
+    #[test]
+    fn appends_keep_order_and_evict_the_given_items() {
+        for (store, path) in backends() {
+            let twin = Uuid::new_v4();
+            let items: Vec<MemoryItem> = ["one", "two", "three"].iter().map(|c| item("user", c)).collect();
+            for item in &items {
+                store.append(twin, item, &[]).unwrap();
+            }
+            store.append(twin, &item("user", "four"), &[items[0].id, items[2].id]).unwrap();
+            assert_eq!(contents(&store.list(twin).unwrap()), ["two", "four"], "{}", store.name());
+
+            let stored = store.list(twin).unwrap();
+            let ids = [stored[0].id, stored[1].id, Uuid::new_v4()];
+            assert_eq!(store.delete_items(twin, &ids).unwrap(), 2, "{}", store.name());
+            assert!(store.list(twin).unwrap().is_empty(), "{}", store.name());
+            assert!(store.list(Uuid::new_v4()).unwrap().is_empty(), "{}", store.name());
+            cleanup(path);
+        }
+    }
+
+    #[test]
+    fn usage_follows_appends_edits_pins_and_deletes() {
+        for (store, path) in backends() {
+            let twin = Uuid::new_v4();
+            assert_eq!(store.usage(twin).unwrap(), Usage::default(), "{}", store.name());
+            let mut first = item("user", "héllo");
+            store.append(twin, &first, &[]).unwrap();
+            store.append(twin, &item("tool", "ok"), &[]).unwrap();
+            store.append(Uuid::new_v4(), &item("user", "someone else"), &[]).unwrap();
+            assert_eq!(store.usage(twin).unwrap(), Usage { items: 2, bytes: 10 + 6 }, "{}", store.name());
+
+            assert_eq!(store.pinned_usage(twin).unwrap(), Usage::default(), "{}", store.name());
+
+            first.content = "hello, world".to_string();
+            first.pinned = true;
+            assert!(store.update(twin, &first).unwrap());
+            assert_eq!(store.usage(twin).unwrap(), Usage { items: 2, bytes: 16 + 6 }, "{}", store.name());
+            assert_eq!(store.pinned_usage(twin).unwrap(), Usage { items: 1, bytes: 16 }, "{}", store.name());
+            assert!(store.delete(twin, first.id).unwrap());
+            assert!(!store.delete(twin, first.id).unwrap());
+            assert_eq!(store.usage(twin).unwrap(), Usage { items: 1, bytes: 6 }, "{}", store.name());
+            cleanup(path);
+        }
+    }
+
+    #[test]
+    fn queries_filter_and_page_back() {
+        for (store, path) in backends() {
+            let twin = Uuid::new_v4();
+            for n in 1..=5 {
+                let mut item = item(if n % 2 == 0 { "assistant" } else { "user" }, &format!("m{n}"));
+                if n >= 4 {
+                    item.tags = vec!["late".to_string()];
+                }
+                store.append(twin, &item, &[]).unwrap();
+            }
+            let page = |query: ItemQuery| -> Vec<String> {
+                store.query(twin, &query).unwrap().into_iter().map(|(_, item)| item.content).collect()
+            };
+            assert_eq!(page(ItemQuery { role: Some("user".into()), ..Default::default() }), ["m1", "m3", "m5"]);
+            assert_eq!(page(ItemQuery { tag: Some("late".into()), ..Default::default() }), ["m4", "m5"]);
+
+            let newest = store.query(twin, &ItemQuery { limit: Some(2), ..Default::default() }).unwrap();
+            assert_eq!(newest.iter().map(|(_, item)| item.content.as_str()).collect::<Vec<_>>(), ["m4", "m5"]);
+            let older = ItemQuery { before: Some(newest[0].0), limit: Some(2), ..Default::default() };
+            assert_eq!(page(older), ["m2", "m3"], "{}", store.name());
+            cleanup(path);
+        }
+    }
+
+    #[test]
+    fn expiry_finds_only_the_twins_old_items() {
+        for (store, path) in backends() {
+            let (twin, other) = (Uuid::new_v4(), Uuid::new_v4());
+            let now = OffsetDateTime::now_utc();
+            let mut old = item("user", "old");
+            old.created_at = now - Duration::days(2);
+            store.append(twin, &old, &[]).unwrap();
+            store.append(twin, &item("user", "new"), &[]).unwrap();
+            store.append(other, &old, &[]).unwrap();
+
+            let expired = store.expired(twin, now - Duration::days(1)).unwrap();
+            assert_eq!(contents(&expired), ["old"], "{}", store.name());
+            // Finding them removes nothing.
+            assert_eq!(store.list(twin).unwrap().len(), 2, "{}", store.name());
+            assert!(store.expired(twin, now - Duration::days(3)).unwrap().is_empty(), "{}", store.name());
+            cleanup(path);
+        }
+    }
+
+    #[test]
+    fn long_term_records_are_found_by_dedup_key() {
+        for (store, path) in backends() {
+            let twin = Uuid::new_v4();
+            let now = OffsetDateTime::now_utc();
+            let mut record = LongTermRecord {
+                id: Uuid::new_v4(),
+                kind: RecordKind::Episodic,
+                role: Some("user".to_string()),
+                content: "Likes  Tea".to_string(),
+                created_at: now,
+                first_seen: now,
+                last_seen: now,
+                occurrences: 1,
+                tags: Vec::new(),
+                source_items: Vec::new(),
+                staged: false,
+            };
+            store.put_record(twin, &record).unwrap();
+            let key = record.dedup_key().unwrap();
+            assert_eq!(store.find_episode(twin, &key, false).unwrap().map(|r| r.id), Some(record.id), "{}", store.name());
+            assert!(store.find_episode(twin, &key, true).unwrap().is_none(), "{}", store.name());
+            assert!(store.find_episode(Uuid::new_v4(), &key, false).unwrap().is_none());
+
+            record.occurrences = 2;
+            store.put_record(twin, &record).unwrap();
+            let records = store.records(twin, RecordKind::Episodic, &ItemQuery::default()).unwrap();
+            assert_eq!(records.len(), 1, "{}: put_record overwrites in place", store.name());
+            assert_eq!(records[0].1.occurrences, 2);
+            assert!(store.records(twin, RecordKind::Semantic, &ItemQuery::default()).unwrap().is_empty());
+
+            assert_eq!(store.delete_records(twin, &[record.id, Uuid::new_v4()]).unwrap(), 1, "{}", store.name());
+            assert!(store.find_episode(twin, &key, false).unwrap().is_none());
+            cleanup(path);
+        }
+    }
+
+    #[test]
+    fn staged_episodes_are_listed_apart() {
+        for (store, path) in backends() {
+            let twin = Uuid::new_v4();
+            let now = OffsetDateTime::now_utc();
+            let record = |content: &str, staged: bool| LongTermRecord {
+                id: Uuid::new_v4(),
+                kind: RecordKind::Episodic,
+                role: Some("user".to_string()),
+                content: content.to_string(),
+                created_at: now,
+                first_seen: now,
+                last_seen: now,
+                occurrences: 1,
+                tags: Vec::new(),
+                source_items: Vec::new(),
+                staged,
+            };
+            let (kept, first, second) = (record("tea", false), record("tea", true), record("coffee", true));
+            for record in [&kept, &first, &second] {
+                store.put_record(twin, record).unwrap();
+            }
+            let key = kept.dedup_key().unwrap();
+            assert_eq!(store.find_episode(twin, &key, true).unwrap().map(|r| r.id), Some(first.id), "{}", store.name());
+            assert_eq!(store.staged(twin, 10).unwrap(), [first.clone(), second.clone()], "{}", store.name());
+            assert_eq!(store.staged(twin, 1).unwrap(), vec![first.clone()], "{}", store.name());
+
+            let mut unstaged = second.clone();
+            unstaged.staged = false;
+            store.put_record(twin, &unstaged).unwrap();
+            assert_eq!(store.staged(twin, 10).unwrap(), vec![first.clone()], "{}", store.name());
+
+            let found = store.get_records(twin, &[second.id, Uuid::new_v4(), kept.id]).unwrap();
+            assert_eq!(found, [unstaged, kept], "{}", store.name());
+            cleanup(path);
+        }
+    }
+
+    #[test]
+    fn removing_a_twin_drops_everything_held_for_it() {
+        for (store, path) in backends() {
+            let (twin, other) = (Uuid::new_v4(), Uuid::new_v4());
+            store.append(twin, &item("user", "hi"), &[]).unwrap();
+            store.append(other, &item("user", "hi"), &[]).unwrap();
+            let held = Quarantined {
+                item: item("user", "held"),
+                violations: vec!["tags is required".to_string()],
+                quarantined_at: OffsetDateTime::now_utc(),
+            };
+            store.quarantine(twin, &held, 10).unwrap();
+            store.set_schema(twin, &MemorySchema::default()).unwrap();
+            store.set_policy(twin, &CapacityPolicy { max_items: Some(5), max_bytes: None, overflow: Default::default(), eviction_order: Default::default(), max_pinned: 50, max_quarantined: 100 }).unwrap();
+
+            let removed = store.remove(twin).unwrap();
+            assert_eq!((removed.items, removed.quarantined), (1, 1), "{}", store.name());
+            assert!(store.list(twin).unwrap().is_empty());
+            assert!(store.quarantined(twin, None, 10).unwrap().is_empty());
+            assert!(store.schema(twin).unwrap().is_none());
+            assert!(store.policy(twin).unwrap().is_none());
+            assert_eq!(store.twins().unwrap(), [other], "{}", store.name());
+            cleanup(path);
+        }
+    }
+
+    #[test]
+    fn quarantined_items_are_taken_once() {
+        for (store, path) in backends() {
+            let twin = Uuid::new_v4();
+            let held = Quarantined {
+                item: item("user", "held"),
+                violations: vec!["tags is required".to_string()],
+                quarantined_at: OffsetDateTime::now_utc(),
+            };
+            assert_eq!(store.quarantine(twin, &held, 10).unwrap(), 0);
+            assert_eq!(store.quarantine(twin, &held, 10).unwrap(), 0);
+            assert_eq!(store.quarantined(twin, None, 10).unwrap().len(), 1, "{}", store.name());
+            assert_eq!(store.take_quarantined(twin, held.item.id).unwrap(), Some(held.clone()));
+            assert!(store.take_quarantined(twin, held.item.id).unwrap().is_none(), "{}", store.name());
+            cleanup(path);
+        }
+    }
+
+    #[test]
+    fn quarantine_keeps_the_twins_newest_entries() {
+        for (store, path) in backends() {
+            let twin = Uuid::new_v4();
+            let held: Vec<Quarantined> = (0..4)
+                .map(|n| Quarantined {
+                    item: item("user", &format!("held {n}")),
+                    violations: vec!["tags is required".to_string()],
+                    quarantined_at: OffsetDateTime::now_utc(),
+                })
+                .collect();
+            for entry in &held[..3] {
+                assert_eq!(store.quarantine(twin, entry, 3).unwrap(), 0, "{}", store.name());
+            }
+            // Quarantining an entry again makes it the newest.
+            assert_eq!(store.quarantine(twin, &held[0], 3).unwrap(), 0, "{}", store.name());
+            assert_eq!(store.quarantine(twin, &held[3], 3).unwrap(), 1, "{}", store.name());
+            let left: Vec<_> = store.quarantined(twin, None, 10).unwrap().into_iter().map(|(_, q)| q.item.content).collect();
+            assert_eq!(left, ["held 2", "held 0", "held 3"], "{}", store.name());
+            cleanup(path);
+        }
+    }
+
+    #[test]
+    fn older_databases_are_migrated() {
+        let path = std::env::temp_dir().join(format!("pagi-working-memory-{}.db", Uuid::new_v4()));
+        let twin = Uuid::new_v4();
+        {
+            let conn = Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE items (
+                     seq        INTEGER PRIMARY KEY AUTOINCREMENT,
+                     twin_id    TEXT NOT NULL,
+                     created_at INTEGER NOT NULL,
+                     item       TEXT NOT NULL
+                 );
+                 CREATE TABLE long_term (
+                     seq       INTEGER PRIMARY KEY AUTOINCREMENT,
+                     twin_id   TEXT NOT NULL,
+                     record_id TEXT NOT NULL UNIQUE,
+                     kind      TEXT NOT NULL,
+                     dedup_key TEXT,
+                     seen_at   INTEGER NOT NULL,
+                     record    TEXT NOT NULL
+                 );",
+            )
+            .unwrap();
+            let record = json!({
+                "id": Uuid::new_v4(),
+                "kind": "episodic",
+                "role": "user",
+                "content": "tea",
+                "created_at": "2024-01-01T00:00:00Z",
+                "first_seen": "2024-01-01T00:00:00Z",
+                "last_seen": "2024-01-01T00:00:00Z",
+                "occurrences": 1,
+            });
+            conn.execute(
+                "INSERT INTO long_term (twin_id, record_id, kind, dedup_key, seen_at, record)
+                 VALUES (?1, ?2, 'episodic', 'user\u{1f}tea', 0, ?3)",
+                params![twin.to_string(), record["id"].as_str().unwrap(), record.to_string()],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO items (twin_id, created_at, item) VALUES (?1, 0, ?2)",
+                params![twin.to_string(), r#"{"role":"user","content":"héllo","created_at":"2024-01-01T00:00:00Z"}"#],
+            )
+            .unwrap();
+        }
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.usage(twin).unwrap(), Usage { items: 1, bytes: 10 });
+        let items = store.list(twin).unwrap();
+        assert_eq!(store.get(twin, items[0].id).unwrap().map(|item| item.content), Some("héllo".to_string()));
+        // Episodes from before staging was marked are kept, not summarized.
+        assert!(store.staged(twin, 10).unwrap().is_empty());
+        assert_eq!(store.records(twin, RecordKind::Episodic, &ItemQuery::default()).unwrap().len(), 1);
+        drop(store);
+        cleanup(Some(path));
+    }
+}